@@ -0,0 +1,449 @@
+// Computer players. Boards are seen from the AI's side: 1 for its own
+// marks, -1 for the opponent's and 0 for empty cells, indexed like Table
+// cells (0 top-left .. 8 bottom-right).
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::him_network::HimNetwork;
+use crate::model::PolicyModel;
+use crate::output::{MovePolicy, Table};
+
+pub(crate) const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+/// The table from the point of view of the player using `symbol`. The AIs
+/// here only know the 3x3 game.
+pub fn board_for(table: &Table, symbol: char) -> [i8; 9] {
+    table.to_state_for(symbol)
+}
+
+/// 1 or -1 for a completed line, 0 otherwise.
+pub fn winner(board: &[i8; 9]) -> i8 {
+    for line in LINES.iter() {
+        let sum: i8 = line.iter().map(|&i| board[i]).sum();
+        if sum == 3 {
+            return 1;
+        } else if sum == -3 {
+            return -1;
+        }
+    }
+    0
+}
+
+/// How far ahead `MinimaxAi` looks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    /// Only its own next move.
+    Easy,
+    /// Its move, the reply and its next move.
+    Medium,
+    /// The whole game tree: never loses.
+    Hard,
+}
+
+impl Difficulty {
+    /// Search depth in plies, None for a full search.
+    pub fn max_depth(self) -> Option<u32> {
+        match self {
+            Difficulty::Easy => Some(1),
+            Difficulty::Medium => Some(3),
+            Difficulty::Hard => None,
+        }
+    }
+}
+
+/// Score of a win found right after the root move; later wins score less.
+const WIN: i32 = 100;
+
+/// Any legal move, uniformly at random. Draws from the thread RNG unless
+/// built `with_seed`.
+#[derive(Clone, Debug, Default)]
+pub struct RandomAi {
+    rng: Option<StdRng>,
+}
+
+impl RandomAi {
+    pub fn new() -> RandomAi {
+        RandomAi::default()
+    }
+
+    /// A player whose moves are reproducible for a given seed.
+    pub fn with_seed(seed: u64) -> RandomAi {
+        RandomAi {
+            rng: Some(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// A uniformly random free position of `table`, None when it is full.
+    pub fn pick(&mut self, table: &Table) -> Option<i32> {
+        self.choose_from(&table.legal_positions())
+    }
+
+    fn choose_from(&mut self, positions: &[i32]) -> Option<i32> {
+        let rng: &mut dyn rand::RngCore = match &mut self.rng {
+            Some(rng) => rng,
+            None => &mut rand::thread_rng(),
+        };
+        positions.choose(rng).copied()
+    }
+}
+
+impl MovePolicy for RandomAi {
+    fn choose_move(&mut self, _table: &Table, legal: &[i32]) -> i32 {
+        self.choose_from(legal).expect("no legal move on a full board")
+    }
+}
+
+/// Alpha-beta search player for `symbol`. Positions at the depth limit are
+/// scored by `evaluate`; without a limit it plays perfectly.
+#[derive(Clone, Copy, Debug)]
+pub struct MinimaxAi {
+    pub symbol: char,
+    pub max_depth: Option<u32>,
+}
+
+impl MinimaxAi {
+    /// Full-depth search.
+    pub fn new(symbol: char) -> MinimaxAi {
+        MinimaxAi {
+            symbol,
+            max_depth: None,
+        }
+    }
+
+    pub fn with_difficulty(symbol: char, difficulty: Difficulty) -> MinimaxAi {
+        MinimaxAi {
+            symbol,
+            max_depth: difficulty.max_depth(),
+        }
+    }
+
+    /// Best cell for side 1 to play. Wins score `WIN` minus the depth and
+    /// losses the depth minus `WIN`, so quicker wins and slower losses are
+    /// preferred; ties go to the lowest index. Panics on a full board.
+    pub fn best_move(&self, board: &[i8; 9]) -> usize {
+        let mut board = *board;
+        let mut best: Option<(usize, i32)> = None;
+        for index in 0..9 {
+            if board[index] != 0 {
+                continue;
+            }
+            board[index] = 1;
+            // Moves that can't beat the best so far only need an upper bound.
+            let alpha = best.map_or(-WIN, |(_, s)| s);
+            let score = -self.negamax(&mut board, -1, 1, -WIN, -alpha);
+            board[index] = 0;
+            if best.is_none_or(|(_, s)| score > s) {
+                best = Some((index, score));
+            }
+        }
+        best.expect("no legal move on a full board").0
+    }
+
+    /// Score of the position for `side` to move, `depth` plies in, within
+    /// the (`alpha`, `beta`) window.
+    fn negamax(&self, board: &mut [i8; 9], side: i8, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+        // Only the previous move, by the other side, can have completed a line.
+        if winner(board) == -side {
+            return depth as i32 - WIN;
+        }
+        if !board.contains(&0) {
+            return 0;
+        }
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            return evaluate(board, side);
+        }
+        for index in 0..9 {
+            if board[index] != 0 {
+                continue;
+            }
+            board[index] = side;
+            let score = -self.negamax(board, -side, depth + 1, -beta, -alpha);
+            board[index] = 0;
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        alpha
+    }
+}
+
+impl MovePolicy for MinimaxAi {
+    fn choose_move(&mut self, table: &Table, _legal: &[i32]) -> i32 {
+        table.index_to_position(self.best_move(&board_for(table, self.symbol)) as i32)
+    }
+}
+
+/// Rule-based player for `symbol`: win if it can, otherwise block the
+/// opponent's win, otherwise take the center, then a corner, then an edge.
+#[derive(Clone, Copy, Debug)]
+pub struct HeuristicAi {
+    pub symbol: char,
+}
+
+/// Cells in order of preference when there is nothing to win or block.
+const PREFERRED_CELLS: [usize; 9] = [4, 0, 2, 6, 8, 1, 3, 5, 7];
+
+impl HeuristicAi {
+    pub fn new(symbol: char) -> HeuristicAi {
+        HeuristicAi { symbol }
+    }
+
+    /// Cell for side 1 to play. Panics on a full board.
+    pub fn best_move(&self, board: &[i8; 9]) -> usize {
+        completing_cell(board, 1)
+            .or_else(|| completing_cell(board, -1))
+            .or_else(|| PREFERRED_CELLS.iter().copied().find(|&i| board[i] == 0))
+            .expect("no legal move on a full board")
+    }
+}
+
+impl MovePolicy for HeuristicAi {
+    fn choose_move(&mut self, table: &Table, _legal: &[i32]) -> i32 {
+        table.index_to_position(self.best_move(&board_for(table, self.symbol)) as i32)
+    }
+}
+
+/// The empty cell that would complete a line for `side`, if any.
+fn completing_cell(board: &[i8; 9], side: i8) -> Option<usize> {
+    LINES.iter().find_map(|line| {
+        let own = line.iter().filter(|&&i| board[i] == side).count();
+        let empty = line.iter().copied().find(|&i| board[i] == 0);
+        if own == 2 {
+            empty
+        } else {
+            None
+        }
+    })
+}
+
+/// Plays the move a trained `PolicyModel` rates highest among the empty
+/// cells. Without a model it plays random legal moves.
+pub struct NetworkAi {
+    pub symbol: char,
+    network: Option<Box<dyn PolicyModel>>,
+    fallback: RandomAi,
+}
+
+impl NetworkAi {
+    pub fn new(symbol: char, network: impl PolicyModel + 'static) -> NetworkAi {
+        NetworkAi::with_model(symbol, Box::new(network))
+    }
+
+    pub fn with_model(symbol: char, network: Box<dyn PolicyModel>) -> NetworkAi {
+        NetworkAi {
+            symbol,
+            network: Some(network),
+            fallback: RandomAi::new(),
+        }
+    }
+
+    /// Load a model saved with `HimNetwork::save`. A missing or unreadable
+    /// file is logged and leaves the player on random moves.
+    pub fn load(symbol: char, path: &Path) -> NetworkAi {
+        match HimNetwork::load(path) {
+            Ok(network) => NetworkAi::new(symbol, network),
+            Err(e) => {
+                eprintln!("could not load model {}: {}; playing random moves", path.display(), e);
+                NetworkAi {
+                    symbol,
+                    network: None,
+                    fallback: RandomAi::new(),
+                }
+            }
+        }
+    }
+
+    pub fn network(&self) -> Option<&dyn PolicyModel> {
+        self.network.as_deref()
+    }
+
+    /// Make the random fallback moves reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.fallback = RandomAi::with_seed(seed);
+    }
+}
+
+impl MovePolicy for NetworkAi {
+    /// The network sees its own marks as 1.0 and the opponent's as -1.0.
+    fn choose_move(&mut self, table: &Table, legal: &[i32]) -> i32 {
+        let board = board_for(table, self.symbol);
+        match &self.network {
+            Some(network) if board.contains(&0) => {
+                let index = network.best_move(&board.map(f32::from), &board.map(|cell| cell == 0));
+                table.index_to_position(index as i32)
+            }
+            _ => self.fallback.choose_move(table, legal),
+        }
+    }
+}
+
+/// Heuristic for an unfinished position: open two-in-a-rows (two marks and
+/// an empty cell) for `side` minus those of the other side.
+pub fn evaluate(board: &[i8; 9], side: i8) -> i32 {
+    let open_twos = |who: i8| {
+        LINES
+            .iter()
+            .filter(|line| {
+                line.iter().filter(|&&i| board[i] == who).count() == 2
+                    && line.iter().any(|&i| board[i] == 0)
+            })
+            .count() as i32
+    };
+    open_twos(side) - open_twos(-side)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::output::KeyLayout;
+
+    /// Cell index `policy` picks on a table given by `Table::from_symbols`.
+    fn cell_chosen(policy: &mut dyn MovePolicy, symbols: &str) -> i32 {
+        let table = Table::from_symbols(symbols);
+        table.position_to_index(policy.choose_move(&table, &table.legal_positions()))
+    }
+
+    #[test]
+    fn test_ai_moves_follow_the_table_layout() {
+        let mut table = Table::from_symbols("XX.OO....");
+        table.set_key_layout(KeyLayout::ReadingOrder);
+        assert_eq!(HeuristicAi::new('X').choose_move(&table, &table.legal_positions()), 3);
+        assert_eq!(MinimaxAi::new('X').choose_move(&table, &table.legal_positions()), 3);
+    }
+
+    #[test]
+    fn test_minimax_never_loses_to_random() {
+        let ai = MinimaxAi::new('X');
+        let mut rng = StdRng::seed_from_u64(17);
+        for game in 0..100 {
+            // Board from the AI's side; alternate who starts.
+            let mut board = [0i8; 9];
+            let mut side = if game % 2 == 0 { 1 } else { -1 };
+            while winner(&board) == 0 && board.contains(&0) {
+                let index = if side == 1 {
+                    ai.best_move(&board)
+                } else {
+                    let empty: Vec<usize> = (0..9).filter(|&i| board[i] == 0).collect();
+                    empty[rng.gen_range(0..empty.len())]
+                };
+                board[index] = side;
+                side = -side;
+            }
+            assert_ne!(winner(&board), -1, "lost game {}: {:?}", game, board);
+        }
+    }
+
+    /// Whether `ai`, playing side 1 from `board` with side 1 to move, wins
+    /// against every possible reply.
+    fn always_wins(ai: &MinimaxAi, board: &[i8; 9]) -> bool {
+        let mut board = *board;
+        board[ai.best_move(&board)] = 1;
+        if winner(&board) == 1 {
+            return true;
+        }
+        board.contains(&0)
+            && (0..9).filter(|&i| board[i] == 0).all(|reply| {
+                let mut next = board;
+                next[reply] = -1;
+                winner(&next) == 0 && next.contains(&0) && always_wins(ai, &next)
+            })
+    }
+
+    #[test]
+    fn test_easy_misses_forced_wins_hard_takes() {
+        let easy = MinimaxAi::with_difficulty('X', Difficulty::Easy);
+        let hard = MinimaxAi::with_difficulty('X', Difficulty::Hard);
+        // Opponent on 0 and 1, own mark on 2: playing 5 forces a win, Easy
+        // settles for the center.
+        let board = [-1, -1, 1, 0, 0, 0, 0, 0, 0];
+        assert_eq!(hard.best_move(&board), 5);
+        assert_eq!(easy.best_move(&board), 4);
+        assert!(always_wins(&hard, &board));
+        assert!(!always_wins(&easy, &board));
+        // Blocking at 2 also sets up a fork; Easy goes for an open two and
+        // ignores the threat.
+        let board = [1, 0, 0, 0, 0, -1, 0, 1, -1];
+        assert_eq!(hard.best_move(&board), 2);
+        assert_ne!(easy.best_move(&board), 2);
+        assert!(always_wins(&hard, &board));
+        assert!(!always_wins(&easy, &board));
+    }
+
+    #[test]
+    fn test_evaluate_counts_open_twos() {
+        // Own marks on 0 and 4 share the diagonal; blocked, it doesn't count.
+        let board = [1, 0, 0, 0, 1, 0, 0, 0, 0];
+        assert_eq!(evaluate(&board, 1), 1);
+        assert_eq!(evaluate(&board, -1), -1);
+        let board = [1, 0, 0, 0, 1, 0, 0, 0, -1];
+        assert_eq!(evaluate(&board, 1), 0);
+        // A fork counts twice.
+        let board = [1, 1, 0, 0, 1, 0, 0, 0, -1];
+        assert_eq!(evaluate(&board, 1), 2);
+    }
+
+    #[test]
+    fn test_heuristic_takes_the_win() {
+        // X on 0 and 1 and O on 3 and 4: completing the top row beats blocking.
+        assert_eq!(cell_chosen(&mut HeuristicAi::new('X'), "XX.OO...."), 2);
+    }
+
+    #[test]
+    fn test_heuristic_blocks_the_opponent() {
+        assert_eq!(cell_chosen(&mut HeuristicAi::new('X'), "O.X.O...."), 8);
+    }
+
+    #[test]
+    fn test_heuristic_prefers_center_then_corners() {
+        assert_eq!(cell_chosen(&mut HeuristicAi::new('X'), "........."), 4);
+        assert_eq!(cell_chosen(&mut HeuristicAi::new('X'), "....O...."), 0);
+    }
+
+    #[test]
+    fn test_network_ai_plays_legal_moves() {
+        let mut network = HimNetwork::new();
+        network.init_params_with_rng(&mut StdRng::seed_from_u64(3));
+        let index = cell_chosen(&mut NetworkAi::new('X', network), "XO.OX.XO.");
+        assert!([2, 5, 8].contains(&index), "{}", index);
+    }
+
+    #[test]
+    fn test_network_ai_without_model_plays_randomly() {
+        let mut ai = NetworkAi::load('X', Path::new("no/such/model.himn"));
+        assert!(ai.network().is_none());
+        assert_eq!(cell_chosen(&mut ai, "XOXOXOOX."), 8);
+    }
+
+    #[test]
+    fn test_random_pick_is_free_or_none() {
+        assert_eq!(RandomAi::new().pick(&Table::from_symbols("XOXOXOOX.")), Some(3));
+        assert_eq!(RandomAi::new().pick(&Table::from_symbols("XOXOXOOXO")), None);
+    }
+
+    #[test]
+    fn test_minimax_prefers_the_quickest_win() {
+        // Own marks at 0 and 1, opponent at 3 and 4: winning now at 2 beats
+        // blocking at 5.
+        let board = [1, 1, 0, -1, -1, 0, 0, 0, 0];
+        assert_eq!(MinimaxAi::new('X').best_move(&board), 2);
+        // Must block the opponent's open line.
+        let board = [-1, -1, 0, 0, 1, 0, 0, 0, 0];
+        assert_eq!(MinimaxAi::new('X').best_move(&board), 2);
+    }
+}
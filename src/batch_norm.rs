@@ -0,0 +1,162 @@
+// Batch normalization for one HimNetwork hidden layer. Works on the same
+// one-row-per-example matrices as the network itself.
+use crate::layers::Layer;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchNorm {
+    pub gamma: Vec<f32>,
+    pub beta: Vec<f32>,
+    pub running_mean: Vec<f32>,
+    pub running_var: Vec<f32>,
+    pub momentum: f32,
+    pub eps: f32,
+    pub d_gamma: Vec<f32>,
+    pub d_beta: Vec<f32>,
+    // Cached by forward for backward.
+    x_hat: Vec<Vec<f32>>,
+    inv_std: Vec<f32>,
+    used_batch_stats: bool,
+    training: bool,
+}
+
+impl BatchNorm {
+    pub fn new(width: usize) -> BatchNorm {
+        BatchNorm {
+            gamma: vec![1.0; width],
+            beta: vec![0.0; width],
+            running_mean: vec![0.0; width],
+            running_var: vec![1.0; width],
+            momentum: 0.9,
+            eps: 1e-5,
+            d_gamma: vec![0.0; width],
+            d_beta: vec![0.0; width],
+            x_hat: Vec::new(),
+            inv_std: Vec::new(),
+            used_batch_stats: false,
+            training: true,
+        }
+    }
+
+    /// Normalize one example with the running statistics, leaving the
+    /// training caches alone.
+    pub fn apply(&self, z: &[f32]) -> Vec<f32> {
+        z.iter()
+            .enumerate()
+            .map(|(j, v)| {
+                let x_hat = (v - self.running_mean[j]) / (self.running_var[j] + self.eps).sqrt();
+                self.gamma[j] * x_hat + self.beta[j]
+            })
+            .collect()
+    }
+}
+
+impl Layer for BatchNorm {
+    /// Normalize z column by column. In training with more than one example
+    /// the batch statistics are used and folded into the running ones;
+    /// otherwise (inference, or a batch of 1) the running statistics are used.
+    fn forward(&mut self, z: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let m = z.len();
+        let width = self.gamma.len();
+        self.used_batch_stats = self.training && m > 1;
+        let (mean, var) = if self.used_batch_stats {
+            let mut mean = vec![0.0; width];
+            for row in z {
+                for (mu, v) in mean.iter_mut().zip(row.iter()) {
+                    *mu += v / m as f32;
+                }
+            }
+            let mut var = vec![0.0; width];
+            for row in z {
+                for ((s, v), mu) in var.iter_mut().zip(row.iter()).zip(mean.iter()) {
+                    *s += (v - mu) * (v - mu) / m as f32;
+                }
+            }
+            for j in 0..width {
+                self.running_mean[j] =
+                    self.momentum * self.running_mean[j] + (1.0 - self.momentum) * mean[j];
+                self.running_var[j] =
+                    self.momentum * self.running_var[j] + (1.0 - self.momentum) * var[j];
+            }
+            (mean, var)
+        } else {
+            (self.running_mean.clone(), self.running_var.clone())
+        };
+
+        self.inv_std = var.iter().map(|v| 1.0 / (v + self.eps).sqrt()).collect();
+        self.x_hat = z
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(mean.iter())
+                    .zip(self.inv_std.iter())
+                    .map(|((v, mu), inv)| (v - mu) * inv)
+                    .collect()
+            })
+            .collect();
+        self.x_hat
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(self.gamma.iter())
+                    .zip(self.beta.iter())
+                    .map(|((x, g), b)| g * x + b)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Given the gradient w.r.t. the normalized output, store dGamma/dBeta
+    /// and return the gradient w.r.t. the un-normalized z.
+    fn backward(&mut self, d_out: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let m = d_out.len() as f32;
+        let width = self.gamma.len();
+        let mut sum_d_hat = vec![0.0; width];
+        let mut sum_d_hat_x_hat = vec![0.0; width];
+        self.d_gamma = vec![0.0; width];
+        self.d_beta = vec![0.0; width];
+        for (row, x_row) in d_out.iter().zip(self.x_hat.iter()) {
+            for j in 0..width {
+                let d_hat = row[j] * self.gamma[j];
+                sum_d_hat[j] += d_hat;
+                sum_d_hat_x_hat[j] += d_hat * x_row[j];
+                self.d_gamma[j] += row[j] * x_row[j];
+                self.d_beta[j] += row[j];
+            }
+        }
+        d_out
+            .iter()
+            .zip(self.x_hat.iter())
+            .map(|(row, x_row)| {
+                (0..width)
+                    .map(|j| {
+                        let d_hat = row[j] * self.gamma[j];
+                        if self.used_batch_stats {
+                            self.inv_std[j] / m
+                                * (m * d_hat - sum_d_hat[j] - x_row[j] * sum_d_hat_x_hat[j])
+                        } else {
+                            // Running statistics are constants here.
+                            d_hat * self.inv_std[j]
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn infer(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        input.iter().map(|row| self.apply(row)).collect()
+    }
+
+    fn update(&mut self, alpha: f32) {
+        for (g, d) in self.gamma.iter_mut().zip(self.d_gamma.iter()) {
+            *g -= alpha * d;
+        }
+        for (b, d) in self.beta.iter_mut().zip(self.d_beta.iter()) {
+            *b -= alpha * d;
+        }
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
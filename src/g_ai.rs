@@ -1,22 +1,44 @@
-/*mod g_class;
-use g_class::NeuralNetwork;
-use std::io;
-use output::Player;
+// Move suggestions from a g_class network trained on (state, move) pairs,
+// with the board seen from the side of the player to move as in `ai`.
+use crate::ai::board_for;
+use crate::g_class::{NeuralNetwork, NnError};
+use crate::output::{Player, Table};
 
-pub fn recommend_play(nn: &NeuralNetwork, player: &Player, table: &Table) -> i32 {
-    let mut best_play = 0;
-    let mut best_score = -1.0;
-    for i in 0..9 {
-        if table.is_empty(i) {
-            let mut input = vec![0.0; 9];
-            input[i] = 1.0;
-            let (hidden, output) = nn.forward(&input);
-            if output[0] > best_score {
-                best_score = output[0];
-                best_play = i;
-            }
+/// The free cell of `table` that `nn` rates highest for `player`, the
+/// lower index in a tie. None when no cell is free.
+pub fn recommend_play(nn: &NeuralNetwork, player: &Player, table: &Table) -> Result<Option<usize>, NnError> {
+    let board = board_for(table, player.symbol).map(f64::from);
+    let taken: Vec<usize> = (0..9).filter(|&cell| !table.is_empty(cell)).collect();
+    let best = nn.predict_topk_excluding(&board, 1, &taken)?;
+    Ok(best.first().map(|&(cell, _)| cell))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::g_class::OutputKind;
+
+    #[test]
+    fn test_recommend_play() {
+        let x = Player::new("x".to_string(), 'X');
+        let o = Player::new("o".to_string(), 'O');
+        let mut nn = NeuralNetwork::new_seeded(&[9, 12, 9], 0.5, 2).with_output(OutputKind::Softmax);
+        // Always the centre, then the top-left corner.
+        let boards: Vec<Vec<f64>> = vec![vec![0.0; 9], vec![0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0]];
+        for _ in 0..200 {
+            nn.train(&boards[0], 4).unwrap();
+            nn.train(&boards[1], 0).unwrap();
         }
+
+        let empty = Table::from_state(&[0; 9], &x, &o).unwrap();
+        assert_eq!(recommend_play(&nn, &x, &empty), Ok(Some(4)));
+        // The centre is taken, so never recommended.
+        let taken = Table::from_state(&[0, 0, 0, 0, -1, 0, 0, 0, 0], &x, &o).unwrap();
+        assert_eq!(recommend_play(&nn, &x, &taken), Ok(Some(0)));
+        let full = Table::from_state(&[1, -1, 1, 1, -1, -1, -1, 1, 1], &x, &o).unwrap();
+        assert_eq!(recommend_play(&nn, &x, &full), Ok(None));
+
+        let wide = NeuralNetwork::new_seeded(&[10, 9], 0.5, 2);
+        assert_eq!(recommend_play(&wide, &x, &empty), Err(NnError::InputSizeMismatch { expected: 10, found: 9 }));
     }
-    best_play
 }
-*/
\ No newline at end of file
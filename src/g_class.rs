@@ -1,159 +1,2163 @@
-use rand::Rng;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub};
+use std::path::Path;
+use std::time::Instant;
 
-pub struct NeuralNetwork {
-    input_size: usize,
-    hidden_size: usize,
-    output_size: usize,
-    learning_rate: f64,
-    weights_input_hidden: Vec<Vec<f64>>,
-    weights_hidden_output: Vec<Vec<f64>>,
-    bias_hidden: Vec<f64>,
-    bias_output: Vec<f64>,
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::input::GamesData;
+use crate::model::{PolicyModel, TrainReport};
+use crate::training::{EpochStats, FitHistory, FitOptions};
+
+/// The precision a `Network` computes in, f64 or f32. Hyperparameters
+/// such as the learning rate, and the losses `fit` records, stay f64.
+pub trait Float:
+    Copy
+    + Default
+    + PartialOrd
+    + fmt::Debug
+    + fmt::Display
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + MulAssign
+    + Sum
+    + Serialize
+    + DeserializeOwned
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+    /// The nearest value of this precision.
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn tanh(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn is_finite(self) -> bool;
+    fn total_cmp(&self, other: &Self) -> Ordering;
 }
 
-impl NeuralNetwork {
-    pub fn new(input_size: usize, hidden_size: usize, output_size: usize, learning_rate: f64) -> Self {
-        let mut rng = rand::thread_rng();
-        let weights_input_hidden = (0..hidden_size)
-            .map(|_| (0..input_size).map(|_| rng.gen_range(-1.0..1.0)).collect())
-            .collect();
-        let weights_hidden_output = (0..output_size)
-            .map(|_| (0..hidden_size).map(|_| rng.gen_range(-1.0..1.0)).collect())
-            .collect();
-        let bias_hidden = vec![0.0; hidden_size];
-        let bias_output = vec![0.0; output_size];
-        NeuralNetwork {
-            input_size,
-            hidden_size,
-            output_size,
-            learning_rate,
-            weights_input_hidden,
-            weights_hidden_output,
-            bias_hidden,
-            bias_output,
+macro_rules! impl_float {
+    ($float:ty) => {
+        impl Float for $float {
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+
+            fn from_f64(x: f64) -> Self {
+                x as $float
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn exp(self) -> Self {
+                <$float>::exp(self)
+            }
+
+            fn ln(self) -> Self {
+                <$float>::ln(self)
+            }
+
+            fn tanh(self) -> Self {
+                <$float>::tanh(self)
+            }
+
+            fn sqrt(self) -> Self {
+                <$float>::sqrt(self)
+            }
+
+            fn abs(self) -> Self {
+                <$float>::abs(self)
+            }
+
+            fn max(self, other: Self) -> Self {
+                <$float>::max(self, other)
+            }
+
+            fn is_finite(self) -> bool {
+                <$float>::is_finite(self)
+            }
+
+            fn total_cmp(&self, other: &Self) -> Ordering {
+                <$float>::total_cmp(self, other)
+            }
+        }
+    };
+}
+
+impl_float!(f64);
+impl_float!(f32);
+
+/// How the output layer turns its sums into outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputKind {
+    /// Each output on its own, trained on the squared error.
+    #[default]
+    Sigmoid,
+    /// Outputs summing to 1, one probability per class, trained on the
+    /// cross-entropy.
+    Softmax,
+}
+
+/// What the hidden layers apply to their sums.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Activation {
+    #[default]
+    Sigmoid,
+    Tanh,
+    ReLU,
+}
+
+impl Activation {
+    pub fn apply<F: Float>(self, x: F) -> F {
+        match self {
+            Activation::Sigmoid => Network::sigmoid(x),
+            Activation::Tanh => x.tanh(),
+            Activation::ReLU => x.max(F::ZERO),
+        }
+    }
+
+    /// The derivative at the sum that `apply` turned into `y`, taken from
+    /// `y` itself as the network keeps only the activations.
+    pub fn derivative<F: Float>(self, y: F) -> F {
+        match self {
+            Activation::Sigmoid => Network::sigmoid_derivative(y),
+            Activation::Tanh => F::ONE - y * y,
+            Activation::ReLU => {
+                if y > F::ZERO {
+                    F::ONE
+                } else {
+                    F::ZERO
+                }
+            }
+        }
+    }
+}
+
+/// What `NeuralNetwork::train` should output for an input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target<'a, F = f64> {
+    /// Every output.
+    Vector(&'a [F]),
+    /// 1 for this output and 0 for the others.
+    Class(usize),
+}
+
+impl<'a, F: Float> Target<'a, F> {
+    /// The target as `outputs` values.
+    fn to_vector(self, outputs: usize) -> Cow<'a, [F]> {
+        match self {
+            Target::Vector(target) => Cow::Borrowed(target),
+            Target::Class(class) => (0..outputs).map(|i| if i == class { F::ONE } else { F::ZERO }).collect(),
         }
     }
+}
 
-    fn sigmoid(x: f64) -> f64 {
-        1.0 / (1.0 + (-x).exp())
+impl<'a, F> From<&'a [F]> for Target<'a, F> {
+    fn from(target: &'a [F]) -> Target<'a, F> {
+        Target::Vector(target)
     }
+}
 
-    fn sigmoid_derivative(x: f64) -> f64 {
-        x * (1.0 - x)
+impl<'a, F> From<&'a Vec<F>> for Target<'a, F> {
+    fn from(target: &'a Vec<F>) -> Target<'a, F> {
+        Target::Vector(target)
     }
+}
 
-    pub fn forward(&self, input: &[f64]) -> (Vec<f64>, Vec<f64>) {
-        let hidden: Vec<f64> = self
-            .weights_input_hidden
-            .iter()
-            .zip(self.bias_hidden.iter())
-            .map(|(w, b)| {
-                let sum: f64 = w.iter().zip(input.iter()).map(|(wi, xi)| wi * xi).sum();
-                Self::sigmoid(sum + b)
+impl<F> From<usize> for Target<'_, F> {
+    fn from(class: usize) -> Self {
+        Target::Class(class)
+    }
+}
+
+/// An input or target that doesn't fit the network's layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NnError {
+    /// An input with `found` values for `expected` input nodes.
+    InputSizeMismatch { expected: usize, found: usize },
+    /// A target vector with `found` values for `expected` outputs.
+    TargetSizeMismatch { expected: usize, found: usize },
+    /// A class target past the last of `outputs`.
+    ClassOutOfRange { class: usize, outputs: usize },
+    /// `train_batch` or `fit` got a different number of targets than
+    /// inputs.
+    ExampleCountMismatch { inputs: usize, targets: usize },
+    /// `HimNetwork::fit_weighted` got a different number of weights than
+    /// examples.
+    WeightCountMismatch { examples: usize, weights: usize },
+    /// `fit_from_games` found no moves to learn from, or
+    /// `HimNetwork::fit` got no examples.
+    NoExamples,
+    /// `restore` got a snapshot of differently sized layers.
+    SnapshotMismatch,
+    /// `train_on` got a negative, infinite or NaN learning rate.
+    InvalidLearningRate,
+}
+
+impl fmt::Display for NnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NnError::InputSizeMismatch { expected, found } => {
+                write!(f, "input of {} values for a network of {} inputs", found, expected)
+            }
+            NnError::TargetSizeMismatch { expected, found } => {
+                write!(f, "target of {} values for a network of {} outputs", found, expected)
+            }
+            NnError::ClassOutOfRange { class, outputs } => {
+                write!(f, "class {} for a network of {} outputs", class, outputs)
+            }
+            NnError::ExampleCountMismatch { inputs, targets } => write!(f, "{} targets for {} inputs", targets, inputs),
+            NnError::WeightCountMismatch { examples, weights } => {
+                write!(f, "{} weights for {} examples", weights, examples)
+            }
+            NnError::NoExamples => write!(f, "no examples to train on"),
+            NnError::SnapshotMismatch => write!(f, "snapshot of a network with other layer sizes"),
+            NnError::InvalidLearningRate => write!(f, "learning rate is not a finite, non-negative number"),
+        }
+    }
+}
+
+impl std::error::Error for NnError {}
+
+/// Why `NeuralNetwork::evaluate` couldn't score a dataset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvalError {
+    LabelCount { inputs: usize, labels: usize },
+    /// Input `example` has `features` values for `expected` input nodes.
+    FeatureCount { example: usize, features: usize, expected: usize },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::LabelCount { inputs, labels } => write!(f, "{} labels for {} inputs", labels, inputs),
+            EvalError::FeatureCount { example, features, expected } => {
+                write!(f, "input {} has {} features, the network takes {}", example, features, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// A learning rate `NeuralNetwork::set_learning_rate` refused: negative,
+/// infinite or NaN.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidLearningRate(pub f64);
+
+impl fmt::Display for InvalidLearningRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "learning rate {} is not a finite, non-negative number", self.0)
+    }
+}
+
+impl std::error::Error for InvalidLearningRate {}
+
+/// How `NeuralNetwork::fit` changes the learning rate from epoch to epoch,
+/// starting from the network's own.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LrSchedule {
+    #[default]
+    Constant,
+    /// Multiply the rate by `factor` every `every` epochs.
+    Step { every: usize, factor: f64 },
+    /// Multiply the rate by `factor` every epoch.
+    Exponential { factor: f64 },
+}
+
+impl LrSchedule {
+    /// The rate of `epoch`, counted from 0, for a fit starting at `initial`.
+    pub fn rate(self, initial: f64, epoch: usize) -> f64 {
+        match self {
+            LrSchedule::Constant => initial,
+            LrSchedule::Step { every, factor } => initial * factor.powi((epoch / every.max(1)) as i32),
+            LrSchedule::Exponential { factor } => initial * factor.powi(epoch as i32),
+        }
+    }
+}
+
+/// How a network did on a labelled dataset, see `NeuralNetwork::evaluate`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EvalResult {
+    pub examples: usize,
+    /// Mean loss against the one-hot labels, see `NeuralNetwork::loss`.
+    pub loss: f64,
+    /// Share of examples whose label is the largest output.
+    pub accuracy: f64,
+}
+
+/// How `NeuralNetwork::fit` goes over the examples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FitConfig {
+    pub epochs: usize,
+    /// Examples per `train_batch` step, at least one.
+    pub batch_size: usize,
+    /// Reshuffle the order of the examples before every epoch.
+    pub shuffle: bool,
+    /// Seed of the shuffles, so a fit can be repeated exactly.
+    pub seed: u64,
+    /// Print a line per epoch.
+    pub verbose: bool,
+    /// Have `fit_from_games` also train on the games' symmetric copies.
+    pub augment: bool,
+    /// Epochs `fit_validated` goes on without the validation loss
+    /// improving; None to run every epoch.
+    pub patience: Option<usize>,
+    pub schedule: LrSchedule,
+    /// Put back the weights and biases of the epoch with the lowest
+    /// validation loss, or training loss without validation examples,
+    /// before returning.
+    pub keep_best: bool,
+}
+
+impl Default for FitConfig {
+    fn default() -> Self {
+        FitConfig {
+            epochs: 10,
+            batch_size: 32,
+            shuffle: true,
+            seed: 0,
+            verbose: false,
+            augment: false,
+            patience: None,
+            schedule: LrSchedule::Constant,
+            keep_best: false,
+        }
+    }
+}
+
+/// Inputs and targets of the same number of examples.
+type Examples<'a, F> = (&'a [Vec<F>], &'a [Vec<F>]);
+
+/// Shifts and scales every input feature to mean 0 and standard deviation
+/// 1 over the inputs it was fitted on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Normalizer<F = f64> {
+    pub mean: Vec<F>,
+    /// 1 for a feature that doesn't vary, which is then only shifted.
+    pub std: Vec<F>,
+}
+
+impl<F: Float> Normalizer<F> {
+    /// Fitted on `inputs`, each with `features` values. None without
+    /// inputs.
+    pub fn fit(inputs: &[Vec<F>], features: usize) -> Option<Normalizer<F>> {
+        if inputs.is_empty() {
+            return None;
+        }
+        let n = F::from_f64(inputs.len() as f64);
+        let mean: Vec<F> = (0..features).map(|i| inputs.iter().map(|x| x[i]).sum::<F>() / n).collect();
+        let std = (0..features)
+            .map(|i| {
+                let var = inputs.iter().map(|x| (x[i] - mean[i]) * (x[i] - mean[i])).sum::<F>() / n;
+                if var.to_f64() > 1e-12 { var.sqrt() } else { F::ONE }
             })
             .collect();
+        Some(Normalizer { mean, std })
+    }
 
-        let output: Vec<f64> = self
-            .weights_hidden_output
+    pub fn apply(&self, input: &[F]) -> Vec<F> {
+        input
             .iter()
-            .zip(self.bias_output.iter())
-            .map(|(w, b)| {
-                let sum: f64 = w.iter().zip(hidden.iter()).map(|(wi, hi)| wi * hi).sum();
-                Self::sigmoid(sum + b)
+            .zip(self.mean.iter().zip(self.std.iter()))
+            .map(|(&x, (&mean, &std))| (x - mean) / std)
+            .collect()
+    }
+
+    fn cast<G: Float>(&self) -> Normalizer<G> {
+        Normalizer { mean: cast_values(&self.mean), std: cast_values(&self.std) }
+    }
+}
+
+fn cast_values<F: Float, G: Float>(values: &[F]) -> Vec<G> {
+    values.iter().map(|value| G::from_f64(value.to_f64())).collect()
+}
+
+/// How `train` and `train_batch` move the weights along the gradient.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Optimizer {
+    /// The learning rate times the gradient.
+    #[default]
+    Sgd,
+    /// Keeps `beta` of the last step and adds the new gradient.
+    Momentum { beta: f64 },
+    /// Steps by running averages of the gradient and its square, see
+    /// Kingma and Ba, "Adam: A Method for Stochastic Optimization".
+    Adam { beta1: f64, beta2: f64, epsilon: f64 },
+}
+
+impl Optimizer {
+    /// Momentum with the usual beta of 0.9.
+    pub fn momentum() -> Optimizer {
+        Optimizer::Momentum { beta: 0.9 }
+    }
+
+    /// Adam with the paper's defaults.
+    pub fn adam() -> Optimizer {
+        Optimizer::Adam { beta1: 0.9, beta2: 0.999, epsilon: 1e-8 }
+    }
+}
+
+/// A change to every weight and bias of a network, shaped like them.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Gradient<F> {
+    weights: Vec<Vec<Vec<F>>>,
+    biases: Vec<Vec<F>>,
+}
+
+impl<F: Float> Gradient<F> {
+    fn zeros(network: &Network<F>) -> Gradient<F> {
+        Gradient {
+            weights: network
+                .weights
+                .iter()
+                .map(|layer| layer.iter().map(|node| vec![F::ZERO; node.len()]).collect())
+                .collect(),
+            biases: network.biases.iter().map(|layer| vec![F::ZERO; layer.len()]).collect(),
+        }
+    }
+
+    /// Every weight, then every bias.
+    fn values(&self) -> impl Iterator<Item = &F> {
+        self.weights.iter().flatten().flatten().chain(self.biases.iter().flatten())
+    }
+
+    fn values_mut(&mut self) -> impl Iterator<Item = &mut F> {
+        self.weights.iter_mut().flatten().flatten().chain(self.biases.iter_mut().flatten())
+    }
+
+    fn add(&mut self, other: &Gradient<F>) {
+        for (value, &other) in self.values_mut().zip(other.values()) {
+            *value += other;
+        }
+    }
+
+    fn scale(&mut self, factor: F) {
+        for value in self.values_mut() {
+            *value *= factor;
+        }
+    }
+}
+
+/// What the optimizer keeps between steps, empty for plain SGD.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct OptimizerState<F> {
+    /// The last step for momentum, the average gradient for Adam.
+    first: Gradient<F>,
+    /// The average squared gradient for Adam.
+    second: Gradient<F>,
+    /// Steps taken.
+    steps: i32,
+}
+
+impl<F: Float> OptimizerState<F> {
+    fn new(network: &Network<F>) -> OptimizerState<F> {
+        let (first, second) = match network.optimizer {
+            Optimizer::Sgd => (Gradient::default(), Gradient::default()),
+            Optimizer::Momentum { .. } => (Gradient::zeros(network), Gradient::default()),
+            Optimizer::Adam { .. } => (Gradient::zeros(network), Gradient::zeros(network)),
+        };
+        OptimizerState { first, second, steps: 0 }
+    }
+}
+
+/// The weights and biases of a network at one point, see
+/// `Network::snapshot`. Saved on its own it makes a small checkpoint of a
+/// network whose other settings are known.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WeightSnapshot<F = f64> {
+    weights: Vec<Vec<Vec<F>>>,
+    biases: Vec<Vec<F>>,
+}
+
+impl<F: Float> WeightSnapshot<F> {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut out, self)?;
+        out.flush()
+    }
+
+    /// Read a snapshot written by `save`. Whether it fits a network is
+    /// only known on `Network::restore`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<WeightSnapshot<F>> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+}
+
+/// A network computing in f64.
+pub type NeuralNetwork = Network<f64>;
+
+/// A network computing in f32, e.g. a `NeuralNetwork` cast for inference.
+pub type NeuralNetwork32 = Network<f32>;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Network<F> {
+    /// Nodes per layer, the input first and the output last.
+    layer_sizes: Vec<usize>,
+    learning_rate: f64,
+    /// Per layer after the input, per node, the weight of each node of
+    /// the layer before: `weights[layer][i][j]` carries node `j` of the
+    /// layer before into node `i`.
+    weights: Vec<Vec<Vec<F>>>,
+    /// Per layer after the input, per node.
+    biases: Vec<Vec<F>>,
+    output_kind: OutputKind,
+    #[serde(default)]
+    activation: Activation,
+    /// Applied to every input first, once fitted.
+    #[serde(default)]
+    normalizer: Option<Normalizer<F>>,
+    #[serde(default)]
+    optimizer: Optimizer,
+    /// Share of every weight, not the biases, that each step also takes
+    /// off times the learning rate; 0 for none.
+    #[serde(default)]
+    weight_decay: f64,
+    /// Not saved; a loaded network starts it afresh.
+    #[serde(skip)]
+    state: OptimizerState<F>,
+}
+
+impl<F: Float> Network<F> {
+    /// A network with `layer_sizes` nodes per layer, the input first and
+    /// the output last, with any number of hidden layers between. Weights
+    /// start between -1 and 1. Panics with fewer than two layers.
+    pub fn new(layer_sizes: &[usize], learning_rate: f64) -> Self {
+        Self::new_with_rng(layer_sizes, learning_rate, &mut rand::thread_rng())
+    }
+
+    /// `new`, drawing the weights from `rng`.
+    pub fn new_with_rng<R: Rng>(layer_sizes: &[usize], learning_rate: f64, rng: &mut R) -> Self {
+        Self::init(layer_sizes, learning_rate, rng, |_| 1.0)
+    }
+
+    /// `new` with the weights of each node between ±1/sqrt(fan_in), fan_in
+    /// being the size of the layer before (Xavier initialization), so wide
+    /// layers don't start saturated. The same seed gives the same network.
+    pub fn new_seeded(layer_sizes: &[usize], learning_rate: f64, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self::init(layer_sizes, learning_rate, &mut rng, |fan_in| 1.0 / (fan_in.max(1) as f64).sqrt())
+    }
+
+    /// Weights drawn from `rng` between ±`limit(fan_in)`, biases 0.
+    fn init<R: Rng>(layer_sizes: &[usize], learning_rate: f64, rng: &mut R, limit: impl Fn(usize) -> f64) -> Self {
+        assert!(layer_sizes.len() >= 2, "a network needs an input and an output layer");
+        // Drawn in f64 whatever the precision, so the same seed gives the
+        // same weights in both
+        let weights = layer_sizes
+            .windows(2)
+            .map(|pair| {
+                let limit = limit(pair[0]);
+                (0..pair[1])
+                    .map(|_| (0..pair[0]).map(|_| F::from_f64(rng.gen_range(-limit..limit))).collect())
+                    .collect()
             })
             .collect();
+        let biases = layer_sizes[1..].iter().map(|&size| vec![F::ZERO; size]).collect();
+        Network {
+            layer_sizes: layer_sizes.to_vec(),
+            learning_rate,
+            weights,
+            biases,
+            output_kind: OutputKind::default(),
+            activation: Activation::default(),
+            normalizer: None,
+            optimizer: Optimizer::default(),
+            weight_decay: 0.0,
+            state: OptimizerState::default(),
+        }
+    }
+
+    /// A board evaluator: the nine cells of a board from the side of the
+    /// player to move, as in `ai`, in and one sigmoid output out, see
+    /// `evaluate_board`.
+    pub fn value_net(hidden: usize, learning_rate: f64) -> Self {
+        Self::new(&[9, hidden, 1], learning_rate)
+    }
+
+    /// A network with one hidden layer.
+    pub fn single_hidden(input_size: usize, hidden_size: usize, output_size: usize, learning_rate: f64) -> Self {
+        Self::new(&[input_size, hidden_size, output_size], learning_rate)
+    }
+
+    /// The network with `kind` outputs.
+    pub fn with_output(mut self, kind: OutputKind) -> Self {
+        self.output_kind = kind;
+        self
+    }
+
+    pub fn output_kind(&self) -> OutputKind {
+        self.output_kind
+    }
+
+    /// The network with `activation` in its hidden layers.
+    pub fn with_activation(mut self, activation: Activation) -> Self {
+        self.activation = activation;
+        self
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.activation
+    }
+
+    /// The network stepping with `optimizer`.
+    pub fn with_optimizer(mut self, optimizer: Optimizer) -> Self {
+        self.set_optimizer(optimizer);
+        self
+    }
+
+    /// Step with `optimizer` from now on, forgetting what the previous one
+    /// kept.
+    pub fn set_optimizer(&mut self, optimizer: Optimizer) {
+        self.optimizer = optimizer;
+        self.state = OptimizerState::new(self);
+    }
+
+    pub fn optimizer(&self) -> Optimizer {
+        self.optimizer
+    }
 
-        (hidden, output)
+    /// Also shrink every weight towards 0 by `learning_rate *
+    /// weight_decay` of itself each step, apart from the gradient
+    /// (decoupled L2 regularization). The biases aren't decayed.
+    pub fn with_weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
     }
 
-    pub fn train(&mut self, input: &[f64], target: &[f64]) {
-        let (hidden, output) = self.forward(&input);
+    pub fn weight_decay(&self) -> f64 {
+        self.weight_decay
+    }
+
+    pub fn layer_sizes(&self) -> &[usize] {
+        &self.layer_sizes
+    }
+
+    pub fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    /// Train with `learning_rate` from now on, keeping the weights. 0
+    /// leaves them as they are.
+    pub fn set_learning_rate(&mut self, learning_rate: f64) -> Result<(), InvalidLearningRate> {
+        if !learning_rate.is_finite() || learning_rate < 0.0 {
+            return Err(InvalidLearningRate(learning_rate));
+        }
+        self.learning_rate = learning_rate;
+        Ok(())
+    }
+
+    /// Normalize every input from now on by the mean and standard
+    /// deviation of each feature over `inputs`, e.g. the training set.
+    /// Without inputs, inputs are taken as they are again.
+    pub fn fit_normalizer(&mut self, inputs: &[Vec<F>]) {
+        self.normalizer = Normalizer::fit(inputs, self.layer_sizes[0]);
+    }
+
+    pub fn normalizer(&self) -> Option<&Normalizer<F>> {
+        self.normalizer.as_ref()
+    }
+
+    /// The network in precision `G`, each weight rounded to the nearest
+    /// value, e.g. a `NeuralNetwork` trained in f64 as a `NeuralNetwork32`
+    /// for inference. What the optimizer kept starts afresh.
+    pub fn cast<G: Float>(&self) -> Network<G> {
+        let mut network = Network {
+            layer_sizes: self.layer_sizes.clone(),
+            learning_rate: self.learning_rate,
+            weights: self.weights.iter().map(|layer| layer.iter().map(|node| cast_values(node)).collect()).collect(),
+            biases: self.biases.iter().map(|layer| cast_values(layer)).collect(),
+            output_kind: self.output_kind,
+            activation: self.activation,
+            normalizer: self.normalizer.as_ref().map(Normalizer::cast),
+            optimizer: self.optimizer,
+            weight_decay: self.weight_decay,
+            state: OptimizerState::default(),
+        };
+        network.state = OptimizerState::new(&network);
+        network
+    }
+
+    /// A copy of the weights and biases, for `restore`.
+    pub fn snapshot(&self) -> WeightSnapshot<F> {
+        WeightSnapshot { weights: self.weights.clone(), biases: self.biases.clone() }
+    }
+
+    /// Put back the weights and biases of `snapshot`, leaving everything
+    /// else, the optimizer's state included, as it is.
+    pub fn restore(&mut self, snapshot: &WeightSnapshot<F>) -> Result<(), NnError> {
+        let shape = |weights: &Vec<Vec<Vec<F>>>, biases: &Vec<Vec<F>>| {
+            let weights: Vec<Vec<usize>> = weights.iter().map(|layer| layer.iter().map(Vec::len).collect()).collect();
+            let biases: Vec<usize> = biases.iter().map(Vec::len).collect();
+            (weights, biases)
+        };
+        if shape(&snapshot.weights, &snapshot.biases) != shape(&self.weights, &self.biases) {
+            return Err(NnError::SnapshotMismatch);
+        }
+        self.weights.clone_from(&snapshot.weights);
+        self.biases.clone_from(&snapshot.biases);
+        Ok(())
+    }
+
+    /// Write the network, weights, learning rate, output kind, normalizer
+    /// and optimizer included, as JSON to `path`. What the optimizer keeps
+    /// between steps isn't saved.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut out, self)?;
+        out.flush()
+    }
+
+    /// Read a network written by `save`. A file whose weights don't fit
+    /// its layer sizes is `InvalidData`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Network<F>> {
+        let mut network: Network<F> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        network.check_shapes().map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))?;
+        network.state = OptimizerState::new(&network);
+        Ok(network)
+    }
+
+    /// Whether the weights and biases fit `layer_sizes`, and if not why.
+    fn check_shapes(&self) -> Result<(), String> {
+        if self.layer_sizes.len() < 2 {
+            return Err(format!("{} layers, a network needs at least 2", self.layer_sizes.len()));
+        }
+        let layers = self.layer_sizes.len() - 1;
+        if self.weights.len() != layers || self.biases.len() != layers {
+            return Err(format!(
+                "{} weight and {} bias layers for {} layer sizes",
+                self.weights.len(),
+                self.biases.len(),
+                self.layer_sizes.len()
+            ));
+        }
+        for (layer, pair) in self.layer_sizes.windows(2).enumerate() {
+            let (inputs, nodes) = (pair[0], pair[1]);
+            let weights = &self.weights[layer];
+            if weights.len() != nodes || self.biases[layer].len() != nodes {
+                return Err(format!(
+                    "layer {} has {} weight rows and {} biases for {} nodes",
+                    layer + 1,
+                    weights.len(),
+                    self.biases[layer].len(),
+                    nodes
+                ));
+            }
+            if let Some(row) = weights.iter().find(|row| row.len() != inputs) {
+                return Err(format!("layer {} has a node with {} weights for {} inputs", layer + 1, row.len(), inputs));
+            }
+        }
+        if let Some(normalizer) = &self.normalizer {
+            let inputs = self.layer_sizes[0];
+            if normalizer.mean.len() != inputs || normalizer.std.len() != inputs {
+                return Err(format!(
+                    "normalizer of {} means and {} deviations for {} inputs",
+                    normalizer.mean.len(),
+                    normalizer.std.len(),
+                    inputs
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn sigmoid(x: F) -> F {
+        F::ONE / (F::ONE + (-x).exp())
+    }
+
+    fn sigmoid_derivative(x: F) -> F {
+        x * (F::ONE - x)
+    }
+
+    fn softmax(sums: &[F]) -> Vec<F> {
+        // Shifted by the largest sum so exp can't overflow.
+        let max = sums.iter().copied().fold(F::from_f64(f64::NEG_INFINITY), F::max);
+        let exps: Vec<F> = sums.iter().map(|&s| (s - max).exp()).collect();
+        let total: F = exps.iter().copied().sum();
+        exps.iter().map(|&e| e / total).collect()
+    }
+
+    /// The activations of every layer after the input, the output last.
+    pub fn forward(&self, input: &[F]) -> Result<Vec<Vec<F>>, NnError> {
+        self.check_input(input)?;
+        Ok(self.forward_normalized(&self.normalize(input)))
+    }
+
+    fn check_input(&self, input: &[F]) -> Result<(), NnError> {
+        let expected = self.layer_sizes[0];
+        if input.len() != expected {
+            return Err(NnError::InputSizeMismatch { expected, found: input.len() });
+        }
+        Ok(())
+    }
+
+    fn check_target(&self, target: Target<F>) -> Result<(), NnError> {
+        let outputs = *self.layer_sizes.last().expect("an output layer");
+        match target {
+            Target::Vector(target) if target.len() != outputs => {
+                Err(NnError::TargetSizeMismatch { expected: outputs, found: target.len() })
+            }
+            Target::Class(class) if class >= outputs => Err(NnError::ClassOutOfRange { class, outputs }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks every example once, for the batch methods to skip it.
+    fn check_examples(&self, inputs: &[Vec<F>], targets: &[Vec<F>]) -> Result<(), NnError> {
+        if inputs.len() != targets.len() {
+            return Err(NnError::ExampleCountMismatch { inputs: inputs.len(), targets: targets.len() });
+        }
+        for (input, target) in inputs.iter().zip(targets.iter()) {
+            self.check_input(input)?;
+            self.check_target(Target::Vector(target))?;
+        }
+        Ok(())
+    }
+
+    /// `predict` without the check.
+    fn output(&self, input: &[F]) -> Vec<F> {
+        self.forward_normalized(&self.normalize(input)).pop().expect("an output layer")
+    }
+
+    fn normalize<'a>(&self, input: &'a [F]) -> Cow<'a, [F]> {
+        match &self.normalizer {
+            Some(normalizer) => Cow::Owned(normalizer.apply(input)),
+            None => Cow::Borrowed(input),
+        }
+    }
+
+    fn forward_normalized(&self, input: &[F]) -> Vec<Vec<F>> {
+        let mut activations: Vec<Vec<F>> = Vec::with_capacity(self.weights.len());
+        for (layer, (weights, biases)) in self.weights.iter().zip(self.biases.iter()).enumerate() {
+            let previous = activations.last().map_or(input, |a| a.as_slice());
+            let sums: Vec<F> = weights
+                .iter()
+                .zip(biases.iter())
+                .map(|(w, &b)| {
+                    let sum: F = w.iter().zip(previous.iter()).map(|(&wi, &xi)| wi * xi).sum();
+                    sum + b
+                })
+                .collect();
+            let last = layer + 1 == self.weights.len();
+            activations.push(match self.output_kind {
+                _ if !last => sums.into_iter().map(|sum| self.activation.apply(sum)).collect(),
+                OutputKind::Sigmoid => sums.into_iter().map(Self::sigmoid).collect(),
+                OutputKind::Softmax => Self::softmax(&sums),
+            });
+        }
+        activations
+    }
+
+    /// One gradient step towards `target`: a vector of outputs, or a class
+    /// index for a one-hot vector. Returns the loss before the step, see
+    /// `loss`.
+    pub fn train<'a>(&mut self, input: &[F], target: impl Into<Target<'a, F>>) -> Result<F, NnError> {
+        let target = target.into();
+        self.check_input(input)?;
+        self.check_target(target)?;
+        let (gradient, loss) = self.gradient(input, target);
+        self.step(&gradient);
+        Ok(loss)
+    }
+
+    /// How far the output for `input` is from `target`: the mean squared
+    /// error for sigmoid outputs, the cross-entropy for softmax ones.
+    pub fn loss<'a>(&self, input: &[F], target: impl Into<Target<'a, F>>) -> Result<F, NnError> {
+        let target = target.into();
+        self.check_input(input)?;
+        self.check_target(target)?;
+        let output = self.output(input);
+        Ok(self.loss_of(&output, &target.to_vector(output.len())))
+    }
+
+    fn loss_of(&self, output: &[F], target: &[F]) -> F {
+        match self.output_kind {
+            OutputKind::Sigmoid => {
+                let sum: F = target.iter().zip(output.iter()).map(|(&t, &o)| (t - o) * (t - o)).sum();
+                sum / F::from_f64(output.len() as f64)
+            }
+            // Clamped so a confident wrong output stays finite
+            OutputKind::Softmax => {
+                let floor = F::from_f64(1e-12);
+                -target.iter().zip(output.iter()).map(|(&t, &o)| t * o.max(floor).ln()).sum::<F>()
+            }
+        }
+    }
+
+    /// One step along the gradient averaged over the examples, each input
+    /// with the target of the same index. Returns the mean loss before
+    /// the step, see `loss`. An empty batch changes nothing.
+    pub fn train_batch(&mut self, inputs: &[Vec<F>], targets: &[Vec<F>]) -> Result<F, NnError> {
+        self.check_examples(inputs, targets)?;
+        Ok(self.batch_step(inputs, targets))
+    }
+
+    /// `train_batch` on checked examples.
+    fn batch_step(&mut self, inputs: &[Vec<F>], targets: &[Vec<F>]) -> F {
+        if inputs.is_empty() {
+            return F::ZERO;
+        }
+        let mut total = Gradient::zeros(self);
+        let mut loss = F::ZERO;
+        for (input, target) in inputs.iter().zip(targets.iter()) {
+            let (gradient, example_loss) = self.gradient(input, Target::Vector(target));
+            total.add(&gradient);
+            loss += example_loss;
+        }
+        let n = F::from_f64(inputs.len() as f64);
+        total.scale(F::ONE / n);
+        self.step(&total);
+        loss / n
+    }
+
+    /// `options.epochs` passes over the examples in batches, see
+    /// `FitConfig`, each input with the target of the same index. Records
+    /// the mean loss over all examples after each epoch. The slices are
+    /// left as they are; only an order of them is shuffled. The learning
+    /// rate follows `options.schedule` and is back to where it started
+    /// afterwards.
+    pub fn fit(
+        &mut self,
+        inputs: &[Vec<F>],
+        targets: &[Vec<F>],
+        options: &FitConfig,
+    ) -> Result<FitHistory, NnError> {
+        self.check_examples(inputs, targets)?;
+        Ok(self.fit_with(inputs, targets, None, None, options))
+    }
+
+    /// `fit` on class labels, also recording the accuracy after each
+    /// epoch.
+    pub fn fit_classes(
+        &mut self,
+        inputs: &[Vec<F>],
+        labels: &[usize],
+        options: &FitConfig,
+    ) -> Result<FitHistory, NnError> {
+        if inputs.len() != labels.len() {
+            return Err(NnError::ExampleCountMismatch { inputs: inputs.len(), targets: labels.len() });
+        }
+        for (input, &label) in inputs.iter().zip(labels.iter()) {
+            self.check_input(input)?;
+            self.check_target(Target::Class(label))?;
+        }
+        let outputs = *self.layer_sizes.last().expect("an output layer");
+        let targets: Vec<Vec<F>> =
+            labels.iter().map(|&label| Target::Class(label).to_vector(outputs).into_owned()).collect();
+        Ok(self.fit_with(inputs, &targets, Some(labels), None, options))
+    }
+
+    /// `fit_classes` on every move of `games`: the board before it from
+    /// the mover's side, labelled with the cell taken (see
+    /// `GamesData::training_set`). The network needs 9 inputs and 9
+    /// outputs, and is switched to `OutputKind::Softmax`.
+    pub fn fit_from_games(&mut self, games: &GamesData, options: &FitConfig) -> Result<FitHistory, NnError> {
+        self.check_input(&[F::ZERO; 9])?;
+        let outputs = *self.layer_sizes.last().expect("an output layer");
+        if outputs != 9 {
+            return Err(NnError::TargetSizeMismatch { expected: outputs, found: 9 });
+        }
+        let set = if options.augment { games.augment().training_set() } else { games.training_set() };
+        if set.x.is_empty() {
+            return Err(NnError::NoExamples);
+        }
+        let inputs: Vec<Vec<F>> = set.x.iter().map(|row| from_f32(row)).collect();
+        self.output_kind = OutputKind::Softmax;
+        self.fit_classes(&inputs, &set.y, options)
+    }
+
+    /// `fit`, also measuring the loss on the validation examples after
+    /// each epoch. With `options.patience`, training stops once that loss
+    /// hasn't improved for that many epochs, and the weights and biases of
+    /// the best epoch are put back.
+    pub fn fit_validated(
+        &mut self,
+        inputs: &[Vec<F>],
+        targets: &[Vec<F>],
+        validation_inputs: &[Vec<F>],
+        validation_targets: &[Vec<F>],
+        options: &FitConfig,
+    ) -> Result<FitHistory, NnError> {
+        self.check_examples(inputs, targets)?;
+        self.check_examples(validation_inputs, validation_targets)?;
+        Ok(self.fit_with(inputs, targets, None, Some((validation_inputs, validation_targets)), options))
+    }
+
+    /// `fit` on checked examples.
+    fn fit_with(
+        &mut self,
+        inputs: &[Vec<F>],
+        targets: &[Vec<F>],
+        labels: Option<&[usize]>,
+        validation: Option<Examples<F>>,
+        options: &FitConfig,
+    ) -> FitHistory {
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let mut order: Vec<usize> = (0..inputs.len()).collect();
+        let mut history = FitHistory::default();
+        // The weights and biases of the best epoch so far
+        let mut best: Option<WeightSnapshot<F>> = None;
+        let initial_rate = self.learning_rate;
+        for epoch in 0..options.epochs {
+            let start = Instant::now();
+            if options.shuffle {
+                order.shuffle(&mut rng);
+            }
+            self.learning_rate = options.schedule.rate(initial_rate, epoch);
+            for batch in order.chunks(options.batch_size.max(1)) {
+                let batch_inputs: Vec<Vec<F>> = batch.iter().map(|&i| inputs[i].clone()).collect();
+                let batch_targets: Vec<Vec<F>> = batch.iter().map(|&i| targets[i].clone()).collect();
+                self.batch_step(&batch_inputs, &batch_targets);
+            }
+
+            let outputs: Vec<Vec<F>> = inputs.iter().map(|input| self.output(input)).collect();
+            let n = inputs.len().max(1) as f64;
+            let loss = self.mean_loss_of(&outputs, targets);
+            let mut stats = EpochStats { loss, learning_rate: self.learning_rate, ..EpochStats::default() };
+            let mut line = format!("epoch {}/{}: loss {:.4}", epoch + 1, options.epochs, loss);
+            if let Some(labels) = labels {
+                let correct = outputs.iter().zip(labels.iter()).filter(|&(o, &l)| Self::argmax(o) == l).count();
+                stats.accuracy = Some(correct as f64 / n);
+                line += &format!(", accuracy {:.3}", correct as f64 / n);
+            }
+            if let Some((validation_inputs, validation_targets)) = validation {
+                let loss = self.mean_loss(validation_inputs, validation_targets);
+                stats.validation_loss = Some(loss);
+                line += &format!(", validation loss {:.4}", loss);
+            }
+            let improved = match stats.validation_loss {
+                Some(loss) => history.best_epoch.is_none_or(|best| Some(loss) < history.epochs[best].validation_loss),
+                None => options.keep_best && history.best_epoch.is_none_or(|best| loss < history.epochs[best].loss),
+            };
+            if improved {
+                history.best_epoch = Some(epoch);
+                if options.keep_best || options.patience.is_some() {
+                    best = Some(self.snapshot());
+                }
+            }
+            if options.verbose {
+                println!("{}", line);
+            }
+            stats.duration = start.elapsed();
+            history.epochs.push(stats);
+            let since_best = history.best_epoch.map(|best| epoch - best);
+            if options.patience.is_some_and(|patience| since_best.is_some_and(|since| since >= patience)) {
+                history.stopped_epoch = Some(epoch);
+                break;
+            }
+        }
+        if let Some(best) = best {
+            self.restore(&best).expect("a snapshot of the same network");
+        }
+        self.learning_rate = initial_rate;
+        history
+    }
+
+    /// Mean loss over checked examples.
+    fn mean_loss(&self, inputs: &[Vec<F>], targets: &[Vec<F>]) -> f64 {
+        let outputs: Vec<Vec<F>> = inputs.iter().map(|input| self.output(input)).collect();
+        self.mean_loss_of(&outputs, targets)
+    }
+
+    /// Mean loss of `outputs` against `targets`, 0 without any.
+    fn mean_loss_of(&self, outputs: &[Vec<F>], targets: &[Vec<F>]) -> f64 {
+        let total: F = outputs.iter().zip(targets.iter()).map(|(o, t)| self.loss_of(o, t)).sum();
+        (total / F::from_f64(outputs.len().max(1) as f64)).to_f64()
+    }
+
+    /// The gradient of the loss of one checked example, pointing
+    /// downhill, and the loss.
+    fn gradient(&self, input: &[F], target: Target<F>) -> (Gradient<F>, F) {
+        let input = self.normalize(input);
+        let input = input.as_ref();
+        let activations = self.forward_normalized(input);
+        let output = activations.last().expect("an output layer");
+
+        let target = target.to_vector(output.len());
 
         // Calculate output errors
-        let output_errors: Vec<f64> = target
+        let output_errors: Vec<F> = target
             .iter()
             .zip(output.iter())
-            .map(|(t, o)| t - o)
+            .map(|(&t, &o)| t - o)
             .collect();
 
-        // Calculate output deltas
-        let output_deltas: Vec<f64> = output_errors
-            .iter()
-            .zip(output.iter())
-            .map(|(e, o)| e * Self::sigmoid_derivative(*o))
-            .collect();
+        let loss = self.loss_of(output, &target);
 
-        // Calculate hidden errors
-        let hidden_errors: Vec<f64> = self
-            .weights_hidden_output
-            .iter()
-            .zip(output_deltas.iter())
-            .map(|(w, od)| w.iter().map(move |wi| wi * od).sum())
-            .collect();
+        // Calculate output deltas; the softmax's derivative cancels against
+        // the cross-entropy's, leaving the error itself.
+        let mut deltas: Vec<F> = match self.output_kind {
+            OutputKind::Sigmoid => output_errors
+                .iter()
+                .zip(output.iter())
+                .map(|(&e, &o)| e * Self::sigmoid_derivative(o))
+                .collect(),
+            OutputKind::Softmax => output_errors,
+        };
 
-        // Calculate hidden deltas
-        let hidden_deltas: Vec<f64> = hidden_errors
-            .iter()
-            .zip(hidden.iter())
-            .map(|(e, h)| e * Self::sigmoid_derivative(*h))
-            .collect();
+        // From the output back, take each layer's gradient and pass the
+        // deltas on to the layer before.
+        let mut gradient = Gradient::zeros(self);
+        for layer in (0..self.weights.len()).rev() {
+            let previous = if layer == 0 { input } else { &activations[layer - 1] };
 
-        // Update weights_hidden_output
-        for (i, weights) in self.weights_hidden_output.iter_mut().enumerate() {
-            for (j, weight) in weights.iter_mut().enumerate() {
-                *weight += self.learning_rate * output_deltas[i] * hidden[j];
+            // Calculate the previous layer's deltas
+            let previous_deltas: Vec<F> = if layer == 0 {
+                Vec::new()
+            } else {
+                previous
+                    .iter()
+                    .enumerate()
+                    .map(|(j, p)| {
+                        let error: F = self.weights[layer].iter().zip(deltas.iter()).map(|(w, &d)| w[j] * d).sum();
+                        error * self.activation.derivative(*p)
+                    })
+                    .collect()
+            };
+
+            for (i, weights) in gradient.weights[layer].iter_mut().enumerate() {
+                for (j, weight) in weights.iter_mut().enumerate() {
+                    *weight = deltas[i] * previous[j];
+                }
             }
+            gradient.biases[layer] = deltas;
+
+            deltas = previous_deltas;
         }
+        (gradient, loss)
+    }
 
-        // Update bias_output
-        for (i, b) in self.bias_output.iter_mut().enumerate() {
-            *b += self.learning_rate * output_deltas[i];
+    /// The largest relative difference, over every weight and bias, between
+    /// the gradient `train` steps along for one example and centred finite
+    /// differences of `loss`, each moving the parameter by `epsilon` either
+    /// way. Near 0 when the backward pass is right. The differences are
+    /// taken in f64 whatever `F` is.
+    pub fn check_gradients<'a>(
+        &self,
+        input: &[F],
+        target: impl Into<Target<'a, F>>,
+        epsilon: f64,
+    ) -> Result<f64, NnError> {
+        let target = target.into();
+        self.check_input(input)?;
+        self.check_target(target)?;
+        let (gradient, _) = self.gradient(input, target);
+        let outputs = *self.layer_sizes.last().expect("an output layer");
+        // `gradient` is of half the squared error for sigmoid outputs, which
+        // `loss` averages instead, and it points downhill.
+        let scale = match self.output_kind {
+            OutputKind::Sigmoid => -2.0 / outputs as f64,
+            OutputKind::Softmax => -1.0,
+        };
+
+        let mut probe: Network<f64> = self.cast();
+        let input: Vec<f64> = cast_values(input);
+        let target: Vec<f64> = cast_values(&target.to_vector(outputs));
+        let mut worst: f64 = 0.0;
+        for (index, g) in gradient.values().enumerate() {
+            let original = *probe.parameters_mut().nth(index).expect("a parameter per gradient value");
+            let mut loss_at = |value: f64| {
+                *probe.parameters_mut().nth(index).expect("a parameter per gradient value") = value;
+                probe.loss(&input, &target).expect("a checked example")
+            };
+            let numeric = (loss_at(original + epsilon) - loss_at(original - epsilon)) / (2.0 * epsilon);
+            loss_at(original);
+            let analytic = g.to_f64() * scale;
+            // Gradients that small compare by their absolute difference
+            let size = analytic.abs().max(numeric.abs()).max(1e-8);
+            worst = worst.max((analytic - numeric).abs() / size);
         }
+        Ok(worst)
+    }
+
+    /// Every weight, then every bias, in the order of `Gradient::values`.
+    fn parameters_mut(&mut self) -> impl Iterator<Item = &mut F> {
+        self.weights.iter_mut().flatten().flatten().chain(self.biases.iter_mut().flatten())
+    }
 
-        // Update weights_input_hidden
-        for (i, weights) in self.weights_input_hidden.iter_mut().enumerate() {
-            for (j, weight) in weights.iter_mut().enumerate() {
-                *weight += self.learning_rate * hidden_deltas[i] * input[j];
+    /// Move every weight and bias along `gradient`, as the optimizer does.
+    fn step(&mut self, gradient: &Gradient<F>) {
+        if self.weight_decay != 0.0 {
+            let keep = F::from_f64(1.0 - self.learning_rate * self.weight_decay);
+            for weight in self.weights.iter_mut().flatten().flatten() {
+                *weight *= keep;
             }
         }
+        let rate = F::from_f64(self.learning_rate);
+        let params = self.weights.iter_mut().flatten().flatten().chain(self.biases.iter_mut().flatten());
+        let state = &mut self.state;
+        match self.optimizer {
+            Optimizer::Sgd => {
+                for (param, &g) in params.zip(gradient.values()) {
+                    *param += rate * g;
+                }
+            }
+            Optimizer::Momentum { beta } => {
+                let beta = F::from_f64(beta);
+                for ((param, &g), velocity) in params.zip(gradient.values()).zip(state.first.values_mut()) {
+                    *velocity = beta * *velocity + g;
+                    *param += rate * *velocity;
+                }
+            }
+            Optimizer::Adam { beta1, beta2, epsilon } => {
+                state.steps += 1;
+                // The averages start at 0, so early ones are scaled up
+                let first_scale = F::from_f64(1.0 / (1.0 - beta1.powi(state.steps)));
+                let second_scale = F::from_f64(1.0 / (1.0 - beta2.powi(state.steps)));
+                let (beta1, beta2, epsilon) = (F::from_f64(beta1), F::from_f64(beta2), F::from_f64(epsilon));
+                let moments = state.first.values_mut().zip(state.second.values_mut());
+                for ((param, &g), (mean, square)) in params.zip(gradient.values()).zip(moments) {
+                    *mean = beta1 * *mean + (F::ONE - beta1) * g;
+                    *square = beta2 * *square + (F::ONE - beta2) * g * g;
+                    *param += rate * *mean * first_scale / ((*square * second_scale).sqrt() + epsilon);
+                }
+            }
+        }
+    }
+
+    pub fn predict(&self, input: &[F]) -> Result<Vec<F>, NnError> {
+        self.check_input(input)?;
+        Ok(self.output(input))
+    }
+
+    /// The output that `predict` puts highest, the first of a tie.
+    pub fn predict_class(&self, input: &[F]) -> Result<usize, NnError> {
+        self.check_input(input)?;
+        Ok(Self::argmax(&self.output(input)))
+    }
+
+    fn argmax(output: &[F]) -> usize {
+        (0..output.len()).rev().max_by(|&a, &b| output[a].total_cmp(&output[b])).expect("an output")
+    }
+
+    /// The `k` highest outputs with their indices, highest first and the
+    /// lower index first in a tie. All of them when `k` is larger.
+    pub fn predict_topk(&self, input: &[F], k: usize) -> Result<Vec<(usize, F)>, NnError> {
+        self.predict_topk_excluding(input, k, &[])
+    }
+
+    /// `predict_topk` without the outputs at the `excluded` indices, e.g.
+    /// the taken cells of a board.
+    pub fn predict_topk_excluding(
+        &self,
+        input: &[F],
+        k: usize,
+        excluded: &[usize],
+    ) -> Result<Vec<(usize, F)>, NnError> {
+        self.check_input(input)?;
+        let output = self.output(input);
+        let mut ranked: Vec<(usize, F)> =
+            output.into_iter().enumerate().filter(|(index, _)| !excluded.contains(index)).collect();
+        // A stable sort keeps ties in index order.
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked.truncate(k);
+        Ok(ranked)
+    }
 
-        // Update bias_hidden
-        for (i, b) in self.bias_hidden.iter_mut().enumerate() {
-            *b += self.learning_rate * hidden_deltas[i];
+    /// The mean loss and accuracy over `inputs`, each labelled with the
+    /// class of the same index. An empty dataset scores 0 on both.
+    pub fn evaluate(&self, inputs: &[Vec<F>], labels: &[usize]) -> Result<EvalResult, EvalError> {
+        if inputs.len() != labels.len() {
+            return Err(EvalError::LabelCount { inputs: inputs.len(), labels: labels.len() });
+        }
+        let expected = self.layer_sizes[0];
+        if let Some((example, input)) = inputs.iter().enumerate().find(|(_, input)| input.len() != expected) {
+            return Err(EvalError::FeatureCount { example, features: input.len(), expected });
+        }
+        if inputs.is_empty() {
+            return Ok(EvalResult::default());
         }
+        let mut loss = F::ZERO;
+        let mut correct = 0;
+        for (input, &label) in inputs.iter().zip(labels.iter()) {
+            let output = self.output(input);
+            loss += self.loss_of(&output, &Target::Class(label).to_vector(output.len()));
+            correct += (Self::argmax(&output) == label) as usize;
+        }
+        let loss = (loss / F::from_f64(inputs.len() as f64)).to_f64();
+        Ok(EvalResult { examples: inputs.len(), loss, accuracy: correct as f64 / inputs.len() as f64 })
+    }
+
+    /// One `train` step of a `value_net` towards `outcome` for the player
+    /// to move on `board`: 1 for a win, 0.5 for a draw, 0 for a loss.
+    pub fn train_value(&mut self, board: &[F; 9], outcome: F) -> Result<F, NnError> {
+        self.train(board, &[outcome][..])
     }
 
-    pub fn predict(&self, input: &[f64]) -> Vec<f64> {
-        let (_, output) = self.forward(&input);
-        output
+    /// How a `value_net` expects the game to end for the player to move on
+    /// `board`, from 0 for a loss to 1 for a win.
+    pub fn evaluate_board(&self, board: &[F; 9]) -> Result<F, NnError> {
+        Ok(self.predict(board)?[0])
     }
     
 }
 
+/// Panics unless the network has 9 inputs and 9 outputs.
+impl<F: Float> PolicyModel for Network<F> {
+    /// Sigmoid outputs are scaled to sum to 1.
+    fn predict_probs(&self, board: &[f32; 9]) -> [f32; 9] {
+        let output = self.predict(&from_f32(board)).expect("a 9-input network");
+        let total = match self.output_kind {
+            OutputKind::Sigmoid => output.iter().copied().sum(),
+            OutputKind::Softmax => F::ONE,
+        };
+        let probs: Vec<f32> = output.iter().map(|&p| (p / total).to_f64() as f32).collect();
+        probs.try_into().expect("a probability per cell")
+    }
+
+    /// Trains at `config.learning_rate`, then goes back to the network's own.
+    fn train_on(&mut self, x: &[Vec<f32>], y: &[usize], config: &FitOptions) -> Result<TrainReport, NnError> {
+        let own_rate = self.learning_rate;
+        self.set_learning_rate(f64::from(config.learning_rate)).map_err(|_| NnError::InvalidLearningRate)?;
+        let inputs: Vec<Vec<F>> = x.iter().map(|row| from_f32(row)).collect();
+        let options = FitConfig {
+            epochs: config.epochs,
+            batch_size: config.batch_size,
+            shuffle: config.shuffle,
+            ..FitConfig::default()
+        };
+        let history = self.fit_classes(&inputs, y, &options);
+        self.learning_rate = own_rate;
+        Ok(TrainReport { loss: history?.loss().iter().map(|&loss| loss as f32).collect() })
+    }
+}
+
+/// A board or example from the f32 encodings elsewhere in the crate.
+fn from_f32<F: Float>(values: &[f32]) -> Vec<F> {
+    values.iter().map(|&value| F::from_f64(f64::from(value))).collect()
+}
+
+/// Every snapshot of `games` with how the game ended for the player to
+/// move in it, as `value_net` inputs and targets; see
+/// `GamesData::outcome_labels` for the games left out.
+pub fn value_examples<F: Float>(games: &GamesData) -> Vec<([F; 9], F)> {
+    let set = games.outcome_labels();
+    set.states
+        .iter()
+        .zip(set.outcomes.iter())
+        .map(|(state, &outcome)| {
+            (state.map(|cell| F::from_f64(f64::from(cell))), F::from_f64((f64::from(outcome) + 1.0) / 2.0))
+        })
+        .collect()
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // The tests of each precision, in a module of their own.
+    macro_rules! precision_tests {
+        ($module:ident, $float:ty) => {
+            mod $module {
+                use super::super::*;
+
+                type F = $float;
+                type NeuralNetwork = Network<F>;
+
+                /// Whether tolerances can be those of f64.
+                const PRECISE: bool = std::mem::size_of::<F>() == 8;
+
+                #[test]
+                fn test_neural_network() {
+                    // Create a new neural network with 9 input nodes, 10 hidden nodes, and 9 output nodes
+                    let mut nn = NeuralNetwork::single_hidden(9, 10, 9, 0.1);
+                    // Define the input vector with 9 elements, all set to 0.0
+                    let input = vec![0.0; 9];
+                    // Define the target vector with 9 elements, all set to 1.0
+                    let target = vec![1.0; 9];
+                    // Train the neural network with the input and target vectors
+                    let before = nn.loss(&input, &target).unwrap();
+                    let first = nn.train(&input, &target).unwrap();
+                    // The loss returned is the one before the update
+                    assert_eq!(first, before);
+                    for _ in 0..99 {
+                        nn.train(&input, &target).unwrap();
+                    }
+                    let after = nn.loss(&input, &target).unwrap();
+                    assert!(after < first, "{} then {}", first, after);
+                    // Predict the output using the same input vector
+                    let output = nn.predict(&input).unwrap();
+                    // Check that the output vector has 9 elements
+                    assert_eq!(output.len(), 9);
+                    assert_eq!(output.len(), 9);
+
+                    // Use other methods to avoid unused warnings
+                    let _ = NeuralNetwork::sigmoid(0.5);
+                    let _ = NeuralNetwork::sigmoid_derivative(0.5);
+                    let _ = nn.forward(&input).unwrap();
+                    assert_eq!(nn.output_kind(), OutputKind::Sigmoid);
+                    assert_eq!(nn.layer_sizes(), &[9, 10, 9]);
+
+                    // Two hidden layers, with a vector and a class target
+                    let mut deep = NeuralNetwork::new(&[9, 16, 16, 9], 0.1);
+                    let board = [1.0, -1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, -1.0];
+                    for _ in 0..10 {
+                        deep.train(&board, &target).unwrap();
+                        deep.train(&board, 2).unwrap();
+                    }
+                    let activations = deep.forward(&board).unwrap();
+                    let sizes: Vec<usize> = activations.iter().map(Vec::len).collect();
+                    assert_eq!(sizes, vec![16, 16, 9]);
+                    let output = deep.predict(&board).unwrap();
+                    assert_eq!(output.len(), 9);
+                    assert!(output.iter().all(|o| o.is_finite()));
+                }
+
+                #[test]
+                fn test_fit_lowers_the_loss() {
+                    // OR of two inputs
+                    let inputs = vec![vec![0.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.0], vec![1.0, 1.0]];
+                    let targets = vec![vec![0.0], vec![1.0], vec![1.0], vec![1.0]];
+                    let mut nn = NeuralNetwork::new_with_rng(&[2, 4, 1], 0.5, &mut StdRng::seed_from_u64(1));
+                    let options = FitConfig { epochs: 20, batch_size: 4, ..FitConfig::default() };
+                    let losses = nn.fit(&inputs, &targets, &options).unwrap().loss();
+                    assert_eq!(losses.len(), 20);
+                    assert!(losses.windows(2).all(|pair| pair[1] <= pair[0]), "{:?}", losses);
+                    assert!(losses[19] < losses[0]);
+
+                    // A batch of one is the same step as `train`.
+                    let mut single = NeuralNetwork::new_with_rng(&[2, 4, 1], 0.5, &mut StdRng::seed_from_u64(2));
+                    let mut batch = NeuralNetwork::new_with_rng(&[2, 4, 1], 0.5, &mut StdRng::seed_from_u64(2));
+                    single.train(&inputs[1], &targets[1]).unwrap();
+                    let loss = batch.train_batch(&inputs[1..2], &targets[1..2]).unwrap();
+                    assert_eq!(single.predict(&inputs[1]).unwrap(), batch.predict(&inputs[1]).unwrap());
+                    assert!(loss > 0.0);
+                    assert_eq!(batch.train_batch(&[], &[]), Ok(0.0));
+
+                    let error = NnError::ExampleCountMismatch { inputs: 4, targets: 3 };
+                    assert_eq!(nn.train_batch(&inputs, &targets[..3]), Err(error));
+                    assert_eq!(nn.fit(&inputs, &targets[..3], &FitConfig::default()), Err(error));
+                    assert_eq!(error.to_string(), "3 targets for 4 inputs");
+                    // Every example is checked before the first step
+                    let mut bad = targets.clone();
+                    bad[3].push(0.0);
+                    let before = nn.clone();
+                    let error = NnError::TargetSizeMismatch { expected: 1, found: 2 };
+                    assert_eq!(nn.fit(&inputs, &bad, &FitConfig::default()), Err(error));
+                    assert_eq!(nn, before);
+                }
+
+                #[test]
+                fn test_new_seeded() {
+                    let sizes = [81, 81, 16, 9];
+                    let a = NeuralNetwork::new_seeded(&sizes, 0.1, 7);
+                    let b = NeuralNetwork::new_seeded(&sizes, 0.1, 7);
+                    assert_eq!(a, b);
+                    let bits = |nn: &NeuralNetwork| -> Vec<u64> {
+                        nn.weights.iter().flatten().flatten().map(|w| w.to_f64().to_bits()).collect()
+                    };
+                    assert_eq!(bits(&a), bits(&b));
+                    assert_ne!(a, NeuralNetwork::new_seeded(&sizes, 0.1, 8));
+
+                    // Uniform between ±1/sqrt(fan_in) has a deviation of 1/sqrt(3 fan_in)
+                    let std = |weights: &Vec<Vec<F>>| {
+                        let values: Vec<f64> = weights.iter().flatten().map(|w| w.to_f64()).collect();
+                        let mean = values.iter().sum::<f64>() / values.len() as f64;
+                        (values.iter().map(|w| (w - mean) * (w - mean)).sum::<f64>() / values.len() as f64).sqrt()
+                    };
+                    let wide = NeuralNetwork::new_seeded(&[81, 64, 9, 4], 0.1, 1);
+                    let stds: Vec<f64> = wide.weights.iter().map(std).collect();
+                    assert!(stds[0] < stds[1] && stds[1] < stds[2], "{:?}", stds);
+                    for (std, fan_in) in stds.iter().zip([81.0f64, 64.0, 9.0]) {
+                        let expected = 1.0 / (3.0 * fan_in).sqrt();
+                        assert!((std - expected).abs() < 0.25 * expected, "{} vs {}", std, expected);
+                    }
+                    assert!(wide.weights[0].iter().flatten().all(|w| w.abs() < 1.0 / 9.0));
+                    // The unscaled constructor is as before
+                    let old = NeuralNetwork::new_with_rng(&[81, 64, 9, 4], 0.1, &mut StdRng::seed_from_u64(1));
+                    assert!(std(&old.weights[0]) > 0.5);
+                }
+
+                #[test]
+                fn test_save_and_load() {
+                    let name = format!("tictac_g_class_{}_{}.json", std::any::type_name::<F>(), std::process::id());
+                    let path = std::env::temp_dir().join(name);
+                    let mut nn = NeuralNetwork::new(&[9, 16, 8, 9], 0.3).with_output(OutputKind::Softmax);
+                    let board = [1.0, -1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, -1.0];
+                    nn.train(&board, 2).unwrap();
+                    nn.save(&path).unwrap();
+                    let loaded = NeuralNetwork::load(&path).unwrap();
+                    assert_eq!(loaded, nn);
+                    assert_eq!(loaded.predict(&board).unwrap(), nn.predict(&board).unwrap());
+
+                    // Keeps training where it left off
+                    let mut resumed = loaded.clone();
+                    assert_eq!(resumed.train(&board, 2).unwrap(), nn.train(&board, 2).unwrap());
+                    assert_eq!(resumed, nn);
+
+                    // One hidden node short in the second layer's biases
+                    let mut broken = nn.clone();
+                    broken.biases[1].pop();
+                    broken.save(&path).unwrap();
+                    let error = NeuralNetwork::load(&path).unwrap_err();
+                    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+                    assert_eq!(error.to_string(), "layer 2 has 8 weight rows and 7 biases for 8 nodes");
+
+                    let mut broken = nn.clone();
+                    broken.weights[0][3].push(0.0);
+                    broken.save(&path).unwrap();
+                    let error = NeuralNetwork::load(&path).unwrap_err();
+                    assert_eq!(error.to_string(), "layer 1 has a node with 10 weights for 9 inputs");
+
+                    std::fs::write(&path, "{}").unwrap();
+                    assert_eq!(NeuralNetwork::load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+                    std::fs::remove_file(&path).unwrap();
+                    assert_eq!(NeuralNetwork::load(&path).unwrap_err().kind(), io::ErrorKind::NotFound);
+                }
+
+                #[test]
+                fn test_normalizer() {
+                    // A board, a move count on another scale and a feature that is
+                    // always 1
+                    let inputs = vec![
+                        vec![1.0, -1.0, 0.0, 7.0, 1.0],
+                        vec![0.0, 1.0, 1.0, 3.0, 1.0],
+                        vec![-1.0, 0.0, 1.0, 5.0, 1.0],
+                    ];
+                    let mut plain = NeuralNetwork::new_with_rng(&[5, 6, 2], 0.5, &mut StdRng::seed_from_u64(3));
+                    let mut nn = plain.clone();
+                    nn.fit_normalizer(&inputs);
+                    let normalizer = nn.normalizer().unwrap();
+                    assert_eq!(normalizer.mean, vec![0.0, 0.0, 2.0 / 3.0, 5.0, 1.0]);
+                    assert_eq!(normalizer.std[4], 1.0);
+                    let tolerance = if PRECISE { 1e-12 } else { 1e-6 };
+                    assert!((normalizer.std[3].to_f64() - (8.0f64 / 3.0).sqrt()).abs() < tolerance);
+
+                    let by_hand = |x: &[F]| -> Vec<F> {
+                        let (mean, std) = (&normalizer.mean, &normalizer.std);
+                        (0..5).map(|i| (x[i] - mean[i]) / std[i]).collect()
+                    };
+                    let normalized: Vec<Vec<F>> = inputs.iter().map(|x| by_hand(x)).collect();
+                    assert_eq!(normalized[0][4], 0.0);
+                    for (x, y) in inputs.iter().zip(normalized.iter()) {
+                        assert_eq!(nn.predict(x).unwrap(), plain.predict(y).unwrap());
+                    }
+                    // Training sees the normalized inputs too
+                    assert_eq!(nn.train(&inputs[0], 1).unwrap(), plain.train(&normalized[0], 1).unwrap());
+                    assert_eq!(nn.predict(&inputs[1]).unwrap(), plain.predict(&normalized[1]).unwrap());
+
+                    let path = std::env::temp_dir().join(format!("tictac_g_class_norm_{}.json", std::process::id()));
+                    nn.save(&path).unwrap();
+                    let loaded = NeuralNetwork::load(&path).unwrap();
+                    std::fs::remove_file(&path).unwrap();
+                    assert_eq!(loaded, nn);
+
+                    nn.fit_normalizer(&[]);
+                    assert_eq!(nn.normalizer(), None);
+                }
+
+                #[test]
+                fn test_fit_history_is_reproducible() {
+                    // The class is the cell that is set, among four
+                    let inputs: Vec<Vec<F>> =
+                        (0..8).map(|i| (0..4).map(|cell| (cell == i % 4) as u8 as F).collect()).collect();
+                    let labels: Vec<usize> = (0..8).map(|i| i % 4).collect();
+                    let options =
+                        FitConfig { epochs: 12, batch_size: 3, seed: 5, verbose: true, ..FitConfig::default() };
+                    let fit = || {
+                        let mut nn = NeuralNetwork::new_seeded(&[4, 6, 4], 0.5, 1).with_output(OutputKind::Softmax);
+                        let history = nn.fit_classes(&inputs, &labels, &options).unwrap();
+                        (nn, history)
+                    };
+                    let (first, history) = fit();
+                    let (second, again) = fit();
+                    assert_eq!(first, second);
+                    assert_eq!(history.loss(), again.loss());
+                    assert_eq!(history.epochs.len(), 12);
+                    assert!(history.epochs.iter().all(|epoch| epoch.accuracy.is_some() && epoch.learning_rate == 0.5));
+                    assert!(history.epochs[11].loss < history.epochs[0].loss);
+                    assert_eq!(history.epochs[11].accuracy, Some(1.0));
+                    assert_eq!(first.evaluate(&inputs, &labels).unwrap().loss, history.epochs[11].loss);
+                    assert_eq!(labels, (0..8).map(|i| i % 4).collect::<Vec<usize>>());
+
+                    // Another seed shuffles differently
+                    let mut other = NeuralNetwork::new_seeded(&[4, 6, 4], 0.5, 1).with_output(OutputKind::Softmax);
+                    let reseeded =
+                        other.fit_classes(&inputs, &labels, &FitConfig { seed: 6, ..options.clone() }).unwrap();
+                    assert_ne!(reseeded.loss(), history.loss());
+
+                    // Vector targets record no accuracy
+                    let targets: Vec<Vec<F>> = inputs.clone();
+                    let mut nn = NeuralNetwork::new_seeded(&[4, 3, 4], 0.5, 2);
+                    let history = nn.fit(&inputs, &targets, &FitConfig { epochs: 3, ..FitConfig::default() }).unwrap();
+                    assert_eq!((history.epochs.len(), history.epochs[0].accuracy), (3, None));
+                    assert_eq!(
+                        nn.fit_classes(&inputs, &[4; 8], &options),
+                        Err(NnError::ClassOutOfRange { class: 4, outputs: 4 })
+                    );
+                    let nothing = nn.fit(&inputs, &targets, &FitConfig { epochs: 0, ..options }).unwrap();
+                    assert_eq!(nothing, FitHistory::default());
+                }
+
+                #[test]
+                fn test_weight_decay() {
+                    let inputs: Vec<Vec<F>> = (0..4).map(|i| vec![(i % 2) as F, (i / 2) as F]).collect();
+                    let targets: Vec<Vec<F>> = inputs.iter().map(|x| vec![(x[0] + x[1]) / 2.0, x[0]]).collect();
+                    let norm =
+                        |nn: &NeuralNetwork| nn.weights.iter().flatten().flatten().map(|&w| w * w).sum::<F>().sqrt();
+                    let plain = NeuralNetwork::new_seeded(&[2, 6, 2], 0.5, 7);
+                    let decayed = plain.clone().with_weight_decay(0.5);
+                    assert_eq!((plain.weight_decay(), decayed.weight_decay()), (0.0, 0.5));
+
+                    // One step: the same gradient, then the weights alone shrink
+                    // by 0.25 of where they started.
+                    let (mut once, mut decayed_once) = (plain.clone(), decayed.clone());
+                    once.train_batch(&inputs, &targets).unwrap();
+                    decayed_once.train_batch(&inputs, &targets).unwrap();
+                    assert_eq!(once.biases, decayed_once.biases);
+                    let starts = plain.weights.iter().flatten().flatten();
+                    let steps = once.weights.iter().flatten().flatten();
+                    let steps = steps.zip(decayed_once.weights.iter().flatten().flatten());
+                    let tolerance = if PRECISE { 1e-12 } else { 1e-6 };
+                    for (&start, (&free, &shrunk)) in starts.zip(steps) {
+                        assert!((free - shrunk - 0.25 * start).abs().to_f64() < tolerance);
+                    }
+
+                    let options = FitConfig { epochs: 100, batch_size: 2, ..FitConfig::default() };
+                    let (mut trained, mut regularized) = (plain.clone(), decayed.clone());
+                    trained.fit(&inputs, &targets, &options).unwrap();
+                    regularized.fit(&inputs, &targets, &options).unwrap();
+                    assert!(norm(&regularized) < norm(&trained), "{} vs {}", norm(&regularized), norm(&trained));
+
+                    // Saved with the network, and 0 for files from before it
+                    let name = format!("tictac_decay_{}_{}.json", std::any::type_name::<F>(), std::process::id());
+                    let path = std::env::temp_dir().join(name);
+                    regularized.save(&path).unwrap();
+                    assert_eq!(NeuralNetwork::load(&path).unwrap().weight_decay(), 0.5);
+                    let text = std::fs::read_to_string(&path).unwrap().replace(",\"weight_decay\":0.5", "");
+                    std::fs::write(&path, text).unwrap();
+                    assert_eq!(NeuralNetwork::load(&path).unwrap().weight_decay(), 0.0);
+                    std::fs::remove_file(&path).unwrap();
+                    assert_eq!(regularized.cast::<f32>().weight_decay(), 0.5);
+                }
+
+                #[test]
+                fn test_snapshot_and_restore() {
+                    let name = format!("tictac_snapshot_{}_{}.json", std::any::type_name::<F>(), std::process::id());
+                    let path = std::env::temp_dir().join(name);
+                    let mut nn = NeuralNetwork::new_seeded(&[3, 4, 2], 0.5, 8).with_optimizer(Optimizer::momentum());
+                    let input = [1.0, 0.0, -1.0];
+                    let snapshot = nn.snapshot();
+                    let before = nn.predict(&input).unwrap();
+                    nn.train(&input, 1).unwrap();
+                    assert_ne!(nn.predict(&input).unwrap(), before);
+                    let state = nn.state.clone();
+                    nn.restore(&snapshot).unwrap();
+                    assert_eq!(nn.predict(&input).unwrap(), before);
+                    assert_eq!(nn.snapshot(), snapshot);
+                    assert_eq!(nn.state, state);
+
+                    snapshot.save(&path).unwrap();
+                    assert_eq!(WeightSnapshot::<F>::load(&path).unwrap(), snapshot);
+                    std::fs::remove_file(&path).unwrap();
+
+                    let mut other = NeuralNetwork::new_seeded(&[3, 5, 2], 0.5, 8);
+                    assert_eq!(other.restore(&snapshot), Err(NnError::SnapshotMismatch));
+                    let mut deeper = NeuralNetwork::new_seeded(&[3, 4, 4, 2], 0.5, 8);
+                    assert_eq!(deeper.restore(&snapshot), Err(NnError::SnapshotMismatch));
+                }
+
+                #[test]
+                fn test_fit_keeps_the_best_weights() {
+                    let inputs: Vec<Vec<F>> = (0..4).map(|i| vec![(i % 2) as F, (i / 2) as F]).collect();
+                    let targets: Vec<Vec<F>> = inputs.iter().map(|x| vec![x[0]]).collect();
+                    // The rate grows tenfold every 5 epochs, until the loss goes
+                    // up again.
+                    let options = FitConfig {
+                        epochs: 20,
+                        batch_size: 1,
+                        schedule: LrSchedule::Step { every: 5, factor: 10.0 },
+                        keep_best: true,
+                        ..FitConfig::default()
+                    };
+                    let start = NeuralNetwork::new_seeded(&[2, 3, 1], 0.5, 1);
+                    let mut nn = start.clone();
+                    let history = nn.fit(&inputs, &targets, &options).unwrap();
+                    let losses = history.loss();
+                    let best = history.best_epoch.unwrap();
+                    assert!(losses.iter().all(|&loss| loss >= losses[best]), "{:?}", losses);
+                    assert!(losses[19] > losses[best], "{:?}", losses);
+
+                    // The weights are those after the best epoch
+                    let mut replay = start.clone();
+                    let epochs = best + 1;
+                    replay.fit(&inputs, &targets, &FitConfig { epochs, keep_best: false, ..options.clone() }).unwrap();
+                    assert_eq!(nn.snapshot(), replay.snapshot());
+                    let loss = inputs.iter().zip(targets.iter()).map(|(x, t)| nn.loss(x, t).unwrap()).sum::<F>() / 4.0;
+                    assert_eq!(loss.to_f64(), losses[best]);
+
+                    // Without the flag they are the last epoch's
+                    let mut last = start.clone();
+                    let history =
+                        last.fit(&inputs, &targets, &FitConfig { keep_best: false, ..options.clone() }).unwrap();
+                    assert_eq!(history.best_epoch, None);
+                    assert_eq!(history.loss(), losses);
+                    assert_ne!(last.snapshot(), nn.snapshot());
+                }
+
+                #[test]
+                fn test_fit_stops_early() {
+                    // The output should follow the first input, but the two training
+                    // examples have it the other way round.
+                    let validation_inputs: Vec<Vec<F>> = (0..8).map(|i| vec![(i % 2) as F, (i / 2 % 2) as F]).collect();
+                    let validation_targets: Vec<Vec<F>> = validation_inputs.iter().map(|x| vec![x[0]]).collect();
+                    let inputs = vec![vec![0.0, 1.0], vec![1.0, 0.0]];
+                    let targets = vec![vec![1.0], vec![0.0]];
+                    let options = FitConfig { epochs: 200, batch_size: 2, patience: Some(5), ..FitConfig::default() };
+
+                    let mut nn = NeuralNetwork::new_seeded(&[2, 4, 1], 0.5, 3);
+                    let history =
+                        nn.fit_validated(&inputs, &targets, &validation_inputs, &validation_targets, &options).unwrap();
+                    let (best, stopped) = (history.best_epoch.unwrap(), history.stopped_epoch.unwrap());
+                    assert_eq!(stopped, best + 5);
+                    assert!(stopped < 199);
+                    assert_eq!(history.epochs.len(), stopped + 1);
+                    assert!(history.epochs[stopped].validation_loss > history.epochs[best].validation_loss);
+                    // The best epoch's weights are back
+                    let restored = nn.fit_validated(
+                        &inputs,
+                        &targets,
+                        &validation_inputs,
+                        &validation_targets,
+                        &FitConfig { epochs: 0, ..options.clone() },
+                    );
+                    assert_eq!(restored.unwrap(), FitHistory::default());
+                    let loss: F = validation_inputs
+                        .iter()
+                        .zip(validation_targets.iter())
+                        .map(|(x, t)| nn.loss(x, t).unwrap())
+                        .sum::<F>()
+                        / 8.0;
+                    assert_eq!(Some(loss.to_f64()), history.epochs[best].validation_loss);
+
+                    // Without patience every epoch runs and the weights stay the last
+                    let mut nn = NeuralNetwork::new_seeded(&[2, 4, 1], 0.5, 3);
+                    let options = FitConfig { epochs: 30, patience: None, ..options };
+                    let history =
+                        nn.fit_validated(&inputs, &targets, &validation_inputs, &validation_targets, &options).unwrap();
+                    assert_eq!((history.epochs.len(), history.stopped_epoch), (30, None));
+                    assert!(history.epochs.iter().all(|epoch| epoch.validation_loss.is_some()));
+                    assert!(history.best_epoch.is_some());
+                    // Keeping the best instead puts that epoch's weights back
+                    let mut kept = NeuralNetwork::new_seeded(&[2, 4, 1], 0.5, 3);
+                    let options = FitConfig { keep_best: true, ..options };
+                    let kept_history = kept
+                        .fit_validated(&inputs, &targets, &validation_inputs, &validation_targets, &options)
+                        .unwrap();
+                    assert_eq!(kept_history.loss(), history.loss());
+                    let mut replay = NeuralNetwork::new_seeded(&[2, 4, 1], 0.5, 3);
+                    let epochs = history.best_epoch.unwrap() + 1;
+                    replay.fit(&inputs, &targets, &FitConfig { epochs, keep_best: false, ..options.clone() }).unwrap();
+                    assert_eq!(kept.snapshot(), replay.snapshot());
+                    let error = NnError::ExampleCountMismatch { inputs: 8, targets: 7 };
+                    let validated =
+                        nn.fit_validated(&inputs, &targets, &validation_inputs, &validation_targets[1..], &options);
+                    assert_eq!(validated, Err(error));
+                }
+
+                #[test]
+                fn test_learning_rate() {
+                    fn rates(history: &FitHistory) -> Vec<f64> {
+                        history.epochs.iter().map(|epoch| epoch.learning_rate).collect()
+                    }
+                    let inputs: Vec<Vec<F>> = (0..4).map(|i| vec![(i % 2) as F, (i / 2) as F]).collect();
+                    let targets: Vec<Vec<F>> = inputs.iter().map(|x| vec![x[0]]).collect();
+                    let mut nn = NeuralNetwork::new_seeded(&[2, 3, 1], 0.5, 1).with_optimizer(Optimizer::adam());
+                    assert_eq!(nn.set_learning_rate(-0.1), Err(InvalidLearningRate(-0.1)));
+                    assert!(nn.set_learning_rate(f64::NAN).is_err());
+                    assert_eq!(nn.learning_rate(), 0.5);
+
+                    // A rate of 0 keeps the weights
+                    nn.set_learning_rate(0.0).unwrap();
+                    let before = nn.clone();
+                    nn.fit(&inputs, &targets, &FitConfig { epochs: 3, batch_size: 2, ..FitConfig::default() }).unwrap();
+                    assert_eq!((nn.weights.clone(), nn.biases.clone()), (before.weights, before.biases));
+
+                    nn.set_learning_rate(0.4).unwrap();
+                    let schedule = LrSchedule::Step { every: 2, factor: 0.5 };
+                    let options = FitConfig { epochs: 5, schedule, ..FitConfig::default() };
+                    let history = nn.fit(&inputs, &targets, &options).unwrap();
+                    assert_eq!(rates(&history), vec![0.4, 0.4, 0.2, 0.2, 0.1]);
+                    assert_eq!(nn.learning_rate(), 0.4);
+                    let schedule = LrSchedule::Exponential { factor: 0.5 };
+                    let options = FitConfig { epochs: 3, schedule, ..FitConfig::default() };
+                    assert_eq!(rates(&nn.fit(&inputs, &targets, &options).unwrap()), vec![0.4, 0.2, 0.1]);
+                    let history = nn.fit(&inputs, &targets, &FitConfig { epochs: 2, ..FitConfig::default() }).unwrap();
+                    assert_eq!(rates(&history), vec![0.4, 0.4]);
+                }
+
+                #[test]
+                fn test_fit_from_games() {
+                    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/games.csv");
+                    let mut games = GamesData::new(path.to_string_lossy().into_owned());
+                    games.read_data().unwrap();
+                    assert_eq!(games.len(), 12);
+                    let options = FitConfig { epochs: 40, batch_size: 8, seed: 1, ..FitConfig::default() };
+
+                    let mut nn = NeuralNetwork::new_seeded(&[9, 32, 9], 0.5, 2);
+                    let history = nn.fit_from_games(&games, &options).unwrap();
+                    assert_eq!(nn.output_kind(), OutputKind::Softmax);
+                    let accuracy = history.epochs[39].accuracy.unwrap();
+                    assert!(accuracy > 1.0 / 9.0, "{}", accuracy);
+                    let set = games.training_set();
+                    let inputs: Vec<Vec<F>> = set.x.iter().map(|row| from_f32(row)).collect();
+                    assert_eq!(nn.evaluate(&inputs, &set.y).unwrap().accuracy, accuracy);
+
+                    let mut nn = NeuralNetwork::new_seeded(&[9, 32, 9], 0.5, 2);
+                    let augment = FitConfig { epochs: 1, augment: true, ..options.clone() };
+                    let augmented = nn.fit_from_games(&games, &augment).unwrap();
+                    assert!(augmented.epochs[0].accuracy > Some(0.0));
+
+                    let empty = GamesData::new(String::from("no/such/games.csv"));
+                    assert_eq!(nn.fit_from_games(&empty, &options), Err(NnError::NoExamples));
+                    let mut wide = NeuralNetwork::new_seeded(&[10, 4, 9], 0.5, 2);
+                    assert_eq!(
+                        wide.fit_from_games(&games, &options),
+                        Err(NnError::InputSizeMismatch { expected: 10, found: 9 })
+                    );
+                    let mut value = NeuralNetwork::value_net(4, 0.5);
+                    assert_eq!(
+                        value.fit_from_games(&games, &options),
+                        Err(NnError::TargetSizeMismatch { expected: 1, found: 9 })
+                    );
+                }
+
+                #[test]
+                fn test_adam_converges_faster() {
+                    // XOR of two inputs
+                    let inputs = vec![vec![0.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.0], vec![1.0, 1.0]];
+                    let targets = vec![vec![0.0], vec![1.0], vec![1.0], vec![0.0]];
+                    let epochs_to = |optimizer: Optimizer| {
+                        let mut nn = NeuralNetwork::new_with_rng(&[2, 4, 1], 0.1, &mut StdRng::seed_from_u64(6))
+                            .with_optimizer(optimizer);
+                        (1..=5000)
+                            .find(|_| nn.train_batch(&inputs, &targets).unwrap() < 0.05)
+                            .unwrap_or(usize::MAX)
+                    };
+                    let sgd = epochs_to(Optimizer::Sgd);
+                    let momentum = epochs_to(Optimizer::momentum());
+                    let adam = epochs_to(Optimizer::adam());
+                    assert!(adam < 5000);
+                    assert!(adam < sgd, "Adam took {} epochs, SGD {}", adam, sgd);
+                    assert!(momentum < sgd, "momentum took {} epochs, SGD {}", momentum, sgd);
+
+                    // Switching optimizers starts their state afresh
+                    let mut nn = NeuralNetwork::new(&[2, 4, 1], 0.1).with_optimizer(Optimizer::adam());
+                    let fresh = nn.state.clone();
+                    assert_eq!(fresh.first.values().count(), 2 * 4 + 4 + 4 + 1);
+                    nn.train_batch(&inputs, &targets).unwrap();
+                    assert_eq!(nn.state.steps, 1);
+                    nn.set_optimizer(Optimizer::adam());
+                    assert_eq!(nn.state, fresh);
+                    nn.set_optimizer(Optimizer::Sgd);
+                    assert_eq!(nn.state, OptimizerState::default());
+
+                    // The optimizer is saved, its state isn't
+                    let mut nn = nn.with_optimizer(Optimizer::momentum());
+                    nn.train_batch(&inputs, &targets).unwrap();
+                    let path = std::env::temp_dir().join(format!("tictac_g_class_opt_{}.json", std::process::id()));
+                    nn.save(&path).unwrap();
+                    let loaded = NeuralNetwork::load(&path).unwrap();
+                    std::fs::remove_file(&path).unwrap();
+                    assert_eq!(loaded.optimizer(), Optimizer::momentum());
+                    assert_eq!(loaded.state, OptimizerState::new(&loaded));
+                    assert_ne!(loaded.state, nn.state);
+                }
+
+                #[test]
+                fn test_value_net_scores_won_above_lost() {
+                    let path = std::env::temp_dir().join(format!("tictac_g_class_value_{}.csv", std::process::id()));
+                    // ai opens and wins on the diagonal, then a draw cut short
+                    let fixture = "1,0,0,0,0,0,0,0,0,\
+                                   \n1,-1,0,0,0,0,0,0,0,\
+                                   \n1,-1,0,0,1,0,0,0,0,\
+                                   \n1,-1,-1,0,1,0,0,0,0,\
+                                   \n1,-1,-1,0,1,0,0,0,1,ai\
+                                   \n0,0,0,0,1,0,0,0,0,\
+                                   \n-1,0,0,0,1,0,0,0,0,draw";
+                    std::fs::write(&path, fixture).unwrap();
+                    let mut data = GamesData::new(path.to_string_lossy().into_owned());
+                    data.read_data().unwrap();
+                    std::fs::remove_file(&path).unwrap();
+                    let examples = value_examples(&data);
+                    let outcomes: Vec<F> = examples.iter().map(|&(_, outcome)| outcome).collect();
+                    assert_eq!(outcomes, vec![0.0, 1.0, 0.0, 1.0, 0.0, 0.5, 0.5]);
+                    // ai_2 is to move after ai's winning move, and sees it as -1
+                    assert_eq!(examples[4].0, [-1.0, 1.0, 1.0, 0.0, -1.0, 0.0, 0.0, 0.0, -1.0]);
+
+                    // Finished games, from the side of the player to move: a line of
+                    // their own is a win, one of the opponent's a loss.
+                    let won = [
+                        [1.0, 1.0, 1.0, -1.0, -1.0, 0.0, 0.0, 0.0, 0.0],
+                        [1.0, -1.0, 0.0, 1.0, -1.0, 0.0, 1.0, 0.0, 0.0],
+                        [1.0, -1.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+                    ];
+                    let lost = won.map(|board| board.map(|cell| -cell));
+                    let mut nn = NeuralNetwork::new_with_rng(&[9, 6, 1], 0.5, &mut StdRng::seed_from_u64(8));
+                    assert_eq!(nn.layer_sizes(), NeuralNetwork::value_net(6, 0.5).layer_sizes());
+                    for _ in 0..300 {
+                        for (won, lost) in won.iter().zip(lost.iter()) {
+                            nn.train_value(won, 1.0).unwrap();
+                            nn.train_value(lost, 0.0).unwrap();
+                        }
+                    }
+                    let score = |board: &[F; 9]| nn.evaluate_board(board).unwrap().to_f64();
+                    let lowest_win = won.iter().map(score).fold(f64::INFINITY, f64::min);
+                    let highest_loss = lost.iter().map(score).fold(0.0, f64::max);
+                    assert!(lowest_win > highest_loss, "{} vs {}", lowest_win, highest_loss);
+                    assert!((0.0..=1.0).contains(&lowest_win));
+                }
+
+                #[test]
+                fn test_gradient_matches_finite_differences() {
+                    let input = [0.7, -0.4];
+                    let target = [0.9];
+                    // The gradient is of half the squared error
+                    let half_error = |nn: &NeuralNetwork| {
+                        let output = nn.predict(&input).unwrap()[0];
+                        (target[0] - output) * (target[0] - output) / 2.0
+                    };
+                    for activation in [Activation::Sigmoid, Activation::Tanh, Activation::ReLU] {
+                        let nn = NeuralNetwork::new_with_rng(&[2, 2, 1], 0.1, &mut StdRng::seed_from_u64(9))
+                            .with_activation(activation);
+                        // Both hidden nodes active, so ReLU is differentiable here
+                        assert!(nn.forward(&input).unwrap()[0].iter().all(|&h| h > 0.0), "{:?}", activation);
+                        let (gradient, _) = nn.gradient(&input, Target::Vector(&target));
+                        let (eps, tolerance) = if PRECISE { (1e-6, 1e-7) } else { (1e-3, 1e-4) };
+                        // In the order of `Gradient::values`
+                        fn params(nn: &mut NeuralNetwork) -> Vec<&mut F> {
+                            nn.weights.iter_mut().flatten().flatten().chain(nn.biases.iter_mut().flatten()).collect()
+                        }
+                        for (index, &g) in gradient.values().enumerate() {
+                            let mut up = nn.clone();
+                            let mut down = nn.clone();
+                            *params(&mut up).swap_remove(index) += eps;
+                            *params(&mut down).swap_remove(index) -= eps;
+                            let numeric = (half_error(&up) - half_error(&down)) / (2.0 * eps);
+                            // The gradient points downhill
+                            assert!(
+                                (numeric + g).abs() < tolerance,
+                                "{:?} parameter {}: {} vs {}",
+                                activation,
+                                index,
+                                numeric,
+                                -g
+                            );
+                        }
+                    }
+                    assert_eq!(NeuralNetwork::value_net(3, 0.1).activation(), Activation::Sigmoid);
+                }
+
+                #[test]
+                fn test_check_gradients() {
+                    let input = [0.3, -0.8, 0.5];
+                    for activation in [Activation::Sigmoid, Activation::Tanh] {
+                        for (seed, kind) in [OutputKind::Sigmoid, OutputKind::Softmax].into_iter().enumerate() {
+                            let nn = NeuralNetwork::new_seeded(&[3, 4, 2], 0.1, seed as u64)
+                                .with_activation(activation)
+                                .with_output(kind);
+                            let worst = nn.check_gradients(&input, &[0.3, 0.7][..], 1e-5).unwrap();
+                            assert!(worst < 1e-4, "{:?} {:?}: {}", activation, kind, worst);
+                            assert!(nn.check_gradients(&input, 1, 1e-5).unwrap() < 1e-4, "{:?} {:?}", activation, kind);
+                        }
+                    }
+
+                    // Through a normalizer too, which the gradient must see past.
+                    let mut nn = NeuralNetwork::new_seeded(&[3, 4, 2], 0.1, 5);
+                    nn.fit_normalizer(&[input.to_vec(), vec![1.0, 1.0, 1.0]]);
+                    assert!(nn.check_gradients(&input, 0, 1e-5).unwrap() < 1e-4);
+                    assert_eq!(
+                        nn.check_gradients(&input, 2, 1e-5),
+                        Err(NnError::ClassOutOfRange { class: 2, outputs: 2 })
+                    );
+                }
+
+                #[test]
+                fn test_evaluate() {
+                    let inputs =
+                        vec![vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0], vec![0.0, 0.0, 1.0], vec![0.0, 1.0, 0.0]];
+                    let labels = vec![0, 1, 2, 1];
+                    let mut nn = NeuralNetwork::new_with_rng(&[3, 5, 3], 0.5, &mut StdRng::seed_from_u64(2))
+                        .with_output(OutputKind::Softmax);
+                    let before = nn.evaluate(&inputs, &labels).unwrap();
+                    assert_eq!(before.examples, 4);
+                    for _ in 0..200 {
+                        for (input, &label) in inputs.iter().zip(labels.iter()) {
+                            nn.train(input, label).unwrap();
+                        }
+                    }
+                    let after = nn.evaluate(&inputs, &labels).unwrap();
+                    assert_eq!(after.accuracy, 1.0);
+                    assert!(after.loss < before.loss);
+                    assert_eq!(nn.predict_class(&inputs[2]).unwrap(), 2);
+                    // Relabelled, the same predictions are all or half wrong
+                    let wrong = nn.evaluate(&inputs[..2], &[1, 0]).unwrap();
+                    assert_eq!(wrong.accuracy, 0.0);
+                    let half = nn.evaluate(&inputs[..2], &[0, 0]).unwrap();
+                    assert_eq!(half.accuracy, 0.5);
+                    assert_eq!(nn.evaluate(&[], &[]), Ok(EvalResult::default()));
+
+                    assert_eq!(nn.evaluate(&inputs, &labels[..3]), Err(EvalError::LabelCount { inputs: 4, labels: 3 }));
+                    let mut short = inputs.clone();
+                    short[1].pop();
+                    let error = nn.evaluate(&short, &labels).unwrap_err();
+                    assert_eq!(error, EvalError::FeatureCount { example: 1, features: 2, expected: 3 });
+                    assert_eq!(error.to_string(), "input 1 has 2 features, the network takes 3");
+                    let mut long = inputs.clone();
+                    long[3].push(1.0);
+                    let error = nn.evaluate(&long, &labels);
+                    assert!(matches!(error, Err(EvalError::FeatureCount { example: 3, features: 4, .. })));
+                }
+
+                #[test]
+                fn test_predict_topk() {
+                    let mut nn = NeuralNetwork::new_seeded(&[2, 3, 4], 0.1, 4);
+                    // Every output the same: ties go to the lower index.
+                    nn.weights.iter_mut().flatten().flatten().for_each(|w| *w = 0.0);
+                    nn.biases.iter_mut().flatten().for_each(|b| *b = 0.0);
+                    let ranked = nn.predict_topk(&[1.0, -1.0], 3).unwrap();
+                    assert_eq!(ranked, vec![(0, 0.5), (1, 0.5), (2, 0.5)]);
+                    assert_eq!(nn.predict_class(&[1.0, -1.0]).unwrap(), 0);
+
+                    // Output 2 highest, then 0, then 1 and 3 tied.
+                    nn.biases[1] = vec![1.0, -1.0, 2.0, -1.0];
+                    let input = [0.5, 0.5];
+                    let order: Vec<usize> =
+                        nn.predict_topk(&input, 4).unwrap().iter().map(|&(index, _)| index).collect();
+                    assert_eq!(order, vec![2, 0, 1, 3]);
+                    assert_eq!(nn.predict_class(&input).unwrap(), 2);
+                    let output = nn.predict(&input).unwrap();
+                    assert_eq!(nn.predict_topk(&input, 1).unwrap(), vec![(2, output[2])]);
+                    assert_eq!(nn.predict_topk(&input, 10).unwrap().len(), 4);
+                    assert_eq!(nn.predict_topk(&input, 0).unwrap(), vec![]);
+
+                    let legal: Vec<usize> = nn
+                        .predict_topk_excluding(&input, 10, &[2, 1])
+                        .unwrap()
+                        .iter()
+                        .map(|&(index, _)| index)
+                        .collect();
+                    assert_eq!(legal, vec![0, 3]);
+                    assert_eq!(nn.predict_topk_excluding(&input, 2, &[0, 1, 2, 3]).unwrap(), vec![]);
+                    assert_eq!(nn.predict_topk(&[1.0], 2), Err(NnError::InputSizeMismatch { expected: 2, found: 1 }));
+                }
+
+                #[test]
+                fn test_input_size_mismatch() {
+                    let mut nn = NeuralNetwork::new(&[3, 2, 1], 0.1);
+                    let error = NnError::InputSizeMismatch { expected: 3, found: 2 };
+                    assert_eq!(nn.predict(&[1.0, 0.0]), Err(error));
+                    assert_eq!(nn.predict_class(&[1.0, 0.0]), Err(error));
+                    assert_eq!(nn.forward(&[1.0, 0.0]), Err(error));
+                    assert_eq!(nn.loss(&[1.0, 0.0], 0), Err(error));
+                    let before = nn.clone();
+                    assert_eq!(nn.train(&[1.0, 0.0], &[1.0][..]), Err(error));
+                    let longer = NnError::InputSizeMismatch { expected: 3, found: 4 };
+                    assert_eq!(nn.train(&[1.0, 0.0, 0.0, 1.0], &[1.0][..]), Err(longer));
+                    assert_eq!(nn, before);
+                    assert_eq!(error.to_string(), "input of 2 values for a network of 3 inputs");
+                    // A value net takes boards of nine cells
+                    assert_eq!(nn.evaluate_board(&[0.0; 9]), Err(NnError::InputSizeMismatch { expected: 3, found: 9 }));
+                }
+
+                #[test]
+                fn test_target_size_mismatch() {
+                    let mut nn = NeuralNetwork::new(&[3, 2, 2], 0.1);
+                    let before = nn.clone();
+                    let input = [1.0, 0.0, -1.0];
+                    let error = NnError::TargetSizeMismatch { expected: 2, found: 3 };
+                    let short = NnError::TargetSizeMismatch { expected: 2, found: 1 };
+                    assert_eq!(nn.train(&input, &[1.0, 0.0, 0.0][..]), Err(error));
+                    assert_eq!(nn.loss(&input, &[1.0][..]), Err(short));
+                    assert_eq!(nn.train(&input, 2), Err(NnError::ClassOutOfRange { class: 2, outputs: 2 }));
+                    assert_eq!(nn.train_batch(&[input.to_vec()], &[vec![1.0]]), Err(short));
+                    assert_eq!(nn, before);
+                    assert_eq!(error.to_string(), "target of 3 values for a network of 2 outputs");
+                    assert!(nn.train(&input, 1).is_ok());
+                }
+
+                /// Steps over the examples until every one is predicted right, or
+                /// `max_epochs`.
+                fn epochs_to_fit(nn: &mut NeuralNetwork, examples: &[(Vec<F>, usize)], max_epochs: usize) -> usize {
+                    for epoch in 0..max_epochs {
+                        if examples.iter().all(|(input, class)| nn.predict_class(input).unwrap() == *class) {
+                            return epoch;
+                        }
+                        for (input, class) in examples {
+                            nn.train(input, *class).unwrap();
+                        }
+                    }
+                    max_epochs
+                }
+
+                #[test]
+                fn test_softmax_learns_classes_faster() {
+                    // The class is the cell that is set, among nine.
+                    let examples: Vec<(Vec<F>, usize)> = (0..9)
+                        .map(|cell| ((0..9).map(|i| if i == cell { 1.0 } else { 0.0 }).collect(), cell))
+                        .collect();
+                    let mut softmax = NeuralNetwork::new_with_rng(&[9, 10, 9], 0.5, &mut StdRng::seed_from_u64(4))
+                        .with_output(OutputKind::Softmax);
+                    let mut sigmoid = NeuralNetwork::new_with_rng(&[9, 10, 9], 0.5, &mut StdRng::seed_from_u64(4));
+
+                    let output = softmax.predict(&examples[0].0).unwrap();
+                    assert!((output.iter().sum::<F>() - 1.0).abs() < if PRECISE { 1e-9 } else { 1e-6 });
+                    let softmax_epochs = epochs_to_fit(&mut softmax, &examples, 2000);
+                    let sigmoid_epochs = epochs_to_fit(&mut sigmoid, &examples, 2000);
+                    assert!(softmax_epochs < 2000);
+                    assert!(softmax_epochs < sigmoid_epochs, "{} vs {} epochs", softmax_epochs, sigmoid_epochs);
+
+                    // A vector target is the same step as its class.
+                    let mut by_class = NeuralNetwork::new_with_rng(&[9, 4, 3], 0.5, &mut StdRng::seed_from_u64(9))
+                        .with_output(OutputKind::Softmax);
+                    let mut by_vector = NeuralNetwork::new_with_rng(&[9, 4, 3], 0.5, &mut StdRng::seed_from_u64(9))
+                        .with_output(OutputKind::Softmax);
+                    by_class.train(&examples[1].0, 2).unwrap();
+                    by_vector.train(&examples[1].0, &[0.0, 0.0, 1.0][..]).unwrap();
+                    assert_eq!(by_class.predict(&examples[1].0).unwrap(), by_vector.predict(&examples[1].0).unwrap());
+                }
+            }
+        };
+    }
+
+    precision_tests!(double, f64);
+    precision_tests!(single, f32);
+
     #[test]
-    fn test_neural_network() {
-        // Create a new neural network with 9 input nodes, 10 hidden nodes, and 9 output nodes
-        let mut nn = NeuralNetwork::new(9, 10, 9, 0.1);
-        // Define the input vector with 9 elements, all set to 0.0
-        let input = vec![0.0; 9];
-        // Define the target vector with 9 elements, all set to 1.0
-        let target = vec![1.0; 9];
-        // Train the neural network with the input and target vectors
-        nn.train(&input, &target);
-        // Predict the output using the same input vector
-        let output = nn.predict(&input);
-        // Check that the output vector has 9 elements
-        assert_eq!(output.len(), 9);
-        assert_eq!(output.len(), 9);
-
-        // Use other methods to avoid unused warnings
-        let _ = NeuralNetwork::sigmoid(0.5);
-        let _ = NeuralNetwork::sigmoid_derivative(0.5);
-        let _ = nn.forward(&input);
-    }
-}
\ No newline at end of file
+    fn test_cast() {
+        let inputs: Vec<Vec<f64>> = (0..4).map(|i| vec![(i % 2) as f64, (i / 2) as f64]).collect();
+        let targets: Vec<Vec<f64>> = inputs.iter().map(|x| vec![x[0]]).collect();
+        let mut nn = NeuralNetwork::new_seeded(&[2, 4, 1], 0.5, 3);
+        nn.fit_normalizer(&inputs);
+        nn.fit(&inputs, &targets, &FitConfig { epochs: 20, ..FitConfig::default() }).unwrap();
+
+        let single: NeuralNetwork32 = nn.cast();
+        assert_eq!(single.layer_sizes(), nn.layer_sizes());
+        assert_eq!(single.learning_rate(), nn.learning_rate());
+        for x in &inputs {
+            let x32: Vec<f32> = x.iter().map(|&v| v as f32).collect();
+            let (wide, narrow) = (nn.predict(x).unwrap(), single.predict(&x32).unwrap());
+            assert!((wide[0] - narrow[0] as f64).abs() < 1e-6, "{} vs {}", wide[0], narrow[0]);
+        }
+        // f32 holds every value it was given, so the way back loses nothing.
+        assert_eq!(single.cast::<f64>().cast::<f32>(), single);
+    }
+}
@@ -0,0 +1,257 @@
+// Compact binary dataset format, much faster to load than CSV:
+//
+//     magic "TTGD", version byte, game count (u32)
+//     per game: player1, player2, winner, id (empty when unknown),
+//               timestamp flag byte (+ u64 when 1),
+//               state count (u32), states (u16 each)
+//
+// Strings are a u16 byte length then UTF-8, integers little-endian. A
+// state packs its nine cells as base-3 digits, cell 0 lowest, each digit
+// the cell value plus one.
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::input::GameData;
+
+pub const MAGIC: &[u8; 4] = b"TTGD";
+pub const BINARY_VERSION: u8 = 1;
+
+/// 3^9, one more than the largest packed state.
+const STATE_CODES: u16 = 19683;
+
+/// Why a binary dataset couldn't be read.
+#[derive(Debug)]
+pub enum BinaryError {
+    Io(io::Error),
+    /// The file doesn't start with `MAGIC`.
+    NotADataset,
+    UnsupportedVersion(u8),
+    /// The data ends in the middle of game `game`.
+    Truncated { game: usize },
+    /// Game `game` holds a string that isn't UTF-8 or a state that isn't a
+    /// board.
+    Corrupt { game: usize },
+    /// Bytes left over after the last game.
+    TrailingData,
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BinaryError::Io(e) => write!(f, "Could not read the dataset: {}", e),
+            BinaryError::NotADataset => write!(f, "Not a binary game dataset"),
+            BinaryError::UnsupportedVersion(version) => write!(
+                f,
+                "Binary dataset version {} is not supported, this build reads {}",
+                version, BINARY_VERSION
+            ),
+            BinaryError::Truncated { game } => write!(f, "The dataset ends in game {}", game),
+            BinaryError::Corrupt { game } => write!(f, "Game {} is corrupt", game),
+            BinaryError::TrailingData => write!(f, "Unexpected data after the last game"),
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {}
+
+fn put_str(out: &mut Vec<u8>, text: &str) -> io::Result<()> {
+    let len = u16::try_from(text.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{} bytes is too long a name", text.len())))?;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(text.as_bytes());
+    Ok(())
+}
+
+fn pack(state: &[i8; 9]) -> u16 {
+    state.iter().rev().fold(0, |code, &value| code * 3 + (value + 1) as u16)
+}
+
+fn unpack(mut code: u16) -> Option<[i8; 9]> {
+    if code >= STATE_CODES {
+        return None;
+    }
+    let mut state = [0; 9];
+    for value in state.iter_mut() {
+        *value = (code % 3) as i8 - 1;
+        code /= 3;
+    }
+    Some(state)
+}
+
+/// The whole dataset as bytes.
+pub fn encode(games: &[GameData]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(16 + games.len() * 32);
+    out.extend_from_slice(MAGIC);
+    out.push(BINARY_VERSION);
+    out.extend_from_slice(&(games.len() as u32).to_le_bytes());
+    for game in games {
+        put_str(&mut out, &game.player1)?;
+        put_str(&mut out, &game.player2)?;
+        put_str(&mut out, &game.winner)?;
+        put_str(&mut out, game.id.as_deref().unwrap_or(""))?;
+        match game.timestamp {
+            Some(timestamp) => {
+                out.push(1);
+                out.extend_from_slice(&timestamp.to_le_bytes());
+            }
+            None => out.push(0),
+        }
+        out.extend_from_slice(&(game.state_of_cells_list.len() as u32).to_le_bytes());
+        for state in &game.state_of_cells_list {
+            out.extend_from_slice(&pack(state).to_le_bytes());
+        }
+    }
+    Ok(out)
+}
+
+/// Reads `encode`'s output, tracking the game being read for errors.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    game: usize,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], BinaryError> {
+        if self.bytes.len() < n {
+            return Err(BinaryError::Truncated { game: self.game });
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, BinaryError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().expect("two bytes")))
+    }
+
+    fn u32(&mut self) -> Result<u32, BinaryError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("four bytes")))
+    }
+
+    fn string(&mut self) -> Result<String, BinaryError> {
+        let len = self.u16()? as usize;
+        let game = self.game;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| BinaryError::Corrupt { game })
+    }
+
+    fn game(&mut self) -> Result<GameData, BinaryError> {
+        let mut game = GameData::new(self.string()?, self.string()?);
+        game.winner = self.string()?;
+        game.id = Some(self.string()?).filter(|id| !id.is_empty());
+        game.timestamp = match self.take(1)?[0] {
+            0 => None,
+            1 => Some(u64::from_le_bytes(self.take(8)?.try_into().expect("eight bytes"))),
+            _ => return Err(BinaryError::Corrupt { game: self.game }),
+        };
+        let count = self.u32()? as usize;
+        let game_index = self.game;
+        game.state_of_cells_list = self
+            .take(count * 2)?
+            .chunks_exact(2)
+            .map(|code| unpack(u16::from_le_bytes([code[0], code[1]])))
+            .collect::<Option<Vec<[i8; 9]>>>()
+            .ok_or(BinaryError::Corrupt { game: game_index })?;
+        game.periodic_state_of_cells = game.state_of_cells_list.last().copied().unwrap_or([0; 9]);
+        Ok(game)
+    }
+}
+
+/// The games of an `encode`d dataset.
+pub fn decode(bytes: &[u8]) -> Result<Vec<GameData>, BinaryError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(BinaryError::NotADataset);
+    }
+    let mut decoder = Decoder {
+        bytes: &bytes[MAGIC.len()..],
+        game: 0,
+    };
+    let version = decoder.take(1)?[0];
+    if version != BINARY_VERSION {
+        return Err(BinaryError::UnsupportedVersion(version));
+    }
+    let count = decoder.u32()? as usize;
+    // Every game takes at least 15 bytes, so a bad count can't reserve much.
+    let mut games = Vec::with_capacity(count.min(decoder.bytes.len() / 15));
+    for game in 0..count {
+        decoder.game = game;
+        games.push(decoder.game()?);
+    }
+    if !decoder.bytes.is_empty() {
+        return Err(BinaryError::TrailingData);
+    }
+    Ok(games)
+}
+
+pub fn write_games(path: &Path, games: &[GameData]) -> io::Result<()> {
+    std::fs::write(path, encode(games)?)
+}
+
+pub fn read_games(path: &Path) -> Result<Vec<GameData>, BinaryError> {
+    decode(&std::fs::read(path).map_err(BinaryError::Io)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::GamesData;
+
+    #[test]
+    fn test_pack_round_trips() {
+        for code in [0, 1, 4, 9841, STATE_CODES - 1] {
+            assert_eq!(pack(&unpack(code).unwrap()), code);
+        }
+        assert_eq!(pack(&[-1; 9]), 0);
+        assert_eq!(pack(&[0, 0, 0, 0, 0, 0, 0, 0, 0]), 9841);
+        assert_eq!(unpack(STATE_CODES), None);
+    }
+
+    #[test]
+    fn test_binary_matches_the_csv() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/games.csv");
+        let mut csv = GamesData::new(fixture.to_string_lossy().into_owned());
+        csv.read_data().unwrap();
+        assert_eq!(csv.len(), 12);
+        let mut named = GameData::new("alice".to_string(), "bob".to_string());
+        named.id = Some("00000000000000a1".to_string());
+        named.timestamp = Some(1_700_000_000);
+        named.winner = "alice".to_string();
+        named.state_of_cells_list = vec![[0, 0, 0, 0, 1, 0, 0, 0, 0]];
+        named.periodic_state_of_cells = named.state_of_cells_list[0];
+        csv.add_game(named);
+
+        let path = std::env::temp_dir().join(format!("tictac_binary_{}.bin", std::process::id()));
+        csv.save_binary(&path).unwrap();
+        let loaded = GamesData::load_binary(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.game_data, csv.game_data);
+    }
+
+    #[test]
+    fn test_binary_rejects_other_data() {
+        let mut game = GameData::new("ai".to_string(), "ai_2".to_string());
+        game.state_of_cells_list = vec![[1, 0, 0, 0, 0, 0, 0, 0, 0]];
+        let bytes = encode(&[game.clone(), game]).unwrap();
+        assert_eq!(decode(&bytes).unwrap().len(), 2);
+
+        assert!(matches!(decode(b"\nai,ai_2"), Err(BinaryError::NotADataset)));
+        let mut newer = bytes.clone();
+        newer[4] = BINARY_VERSION + 1;
+        assert!(matches!(decode(&newer), Err(BinaryError::UnsupportedVersion(2))));
+        assert_eq!(
+            BinaryError::UnsupportedVersion(2).to_string(),
+            "Binary dataset version 2 is not supported, this build reads 1"
+        );
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(BinaryError::Truncated { game: 1 })
+        ));
+        let mut bad_state = bytes.clone();
+        let end = bad_state.len();
+        bad_state[end - 2..].copy_from_slice(&STATE_CODES.to_le_bytes());
+        assert!(matches!(decode(&bad_state), Err(BinaryError::Corrupt { game: 1 })));
+        let mut longer = bytes;
+        longer.push(0);
+        assert!(matches!(decode(&longer), Err(BinaryError::TrailingData)));
+    }
+}
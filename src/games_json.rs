@@ -0,0 +1,231 @@
+// JSON archive of game datasets, for cleaned data kept outside table.csv:
+//
+//     {"version": 1, "games": [{"player1": "ai", "player2": "ai_2",
+//       "winner": "draw", "id": "...", "timestamp": 1700000000,
+//       "states": [[0, 0, 0, 0, 1, 0, 0, 0, 0], ...], "moves": [4, ...]}]}
+//
+// "id" and "timestamp" are left out when unknown. "moves" holds the cell of
+// every move when the states allow deriving them (`GameData::moves`).
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::GameData;
+
+pub const JSON_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    version: u32,
+    games: Vec<JsonGame>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonGame {
+    player1: String,
+    player2: String,
+    winner: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    /// Wider than the cells so out-of-range values reach `to_game_data`.
+    states: Vec<Vec<i64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    moves: Option<Vec<usize>>,
+}
+
+impl JsonGame {
+    fn from_game_data(game: &GameData) -> JsonGame {
+        JsonGame {
+            player1: game.player1.clone(),
+            player2: game.player2.clone(),
+            winner: game.winner.clone(),
+            id: game.id.clone(),
+            timestamp: game.timestamp,
+            states: game
+                .state_of_cells_list
+                .iter()
+                .map(|state| state.iter().map(|&value| value as i64).collect())
+                .collect(),
+            moves: game
+                .moves()
+                .ok()
+                .map(|moves| moves.iter().map(|record| record.cell_index as usize).collect()),
+        }
+    }
+
+    /// The game, or why it isn't one.
+    fn to_game_data(&self) -> Result<GameData, String> {
+        let mut game = GameData::new(self.player1.clone(), self.player2.clone());
+        for (n, values) in self.states.iter().enumerate() {
+            if values.len() != 9 {
+                return Err(format!("state {} has {} cells", n, values.len()));
+            }
+            let mut state = [0; 9];
+            for (cell, &value) in state.iter_mut().zip(values) {
+                if !(-1..=1).contains(&value) {
+                    return Err(format!("state {} has the value {}", n, value));
+                }
+                *cell = value as i8;
+            }
+            game.state_of_cells_list.push(state);
+        }
+        if let Some(moves) = &self.moves {
+            let derived: Vec<usize> = game
+                .moves()
+                .map_err(|e| e.to_string())?
+                .iter()
+                .map(|record| record.cell_index as usize)
+                .collect();
+            if *moves != derived {
+                return Err(format!("moves {:?} don't match the states", moves));
+            }
+        }
+        game.periodic_state_of_cells = game.state_of_cells_list.last().copied().unwrap_or([0; 9]);
+        game.winner = self.winner.clone();
+        game.id = self.id.clone();
+        game.timestamp = self.timestamp;
+        Ok(game)
+    }
+}
+
+/// Why a JSON archive couldn't be read.
+#[derive(Debug)]
+pub enum JsonError {
+    Io(io::Error),
+    /// Not JSON, or not shaped like an archive.
+    Syntax(serde_json::Error),
+    UnsupportedVersion(u32),
+    /// Game `index` (0 for the first) isn't a valid game.
+    InvalidGame { index: usize, reason: String },
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::Io(e) => write!(f, "Could not read the archive: {}", e),
+            JsonError::Syntax(e) => write!(f, "Not a game archive: {}", e),
+            JsonError::UnsupportedVersion(version) => {
+                write!(f, "Archive version {} is not supported, this build reads {}", version, JSON_VERSION)
+            }
+            JsonError::InvalidGame { index, reason } => write!(f, "Game {} is invalid: {}", index, reason),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+pub fn write_games(path: &Path, games: &[GameData]) -> io::Result<()> {
+    let archive = Archive {
+        version: JSON_VERSION,
+        games: games.iter().map(JsonGame::from_game_data).collect(),
+    };
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &archive)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// Every game of the archive at `path`, or the first problem found.
+pub fn read_games(path: &Path) -> Result<Vec<GameData>, JsonError> {
+    let file = File::open(path).map_err(JsonError::Io)?;
+    let archive: Archive = serde_json::from_reader(BufReader::new(file)).map_err(JsonError::Syntax)?;
+    if archive.version != JSON_VERSION {
+        return Err(JsonError::UnsupportedVersion(archive.version));
+    }
+    archive
+        .games
+        .iter()
+        .enumerate()
+        .map(|(index, game)| game.to_game_data().map_err(|reason| JsonError::InvalidGame { index, reason }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::GamesData;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("tictac_{}_{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut data = GamesData::new(String::new());
+        let mut named = GameData::new("alice".to_string(), "bob, \"jr\"".to_string());
+        named.state_of_cells_list = vec![[0, 0, 0, 0, -1, 0, 0, 0, 0], [1, 0, 0, 0, -1, 0, 0, 0, 0]];
+        named.periodic_state_of_cells = named.state_of_cells_list[1];
+        named.id = Some("00000000000000a1".to_string());
+        named.timestamp = Some(1_700_000_000);
+        data.add_game(named);
+        // No moves can be derived from a corrupt game, but it is kept.
+        let mut corrupt = GameData::new("ai".to_string(), "ai_2".to_string());
+        corrupt.state_of_cells_list = vec![[1, 1, 0, 0, 0, 0, 0, 0, 0]];
+        corrupt.periodic_state_of_cells = corrupt.state_of_cells_list[0];
+        corrupt.winner = "draw".to_string();
+        data.add_game(corrupt);
+        data.add_game(GameData::new("ai".to_string(), "ai_2".to_string()));
+
+        let path = temp_path("round_trip");
+        data.save_json(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let loaded = GamesData::load_json(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(text.contains("\"moves\": [\n        4,\n        0\n      ]"), "{}", text);
+        assert_eq!(text.matches("\"moves\"").count(), 2);
+        assert_eq!(loaded.csv_file, path.to_string_lossy());
+        assert_eq!(loaded.game_data.len(), 3);
+        for (before, after) in data.game_data.iter().zip(&loaded.game_data) {
+            assert_eq!((&after.player1, &after.player2, &after.winner), (&before.player1, &before.player2, &before.winner));
+            assert_eq!((&after.id, after.timestamp), (&before.id, before.timestamp));
+            assert_eq!(after.state_of_cells_list, before.state_of_cells_list);
+            assert_eq!(after.periodic_state_of_cells, before.periodic_state_of_cells);
+        }
+    }
+
+    #[test]
+    fn test_json_rejects_invalid_archives() {
+        let load = |name: &str, text: &str| {
+            let path = temp_path(name);
+            std::fs::write(&path, text).unwrap();
+            let result = GamesData::load_json(&path);
+            std::fs::remove_file(&path).unwrap();
+            result.map(|data| data.game_data.len())
+        };
+        let game = |states: &str| format!(r#"{{"player1":"a","player2":"b","winner":"","states":{}}}"#, states);
+        let archive = |games: &[String]| format!(r#"{{"version":1,"games":[{}]}}"#, games.join(","));
+        let good = game("[[0,0,0,0,1,0,0,0,0]]");
+
+        assert_eq!(load("good", &archive(&[good.clone(), good.clone()])).unwrap(), 2);
+        match load("short", &archive(&[good.clone(), game("[[0,0,0,0,1,0,0,0]]")])) {
+            Err(JsonError::InvalidGame { index, reason }) => assert_eq!((index, reason.as_str()), (1, "state 0 has 8 cells")),
+            other => panic!("{:?}", other),
+        }
+        match load("range", &archive(&[good.clone(), good.clone(), game("[[0,0,0,0,1,0,0,0,0],[2,0,0,0,1,0,0,0,0]]")])) {
+            Err(JsonError::InvalidGame { index, reason }) => {
+                assert_eq!((index, reason.as_str()), (2, "state 1 has the value 2"))
+            }
+            other => panic!("{:?}", other),
+        }
+        let wrong_moves = good.replace(r#""states""#, r#""moves":[3],"states""#);
+        assert!(matches!(
+            load("moves", &archive(&[wrong_moves])),
+            Err(JsonError::InvalidGame { index: 0, .. })
+        ));
+        assert!(matches!(load("syntax", r#"{"version":1,"games":[{"player1":"#), Err(JsonError::Syntax(_))));
+        assert!(matches!(load("shape", r#"{"version":1,"games":{}}"#), Err(JsonError::Syntax(_))));
+        assert!(matches!(
+            load("version", r#"{"version":2,"games":[]}"#),
+            Err(JsonError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            GamesData::load_json(temp_path("missing")),
+            Err(JsonError::Io(_))
+        ));
+    }
+}
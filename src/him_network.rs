@@ -1,115 +1,308 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::batch_norm::BatchNorm;
+use crate::g_class::NnError;
+use crate::layers::{self, ActivationLayer, Dense, Layer, Sequential, Softmax};
+use crate::model::{PolicyModel, TrainReport};
+use crate::numpy::{self, NumpyError};
+use crate::onnx;
+use crate::training::{timed, EpochStats, FitHistory, FitOptions, SanityReport, TrainingTimings};
+
+pub use crate::layers::Activation;
+
+const MODEL_MAGIC: &[u8; 4] = b"HIMN";
+const MODEL_VERSION: u8 = 2;
+
+/// Weights ([node][connection]) and biases of one layer.
+type LayerParams = (Vec<Vec<f32>>, Vec<f32>);
+
+/// Probabilities are clamped to at least this before taking the log, so a
+/// confidently wrong prediction costs -ln(1e-12) ~ 27.6 instead of infinity.
+const MIN_PROB: f32 = 1e-12;
+
+// Defaults for sanity_check: learning rate alpha / (1 + decay * epoch).
+const SANITY_SEED: u64 = 0;
+const SANITY_ALPHA: f32 = 0.1;
+const SANITY_DECAY: f32 = 0.01;
+
+#[derive(Debug, PartialEq)]
+pub enum LossError {
+    /// `loss` was called before any forward pass.
+    NoForwardPass,
+    /// The number of labels doesn't match the batch of the last forward pass.
+    LabelCount { batch: usize, labels: usize },
+    /// `loss_weighted` got a different number of weights than labels.
+    WeightCount { labels: usize, weights: usize },
+}
+
+impl std::fmt::Display for LossError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LossError::NoForwardPass => write!(f, "no forward pass has been run"),
+            LossError::LabelCount { batch, labels } => {
+                write!(f, "{} labels for a batch of {} examples", labels, batch)
+            }
+            LossError::WeightCount { labels, weights } => write!(f, "{} weights for {} labels", weights, labels),
+        }
+    }
+}
+
+impl std::error::Error for LossError {}
+
+/// Optional 1-unit tanh head off the last hidden layer predicting the
+/// game outcome for the player to move, in [-1, 1].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueHead {
+    pub w: Vec<f32>,
+    pub b: f32,
+    pub loss_weight: f32, // weight of the MSE term in the combined loss
+    pub d_w: Vec<f32>,
+    pub db: f32,
+    pub output: Vec<f32>, // one value per example from the last forward pass
+    input: Vec<Vec<f32>>,
+}
+
+/// One hidden layer: Dense, optional batch norm, activation, and an
+/// optional skip connection A = g(Z) + input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HiddenLayer {
+    pub dense: Dense,
+    pub batch_norm: Option<BatchNorm>,
+    pub activation: ActivationLayer,
+    pub residual: bool,
+}
 
 pub struct HimNetwork {
-    pub w: Vec<Vec<Vec<f32>>>,   // [layer][node][connection]
-    pub x1: Vec<Vec<f32>>,       // Training examples
-    pub b: Vec<Vec<f32>>,        // [layer][node]
-    pub z: Vec<Vec<Vec<f32>>>,   // Intermediate layer outputs
-    pub a: Vec<Vec<Vec<f32>>>,   // Activations
-    pub dW: Vec<Vec<Vec<f32>>>,  // Gradients for weights
-    pub db: Vec<Vec<f32>>,       // Gradients for biases
+    pub x1: Vec<Vec<f32>>,               // Training examples
+    pub hidden: Sequential<HiddenLayer>, // layers 1..depth-1
+    pub output: Dense,                   // last layer, followed by softmax
+    softmax: Softmax,
+    pub value_head: Option<ValueHead>, // see enable_value_head
+    // Batch norm uses batch statistics while training and the running ones
+    // otherwise. Loaded models start with this off.
+    pub training: bool,
+}
+
+impl Default for HimNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HimNetwork {
     pub fn new() -> HimNetwork {
         // We use 5 layers total: input => hidden => hidden => hidden => output
         // The final layer has 9 outputs (digits 0..8).
+        let mut net = HimNetwork::with_shapes(&[(81, 9), (81, 81), (81, 81), (9, 81)]);
+        net.x1 = vec![vec![0.0; 9]; 10000];
+        net
+    }
+
+    /// Zero-initialized network with one (nodes, connections) pair per
+    /// layer; the last pair is the output layer.
+    fn with_shapes(shapes: &[(usize, usize)]) -> HimNetwork {
+        let (&(rows, cols), hidden) = shapes.split_last().expect("at least one layer");
         HimNetwork {
-            x1: vec![vec![0.0; 9]; 10000],
-            w: vec![
-                // Layer shapes adapted from documentation logic
-                vec![vec![0.0; 9]; 81],     // layer 1
-                vec![vec![0.0; 81]; 81],    // layer 2
-                vec![vec![0.0; 81]; 81],    // layer 3
-                vec![vec![0.0; 81]; 81],    // layer 4
-                vec![vec![0.0; 9]; 81],     // layer 5 => 9 outputs
-            ],
-            b: vec![
-                vec![0.0; 81],
-                vec![0.0; 81],
-                vec![0.0; 81],
-                vec![0.0; 81],
-                vec![0.0; 9],
-            ],
-            z: vec![
-                vec![vec![0.0; 9]; 81],
-                vec![vec![0.0; 81]; 10000],
-                vec![vec![0.0; 81]; 10000],
-                vec![vec![0.0; 81]; 10000],
-                vec![vec![0.0; 9]; 10000],
-            ],
-            a: vec![
-                vec![vec![0.0; 9]; 81],
-                vec![vec![0.0; 81]; 10000],
-                vec![vec![0.0; 81]; 10000],
-                vec![vec![0.0; 81]; 10000],
-                vec![vec![0.0; 9]; 10000],
-            ],
-            dW: vec![
-                vec![vec![0.0; 9]; 81],
-                vec![vec![0.0; 81]; 81],
-                vec![vec![0.0; 81]; 81],
-                vec![vec![0.0; 81]; 81],
-                vec![vec![0.0; 9]; 81],
-            ],
-            db: vec![
-                vec![0.0; 81],
-                vec![0.0; 81],
-                vec![0.0; 81],
-                vec![0.0; 81],
-                vec![0.0; 9],
-            ],
+            x1: Vec::new(),
+            hidden: Sequential {
+                layers: hidden
+                    .iter()
+                    .map(|&(rows, cols)| HiddenLayer::new(cols, rows))
+                    .collect(),
+            },
+            output: Dense::new(cols, rows),
+            softmax: Softmax::new(),
+            value_head: None,
+            training: true,
+        }
+    }
+
+    /// Number of layers with parameters, output layer included.
+    pub fn depth(&self) -> usize {
+        self.hidden.layers.len() + 1
+    }
+
+    /// Dense part of layer `l`, counting from 1; `depth()` is the output layer.
+    pub fn dense(&self, l: usize) -> &Dense {
+        if l == self.depth() {
+            &self.output
+        } else {
+            &self.hidden.layers[l - 1].dense
+        }
+    }
+
+    pub fn dense_mut(&mut self, l: usize) -> &mut Dense {
+        if l == self.depth() {
+            &mut self.output
+        } else {
+            &mut self.hidden.layers[l - 1].dense
+        }
+    }
+
+    /// Use `activation` for hidden layer `l` (the output layer is always softmax).
+    pub fn set_activation(&mut self, l: usize, activation: Activation) {
+        self.hidden.layers[l - 1].activation.activation = activation;
+    }
+
+    /// Turn skip connections on or off. They only apply to hidden layers
+    /// after the first whose input and output widths match.
+    pub fn set_residual(&mut self, on: bool) {
+        for (i, layer) in self.hidden.layers.iter_mut().enumerate() {
+            layer.residual = on && i > 0 && layer.dense.inputs() == layer.dense.outputs();
         }
     }
 
     /// Initialize weights and biases with random values as in the documentation:
     ///    W ~ Uniform(-0.5, 0.5), B ~ Uniform(-0.5, 0.5)
     pub fn init_params(&mut self) {
-        let mut rng = rand::thread_rng();
-        for nodes in 0..81 {
-            self.w[1][nodes] = vec![(rng.gen_range(0.0..1.0) - 0.5); 9];
-            self.w[2][nodes] = vec![(rng.gen_range(0.0..1.0) - 0.5); 81];
-            self.w[3][nodes] = vec![(rng.gen_range(0.0..1.0) - 0.5); 81];
-            self.w[4][nodes] = vec![(rng.gen_range(0.0..1.0) - 0.5); 9];
+        self.init_params_with_rng(&mut rand::thread_rng());
+    }
 
-            self.b[1][nodes] = rng.gen_range(0.0..1.0) - 0.5;
-            self.b[2][nodes] = rng.gen_range(0.0..1.0) - 0.5;
-            self.b[3][nodes] = rng.gen_range(0.0..1.0) - 0.5;
-            self.b[4][nodes] = rng.gen_range(0.0..1.0) - 0.5;
+    /// Same as `init_params` but drawing from the given RNG, so a seeded
+    /// generator gives reproducible weights.
+    pub fn init_params_with_rng<R: Rng>(&mut self, rng: &mut R) {
+        for l in 1..=self.depth() {
+            let dense = self.dense_mut(l);
+            for node in dense.w.iter_mut() {
+                for weight in node.iter_mut() {
+                    *weight = rng.gen_range(0.0..1.0) - 0.5;
+                }
+            }
+            for bias in dense.b.iter_mut() {
+                *bias = rng.gen_range(0.0..1.0) - 0.5;
+            }
         }
     }
 
     /// Forward propagation (adapting the doc steps to our five-layer design).
     /// Z[l] = W[l] * A[l-1] + B[l]
-    /// A[l] = ReLU(Z[l]) for hidden layers; softmax for final layer.
+    /// A[l] = g(Z[l]) for hidden layers (ReLU unless set_activation says
+    /// otherwise); softmax for final layer.
+    /// With residual layers, A[l] = g(Z[l]) + A[l-1] where the widths match.
     pub fn forward_propagation(&mut self) {
-        // Layer 1
-        self.z[1] = self.add_bias(
-            self.multiply_matrix(&self.w[1], &self.x1),
-            &self.b[1],
-        );
-        self.a[1] = self.relu(self.z[1].clone());
+        self.hidden.set_training(self.training);
+        let hidden = self.hidden.forward(&self.x1);
+        if let Some(head) = self.value_head.as_mut() {
+            head.forward(&hidden);
+        }
+        let logits = self.output.forward(&hidden);
+        self.softmax.forward(&logits);
+    }
 
-        // Layer 2
-        self.z[2] = self.add_bias(
-            self.multiply_matrix(&self.w[2], &self.a[1]),
-            &self.b[2],
-        );
-        self.a[2] = self.relu(self.z[2].clone());
+    /// Output probabilities from the last forward pass, one row per example.
+    pub fn probabilities(&self) -> &[Vec<f32>] {
+        self.softmax.output()
+    }
 
-        // Layer 3
-        self.z[3] = self.add_bias(
-            self.multiply_matrix(&self.w[3], &self.a[2]),
-            &self.b[3],
-        );
-        self.a[3] = self.relu(self.z[3].clone());
+    /// Last hidden layer for a single board, without touching the training
+    /// buffers. Batch norm always uses the running statistics here.
+    fn hidden_single(&self, board: &[f32]) -> Vec<f32> {
+        self.hidden.infer(&[board.to_vec()]).remove(0)
+    }
 
-        // Layer 4 (final NN output)
-        self.z[4] = self.add_bias(
-            self.multiply_matrix(&self.w[4], &self.a[3]),
-            &self.b[4],
-        );
-        self.a[4] = self.softmax(&self.z[4]);
+    /// Post-activation values of every layer for a single board, from the
+    /// input through the output probabilities. Leaves the training buffers
+    /// alone, so it also works on a loaded inference-only model.
+    pub fn activations_for(&self, board: &[f32; 9]) -> Vec<Vec<f32>> {
+        let mut out = vec![board.to_vec()];
+        for layer in &self.hidden.layers {
+            let a = layer.infer(&[out[out.len() - 1].clone()]).remove(0);
+            out.push(a);
+        }
+        let logits = self.output.infer(&[out[out.len() - 1].clone()]);
+        out.push(self.softmax.infer(&logits).remove(0));
+        out
+    }
+
+    /// Fraction of units per hidden layer that never activate (Z <= 0 for
+    /// every example of `x`), using inference mode like `activations_for`.
+    /// An empty `x` shows no unit to be dead, so every fraction is 0.
+    pub fn dead_unit_report(&self, x: &[Vec<f32>]) -> Vec<f32> {
+        if x.is_empty() {
+            return vec![0.0; self.hidden.layers.len()];
+        }
+        let mut input = x.to_vec();
+        let mut report = Vec::new();
+        for layer in &self.hidden.layers {
+            let z = layer.pre_activation(&input);
+            let width = layer.dense.outputs();
+            let dead = (0..width).filter(|&j| z.iter().all(|row| row[j] <= 0.0)).count();
+            report.push(dead as f32 / width.max(1) as f32);
+            input = layer.activate(&z, &input);
+        }
+        report
+    }
+
+    /// Most likely next move for a single board.
+    pub fn predict_move(&self, board: &[f32; 9]) -> usize {
+        let hidden = self.hidden_single(board);
+        let probs = self.softmax.infer(&self.output.infer(&[hidden]));
+        row_argmax(&probs[0]).0
+    }
+
+    /// Most likely move among the empty cells (0.0) of `board`, or None
+    /// when the board is full.
+    pub fn predict_legal_move(&self, board: &[f32; 9]) -> Option<usize> {
+        let hidden = self.hidden_single(board);
+        let probs = self.softmax.infer(&self.output.infer(&[hidden])).remove(0);
+        (0..9)
+            .filter(|&i| board[i] == 0.0)
+            .max_by(|&a, &b| probs[a].total_cmp(&probs[b]))
+    }
+
+    /// Value-head estimate for a single board, or None without a value head.
+    pub fn predict_value(&self, board: &[f32; 9]) -> Option<f32> {
+        let head = self.value_head.as_ref()?;
+        Some(head.evaluate(&self.hidden_single(board)))
+    }
+
+    /// Add a value head off the last hidden layer. `loss_weight` scales its
+    /// MSE term against the policy cross-entropy.
+    pub fn enable_value_head(&mut self, loss_weight: f32) {
+        self.enable_value_head_with_rng(loss_weight, &mut rand::thread_rng());
+    }
+
+    /// Same as `enable_value_head` but drawing the head's weights from the
+    /// given RNG, so a seeded network stays reproducible.
+    pub fn enable_value_head_with_rng<R: Rng>(&mut self, loss_weight: f32, rng: &mut R) {
+        let width = self.output.inputs();
+        self.value_head = Some(ValueHead {
+            w: (0..width).map(|_| rng.gen_range(0.0..1.0) - 0.5).collect(),
+            b: 0.0,
+            loss_weight,
+            d_w: vec![0.0; width],
+            db: 0.0,
+            output: Vec::new(),
+            input: Vec::new(),
+        });
+    }
+
+    /// Weighted MSE of the value head against `targets` for the last
+    /// forward pass (0 without a value head).
+    pub fn value_loss(&self, targets: &[f32]) -> f32 {
+        match &self.value_head {
+            Some(head) if !head.output.is_empty() => {
+                let sum: f32 = head
+                    .output
+                    .iter()
+                    .zip(targets.iter())
+                    .map(|(v, t)| (v - t) * (v - t))
+                    .sum();
+                head.loss_weight * sum / head.output.len() as f32
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Normalize the pre-activations of every hidden layer.
+    pub fn enable_batch_norm(&mut self) {
+        for layer in self.hidden.layers.iter_mut() {
+            layer.batch_norm = Some(BatchNorm::new(layer.dense.outputs()));
+        }
     }
 
     /// Convert labels Y to one-hot vectors, as described in doc (size = 9).
@@ -123,88 +316,126 @@ impl HimNetwork {
         encoded
     }
 
-    /// ReLU derivative
-    fn relu_deriv(&self, z: &Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        z.iter()
-            .map(|row| row.iter().map(|&val| if val > 0.0 { 1.0 } else { 0.0 }).collect())
-            .collect()
-    }
-
     /// Backward propagation (based on doc math).
+    /// Activations are stored one row per example, so the doc's
+    /// dW = dZ * A^T becomes dZ^T * A here and db sums over the examples.
     pub fn backward_propagation(&mut self, y: Vec<usize>) {
-        let one_hot_y = self.one_hot_encode(y, 9);
-        let m = self.x1.len() as f32;
-        let inv_m = 1.0 / m;
-
-        // Output layer gradient: dZ4 = A[4] - Y
-        let mut dZ4 = self.a[4].clone();
-        for i in 0..dZ4.len() {
-            for j in 0..dZ4[i].len() {
-                dZ4[i][j] -= one_hot_y[i][j];
-            }
-        }
-        // dW4 = (1/m) dZ4 * A[3]^T
-        let a3_t = self.transpose(self.a[3].clone());
-        let dZ4_a3_t = self.multiply_matrix(&dZ4, &a3_t);
-        let dW4 = self.scale_matrix(dZ4_a3_t, inv_m);
-
-        // db4 = (1/m) sum_rows(dZ4)
-        let db4 = self.sum_rows(&dZ4, inv_m);
-
-        // dZ3 = W4^T dZ4 .* ReLU'(Z3)
-        let w4_t = self.transpose(self.w[4].clone());
-        let dA3 = self.multiply_matrix(&w4_t, &dZ4);
-        let r3 = self.relu_deriv(&self.z[3]);
-        let dZ3 = self.elementwise_multiply(&dA3, &r3);
-
-        // dW3 = (1/m) dZ3 * A[2]^T, db3 = (1/m) sum_rows(dZ3)
-        let a2_t = self.transpose(self.a[2].clone());
-        let dZ3_a2_t = self.multiply_matrix(&dZ3, &a2_t);
-        let dW3 = self.scale_matrix(dZ3_a2_t, inv_m);
-        let db3 = self.sum_rows(&dZ3, inv_m);
-
-        // dZ2 = W3^T * dZ3 .* ReLU'(Z2)
-        let w3_t = self.transpose(self.w[3].clone());
-        let dA2 = self.multiply_matrix(&w3_t, &dZ3);
-        let r2 = self.relu_deriv(&self.z[2]);
-        let dZ2 = self.elementwise_multiply(&dA2, &r2);
+        self.backward(y, None, None);
+    }
 
-        // dW2 = (1/m) dZ2 * A[1]^T, db2 = (1/m) sum_rows(dZ2)
-        let a1_t = self.transpose(self.a[1].clone());
-        let dZ2_a1_t = self.multiply_matrix(&dZ2, &a1_t);
-        let dW2 = self.scale_matrix(dZ2_a1_t, inv_m);
-        let db2 = self.sum_rows(&dZ2, inv_m);
+    /// Backward pass for both heads: cross-entropy on the move policy plus
+    /// the weighted MSE of the value head against `values` (one per example).
+    pub fn backward_propagation_with_values(&mut self, y: Vec<usize>, values: &[f32]) {
+        self.backward(y, Some(values), None);
+    }
 
-        // dZ1 = W2^T * dZ2 .* ReLU'(Z1)
-        let w2_t = self.transpose(self.w[2].clone());
-        let dA1 = self.multiply_matrix(&w2_t, &dZ2);
-        let r1 = self.relu_deriv(&self.z[1]);
-        let dZ1 = self.elementwise_multiply(&dA1, &r1);
+    /// `backward_propagation` with the cross-entropy of example i scaled by
+    /// `weights[i]`, as in `loss_weighted`. Weights of 1.0 give the same
+    /// gradients as `backward_propagation`.
+    pub fn backward_propagation_weighted(&mut self, y: Vec<usize>, weights: &[f32]) {
+        self.backward(y, None, Some(weights));
+    }
 
-        // dW1 = (1/m) dZ1 * X^T, db1 = (1/m) sum_rows(dZ1)
-        let x_t = self.transpose(self.x1.clone());
-        let dZ1_x_t = self.multiply_matrix(&dZ1, &x_t);
-        let dW1 = self.scale_matrix(dZ1_x_t, inv_m);
-        let db1 = self.sum_rows(&dZ1, inv_m);
+    fn backward(&mut self, y: Vec<usize>, values: Option<&[f32]>, weights: Option<&[f32]>) {
+        let one_hot_y = self.one_hot_encode(y, self.output.outputs());
+        let inv_m = 1.0 / self.x1.len() as f32;
 
-        // Store
-        self.dW = vec![dW1, dW2, dW3, dW4];
-        self.db = vec![db1, db2, db3, db4];
+        // Softmax and cross-entropy together: dZ = w * (A - Y) / m
+        let d_z: Vec<Vec<f32>> = self
+            .softmax
+            .output()
+            .iter()
+            .zip(one_hot_y.iter())
+            .enumerate()
+            .map(|(i, (row, target))| {
+                let scale = inv_m * weights.map_or(1.0, |weights| weights[i]);
+                row.iter().zip(target.iter()).map(|(a, t)| (a - t) * scale).collect()
+            })
+            .collect();
+        let mut d_hidden = self.output.backward(&d_z);
+        if let Some(head) = self.value_head.as_mut() {
+            // Without values this pass has no value gradients, so the next
+            // update mustn't apply those of an earlier one.
+            head.zero_grad();
+            if let Some(values) = values {
+                head.backward(&mut d_hidden, values, inv_m);
+            }
+        }
+        self.hidden.backward(&d_hidden);
     }
 
     /// Update parameters (weights/biases).
     /// W := W - alpha * dW
     /// B := B - alpha * dB
     pub fn update_params(&mut self, alpha: f32) {
-        for l in 0..self.w.len() {
-            for i in 0..self.w[l].len() {
-                for j in 0..self.w[l][i].len() {
-                    self.w[l][i][j] -= alpha * self.dW[l][i][j];
-                }
+        self.hidden.update(alpha);
+        self.output.update(alpha);
+        if let Some(head) = self.value_head.as_mut() {
+            for (weight, grad) in head.w.iter_mut().zip(head.d_w.iter()) {
+                *weight -= alpha * grad;
             }
-            for i in 0..self.b[l].len() {
-                self.b[l][i] -= alpha * self.db[l][i];
+            head.b -= alpha * head.db;
+        }
+    }
+
+    /// One SGD step on a single example (forward, backward, update on a
+    /// 1-row batch), e.g. to nudge the network after every finished game.
+    /// Returns the example's loss before the update. `x1` is left alone and
+    /// the value head is not trained.
+    pub fn step_single(&mut self, board: &[f32; 9], label: usize, alpha: f32) -> f32 {
+        self.hidden.set_training(self.training);
+        let hidden = self.hidden.forward(&[board.to_vec()]);
+        let probs = self.softmax.forward(&self.output.forward(&hidden));
+        let loss = cross_entropy(&probs, &[label]);
+        // dZ = A - Y for the single row
+        let d_z: Vec<f32> = probs[0]
+            .iter()
+            .enumerate()
+            .map(|(j, p)| if j == label { p - 1.0 } else { *p })
+            .collect();
+        let d_hidden = self.output.backward(&[d_z]);
+        self.hidden.backward(&d_hidden);
+        self.hidden.update(alpha);
+        self.output.update(alpha);
+        loss
+    }
+
+    /// Overfit-one-batch check: re-initialize from a fixed seed and train on
+    /// the whole of `x`/`y` until the loss is at most `target_loss` or
+    /// `max_epochs` have run. A healthy network drives a handful of
+    /// examples to ~0 loss. Leaves `x` in `x1`. Fails, before touching the
+    /// network, on examples `fit` would refuse.
+    pub fn sanity_check(
+        &mut self,
+        x: &[Vec<f32>],
+        y: &[usize],
+        max_epochs: usize,
+        target_loss: f32,
+    ) -> Result<SanityReport, NnError> {
+        self.check_examples(x, y)?;
+        self.init_params_with_rng(&mut StdRng::seed_from_u64(SANITY_SEED));
+        self.x1 = x.to_vec();
+        let mut epochs = 0;
+        loop {
+            self.forward_propagation();
+            let loss = self.loss(y).expect("labels checked above");
+            if loss <= target_loss || epochs == max_epochs {
+                let correct = self
+                    .predictions()
+                    .iter()
+                    .zip(y.iter())
+                    .filter(|((class, _), &label)| *class == label)
+                    .count();
+                return Ok(SanityReport {
+                    reached: loss <= target_loss,
+                    epochs,
+                    final_loss: loss,
+                    accuracy: correct as f32 / y.len() as f32,
+                });
             }
+            self.backward_propagation(y.to_vec());
+            self.update_params(SANITY_ALPHA / (1.0 + SANITY_DECAY * epochs as f32));
+            epochs += 1;
         }
     }
 
@@ -216,88 +447,112 @@ impl HimNetwork {
         self.update_params(alpha);
     }
 
-    /// Multiply two matrices (inputs: W, X).
-    fn multiply_matrix(&self, w: &Vec<Vec<f32>>, x: &Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        // result shape: x.len() x w.len()
-        let mut result = vec![vec![0.0; w.len()]; x.len()];
-        for i in 0..x.len() {
-            for j in 0..w.len() {
-                let mut sum = 0.0;
-                for k in 0..w[j].len() {
-                    sum += w[j][k] * x[i][k];
-                }
-                result[i][j] = sum;
-            }
-        }
-        result
+    /// Mini-batch training on `x`/`y` (policy head only), recording the
+    /// loss over the whole set after every epoch. `x` and `y` must have the
+    /// same, non-zero length and every example a value per input node.
+    /// Leaves all of `x` in `x1`, as scored for the last epoch's loss.
+    pub fn fit(&mut self, x: &[Vec<f32>], y: &[usize], options: &FitOptions) -> Result<FitHistory, NnError> {
+        self.fit_with(x, y, None, options)
     }
 
-    /// Add bias to each row of a matrix
-    fn add_bias(&self, mat: Vec<Vec<f32>>, bias: &Vec<f32>) -> Vec<Vec<f32>> {
-        let mut out = mat.clone();
-        for i in 0..out.len() {
-            for j in 0..out[i].len() {
-                out[i][j] += bias[j];
-            }
+    /// `fit` with a weight per example (see `backward_propagation_weighted`);
+    /// the recorded losses are `loss_weighted`. `weights` must be as long as
+    /// `y`.
+    pub fn fit_weighted(
+        &mut self,
+        x: &[Vec<f32>],
+        y: &[usize],
+        weights: &[f32],
+        options: &FitOptions,
+    ) -> Result<FitHistory, NnError> {
+        if weights.len() != y.len() {
+            return Err(NnError::WeightCountMismatch { examples: y.len(), weights: weights.len() });
         }
-        out
-    }
-
-    /// ReLU activation
-    fn relu(&self, z: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        z.into_iter()
-            .map(|row| {
-                row.into_iter()
-                    .map(|val| if val > 0.0 { val } else { 0.0 })
-                    .collect()
-            })
-            .collect()
+        self.fit_with(x, y, Some(weights), options)
     }
 
-    /// Softmax as in the doc.
-    pub fn softmax(&self, z: &Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        let mut out = vec![vec![0.0; z[0].len()]; z.len()];
-        for (i, row) in z.iter().enumerate() {
-            let max_val = row.iter().cloned().fold(f32::MIN, f32::max);
-            let exps: Vec<f32> = row.iter().map(|&v| (v - max_val).exp()).collect();
-            let sum_exps: f32 = exps.iter().sum();
-            for (j, &e) in exps.iter().enumerate() {
-                out[i][j] = e / sum_exps;
+    fn fit_with(
+        &mut self,
+        x: &[Vec<f32>],
+        y: &[usize],
+        weights: Option<&[f32]>,
+        options: &FitOptions,
+    ) -> Result<FitHistory, NnError> {
+        self.check_examples(x, y)?;
+        let start = options.timing.then(Instant::now);
+        let on = options.timing;
+        let mut timings = TrainingTimings::default();
+        let mut history = FitHistory::default();
+        let mut order: Vec<usize> = (0..x.len()).collect();
+        let mut rng = rand::thread_rng();
+        for _ in 0..options.epochs {
+            let epoch_start = Instant::now();
+            if options.shuffle {
+                timed(on, &mut timings.shuffle, || order.shuffle(&mut rng));
+            }
+            for batch in order.chunks(options.batch_size.max(1)) {
+                let (labels, batch_weights) = timed(on, &mut timings.shuffle, || {
+                    self.x1 = batch.iter().map(|&i| x[i].clone()).collect();
+                    let labels = batch.iter().map(|&i| y[i]).collect::<Vec<usize>>();
+                    (labels, weights.map(|weights| batch.iter().map(|&i| weights[i]).collect::<Vec<f32>>()))
+                });
+                timed(on, &mut timings.forward, || self.forward_propagation());
+                timed(on, &mut timings.backward, || self.backward(labels, None, batch_weights.as_deref()));
+                timed(on, &mut timings.update, || self.update_params(options.learning_rate));
             }
+            // The forward pass inside evaluate is counted as evaluation only.
+            let (loss, accuracy) = timed(on, &mut timings.evaluation, || {
+                let loss = match weights {
+                    Some(weights) => self.evaluate(x, y).and_then(|_| self.loss_weighted(y, weights)),
+                    None => self.evaluate(x, y),
+                };
+                let correct = self.predict(self.probabilities()).iter().zip(y).filter(|(p, l)| p == l).count();
+                (loss, correct as f64 / y.len() as f64)
+            });
+            history.epochs.push(EpochStats {
+                loss: f64::from(loss.expect("labels checked above")),
+                validation_loss: None,
+                accuracy: Some(accuracy),
+                learning_rate: f64::from(options.learning_rate),
+                duration: epoch_start.elapsed(),
+            });
         }
-        out
+        if let Some(start) = start {
+            timings.total = start.elapsed();
+        }
+        history.timings = timings;
+        Ok(history)
     }
 
-    /// Elementwise multiply for matrix
-    fn elementwise_multiply(&self, a: &Vec<Vec<f32>>, b: &Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        let mut r = vec![vec![0.0; a[0].len()]; a.len()];
-        for i in 0..a.len() {
-            for j in 0..a[i].len() {
-                r[i][j] = a[i][j] * b[i][j];
-            }
+    /// At least one example, a label for each and a value per input node.
+    fn check_examples(&self, x: &[Vec<f32>], y: &[usize]) -> Result<(), NnError> {
+        if x.is_empty() {
+            return Err(NnError::NoExamples);
+        }
+        if x.len() != y.len() {
+            return Err(NnError::ExampleCountMismatch { inputs: x.len(), targets: y.len() });
+        }
+        let expected = self.dense(1).inputs();
+        match x.iter().find(|row| row.len() != expected) {
+            Some(row) => Err(NnError::InputSizeMismatch { expected, found: row.len() }),
+            None => Ok(()),
         }
-        r
     }
 
-    /// Summation across each row, scaled by factor
-    fn sum_rows(&self, matrix: &Vec<Vec<f32>>, factor: f32) -> Vec<f32> {
-        let mut sums = vec![0.0; matrix.len()];
-        for (i, row) in matrix.iter().enumerate() {
-            let sum_row: f32 = row.iter().sum();
-            sums[i] = sum_row * factor;
-        }
-        sums
+    /// Cross-entropy on `x`/`y` with batch norm in inference mode.
+    /// Replaces `x1` with `x`.
+    pub fn evaluate(&mut self, x: &[Vec<f32>], y: &[usize]) -> Result<f32, LossError> {
+        let training = self.training;
+        self.training = false;
+        self.x1 = x.to_vec();
+        self.forward_propagation();
+        self.training = training;
+        self.loss(y)
     }
 
-    /// Multiply each element of a matrix by scalar
-    fn scale_matrix(&self, mat: Vec<Vec<f32>>, scalar: f32) -> Vec<Vec<f32>> {
-        let mut out = mat.clone();
-        for row in out.iter_mut() {
-            for val in row.iter_mut() {
-                *val *= scalar;
-            }
-        }
-        out
+    /// Softmax as in the doc.
+    pub fn softmax(&self, z: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        layers::softmax_rows(z)
     }
 
     /// Transpose a matrix
@@ -308,51 +563,495 @@ impl HimNetwork {
         let rows = m.len();
         let cols = m[0].len();
         let mut out = vec![vec![0.0; rows]; cols];
-        for i in 0..rows {
-            for j in 0..cols {
-                out[j][i] = m[i][j];
+        for (i, row) in m.iter().enumerate() {
+            for (j, &val) in row.iter().enumerate() {
+                out[j][i] = val;
             }
         }
         out
     }
 
-    /// Simple cross-entropy loss
-    pub fn compute_loss(&mut self, preds: Vec<Vec<f32>>, labels: Vec<usize>) -> f32 {
-        let oh_labels = self.one_hot_encode(labels, preds[0].len());
-        let mut total = 0.0;
-        for (i, row) in preds.iter().enumerate() {
-            for j in 0..row.len() {
-                let p = row[j].max(1e-12); // avoid log(0)
-                total -= oh_labels[i][j] * p.ln();
+    /// Mean cross-entropy of the last forward pass against `y`.
+    pub fn loss(&self, y: &[usize]) -> Result<f32, LossError> {
+        let probs = self.probabilities();
+        if probs.is_empty() {
+            return Err(LossError::NoForwardPass);
+        }
+        if probs.len() != y.len() {
+            return Err(LossError::LabelCount { batch: probs.len(), labels: y.len() });
+        }
+        Ok(cross_entropy(probs, y))
+    }
+
+    /// `loss` with the cross-entropy of example i scaled by `weights[i]`,
+    /// still divided by the batch size, so weights of 1.0 give `loss`.
+    pub fn loss_weighted(&self, y: &[usize], weights: &[f32]) -> Result<f32, LossError> {
+        self.loss(y)?;
+        if weights.len() != y.len() {
+            return Err(LossError::WeightCount { labels: y.len(), weights: weights.len() });
+        }
+        Ok(weighted_cross_entropy(self.probabilities(), y, weights))
+    }
+
+    /// Mean cross-entropy of an explicit probability matrix; same clamping
+    /// as `loss`. Labels outside the rows add nothing.
+    pub fn compute_loss(&self, preds: Vec<Vec<f32>>, labels: Vec<usize>) -> f32 {
+        cross_entropy(&preds, &labels)
+    }
+
+    /// Get final predictions for an explicit output matrix
+    pub fn predict(&self, output: &[Vec<f32>]) -> Vec<usize> {
+        output.iter().map(|row| row_argmax(row).0).collect()
+    }
+
+    /// (class, probability) per example from the last forward pass.
+    /// Empty until `forward_propagation` has been run.
+    pub fn predictions(&self) -> Vec<(usize, f32)> {
+        self.probabilities().iter().map(|row| row_argmax(row)).collect()
+    }
+
+    /// Print parameters for debugging
+    pub fn print_params(&self) {
+        let weights: Vec<&Vec<Vec<f32>>> = (1..=self.depth()).map(|l| &self.dense(l).w).collect();
+        let biases: Vec<&Vec<f32>> = (1..=self.depth()).map(|l| &self.dense(l).b).collect();
+        println!("Weights: {:?}", weights);
+        println!("Biases: {:?}", biases);
+    }
+
+    /// Write the network as an ONNX graph with a 9-wide input named "board"
+    /// and the softmax output named "move_probs". Hidden layers keep their
+    /// activation, batch norm (running statistics) and skip connection; the
+    /// value head is left out.
+    pub fn export_onnx(&self, path: &Path) -> std::io::Result<()> {
+        let layers: Vec<onnx::DenseLayer> = (1..=self.depth())
+            .map(|l| {
+                let hidden = self.hidden.layers.get(l - 1);
+                onnx::DenseLayer {
+                    weights: &self.dense(l).w,
+                    biases: &self.dense(l).b,
+                    batch_norm: hidden.and_then(|layer| layer.batch_norm.as_ref()),
+                    activation: hidden.map_or(Activation::ReLU, |layer| layer.activation.activation),
+                    residual: hidden.is_some_and(|layer| layer.residual),
+                }
+            })
+            .collect();
+        onnx::write_mlp(path, &layers, "board", "move_probs")
+    }
+
+    /// Save the full model (layer shapes, weights, biases, activations,
+    /// residual flag and batch-norm state) in a little-endian binary format.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut out = MODEL_MAGIC.to_vec();
+        out.push(MODEL_VERSION);
+        let put_u32 = |out: &mut Vec<u8>, v: usize| out.extend((v as u32).to_le_bytes());
+        let put_f32s = |out: &mut Vec<u8>, v: &[f32]| out.extend(v.iter().flat_map(|x| x.to_le_bytes()));
+        // Layer count includes the input layer.
+        put_u32(&mut out, self.depth() + 1);
+        for l in 1..=self.depth() {
+            put_u32(&mut out, self.dense(l).outputs());
+            put_u32(&mut out, self.dense(l).inputs());
+        }
+        out.push(self.hidden.layers.iter().any(|layer| layer.residual) as u8);
+        for l in 1..=self.depth() {
+            // The output layer is always softmax; its slot stays ReLU.
+            let activation = self
+                .hidden
+                .layers
+                .get(l - 1)
+                .map_or(Activation::ReLU, |layer| layer.activation.activation);
+            let (tag, alpha) = match activation {
+                Activation::ReLU => (0, 0.0),
+                Activation::LeakyReLU(alpha) => (1, alpha),
+                Activation::ELU(alpha) => (2, alpha),
+            };
+            out.push(tag);
+            put_f32s(&mut out, &[alpha]);
+        }
+        for l in 1..=self.depth() {
+            for row in &self.dense(l).w {
+                put_f32s(&mut out, row);
             }
+            put_f32s(&mut out, &self.dense(l).b);
         }
-        total / (preds.len() as f32)
+        for l in 1..=self.depth() {
+            match self.hidden.layers.get(l - 1).and_then(|layer| layer.batch_norm.as_ref()) {
+                Some(bn) => {
+                    out.push(1);
+                    put_f32s(&mut out, &bn.gamma);
+                    put_f32s(&mut out, &bn.beta);
+                    put_f32s(&mut out, &bn.running_mean);
+                    put_f32s(&mut out, &bn.running_var);
+                    put_f32s(&mut out, &[bn.momentum, bn.eps]);
+                }
+                None => out.push(0),
+            }
+        }
+        match &self.value_head {
+            Some(head) => {
+                out.push(1);
+                put_u32(&mut out, head.w.len());
+                put_f32s(&mut out, &head.w);
+                put_f32s(&mut out, &[head.b, head.loss_weight]);
+            }
+            None => out.push(0),
+        }
+        std::fs::write(path, out)
     }
 
-    /// Get final predictions
-    pub fn predict(&self, output: &Vec<Vec<f32>>) -> Vec<usize> {
-        let mut res = vec![0; output.len()];
-        for (i, row) in output.iter().enumerate() {
-            let mut max_val = row[0];
-            let mut max_idx = 0;
-            for (j, &v) in row.iter().enumerate() {
-                if v > max_val {
-                    max_val = v;
-                    max_idx = j;
+    /// Load a model written by `save`. The result is in inference mode
+    /// (`training` off), so batch norm uses the stored running statistics.
+    pub fn load(path: &Path) -> std::io::Result<HimNetwork> {
+        let bytes = std::fs::read(path)?;
+        let mut reader = ModelReader { bytes: &bytes, pos: 0 };
+        if reader.take(4)? != MODEL_MAGIC {
+            return Err(invalid_model("not a HimNetwork model file"));
+        }
+        let version = reader.take(1)?[0];
+        if version == 0 || version > MODEL_VERSION {
+            return Err(invalid_model(&format!("unsupported model version {}", version)));
+        }
+        let layers = reader.u32()?;
+        if layers < 2 {
+            return Err(invalid_model("model needs at least one layer"));
+        }
+        let mut shapes = Vec::new();
+        for _ in 1..layers {
+            shapes.push((reader.u32()?, reader.u32()?));
+        }
+
+        let mut net = HimNetwork::with_shapes(&shapes);
+        net.training = false;
+        let residual = reader.take(1)?[0] != 0;
+        net.set_residual(residual);
+        for l in 1..layers {
+            let tag = reader.take(1)?[0];
+            let alpha = reader.f32s(1)?[0];
+            let activation = match tag {
+                0 => Activation::ReLU,
+                1 => Activation::LeakyReLU(alpha),
+                2 => Activation::ELU(alpha),
+                _ => return Err(invalid_model(&format!("unknown activation {}", tag))),
+            };
+            if l < net.depth() {
+                net.set_activation(l, activation);
+            }
+        }
+        for (l, &(rows, cols)) in shapes.iter().enumerate().map(|(i, s)| (i + 1, s)) {
+            let w = (0..rows).map(|_| reader.f32s(cols)).collect::<Result<_, _>>()?;
+            let b = reader.f32s(rows)?;
+            let dense = net.dense_mut(l);
+            dense.w = w;
+            dense.b = b;
+        }
+        for (l, &(rows, _)) in shapes.iter().enumerate().map(|(i, s)| (i + 1, s)) {
+            if reader.take(1)?[0] == 1 {
+                if l == net.depth() {
+                    return Err(invalid_model("batch norm on the output layer"));
                 }
+                let mut bn = BatchNorm::new(rows);
+                bn.gamma = reader.f32s(rows)?;
+                bn.beta = reader.f32s(rows)?;
+                bn.running_mean = reader.f32s(rows)?;
+                bn.running_var = reader.f32s(rows)?;
+                let rest = reader.f32s(2)?;
+                bn.momentum = rest[0];
+                bn.eps = rest[1];
+                net.hidden.layers[l - 1].batch_norm = Some(bn);
             }
-            res[i] = max_idx;
         }
-        res
+        // Version 1 files predate the value head.
+        if version >= 2 && reader.take(1)?[0] == 1 {
+            let width = reader.u32()?;
+            let w = reader.f32s(width)?;
+            let rest = reader.f32s(2)?;
+            net.value_head = Some(ValueHead {
+                w,
+                b: rest[0],
+                loss_weight: rest[1],
+                d_w: vec![0.0; width],
+                db: 0.0,
+                output: Vec::new(),
+                input: Vec::new(),
+            });
+        }
+        Ok(net)
     }
 
-    /// Print parameters for debugging
-    pub fn print_params(&self) {
-        println!("Weights: {:?}", self.w);
-        println!("Biases: {:?}", self.b);
+    /// Load weights trained in NumPy into the configured layers.
+    ///
+    /// `path` is either an .npz archive holding w1, b1, ..., w4, b4 with the
+    /// weights shaped (nodes, connections), or a raw little-endian f32 dump
+    /// holding, layer by layer, the weights row by row followed by the biases.
+    /// Biases may be shaped (n,) or (n, 1). A weight matrix that arrives
+    /// transposed is rejected unless `auto_transpose` is set; square layers
+    /// can't be told apart and are taken as-is. Nothing is changed on error.
+    pub fn load_numpy(&mut self, path: &Path, auto_transpose: bool) -> Result<(), NumpyError> {
+        let bytes = std::fs::read(path)?;
+        let layers = if numpy::is_npz(&bytes) {
+            self.layers_from_npz(&bytes, auto_transpose)?
+        } else {
+            self.layers_from_raw(&bytes)?
+        };
+        for (l, (w, b)) in layers.into_iter().enumerate() {
+            let dense = self.dense_mut(l + 1);
+            dense.w = w;
+            dense.b = b;
+        }
+        Ok(())
+    }
+
+    fn layers_from_npz(
+        &self,
+        bytes: &[u8],
+        auto_transpose: bool,
+    ) -> Result<Vec<LayerParams>, NumpyError> {
+        let mut arrays = numpy::read_npz(bytes)?;
+        let mut layers = Vec::new();
+        for l in 1..=self.depth() {
+            let rows = self.dense(l).outputs();
+            let cols = self.dense(l).inputs();
+            let name = format!("w{}", l);
+            let w = arrays.remove(&name).ok_or(NumpyError::MissingArray(name.clone()))?;
+            let expected = vec![rows, cols];
+            let weights = if w.shape == expected {
+                w.data.chunks(cols).map(|row| row.to_vec()).collect()
+            } else if w.shape == [cols, rows] {
+                if !auto_transpose {
+                    return Err(NumpyError::Transposed { name, expected, found: w.shape });
+                }
+                let flipped: Vec<Vec<f32>> = w.data.chunks(rows).map(|row| row.to_vec()).collect();
+                self.transpose(flipped)
+            } else {
+                return Err(NumpyError::ShapeMismatch { name, expected, found: w.shape });
+            };
+
+            let name = format!("b{}", l);
+            let b = arrays.remove(&name).ok_or(NumpyError::MissingArray(name.clone()))?;
+            if b.data.len() != rows || b.shape.iter().filter(|&&d| d != 1).count() > 1 {
+                return Err(NumpyError::ShapeMismatch { name, expected: vec![rows], found: b.shape });
+            }
+            layers.push((weights, b.data));
+        }
+        Ok(layers)
+    }
+
+    fn layers_from_raw(&self, bytes: &[u8]) -> Result<Vec<LayerParams>, NumpyError> {
+        let expected: usize = (1..=self.depth())
+            .map(|l| self.dense(l).outputs() * (self.dense(l).inputs() + 1))
+            .sum();
+        if bytes.len() != expected * 4 {
+            return Err(NumpyError::Format(format!(
+                "raw weight file has {} bytes, expected {} ({} f32 values)",
+                bytes.len(),
+                expected * 4,
+                expected
+            )));
+        }
+        let mut values = bytes
+            .chunks(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]));
+        let mut layers = Vec::new();
+        for l in 1..=self.depth() {
+            let rows = self.dense(l).outputs();
+            let cols = self.dense(l).inputs();
+            let weights: Vec<Vec<f32>> = (0..rows)
+                .map(|_| values.by_ref().take(cols).collect())
+                .collect();
+            let biases: Vec<f32> = values.by_ref().take(rows).collect();
+            layers.push((weights, biases));
+        }
+        Ok(layers)
+    }
+}
+
+/// Panics unless the output layer has 9 nodes.
+impl PolicyModel for HimNetwork {
+    fn predict_probs(&self, board: &[f32; 9]) -> [f32; 9] {
+        let hidden = self.hidden_single(board);
+        let probs = self.softmax.infer(&self.output.infer(&[hidden])).remove(0);
+        probs.try_into().expect("a probability per cell")
+    }
+
+    fn train_on(&mut self, x: &[Vec<f32>], y: &[usize], config: &FitOptions) -> Result<TrainReport, NnError> {
+        let history = self.fit(x, y, config)?;
+        Ok(TrainReport { loss: history.loss().iter().map(|&loss| loss as f32).collect() })
+    }
+}
+
+impl HiddenLayer {
+    pub fn new(inputs: usize, outputs: usize) -> HiddenLayer {
+        HiddenLayer {
+            dense: Dense::new(inputs, outputs),
+            batch_norm: None,
+            activation: ActivationLayer::new(Activation::ReLU),
+            residual: false,
+        }
+    }
+
+    /// Z for inference: the dense output, batch normalized with the
+    /// running statistics if enabled.
+    pub fn pre_activation(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let z = self.dense.infer(input);
+        match &self.batch_norm {
+            Some(bn) => bn.infer(&z),
+            None => z,
+        }
+    }
+
+    /// A = g(Z), plus the input when the layer is residual.
+    fn activate(&self, z: &[Vec<f32>], input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let mut a = self.activation.infer(z);
+        if self.residual {
+            add_into(&mut a, input);
+        }
+        a
+    }
+}
+
+impl Layer for HiddenLayer {
+    fn forward(&mut self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let mut z = self.dense.forward(input);
+        if let Some(bn) = self.batch_norm.as_mut() {
+            z = bn.forward(&z);
+        }
+        let mut a = self.activation.forward(&z);
+        if self.residual {
+            add_into(&mut a, input);
+        }
+        a
+    }
+
+    fn backward(&mut self, grad_output: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let mut d_z = self.activation.backward(grad_output);
+        if let Some(bn) = self.batch_norm.as_mut() {
+            d_z = bn.backward(&d_z);
+        }
+        let mut d_input = self.dense.backward(&d_z);
+        if self.residual {
+            add_into(&mut d_input, grad_output);
+        }
+        d_input
+    }
+
+    fn infer(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        self.activate(&self.pre_activation(input), input)
+    }
+
+    fn update(&mut self, alpha: f32) {
+        self.dense.update(alpha);
+        if let Some(bn) = self.batch_norm.as_mut() {
+            bn.update(alpha);
+        }
+    }
+
+    fn set_training(&mut self, training: bool) {
+        if let Some(bn) = self.batch_norm.as_mut() {
+            bn.set_training(training);
+        }
+    }
+}
+
+impl ValueHead {
+    fn evaluate(&self, hidden: &[f32]) -> f32 {
+        let sum: f32 = self.w.iter().zip(hidden.iter()).map(|(w, h)| w * h).sum();
+        (sum + self.b).tanh()
+    }
+
+    fn forward(&mut self, hidden: &[Vec<f32>]) {
+        self.output = hidden.iter().map(|row| self.evaluate(row)).collect();
+        self.input = hidden.to_vec();
+    }
+
+    fn zero_grad(&mut self) {
+        self.d_w = vec![0.0; self.w.len()];
+        self.db = 0.0;
+    }
+
+    /// Add the head's gradients to `d_w`/`db`, zeroed by `zero_grad`, and its
+    /// contribution to `d_hidden`, the gradient w.r.t. the shared last
+    /// hidden layer.
+    fn backward(&mut self, d_hidden: &mut [Vec<f32>], targets: &[f32], inv_m: f32) {
+        for (i, (d_row, h_row)) in d_hidden.iter_mut().zip(self.input.iter()).enumerate() {
+            let out = self.output[i];
+            // d/dv of loss_weight * (tanh(v) - t)^2, averaged over the batch
+            let d_v = inv_m * self.loss_weight * 2.0 * (out - targets[i]) * (1.0 - out * out);
+            for (k, (d, h)) in d_row.iter_mut().zip(h_row.iter()).enumerate() {
+                *d += d_v * self.w[k];
+                self.d_w[k] += d_v * h;
+            }
+            self.db += d_v;
+        }
+    }
+}
+
+fn cross_entropy(preds: &[Vec<f32>], labels: &[usize]) -> f32 {
+    weighted_cross_entropy(preds, labels, &vec![1.0; labels.len()])
+}
+
+fn weighted_cross_entropy(preds: &[Vec<f32>], labels: &[usize], weights: &[f32]) -> f32 {
+    if preds.is_empty() {
+        return 0.0;
+    }
+    let total: f32 = preds
+        .iter()
+        .zip(labels.iter().zip(weights.iter()))
+        .map(|(row, (&label, &weight))| row.get(label).map_or(0.0, |&p| -weight * p.max(MIN_PROB).ln()))
+        .sum();
+    total / preds.len() as f32
+}
+
+fn add_into(target: &mut [Vec<f32>], other: &[Vec<f32>]) {
+    for (row, other_row) in target.iter_mut().zip(other.iter()) {
+        for (val, o) in row.iter_mut().zip(other_row.iter()) {
+            *val += o;
+        }
+    }
+}
+
+fn invalid_model(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+struct ModelReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl ModelReader<'_> {
+    fn take(&mut self, n: usize) -> std::io::Result<&[u8]> {
+        if self.pos + n > self.bytes.len() {
+            return Err(invalid_model("model file is truncated"));
+        }
+        self.pos += n;
+        Ok(&self.bytes[self.pos - n..self.pos])
+    }
+
+    fn u32(&mut self) -> std::io::Result<usize> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    }
+
+    fn f32s(&mut self, n: usize) -> std::io::Result<Vec<f32>> {
+        Ok(self
+            .take(n * 4)?
+            .chunks(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect())
     }
 }
 
+/// Index and value of the largest entry; ties go to the lower index.
+fn row_argmax(row: &[f32]) -> (usize, f32) {
+    let mut max_idx = 0;
+    let mut max_val = row[0];
+    for (j, &v) in row.iter().enumerate() {
+        if v > max_val {
+            max_val = v;
+            max_idx = j;
+        }
+    }
+    (max_idx, max_val)
+}
 
 /*use rand::Rng;
 
@@ -833,4 +1532,909 @@ impl HimNetwork {
             self.update_params(alpha);
         }
     }
-}*/
\ No newline at end of file
+}*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn small_network(rows: usize) -> HimNetwork {
+        let mut net = HimNetwork::new();
+        net.init_params();
+        let mut rng = rand::thread_rng();
+        net.x1 = (0..rows)
+            .map(|_| (0..9).map(|_| rng.gen_range(-1..=1) as f32).collect())
+            .collect();
+        net
+    }
+
+    #[test]
+    fn test_predictions_empty_before_forward() {
+        let net = HimNetwork::new();
+        assert!(net.predictions().is_empty());
+    }
+
+    #[test]
+    fn test_predictions_match_row_maximum() {
+        let mut net = small_network(16);
+        net.forward_propagation();
+        let predictions = net.predictions();
+        assert_eq!(predictions.len(), 16);
+        for (row, &(class, prob)) in net.probabilities().iter().zip(predictions.iter()) {
+            let max = row.iter().cloned().fold(f32::MIN, f32::max);
+            assert_eq!(row[class], max);
+            assert_eq!(prob, max);
+        }
+        let classes: Vec<usize> = predictions.iter().map(|p| p.0).collect();
+        assert_eq!(net.predict(net.probabilities()), classes);
+    }
+
+    #[test]
+    fn test_predict_external_matrix() {
+        let net = HimNetwork::new();
+        let output = vec![vec![0.1, 0.7, 0.2], vec![0.5, 0.5, 0.0]];
+        assert_eq!(net.predict(&output), vec![1, 0]);
+    }
+
+    // Just enough protobuf decoding to walk the exported file.
+    fn read_varint(buf: &[u8], pos: &mut usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = buf[*pos];
+            *pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    fn fields(buf: &[u8]) -> Vec<(u64, Result<u64, &[u8]>)> {
+        let mut pos = 0;
+        let mut out = Vec::new();
+        while pos < buf.len() {
+            let key = read_varint(buf, &mut pos);
+            if key & 7 == 0 {
+                out.push((key >> 3, Ok(read_varint(buf, &mut pos))));
+            } else if key & 7 == 5 {
+                let bits = u32::from_le_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]]);
+                out.push((key >> 3, Ok(bits as u64)));
+                pos += 4;
+            } else {
+                let len = read_varint(buf, &mut pos) as usize;
+                out.push((key >> 3, Err(&buf[pos..pos + len])));
+                pos += len;
+            }
+        }
+        out
+    }
+
+    fn bytes_of(fields: &[(u64, Result<u64, &[u8]>)], field: u64) -> Vec<Vec<u8>> {
+        fields
+            .iter()
+            .filter(|(f, _)| *f == field)
+            .filter_map(|(_, v)| v.err().map(|b| b.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_export_onnx_round_trips_weights() {
+        let mut net = HimNetwork::new();
+        net.init_params();
+        let path = std::env::temp_dir().join(format!("him_network_{}.onnx", std::process::id()));
+        net.export_onnx(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let model = fields(&bytes);
+        let graph = bytes_of(&model, 7).remove(0);
+        let graph = fields(&graph);
+        let ops: Vec<String> = bytes_of(&graph, 1)
+            .iter()
+            .map(|n| String::from_utf8(bytes_of(&fields(n), 4).remove(0)).unwrap())
+            .collect();
+        assert_eq!(ops.iter().filter(|op| *op == "Relu").count(), 3);
+        assert_eq!(ops.last().unwrap(), "Softmax");
+
+        let input = bytes_of(&graph, 11).remove(0);
+        assert_eq!(bytes_of(&fields(&input), 1)[0], b"board");
+        let output = bytes_of(&graph, 12).remove(0);
+        assert_eq!(bytes_of(&fields(&output), 1)[0], b"move_probs");
+
+        let initializers = bytes_of(&graph, 5);
+        assert_eq!(initializers.len(), 8);
+        for tensor in initializers {
+            let tensor = fields(&tensor);
+            let name = String::from_utf8(bytes_of(&tensor, 8).remove(0)).unwrap();
+            let dims: Vec<usize> = tensor
+                .iter()
+                .filter(|(f, _)| *f == 1)
+                .map(|(_, v)| *v.as_ref().unwrap() as usize)
+                .collect();
+            let data: Vec<f32> = bytes_of(&tensor, 9)[0]
+                .chunks(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect();
+            let l: usize = name[1..].parse().unwrap();
+            if name.starts_with('w') {
+                assert_eq!(dims, vec![net.dense(l).inputs(), net.dense(l).outputs()]);
+                for (node, row) in net.dense(l).w.iter().enumerate() {
+                    for (conn, &value) in row.iter().enumerate() {
+                        assert_eq!(data[conn * dims[1] + node], value);
+                    }
+                }
+            } else {
+                assert_eq!(dims, vec![net.dense(l).outputs()]);
+                assert_eq!(data, net.dense(l).b);
+            }
+        }
+    }
+
+    /// Run the exported graph on one board, for the ops `encode_mlp` writes.
+    fn run_onnx(bytes: &[u8], board: &[f32]) -> Vec<f32> {
+        let graph = bytes_of(&fields(bytes), 7).remove(0);
+        let graph = fields(&graph);
+        let floats = |raw: &[u8]| -> Vec<f32> {
+            raw.chunks(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect()
+        };
+        let mut values: std::collections::HashMap<String, Vec<f32>> = std::collections::HashMap::new();
+        let mut widths = std::collections::HashMap::new();
+        for tensor in bytes_of(&graph, 5) {
+            let tensor = fields(&tensor);
+            let name = String::from_utf8(bytes_of(&tensor, 8).remove(0)).unwrap();
+            let dims: Vec<usize> = tensor.iter().filter(|(f, _)| *f == 1).map(|(_, v)| v.unwrap() as usize).collect();
+            widths.insert(name.clone(), dims[dims.len() - 1]);
+            values.insert(name, floats(&bytes_of(&tensor, 9)[0]));
+        }
+        values.insert("board".to_string(), board.to_vec());
+        for node in bytes_of(&graph, 1) {
+            let node = fields(&node);
+            let text = |field| bytes_of(&node, field).into_iter().map(|b| String::from_utf8(b).unwrap());
+            let inputs: Vec<Vec<f32>> = text(1).map(|name| values[&name].clone()).collect();
+            let input_names: Vec<String> = text(1).collect();
+            let attribute = bytes_of(&node, 5).first().map(|a| match fields(a)[1].1 {
+                Ok(bits) => f32::from_bits(bits as u32),
+                Err(_) => panic!("float attributes only"),
+            });
+            let x = &inputs[0];
+            let out: Vec<f32> = match text(4).next().unwrap().as_str() {
+                "MatMul" => {
+                    let width = widths[&input_names[1]];
+                    (0..width).map(|j| x.iter().enumerate().map(|(i, v)| v * inputs[1][i * width + j]).sum()).collect()
+                }
+                "Add" => x.iter().zip(&inputs[1]).map(|(a, b)| a + b).collect(),
+                "Relu" => x.iter().map(|v| v.max(0.0)).collect(),
+                "LeakyRelu" => x.iter().map(|&v| if v > 0.0 { v } else { attribute.unwrap() * v }).collect(),
+                "Elu" => x.iter().map(|&v| if v > 0.0 { v } else { attribute.unwrap() * (v.exp() - 1.0) }).collect(),
+                "BatchNormalization" => (0..x.len())
+                    .map(|j| {
+                        let x_hat = (x[j] - inputs[3][j]) / (inputs[4][j] + attribute.unwrap()).sqrt();
+                        inputs[1][j] * x_hat + inputs[2][j]
+                    })
+                    .collect(),
+                "Softmax" => layers::softmax_rows(std::slice::from_ref(x)).remove(0),
+                op => panic!("unexpected op {}", op),
+            };
+            values.insert(text(2).next().unwrap(), out);
+        }
+        values.remove("move_probs").unwrap()
+    }
+
+    #[test]
+    fn test_export_onnx_keeps_activations_batch_norm_and_skips() {
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut StdRng::seed_from_u64(4));
+        scale_weights(&mut net, 0.3);
+        net.set_activation(1, Activation::LeakyReLU(0.1));
+        net.set_activation(2, Activation::ELU(0.5));
+        net.set_residual(true);
+        net.enable_batch_norm();
+        for (l, layer) in net.hidden.layers.iter_mut().enumerate() {
+            let bn = layer.batch_norm.as_mut().unwrap();
+            for (j, (mean, var)) in bn.running_mean.iter_mut().zip(bn.running_var.iter_mut()).enumerate() {
+                *mean = 0.01 * ((j + l) % 7) as f32;
+                *var = 0.5 + 0.1 * (j % 3) as f32;
+            }
+        }
+        let path = std::env::temp_dir().join(format!("him_network_layers_{}.onnx", std::process::id()));
+        net.export_onnx(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let board = [1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0];
+        let exported = run_onnx(&bytes, &board);
+        for (got, want) in exported.iter().zip(net.predict_probs(&board).iter()) {
+            assert!((got - want).abs() < 1e-5, "{} vs {}", got, want);
+        }
+    }
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    #[test]
+    fn test_load_numpy_npz_fixture() {
+        let mut net = HimNetwork::new();
+        net.load_numpy(&fixture("him_weights.npz"), false).unwrap();
+        assert!((net.dense(2).w[3][4] - 0.3 * (1.3f32 * 3.0 + 0.7 * 4.0 + 2.0).sin()).abs() < 1e-6);
+        net.x1 = vec![vec![1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0]];
+        net.forward_propagation();
+        let (class, prob) = net.predictions()[0];
+        assert_eq!(class, 2);
+        assert!((prob - 0.123_649_53).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_load_numpy_raw_round_trip() {
+        let mut source = HimNetwork::new();
+        source.init_params();
+        let mut raw = Vec::new();
+        for l in 1..=source.depth() {
+            for row in &source.dense(l).w {
+                raw.extend(row.iter().flat_map(|v| v.to_le_bytes()));
+            }
+            raw.extend(source.dense(l).b.iter().flat_map(|v| v.to_le_bytes()));
+        }
+        let path = std::env::temp_dir().join(format!("him_weights_{}.bin", std::process::id()));
+        std::fs::write(&path, &raw).unwrap();
+        let mut net = HimNetwork::new();
+        net.load_numpy(&path, false).unwrap();
+        for l in 1..=source.depth() {
+            assert_eq!(net.dense(l).w, source.dense(l).w);
+            assert_eq!(net.dense(l).b, source.dense(l).b);
+        }
+
+        std::fs::write(&path, &raw[4..]).unwrap();
+        assert!(matches!(net.load_numpy(&path, false), Err(NumpyError::Format(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_numpy_detects_transposed_weights() {
+        let mut source = HimNetwork::new();
+        source.load_numpy(&fixture("him_weights.npz"), false).unwrap();
+        let bytes = std::fs::read(fixture("him_weights.npz")).unwrap();
+        let mut arrays = numpy::read_npz(&bytes).unwrap();
+        arrays.get_mut("w1").unwrap().shape = vec![9, 81];
+
+        // Write the archive back with w1 flipped to (connections, nodes).
+        let path = std::env::temp_dir().join(format!("him_transposed_{}.npz", std::process::id()));
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (name, array) in &arrays {
+            let data: Vec<f32> = if name == "w1" {
+                let rows: Vec<Vec<f32>> = array.data.chunks(9).map(|r| r.to_vec()).collect();
+                source.transpose(rows).concat()
+            } else {
+                array.data.clone()
+            };
+            let shape: Vec<String> = array.shape.iter().map(|d| d.to_string()).collect();
+            let header = format!(
+                "{{'descr': '<f4', 'fortran_order': False, 'shape': ({},), }}\n",
+                shape.join(", ")
+            );
+            let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+            npy.extend((header.len() as u16).to_le_bytes());
+            npy.extend(header.as_bytes());
+            npy.extend(data.iter().flat_map(|v| v.to_le_bytes()));
+            writer.start_file(format!("{}.npy", name), Default::default()).unwrap();
+            std::io::Write::write_all(&mut writer, &npy).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut net = HimNetwork::new();
+        match net.load_numpy(&path, false) {
+            Err(NumpyError::Transposed { name, .. }) => assert_eq!(name, "w1"),
+            other => panic!("expected transposed error, got {:?}", other),
+        }
+        net.load_numpy(&path, true).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for l in 1..=source.depth() {
+            assert_eq!(net.dense(l).w, source.dense(l).w);
+        }
+    }
+
+    fn scale_weights(net: &mut HimNetwork, factor: f32) {
+        for l in 1..=net.depth() {
+            for node in net.dense_mut(l).w.iter_mut() {
+                for weight in node.iter_mut() {
+                    *weight *= factor;
+                }
+            }
+        }
+    }
+
+    fn gradient_check(eps: f32, configure: impl Fn(&mut HimNetwork)) {
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut StdRng::seed_from_u64(7));
+        // Smaller weights keep the ReLUs away from their kink so the finite
+        // differences stay meaningful at f32 precision.
+        scale_weights(&mut net, 0.3);
+        configure(&mut net);
+        net.x1 = vec![
+            vec![1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0],
+            vec![0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0, 0.0, -1.0],
+            vec![-1.0, 1.0, 1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0],
+        ];
+        let labels = vec![3, 5, 8];
+        net.forward_propagation();
+        net.backward_propagation(labels.clone());
+        for l in 1..=net.depth() {
+            for &(i, j) in &[(0, 0), (3, 5), (8, 7)] {
+                let original = net.dense(l).w[i][j];
+                net.dense_mut(l).w[i][j] = original + eps;
+                net.forward_propagation();
+                let plus = net.loss(&labels).unwrap();
+                net.dense_mut(l).w[i][j] = original - eps;
+                net.forward_propagation();
+                let minus = net.loss(&labels).unwrap();
+                net.dense_mut(l).w[i][j] = original;
+                let numeric = (plus - minus) / (2.0 * eps);
+                let analytic = net.dense(l).d_w[i][j];
+                assert!(
+                    (numeric - analytic).abs() <= 0.02 * analytic.abs() + 1e-3,
+                    "layer {} w[{}][{}]: numeric {} vs analytic {}",
+                    l, i, j, numeric, analytic
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_gradient_check() {
+        gradient_check(1e-2, |_| {});
+    }
+
+    #[test]
+    fn test_gradient_check_residual() {
+        gradient_check(1e-2, |net| net.set_residual(true));
+    }
+
+    #[test]
+    fn test_gradient_check_batch_norm() {
+        // Normalizing over three examples is sharply curved, so use a finer step.
+        gradient_check(1e-3, |net| net.enable_batch_norm());
+    }
+
+    #[test]
+    fn test_residual_changes_hidden_activations() {
+        let mut net = small_network(3);
+        let a1 = net.hidden.layers[0].infer(&net.x1);
+        let plain = net.hidden.layers[1].infer(&a1);
+        net.set_residual(true);
+        let expected: Vec<Vec<f32>> = plain
+            .iter()
+            .zip(a1.iter())
+            .map(|(p, prev)| p.iter().zip(prev.iter()).map(|(x, y)| x + y).collect())
+            .collect();
+        assert_eq!(net.hidden.layers[1].infer(&a1), expected);
+        // Layer 1 goes from 9 to 81 wide, so it never gets a skip connection.
+        assert!(!net.hidden.layers[0].residual);
+    }
+
+    fn zero_fraction_after_training(activation: Activation) -> f32 {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut rng);
+        for l in 1..4 {
+            net.set_activation(l, activation);
+        }
+        net.x1 = (0..32)
+            .map(|_| (0..9).map(|_| rng.gen_range(-1..=1) as f32).collect())
+            .collect();
+        let labels: Vec<usize> = (0..32).map(|_| rng.gen_range(0..9)).collect();
+        for _ in 0..5 {
+            net.forward_propagation();
+            net.backward_propagation(labels.clone());
+            net.update_params(0.05);
+        }
+        let mut input = net.x1.clone();
+        let (mut zeros, mut total) = (0, 0);
+        for layer in &net.hidden.layers {
+            input = layer.infer(&input);
+            zeros += input.iter().flatten().filter(|&&v| v == 0.0).count();
+            total += input.iter().flatten().count();
+        }
+        zeros as f32 / total as f32
+    }
+
+    #[test]
+    fn test_leaky_relu_has_fewer_dead_units() {
+        let relu = zero_fraction_after_training(Activation::ReLU);
+        let leaky = zero_fraction_after_training(Activation::LeakyReLU(0.01));
+        assert!(relu > 0.0);
+        assert!(leaky < relu);
+    }
+
+    #[test]
+    fn test_activation_derivatives_at_zero() {
+        assert_eq!(Activation::ReLU.derivative(0.0), 0.0);
+        assert_eq!(Activation::LeakyReLU(0.1).derivative(0.0), 0.1);
+        assert_eq!(Activation::ELU(0.5).derivative(0.0), 0.5);
+        assert_eq!(Activation::ELU(1.0).apply(-1.0), (-1.0f32).exp() - 1.0);
+    }
+
+    fn batch_norm_network() -> HimNetwork {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut rng);
+        net.enable_batch_norm();
+        net.x1 = (0..8)
+            .map(|_| (0..9).map(|_| rng.gen_range(-1..=1) as f32).collect())
+            .collect();
+        net
+    }
+
+    #[test]
+    fn test_batch_norm_gamma_gradient() {
+        let mut net = batch_norm_network();
+        let labels = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        net.forward_propagation();
+        net.backward_propagation(labels.clone());
+        let analytic = net.hidden.layers[1].batch_norm.as_ref().unwrap().d_gamma[4];
+        let eps = 1e-2;
+        let loss_with = |net: &mut HimNetwork, delta: f32| {
+            net.hidden.layers[1].batch_norm.as_mut().unwrap().gamma[4] += delta;
+            net.forward_propagation();
+            net.hidden.layers[1].batch_norm.as_mut().unwrap().gamma[4] -= delta;
+            net.loss(&labels).unwrap()
+        };
+        let numeric = (loss_with(&mut net, eps) - loss_with(&mut net, -eps)) / (2.0 * eps);
+        assert!((numeric - analytic).abs() <= 0.02 * analytic.abs() + 1e-3);
+    }
+
+    #[test]
+    fn test_batch_norm_normalizes_training_batch() {
+        let mut net = batch_norm_network();
+        let layer = &mut net.hidden.layers[0];
+        let z = layer.dense.infer(&net.x1);
+        let normalized = layer.batch_norm.as_mut().unwrap().forward(&z);
+        for j in 0..81 {
+            let mean: f32 = normalized.iter().map(|row| row[j]).sum::<f32>() / 8.0;
+            assert!(mean.abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_batch_norm_single_example_uses_running_stats() {
+        let mut net = batch_norm_network();
+        net.x1.truncate(1);
+        let before = net.hidden.layers[0].batch_norm.clone().unwrap();
+        net.forward_propagation();
+        let after = net.hidden.layers[0].batch_norm.as_ref().unwrap();
+        assert_eq!(after.running_mean, before.running_mean);
+        assert_eq!(after.running_var, before.running_var);
+        assert!(net.probabilities()[0].iter().all(|p| p.is_finite()));
+    }
+
+    #[test]
+    fn test_save_load_round_trip_with_batch_norm() {
+        let mut net = batch_norm_network();
+        net.set_activation(2, Activation::ELU(0.5));
+        net.set_residual(true);
+        for _ in 0..3 {
+            net.forward_propagation();
+            net.backward_propagation(vec![4; 8]);
+            net.update_params(0.1);
+        }
+        net.training = false;
+        net.forward_propagation();
+        let expected = net.probabilities().to_vec();
+
+        let path = std::env::temp_dir().join(format!("him_model_{}.bin", std::process::id()));
+        net.save(&path).unwrap();
+        let mut loaded = HimNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!loaded.training);
+        for (got, want) in loaded.hidden.layers.iter().zip(net.hidden.layers.iter()) {
+            assert_eq!(got.dense.w, want.dense.w);
+            assert_eq!(got.activation.activation, want.activation.activation);
+            assert_eq!(got.residual, want.residual);
+            let (got_bn, want_bn) = (got.batch_norm.as_ref().unwrap(), want.batch_norm.as_ref().unwrap());
+            assert_eq!(got_bn.running_mean, want_bn.running_mean);
+        }
+        assert_eq!(loaded.output.w, net.output.w);
+        loaded.x1 = net.x1.clone();
+        loaded.forward_propagation();
+        assert_eq!(loaded.probabilities(), expected);
+    }
+
+    #[test]
+    fn test_save_load_value_head() {
+        let mut net = small_network(2);
+        net.enable_value_head(0.25);
+        let path = std::env::temp_dir().join(format!("him_value_{}.bin", std::process::id()));
+        net.save(&path).unwrap();
+        let loaded = HimNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let board = [1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0];
+        assert_eq!(loaded.value_head.as_ref().unwrap().loss_weight, 0.25);
+        assert_eq!(loaded.predict_value(&board), net.predict_value(&board));
+    }
+
+    #[test]
+    fn test_value_head_trains_jointly() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut rng);
+        scale_weights(&mut net, 0.2);
+        net.enable_value_head(0.5);
+        net.x1 = (0..12)
+            .map(|_| (0..9).map(|_| rng.gen_range(-1..=1) as f32).collect())
+            .collect();
+        let labels: Vec<usize> = (0..12).map(|i| i % 9).collect();
+        let values: Vec<f32> = (0..12).map(|i| if i % 3 == 0 { 1.0 } else { -1.0 }).collect();
+
+        net.forward_propagation();
+        let policy_before = net.loss(&labels).unwrap();
+        let value_before = net.value_loss(&values);
+        let w1_before = net.dense(1).w.clone();
+        for _ in 0..30 {
+            net.forward_propagation();
+            net.backward_propagation_with_values(labels.clone(), &values);
+            net.update_params(0.05);
+        }
+        net.forward_propagation();
+        assert_eq!(net.value_head.as_ref().unwrap().output.len(), 12);
+        assert!(net.loss(&labels).unwrap() < policy_before);
+        assert!(net.value_loss(&values) < value_before);
+        assert_ne!(net.dense(1).w, w1_before);
+
+        let board = [0.0; 9];
+        let value = net.predict_value(&board).unwrap();
+        assert!((-1.0..=1.0).contains(&value));
+        assert!(net.predict_move(&board) < 9);
+    }
+
+    #[test]
+    fn test_value_head_gradient_reaches_shared_layers() {
+        let mut net = small_network(4);
+        net.forward_propagation();
+        net.backward_propagation(vec![1, 2, 3, 4]);
+        let policy_only = net.dense(3).d_w.clone();
+        net.enable_value_head(1.0);
+        net.forward_propagation();
+        net.backward_propagation_with_values(vec![1, 2, 3, 4], &[1.0, -1.0, 0.0, 1.0]);
+        assert_ne!(net.dense(3).d_w, policy_only);
+        // The output layer only sees the policy head.
+        net.backward_propagation(vec![1, 2, 3, 4]);
+        assert_eq!(net.dense(3).d_w, policy_only);
+        assert!(HimNetwork::new().predict_value(&[0.0; 9]).is_none());
+    }
+
+    #[test]
+    fn test_seeded_value_head_is_reproducible() {
+        let seeded = |seed| {
+            let mut net = HimNetwork::new();
+            let mut rng = StdRng::seed_from_u64(seed);
+            net.init_params_with_rng(&mut rng);
+            net.enable_value_head_with_rng(0.5, &mut rng);
+            net.value_head.unwrap()
+        };
+        assert_eq!(seeded(3), seeded(3));
+        assert_ne!(seeded(3).w, seeded(4).w);
+    }
+
+    #[test]
+    fn test_policy_step_does_not_reapply_value_gradients() {
+        let labels = vec![1, 2, 3, 4];
+        let mut net = small_network(4);
+        net.enable_value_head(1.0);
+        net.forward_propagation();
+        net.backward_propagation_with_values(labels.clone(), &[1.0, -1.0, 0.0, 1.0]);
+        net.update_params(0.1);
+        let path = std::env::temp_dir().join(format!("him_value_step_{}.bin", std::process::id()));
+        net.save(&path).unwrap();
+        let mut fresh = HimNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        fresh.x1 = net.x1.clone();
+
+        // The second, policy-only step moves the value head of neither.
+        let head = net.value_head.clone().unwrap();
+        for nn in [&mut net, &mut fresh] {
+            nn.forward_propagation();
+            nn.backward_propagation(labels.clone());
+            nn.update_params(0.1);
+        }
+        let (stepped, fresh_head) = (net.value_head.unwrap(), fresh.value_head.unwrap());
+        assert_eq!((&stepped.w, stepped.b), (&head.w, head.b));
+        assert_eq!((&stepped.w, stepped.b), (&fresh_head.w, fresh_head.b));
+    }
+
+    fn assert_close(got: f32, want: f32, what: &str) {
+        assert!(
+            (got - want).abs() <= 1e-4 * want.abs() + 1e-6,
+            "{}: got {} want {}",
+            what, got, want
+        );
+    }
+
+    // Reference numbers recorded from the network before it was split into
+    // layers: three training steps on a seeded network, plain and with every
+    // optional feature switched on.
+    fn check_against_reference(full: bool, d_w_sums: [[f32; 4]; 3], db_sums: [[f32; 4]; 3], loss: f32, probs: [f32; 9]) {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut rng);
+        scale_weights(&mut net, 0.3);
+        if full {
+            net.set_residual(true);
+            net.set_activation(2, Activation::LeakyReLU(0.1));
+            net.set_activation(3, Activation::ELU(0.5));
+            net.enable_batch_norm();
+            net.enable_value_head(0.5);
+            let head = net.value_head.as_mut().unwrap();
+            for w in head.w.iter_mut() {
+                *w = rng.gen_range(0.0..1.0) - 0.5;
+            }
+        }
+        net.x1 = (0..6)
+            .map(|_| (0..9).map(|_| rng.gen_range(-1..=1) as f32).collect())
+            .collect();
+        let labels = vec![0, 4, 8, 2, 2, 7];
+        let values = [1.0, -1.0, 0.0, 1.0, -1.0, 0.0];
+        for step in 0..3 {
+            net.forward_propagation();
+            if full {
+                net.backward_propagation_with_values(labels.clone(), &values);
+            } else {
+                net.backward_propagation(labels.clone());
+            }
+            for l in 1..=4 {
+                let d_w: f32 = net.dense(l).d_w.iter().flatten().map(|v| v.abs()).sum();
+                assert_close(d_w, d_w_sums[step][l - 1], &format!("step {} |dW{}|", step, l));
+                // With batch norm the hidden biases cancel out and their
+                // gradients are rounding noise.
+                if !full || l == 4 {
+                    let db: f32 = net.dense(l).db.iter().map(|v| v.abs()).sum();
+                    assert_close(db, db_sums[step][l - 1], &format!("step {} |db{}|", step, l));
+                }
+            }
+            net.update_params(0.1);
+        }
+        net.forward_propagation();
+        assert_close(net.loss(&labels).unwrap(), loss, "loss");
+        if full {
+            assert_close(net.value_loss(&values), 0.7323956, "value loss");
+        }
+        for (got, want) in net.probabilities()[5].iter().zip(probs.iter()) {
+            assert_close(*got, *want, "probability");
+        }
+        net.training = false;
+        assert_eq!(net.predict_move(&[1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0]), 2);
+    }
+
+    #[test]
+    fn test_matches_reference_plain() {
+        check_against_reference(
+            false,
+            [
+                [3.1543198, 9.863381, 16.827555, 11.153844],
+                [2.955845, 9.35361, 15.614908, 10.104216],
+                [2.9004502, 9.067988, 14.577546, 9.22361],
+            ],
+            [
+                [0.4590115, 0.7269143, 1.1303965, 0.9252999],
+                [0.3982612, 0.6586832, 1.0318594, 0.82981145],
+                [0.3710595, 0.63274264, 0.9552931, 0.7441486],
+            ],
+            1.9869785,
+            [0.12607652, 0.08113786, 0.21533413, 0.09697621, 0.13010195, 0.07667643, 0.077130206, 0.06614428, 0.13042246],
+        );
+    }
+
+    #[test]
+    fn test_matches_reference_with_all_features() {
+        check_against_reference(
+            true,
+            [
+                [140.63065, 264.869, 170.2278, 114.981674],
+                [19.968946, 37.662903, 28.050844, 13.627935],
+                [73.59883, 159.37238, 122.04455, 4.9553876],
+            ],
+            [[0.0, 0.0, 0.0, 1.0307009], [0.0, 0.0, 0.0, 0.115694396], [0.0, 0.0, 0.0, 0.043397725]],
+            0.06661257,
+            [0.0058984095, 0.0032198478, 0.01983634, 0.01350945, 0.008900969, 0.0027924972, 0.0049069948, 0.9334798, 0.0074557336],
+        );
+    }
+
+    fn fit_data(rows: usize) -> (Vec<Vec<f32>>, Vec<usize>) {
+        let mut rng = StdRng::seed_from_u64(13);
+        let x: Vec<Vec<f32>> = (0..rows)
+            .map(|_| (0..9).map(|_| rng.gen_range(-1..=1) as f32).collect())
+            .collect();
+        // Learnable target: the first empty cell.
+        let y = x.iter().map(|row| row.iter().position(|&v| v == 0.0).unwrap_or(0)).collect();
+        (x, y)
+    }
+
+    #[test]
+    fn test_fit_timings_cover_wall_time() {
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut StdRng::seed_from_u64(2));
+        scale_weights(&mut net, 0.3);
+        let (x, y) = fit_data(256);
+        let options = FitOptions { epochs: 4, batch_size: 32, learning_rate: 0.1, shuffle: true, timing: true };
+        let history = net.fit(&x, &y, &options).unwrap();
+        assert_eq!(history.epochs.len(), 4);
+        assert!(history.epochs[3].loss < history.epochs[0].loss);
+        assert!(history.epochs.iter().all(|epoch| epoch.duration > Duration::ZERO));
+        let timings = &history.timings;
+        assert!(timings.forward > Duration::ZERO && timings.evaluation > Duration::ZERO);
+        assert!(timings.phases() <= timings.total);
+        assert!(timings.phases().as_secs_f64() >= 0.8 * timings.total.as_secs_f64());
+        assert!(timings.to_string().contains('%'));
+    }
+
+    #[test]
+    fn test_fit_without_timing_records_nothing() {
+        let mut net = HimNetwork::new();
+        net.init_params();
+        let (x, y) = fit_data(16);
+        let options = FitOptions { epochs: 1, timing: false, ..FitOptions::default() };
+        let history = net.fit(&x, &y, &options).unwrap();
+        assert_eq!(history.timings, TrainingTimings::default());
+        assert_eq!(history.epochs.len(), 1);
+        // Epochs are timed either way.
+        assert!(history.epochs[0].duration > Duration::ZERO);
+        assert_eq!(net.x1, x);
+    }
+
+    #[test]
+    fn test_fit_rejects_mismatched_examples() {
+        let mut net = HimNetwork::new();
+        net.init_params();
+        let (x, y) = fit_data(4);
+        let options = FitOptions { epochs: 1, ..FitOptions::default() };
+        assert_eq!(net.fit(&[], &[], &options), Err(NnError::NoExamples));
+        assert_eq!(net.fit(&x, &y[..3], &options), Err(NnError::ExampleCountMismatch { inputs: 4, targets: 3 }));
+        let short = vec![vec![0.0; 8]; 4];
+        assert_eq!(net.fit(&short, &y, &options), Err(NnError::InputSizeMismatch { expected: 9, found: 8 }));
+        assert_eq!(
+            net.fit_weighted(&x, &y, &[1.0; 3], &options),
+            Err(NnError::WeightCountMismatch { examples: 4, weights: 3 })
+        );
+    }
+
+    #[test]
+    fn test_loss_hand_computed() {
+        let net = HimNetwork::new();
+        // -ln(0.25) for the first row; the second clamps p = 0 to 1e-12.
+        let preds = vec![vec![0.5, 0.25, 0.25], vec![0.0, 1.0, 0.0]];
+        let expected = (-(0.25f32).ln() - (1e-12f32).ln()) / 2.0;
+        assert!((net.compute_loss(preds, vec![1, 0]) - expected).abs() < 1e-5);
+        assert!((expected - 14.508657).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_loss_reads_last_forward_pass() {
+        // All-zero weights give a uniform output, so the loss is ln(9).
+        let mut net = HimNetwork::new();
+        assert_eq!(net.loss(&[0]), Err(LossError::NoForwardPass));
+        net.x1 = vec![vec![0.0; 9]; 2];
+        net.forward_propagation();
+        assert!((net.loss(&[0, 8]).unwrap() - 9f32.ln()).abs() < 1e-6);
+        assert_eq!(net.loss(&[0]), Err(LossError::LabelCount { batch: 2, labels: 1 }));
+        let explicit = net.compute_loss(net.probabilities().to_vec(), vec![0, 8]);
+        assert_eq!(net.loss(&[0, 8]).unwrap(), explicit);
+    }
+
+    #[test]
+    fn test_weighted_loss_scales_examples_and_gradients() {
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut StdRng::seed_from_u64(17));
+        scale_weights(&mut net, 0.3);
+        let (x, y) = fit_data(8);
+        net.x1 = x;
+        net.forward_propagation();
+        let plain = net.loss(&y).unwrap();
+        assert_eq!(net.loss_weighted(&y, &[1.0; 8]).unwrap(), plain);
+        // Winner's moves at 1.0, loser's at 0.3, draws at 0.5.
+        let weights = [1.0, 0.3, 0.5, 1.0, 0.3, 0.5, 1.0, 0.3];
+        let per_example: Vec<f32> = (0..8)
+            .map(|i| net.compute_loss(vec![net.probabilities()[i].clone()], vec![y[i]]))
+            .collect();
+        let expected = per_example.iter().zip(weights).map(|(loss, w)| loss * w).sum::<f32>() / 8.0;
+        let weighted = net.loss_weighted(&y, &weights).unwrap();
+        assert!((weighted - expected).abs() < 1e-5, "{} vs {}", weighted, expected);
+        assert!((weighted - plain).abs() > 1e-3);
+        assert_eq!(net.loss_weighted(&y, &[1.0]), Err(LossError::WeightCount { labels: 8, weights: 1 }));
+
+        net.backward_propagation(y.clone());
+        let unweighted: Vec<Vec<Vec<f32>>> = (1..=net.depth()).map(|l| net.dense(l).d_w.clone()).collect();
+        net.backward_propagation_weighted(y.clone(), &[1.0; 8]);
+        for l in 1..=net.depth() {
+            assert_eq!(net.dense(l).d_w, unweighted[l - 1], "layer {}", l);
+        }
+        // Doubling every weight doubles every gradient.
+        net.backward_propagation_weighted(y.clone(), &[2.0; 8]);
+        for l in 1..=net.depth() {
+            for (doubled, single) in net.dense(l).d_w.iter().flatten().zip(unweighted[l - 1].iter().flatten()) {
+                assert!((doubled - 2.0 * single).abs() < 1e-5, "layer {}: {} vs {}", l, doubled, single);
+            }
+        }
+        net.backward_propagation_weighted(y.clone(), &weights);
+        assert_ne!(net.output.d_w, unweighted[net.depth() - 1]);
+    }
+
+    #[test]
+    fn test_step_single_lowers_loss_for_its_example() {
+        let mut net = HimNetwork::new();
+        net.init_params_with_rng(&mut StdRng::seed_from_u64(21));
+        scale_weights(&mut net, 0.3);
+        let board = [1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0];
+        let x1_rows = net.x1.len();
+        let mut previous = f32::MAX;
+        for _ in 0..20 {
+            let loss = net.step_single(&board, 8, 0.05);
+            assert!(loss < previous, "{} !< {}", loss, previous);
+            previous = loss;
+        }
+        assert_eq!(net.x1.len(), x1_rows);
+        assert_eq!(net.predict_move(&board), 8);
+    }
+
+    #[test]
+    fn test_predict_legal_move_skips_occupied_cells() {
+        // Zero weights: the output biases alone decide, and cell 4 wins.
+        let mut net = HimNetwork::new();
+        net.output.b = vec![0.0, 0.1, 0.0, 0.0, 1.0, 0.0, 0.0, 0.5, 0.0];
+        let board = [1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+        assert_eq!(net.predict_move(&board), 4);
+        assert_eq!(net.predict_legal_move(&board), Some(7));
+        assert_eq!(net.predict_legal_move(&[1.0; 9]), None);
+    }
+
+    #[test]
+    fn test_sanity_check_overfits_ten_examples() {
+        let (x, y) = fit_data(10);
+        let mut net = HimNetwork::new();
+        let report = net.sanity_check(&x, &y, 500, 0.05).unwrap();
+        assert!(report.reached, "{:?}", report);
+        assert!(report.final_loss <= 0.05);
+        assert_eq!(report.accuracy, 1.0);
+        // Fixed seed: the same batch always takes the same number of epochs.
+        assert_eq!(HimNetwork::new().sanity_check(&x, &y, 500, 0.05), Ok(report));
+        let short = net.sanity_check(&x, &y, 1, 0.0).unwrap();
+        assert!(!short.reached);
+        assert_eq!(short.epochs, 1);
+        assert_eq!(net.sanity_check(&[], &[], 500, 0.05), Err(NnError::NoExamples));
+        assert_eq!(
+            net.sanity_check(&x, &y[..9], 500, 0.05),
+            Err(NnError::ExampleCountMismatch { inputs: 10, targets: 9 })
+        );
+    }
+
+    #[test]
+    fn test_activations_for_match_architecture() {
+        let mut net = small_network(4);
+        net.enable_batch_norm();
+        let path = std::env::temp_dir().join(format!("him_activations_{}.bin", std::process::id()));
+        net.save(&path).unwrap();
+        let loaded = HimNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let board = [1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0];
+        let activations = loaded.activations_for(&board);
+        let lengths: Vec<usize> = activations.iter().map(|a| a.len()).collect();
+        assert_eq!(lengths, vec![9, 81, 81, 81, 9]);
+        assert_eq!(activations[0], board.to_vec());
+        assert_eq!(row_argmax(&activations[4]).0, loaded.predict_move(&board));
+        assert!(loaded.x1.is_empty());
+    }
+
+    #[test]
+    fn test_dead_unit_report() {
+        let mut net = small_network(4);
+        for bias in net.dense_mut(1).b.iter_mut() {
+            *bias = -100.0;
+        }
+        let report = net.dead_unit_report(&net.x1);
+        assert_eq!(report.len(), 3);
+        assert_eq!(report[0], 1.0);
+        assert!(report.iter().all(|f| (0.0..=1.0).contains(f)));
+        assert_eq!(net.dead_unit_report(&[]), vec![0.0; 3]);
+    }
+}
@@ -1,12 +1,192 @@
-use csv::ReaderBuilder;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::ops::Index;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
-#[derive(Clone)]
+use csv::{ReaderBuilder, StringRecord, StringRecordsIntoIter};
+use flate2::read::MultiGzDecoder;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::ai::LINES;
+use crate::games_binary::{self, BinaryError};
+use crate::games_json::{self, JsonError};
+use crate::meta::{DatasetMeta, META_TAG};
+use crate::output::MoveRecord;
+use crate::recorder;
+use crate::solver::{Solver, Value};
+use crate::stats::DatasetStats;
+
+/// Files written by `CsvRecorder` start with a line of `SCHEMA_TAG` and the
+/// schema version, then `CSV_HEADER`.
+pub const SCHEMA_TAG: &str = "tictac-games";
+pub const SCHEMA_VERSION: u32 = 2;
+/// One row per move: the game id, the time the recording run started, the
+/// move number, the two player names, the nine cells, the winner ("draw"
+/// for a draw) on the last row of a game only, and the player to move
+/// next, empty once the game is over.
+pub const CSV_HEADER: &str =
+    "game_id,timestamp,move_no,player1,player2,c0,c1,c2,c3,c4,c5,c6,c7,c8,winner,player_to_move";
+const SCHEMA_FIELDS: usize = 16;
+/// Earlier recorder rows: the schema without the player to move, and
+/// before that without the player names too ("ai" and "ai_2"). Legacy
+/// rows have only the cells and the winner.
+const NAMED_FIELDS: usize = 15;
+const UNNAMED_FIELDS: usize = 13;
+/// A row of `RUN_TAG`, the run id, its seed and its timestamp starts the
+/// games of one generator run, see `CsvRecorder::start_run`; one with an
+/// empty id ends it.
+pub const RUN_TAG: &str = "tictac-run";
+
+/// First bytes of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The eight symmetries of the 3x3 board as cell permutations:
+/// `SYMMETRIES[k][i]` is where cell `i` (0 top-left, reading order) ends up.
+/// The identity, clockwise rotations by 90, 180 and 270 degrees, then the
+/// mirror images left-right, top-bottom, across the main diagonal and
+/// across the other diagonal.
+pub const SYMMETRIES: [[usize; 9]; 8] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8],
+    [2, 5, 8, 1, 4, 7, 0, 3, 6],
+    [8, 7, 6, 5, 4, 3, 2, 1, 0],
+    [6, 3, 0, 7, 4, 1, 8, 5, 2],
+    [2, 1, 0, 5, 4, 3, 8, 7, 6],
+    [6, 7, 8, 3, 4, 5, 0, 1, 2],
+    [0, 3, 6, 1, 4, 7, 2, 5, 8],
+    [8, 5, 2, 7, 4, 1, 6, 3, 0],
+];
+
+/// `state` under symmetry `k` of `SYMMETRIES`. A move to cell `i` becomes
+/// a move to `SYMMETRIES[k][i]`.
+pub fn transform(state: &[i8; 9], k: usize) -> [i8; 9] {
+    let mut out = [0; 9];
+    for (from, &to) in SYMMETRIES[k].iter().enumerate() {
+        out[to] = state[from];
+    }
+    out
+}
+
+/// One of the eight `SYMMETRIES`, as returned by `canonical_form`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SymOp(usize);
+
+impl SymOp {
+    pub const IDENTITY: SymOp = SymOp(0);
+
+    /// Symmetry `k` of `SYMMETRIES`, if there is one.
+    pub fn new(k: usize) -> Option<SymOp> {
+        (k < SYMMETRIES.len()).then_some(SymOp(k))
+    }
+
+    /// All eight, in `SYMMETRIES` order.
+    pub fn all() -> impl Iterator<Item = SymOp> {
+        (0..SYMMETRIES.len()).map(SymOp)
+    }
+
+    /// The position in `SYMMETRIES`.
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    pub fn apply(self, state: &[i8; 9]) -> [i8; 9] {
+        transform(state, self.0)
+    }
+
+    /// Where a move to `cell` goes on the transformed board.
+    pub fn map_cell(self, cell: usize) -> usize {
+        SYMMETRIES[self.0][cell]
+    }
+
+    /// The symmetry undoing this one: the quarter turns swap, the rest
+    /// undo themselves.
+    pub fn inverse(self) -> SymOp {
+        SymOp::all()
+            .find(|inverse| (0..9).all(|cell| inverse.map_cell(self.map_cell(cell)) == cell))
+            .expect("symmetries form a group")
+    }
+
+    /// The cell of the original board that `map_cell` sends to `cell`, e.g.
+    /// to play a move picked on the canonical form.
+    pub fn unmap_cell(self, cell: usize) -> usize {
+        self.inverse().map_cell(cell)
+    }
+}
+
+/// The lexicographically smallest of the symmetric images of `state`, and
+/// the symmetry giving it (the first in `SYMMETRIES` order when the board
+/// is symmetric itself). Boards with the same canonical form are the same
+/// position up to symmetry.
+pub fn canonical_form(state: &[i8; 9]) -> ([i8; 9], SymOp) {
+    SymOp::all()
+        .map(|op| (op.apply(state), op))
+        .min_by_key(|&(image, _)| image)
+        .expect("eight symmetries")
+}
+
+/// The smallest of the symmetric images of `state`, with `cell` moved
+/// along. When several symmetries give that image (the board is symmetric
+/// itself) the cell is the smallest of its images, so equivalent moves
+/// share a label.
+pub fn canonical(state: &[i8; 9], cell: usize) -> ([i8; 9], usize) {
+    let (board, _) = canonical_form(state);
+    let cell = SymOp::all()
+        .filter(|op| op.apply(state) == board)
+        .map(|op| op.map_cell(cell))
+        .min()
+        .expect("one at least");
+    (board, cell)
+}
+
+/// Which side of a `GameData` a move belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerId {
+    Player1,
+    Player2,
+}
+
+impl PlayerId {
+    /// The owner of a mark: 1 for player1, -1 for player2.
+    pub fn of_mark(value: i8) -> PlayerId {
+        if value == 1 {
+            PlayerId::Player1
+        } else {
+            PlayerId::Player2
+        }
+    }
+    /// The value of its marks in a snapshot.
+    pub fn mark(self) -> i8 {
+        match self {
+            PlayerId::Player1 => 1,
+            PlayerId::Player2 => -1,
+        }
+    }
+    pub fn other(self) -> PlayerId {
+        match self {
+            PlayerId::Player1 => PlayerId::Player2,
+            PlayerId::Player2 => PlayerId::Player1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct GameData {
     pub winner: String,
     pub player1: String,
     pub player2: String,
     pub state_of_cells_list: Vec<[i8;9]>,
     pub periodic_state_of_cells : [i8;9],
+    /// Id the recorder gave the game; None for legacy rows.
+    pub id: Option<String>,
+    /// Unix time (seconds) the recording run started; None for legacy rows.
+    pub timestamp: Option<u64>,
+    /// The generator run the game was recorded in, if the file marks it.
+    pub run_id: Option<String>,
 }
 impl GameData {
     pub fn new(player1: String, player2: String) -> GameData {
@@ -16,27 +196,161 @@ impl GameData {
             player2,
             state_of_cells_list : Vec::new(),
             periodic_state_of_cells: [0;9],
+            id: None,
+            timestamp: None,
+            run_id: None,
+        }
+    }
+    /// Snapshot `index`. Past the end it is None, or the last snapshot
+    /// when `clamp` is set; a game without snapshots has none either way.
+    pub fn get_round_state(&self, index: usize, clamp: bool) -> Option<[i8; 9]> {
+        match self.state_of_cells_list.get(index) {
+            Some(state) => Some(*state),
+            None if clamp => self.state_of_cells_list.last().copied(),
+            None => None,
+        }
+    }
+    /// Number of moves recorded, one per snapshot.
+    pub fn move_count(&self) -> usize {
+        self.state_of_cells_list.len()
+    }
+    /// Whoever owns the only mark of the first snapshot: player1 for 1
+    /// ("ai" in table.csv), player2 for -1. Empty for a game without moves.
+    pub fn first_player(&self) -> &str {
+        match self.state_of_cells_list.first() {
+            Some(state) if state.contains(&1) => &self.player1,
+            Some(_) => &self.player2,
+            None => "",
+        }
+    }
+    /// Who made the move that led to snapshot `round`; moves alternate
+    /// from `first_player`.
+    pub fn mover(&self, round: usize) -> &str {
+        let first = self.first_player();
+        if round.is_multiple_of(2) || first.is_empty() {
+            first
+        } else if first == self.player1 {
+            &self.player2
+        } else {
+            &self.player1
+        }
+    }
+    /// The side `winner` names: None for a draw or a name that isn't
+    /// playing, e.g. an empty one.
+    pub fn winner_id(&self) -> Option<PlayerId> {
+        if self.winner == self.player1 {
+            Some(PlayerId::Player1)
+        } else if self.winner == self.player2 {
+            Some(PlayerId::Player2)
+        } else {
+            None
+        }
+    }
+    /// The moves that lead from the empty board through every snapshot:
+    /// each must add exactly one mark, 1 for player1 and -1 for player2,
+    /// the players taking turns. Errors name the first snapshot (0 for the
+    /// first one) no move can reach from the one before.
+    pub fn moves(&self) -> Result<Vec<MoveRecord>, StateDiffError> {
+        let mut moves: Vec<MoveRecord> = Vec::with_capacity(self.state_of_cells_list.len());
+        let mut before = [0i8; 9];
+        for (transition, after) in self.state_of_cells_list.iter().enumerate() {
+            let changed: Vec<usize> = (0..9).filter(|&i| before[i] != after[i]).collect();
+            if let Some(&cell) = changed.iter().find(|&&i| before[i] != 0) {
+                return Err(if after[cell] == 0 {
+                    StateDiffError::Cleared { transition, cell }
+                } else {
+                    StateDiffError::OwnerChanged { transition, cell }
+                });
+            }
+            let cell = match changed[..] {
+                [cell] => cell,
+                [] => return Err(StateDiffError::NoChange { transition }),
+                _ => return Err(StateDiffError::SeveralChanged { transition, cells: changed }),
+            };
+            let player = if after[cell] == 1 { &self.player1 } else { &self.player2 };
+            if moves.last().is_some_and(|last| last.player_name == *player) {
+                return Err(StateDiffError::OutOfTurn { transition });
+            }
+            moves.push(MoveRecord {
+                move_number: transition as u32 + 1,
+                player_name: player.clone(),
+                cell_index: cell as i32,
+            });
+            before = *after;
+        }
+        Ok(moves)
+    }
+    /// Every impossible snapshot: one player more than a mark ahead of the
+    /// other, fewer marks for a player than in the snapshot before, or a
+    /// full line for both players. Unlike `moves` this allows several marks
+    /// per snapshot, so gaps in a recording pass.
+    pub fn validate(&self) -> Result<(), Vec<StateViolation>> {
+        let mut violations = Vec::new();
+        let mut before = (0, 0);
+        for (snapshot, state) in self.state_of_cells_list.iter().enumerate() {
+            let count = |value: i8| state.iter().filter(|&&cell| cell == value).count();
+            let counts = (count(1), count(-1));
+            if counts.0.abs_diff(counts.1) > 1 {
+                violations.push(StateViolation::ExtraPiece {
+                    snapshot,
+                    player1: counts.0,
+                    player2: counts.1,
+                });
+            }
+            for (now, then, player) in [(counts.0, before.0, &self.player1), (counts.1, before.1, &self.player2)] {
+                if now < then {
+                    violations.push(StateViolation::VanishedPiece {
+                        snapshot,
+                        player: player.clone(),
+                    });
+                }
+            }
+            let has_line = |value: i8| LINES.iter().any(|line| line.iter().all(|&i| state[i] == value));
+            if has_line(1) && has_line(-1) {
+                violations.push(StateViolation::BothWon { snapshot });
+            }
+            before = counts;
         }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+    /// The game with every snapshot under symmetry `k` of `SYMMETRIES`;
+    /// players, winner and id stay the same.
+    pub fn transformed(&self, k: usize) -> GameData {
+        let mut game = self.clone();
+        for state in game.state_of_cells_list.iter_mut() {
+            *state = transform(state, k);
+        }
+        game.periodic_state_of_cells = transform(&self.periodic_state_of_cells, k);
+        game
     }
-    pub fn get_round_State (&self, index: usize) -> [i8;9] {
-        if index >= self.state_of_cells_list.len() {
-            return self.state_of_cells_list[self.state_of_cells_list.len()-1];
+    /// The game under each of the eight board symmetries, the game itself
+    /// first. Copies identical to an earlier one, e.g. the mirror image of
+    /// a game played along the middle column, are left out.
+    pub fn symmetric_copies(&self) -> Vec<GameData> {
+        let mut copies: Vec<GameData> = Vec::with_capacity(SYMMETRIES.len());
+        for k in 0..SYMMETRIES.len() {
+            let copy = self.transformed(k);
+            if !copies.iter().any(|seen| seen.state_of_cells_list == copy.state_of_cells_list) {
+                copies.push(copy);
+            }
         }
-        self.state_of_cells_list[index]
+        copies
     }
     pub fn print_game(&self) {
         println!("Winner: {}", self.winner);
         println!("Player 1: {}", self.player1);
         println!("Player 2: {}", self.player2);
         println!("---------------------------------");
-        let mut row = 0;
-        for state in self.state_of_cells_list.iter(){
+        for (row, state) in self.state_of_cells_list.iter().enumerate(){
             print!("{} | => : ", row);
             for cell in state.iter(){
                 print!("{} ", cell);
             }
             println!();
-            row += 1;
         }
     }
 }
@@ -44,23 +358,319 @@ impl GameData {
 pub struct GamesData {
     pub game_data: Vec<GameData>,
     pub csv_file: String,
-
+    /// What the games were generated with, when `csv_file` says so (see
+    /// `meta`).
+    pub meta: Option<DatasetMeta>,
 }
 impl GamesData {
     pub fn new(csv_file: String) -> GamesData {
         GamesData {
             csv_file,
             game_data: Vec::new(),
+            meta: None,
         }
     }
+    /// Write every game to a JSON archive at `path`, see `games_json`.
+    pub fn save_json(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        games_json::write_games(path.as_ref(), &self.game_data)
+    }
+    /// The games of the JSON archive at `path`, which `csv_file` then names.
+    pub fn load_json(path: impl AsRef<Path>) -> Result<GamesData, JsonError> {
+        Ok(GamesData {
+            game_data: games_json::read_games(path.as_ref())?,
+            csv_file: path.as_ref().to_string_lossy().into_owned(),
+            meta: None,
+        })
+    }
+    /// Counts to check a dataset before training on it.
+    pub fn stats(&self) -> DatasetStats {
+        DatasetStats::from_games(&self.game_data)
+    }
+    /// Write every game in the binary format of `games_binary`.
+    pub fn save_binary(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        games_binary::write_games(path.as_ref(), &self.game_data)
+    }
+    /// The games of the binary dataset at `path`, which `csv_file` then
+    /// names.
+    pub fn load_binary(path: impl AsRef<Path>) -> Result<GamesData, BinaryError> {
+        Ok(GamesData {
+            game_data: games_binary::read_games(path.as_ref())?,
+            csv_file: path.as_ref().to_string_lossy().into_owned(),
+            meta: None,
+        })
+    }
     pub fn add_game(&mut self, game_data: GameData) {
         self.game_data.push(game_data);
     }
+    /// A copy of game `index`; panics when there is no such game. Prefer
+    /// `get` or indexing, which borrow.
     pub fn get_game(&self, index: usize) -> GameData {
-        self.game_data[index].clone()
+        self[index].clone()
+    }
+    pub fn get(&self, index: usize) -> Option<&GameData> {
+        self.game_data.get(index)
+    }
+    pub fn len(&self) -> usize {
+        self.game_data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.game_data.is_empty()
+    }
+    /// The games in file order.
+    pub fn iter(&self) -> std::slice::Iter<'_, GameData> {
+        self.game_data.iter()
+    }
+    /// The games for which `pred` holds, in order. `csv_file` and `meta`
+    /// still describe the file they were read from.
+    pub fn filter(&self, pred: impl Fn(&GameData) -> bool) -> GamesData {
+        GamesData {
+            game_data: self.game_data.iter().filter(|game| pred(game)).cloned().collect(),
+            csv_file: self.csv_file.clone(),
+            meta: self.meta.clone(),
+        }
+    }
+    /// The games `name` won; "draw" gives the draws.
+    pub fn by_winner(&self, name: &str) -> GamesData {
+        self.filter(|game| game.winner == name)
+    }
+    /// `GameData::validate` run on every game.
+    pub fn validate_all(&self) -> ValidationReport {
+        ValidationReport {
+            games: self.game_data.len(),
+            invalid: self
+                .game_data
+                .iter()
+                .enumerate()
+                .filter_map(|(index, game)| game.validate().err().map(|violations| (index, violations)))
+                .collect(),
+        }
+    }
+    /// The games of at least `n` moves.
+    pub fn min_length(&self, n: usize) -> GamesData {
+        self.filter(|game| game.move_count() >= n)
+    }
+    /// The games someone won.
+    pub fn decided_only(&self) -> GamesData {
+        self.filter(|game| !game.winner.is_empty() && game.winner != "draw")
+    }
+    /// Shuffle the games with `seed` and put `test_fraction` of them
+    /// (rounded) in the second half, the rest in the first, each half in
+    /// file order. Whole games go one way or the other, so no position of
+    /// a test game is trained on. The same seed gives the same split.
+    pub fn split(&self, test_fraction: f32, seed: u64) -> Result<(GamesData, GamesData), FractionError> {
+        if !(test_fraction > 0.0 && test_fraction < 1.0) {
+            return Err(FractionError(test_fraction));
+        }
+        let mut order: Vec<usize> = (0..self.game_data.len()).collect();
+        order.shuffle(&mut StdRng::seed_from_u64(seed));
+        let test_len = (self.game_data.len() as f32 * test_fraction).round() as usize;
+        let mut in_test = vec![false; self.game_data.len()];
+        for &i in &order[..test_len] {
+            in_test[i] = true;
+        }
+        let train = self.filter_indexed(|i| !in_test[i]);
+        let test = self.filter_indexed(|i| in_test[i]);
+        Ok((train, test))
+    }
+    fn filter_indexed(&self, keep: impl Fn(usize) -> bool) -> GamesData {
+        GamesData {
+            game_data: (0..self.game_data.len())
+                .filter(|&i| keep(i))
+                .map(|i| self.game_data[i].clone())
+                .collect(),
+            csv_file: self.csv_file.clone(),
+            meta: self.meta.clone(),
+        }
+    }
+    /// Every game under each of the eight board symmetries, for eight
+    /// times the training examples, see `GameData::symmetric_copies`.
+    pub fn augment(&self) -> GamesData {
+        let mut augmented = GamesData::new(self.csv_file.clone());
+        augmented.meta = self.meta.clone();
+        for game in self {
+            for copy in game.symmetric_copies() {
+                augmented.add_game(copy);
+            }
+        }
+        augmented
+    }
+    /// Features and labels for `HimNetwork::fit`, see `training_set`.
+    pub fn to_training_set(&self) -> (Vec<Vec<f32>>, Vec<usize>) {
+        let set = self.training_set();
+        (set.x, set.y)
+    }
+    /// One example per move: the board before it, starting from the empty
+    /// board, from the side of the player about to move (signs flipped
+    /// when the -1 player moves), labelled with the cell that player took.
+    /// The last snapshot of a game has no move after it. Transitions that
+    /// aren't a single new mark are skipped and counted.
+    pub fn training_set(&self) -> TrainingSet {
+        self.training_set_capped(usize::MAX)
+    }
+    /// `training_set` keeping only the first `max_copies` examples of any
+    /// board, whatever their labels, so common openings don't swamp the
+    /// rest. The examples left out are counted in `capped`.
+    pub fn training_set_capped(&self, max_copies: usize) -> TrainingSet {
+        capped_set(self, max_copies, None)
+    }
+    /// `training_set_capped` with a weight per example, by how the game
+    /// ended for the mover (see `OutcomeWeights`), in `weights`.
+    pub fn training_set_weighted(&self, max_copies: usize, weights: &OutcomeWeights) -> TrainingSet {
+        capped_set(self, max_copies, Some(weights))
+    }
+    /// `training_set_capped` over the games `GameData::validate` accepts;
+    /// the others are counted in `invalid_games`.
+    pub fn training_set_valid(&self, max_copies: usize) -> TrainingSet {
+        let valid: Vec<&GameData> = self.iter().filter(|game| game.validate().is_ok()).collect();
+        let mut set = capped_set(valid.iter().copied(), max_copies, None);
+        set.invalid_games = self.len() - valid.len();
+        set
+    }
+    /// Write the training examples to `path` as a CSV file of numbers only,
+    /// for `numpy.loadtxt(path, delimiter=",", skiprows=1)`: a header, then
+    /// one row per example with the board as in `training_set`, the cell
+    /// taken, the outcome for the mover (1 won, -1 lost, 0 otherwise), the
+    /// index of the game in this dataset and the move number. Returns the
+    /// number of rows.
+    pub fn export_flat(&self, path: impl AsRef<Path>, opts: &FlatExportOptions) -> std::io::Result<usize> {
+        let mut writer = std::io::BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", FLAT_HEADER)?;
+        let mut rows = 0;
+        let mut error = None;
+        for (game_id, game) in self.iter().enumerate() {
+            if !opts.keep_draws && game.winner == "draw" {
+                continue;
+            }
+            let copies;
+            let games = if opts.augment {
+                copies = game.symmetric_copies();
+                &copies[..]
+            } else {
+                std::slice::from_ref(game)
+            };
+            for_each_example(games, |game, record, board| {
+                let (mover, opponent) = if record.player_name == game.player1 {
+                    (&game.player1, &game.player2)
+                } else {
+                    (&game.player2, &game.player1)
+                };
+                let winner_code = if game.winner == *mover {
+                    1
+                } else if game.winner == *opponent {
+                    -1
+                } else {
+                    0
+                };
+                let cells: Vec<String> = board.iter().map(i8::to_string).collect();
+                let written = writeln!(
+                    writer,
+                    "{},{},{},{},{}",
+                    cells.join(","),
+                    record.cell_index,
+                    winner_code,
+                    game_id,
+                    record.move_number
+                );
+                match written {
+                    Ok(()) => rows += 1,
+                    Err(e) => {
+                        error.get_or_insert(e);
+                    }
+                }
+            });
+        }
+        if let Some(e) = error {
+            return Err(e);
+        }
+        writer.flush()?;
+        Ok(rows)
+    }
+    /// Every snapshot with how the game ended for the player to move in
+    /// it, for a value model: 1 if they went on to win, -1 if they lost, 0
+    /// for a draw. Boards are from that player's side, as in
+    /// `training_set`. Games without a winner or draw, or without a known
+    /// first player, are left out and counted.
+    pub fn outcome_labels(&self) -> OutcomeSet {
+        let mut set = OutcomeSet::default();
+        for game in self {
+            let winner = game.winner_id();
+            if (winner.is_none() && game.winner != "draw") || game.first_player().is_empty() {
+                set.skipped_games += 1;
+                continue;
+            }
+            for (snapshot, state) in game.state_of_cells_list.iter().enumerate() {
+                // Snapshot `snapshot` is the board before move `snapshot + 1`.
+                let to_move = if game.mover(snapshot + 1) == game.player1 {
+                    PlayerId::Player1
+                } else {
+                    PlayerId::Player2
+                };
+                set.states.push(state.map(|value| value * to_move.mark()));
+                set.outcomes.push(match winner {
+                    None => 0,
+                    Some(winner) if winner == to_move => 1,
+                    Some(_) => -1,
+                });
+            }
+        }
+        set
+    }
+    /// Every example of `training_set` with the move a perfect player
+    /// would make instead of the one recorded (see `Solver::solve`), so the
+    /// labels don't teach the recorded players' mistakes. Boards on which
+    /// the game was already over are left out and counted.
+    pub fn annotate_optimal(&self) -> OptimalSet {
+        let mut set = OptimalSet::default();
+        let mut solver = Solver::new();
+        for (game_index, game) in self.iter().enumerate() {
+            set.skipped += for_each_example(std::slice::from_ref(game), |_, record, board| {
+                match solver.solve(&board) {
+                    Some(solution) => set.examples.push(OptimalExample {
+                        game: game_index,
+                        move_no: record.move_number as u8,
+                        board,
+                        played: record.cell_index as usize,
+                        best: solution.best_move,
+                        value: solution.value,
+                    }),
+                    None => set.terminal += 1,
+                }
+            });
+        }
+        set
+    }
+    /// Every distinct board of the training set with the cell most often
+    /// played from it (the lowest cell on a tie) and how many times it
+    /// occurs, in order of first appearance. The counts add up to the
+    /// number of examples.
+    pub fn dedup_states(&self) -> Vec<([i8; 9], usize, u32)> {
+        self.dedup_states_with(false)
+    }
+    /// `dedup_states`, optionally counting boards that are symmetries of
+    /// each other as one (see `canonical`).
+    pub fn dedup_states_with(&self, canonical: bool) -> Vec<([i8; 9], usize, u32)> {
+        let mut index: HashMap<[i8; 9], usize> = HashMap::new();
+        let mut labels: Vec<([i8; 9], [u32; 9])> = Vec::new();
+        for_each_example(self, |_, record, board| {
+            let cell = record.cell_index as usize;
+            let (board, cell) = if canonical { self::canonical(&board, cell) } else { (board, cell) };
+            let i = *index.entry(board).or_insert_with(|| {
+                labels.push((board, [0; 9]));
+                labels.len() - 1
+            });
+            labels[i].1[cell] += 1;
+        });
+        labels
+            .into_iter()
+            .map(|(board, counts)| {
+                // max_by_key keeps the last maximum, so walk the cells backwards.
+                let label = (0..9).rev().max_by_key(|&cell| counts[cell]).unwrap_or(0);
+                (board, label, counts.iter().sum())
+            })
+            .collect()
     }
     pub fn print_game(&self, index: usize) {
-        let game = self.get_game(index);
+        let game = &self[index];
         println!("Winner: {}", game.winner);
         println!("Player 1: {}", game.player1);
         println!("Player 2: {}", game.player2);
@@ -72,53 +682,2270 @@ impl GamesData {
             println!();
         }
     }
-    // the glory code please don't touch it
-    pub fn read_data(&mut self) {
-        let reader = ReaderBuilder::new()
-            .has_headers(false)
-            .from_path(&self.csv_file);
-        match reader {
-            Ok(mut reader) => {
-                let mut temp_game_data = GameData::new("ai".to_string(),"ai_2".to_string());
-                for result in reader.records(){
-                    match result {
-                        Ok(record) =>{
-                            let mut index = 0;
-                            for item in record.iter(){
-                                match item{
-                                    "-1"|"0"|"1" => {
-                                        temp_game_data.periodic_state_of_cells[index] = item.parse::<i8>().unwrap();
-                                        index += 1;
-                                    }
-                                    "" => {
-                                        if index >= 8 {
-                                            temp_game_data.state_of_cells_list.push(temp_game_data.periodic_state_of_cells.clone());
-                                        }
-                                        index = 0;
-                                    }
-                                    "ai"|"ai_2"|"draw" => {
-                                        temp_game_data.winner.push_str(item);
-                                        temp_game_data.state_of_cells_list.push(temp_game_data.periodic_state_of_cells);
-                                        index = 0;
-                                        self.game_data.push(temp_game_data.clone());
-                                        //if true the game ends
-                                        temp_game_data = GameData::new("ai".to_string(),"ai_2".to_string());
-                                    }
-                                    _ => {
-                                        println!("item: {}", item);
-                                    }
-                                }
-                            }
-                        }
-                        Err(error) => {
-                            println!("Error reading record: {}", error);
-                        }
+    /// Load every game of `csv_file`, plain or gzip-compressed: schema rows
+    /// and the older recorder and legacy rows alike, even mixed in one
+    /// file. A row with stray whitespace or without its trailing comma is
+    /// repaired, and a row that isn't a valid board skipped; both are
+    /// listed in the report. A missing file, one that can't be read or
+    /// decompressed, a record the CSV reader can't parse or a schema
+    /// version this build doesn't know is an error. Metadata at the top of
+    /// the file ends up in `meta`.
+    pub fn read_data(&mut self) -> Result<ReadReport, DataError> {
+        self.read_data_with(false)
+    }
+    /// `read_data`, optionally `strict`: then a row that would be skipped
+    /// is an error instead, e.g. to check a dataset in CI. The games read
+    /// before it are kept.
+    pub fn read_data_with(&mut self, strict: bool) -> Result<ReadReport, DataError> {
+        let mut reader = GamesData::iter_from_csv(&self.csv_file).strict(strict);
+        let mut report = ReadReport::default();
+        for game in &mut reader {
+            self.game_data.push(game?);
+            report.games += 1;
+        }
+        report.repaired = std::mem::take(&mut reader.repaired);
+        report.skipped = std::mem::take(&mut reader.skipped);
+        if reader.meta.is_some() {
+            self.meta = reader.meta.take();
+        }
+        Ok(report)
+    }
+    /// The games of the CSV file at `path`, read as `read_data` does but
+    /// `chunk_games` games at a time (at least one). After each chunk, and
+    /// once more at the end of the file, `progress` gets a summary; it
+    /// returns false to stop reading, and the games read until then are
+    /// returned.
+    pub fn read_in_chunks(
+        path: impl AsRef<Path>,
+        chunk_games: usize,
+        progress: impl FnMut(ChunkSummary) -> bool,
+    ) -> Result<(GamesData, ReadReport), DataError> {
+        GamesData::read_in_chunks_filtered(path, chunk_games, |_| true, progress)
+    }
+    /// `read_in_chunks`, keeping only the games of each chunk that `keep`
+    /// accepts, so a large file can be searched without holding all of it.
+    pub fn read_in_chunks_filtered(
+        path: impl AsRef<Path>,
+        chunk_games: usize,
+        keep: impl Fn(&GameData) -> bool,
+        mut progress: impl FnMut(ChunkSummary) -> bool,
+    ) -> Result<(GamesData, ReadReport), DataError> {
+        let total_bytes = std::fs::metadata(path.as_ref()).ok().map(|metadata| metadata.len());
+        let mut data = GamesData::new(path.as_ref().to_string_lossy().into_owned());
+        let mut reader = GamesData::iter_from_csv(path);
+        let mut summary = ChunkSummary { total_bytes, ..ChunkSummary::default() };
+        let mut chunk = Vec::new();
+        loop {
+            let game = reader.next().transpose()?;
+            let done = game.is_none();
+            chunk.extend(game);
+            if !done && chunk.len() < chunk_games.max(1) {
+                continue;
+            }
+            summary.games += chunk.len();
+            chunk.retain(|game| keep(game));
+            summary.kept += chunk.len();
+            data.game_data.append(&mut chunk);
+            summary.bytes_read = reader.bytes_read();
+            summary.done = done;
+            let go_on = progress(summary.clone());
+            summary.chunk += 1;
+            if done || !go_on {
+                break;
+            }
+        }
+        data.meta = reader.meta.take();
+        let report = ReadReport {
+            games: data.len(),
+            repaired: std::mem::take(&mut reader.repaired),
+            skipped: std::mem::take(&mut reader.skipped),
+        };
+        Ok((data, report))
+    }
+    /// The games of every file of `paths`, in order. A file may be a CSV
+    /// file of any schema (read as `read_data` does), a JSON archive or a
+    /// binary dataset; its first bytes tell which. With `dedup`, a game
+    /// with the same snapshots and winner as one kept before is left out.
+    /// Files whose metadata gives different board sizes can't be merged.
+    /// The merged games have no `csv_file` or `meta`.
+    pub fn merge(paths: &[PathBuf], dedup: bool) -> Result<(GamesData, MergeReport), DataError> {
+        let mut merged = GamesData::new(String::new());
+        let mut report = MergeReport::default();
+        let mut seen: HashSet<(Vec<[i8; 9]>, String)> = HashSet::new();
+        let mut board_size = None;
+        for path in paths {
+            let LoadedDataset { games, skipped, meta } = load_dataset(path)?;
+            if let Some(meta) = meta {
+                let expected = *board_size.get_or_insert(meta.board_size);
+                if meta.board_size != expected {
+                    return Err(DataError::IncompatibleBoard {
+                        path: path.to_string_lossy().into_owned(),
+                        board_size: meta.board_size,
+                        expected,
+                    });
+                }
+            }
+            let mut source = SourceCount {
+                path: path.to_string_lossy().into_owned(),
+                skipped,
+                ..SourceCount::default()
+            };
+            for game in games {
+                if dedup && !seen.insert((game.state_of_cells_list.clone(), game.winner.clone())) {
+                    source.duplicates += 1;
+                } else {
+                    source.games += 1;
+                    merged.add_game(game);
+                }
+            }
+            report.sources.push(source);
+        }
+        Ok((merged, report))
+    }
+    /// Add the games of the CSV file at `path`, except those of runs some
+    /// game here already comes from, so appending the output of a run
+    /// twice, e.g. after a rerun, adds its games once. Games without a run
+    /// are always added. The file's metadata is taken when there is none
+    /// yet.
+    pub fn append_from(&mut self, path: impl AsRef<Path>) -> Result<AppendReport, DataError> {
+        let known: HashSet<String> = self.iter().filter_map(|game| game.run_id.clone()).collect();
+        let mut reader = GamesData::iter_from_csv(path);
+        let mut report = AppendReport::default();
+        for game in &mut reader {
+            let game = game?;
+            if game.run_id.as_ref().is_some_and(|run| known.contains(run)) {
+                report.duplicates += 1;
+            } else {
+                self.add_game(game);
+                report.games += 1;
+            }
+        }
+        report.skipped = std::mem::take(&mut reader.skipped);
+        if self.meta.is_none() {
+            self.meta = reader.meta.take();
+        }
+        Ok(report)
+    }
+    /// Write every game to a CSV file at `path` in the current schema, see
+    /// `recorder::save_csv`.
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        recorder::save_csv(path, self)
+    }
+    /// The games of the CSV file at `path`, read as `read_data` does but
+    /// one at a time, so a pass over a large file holds one game in memory.
+    /// Skipped rows are collected in the reader; an error ends it.
+    pub fn iter_from_csv(path: impl AsRef<Path>) -> GameReader {
+        let path = path.as_ref().to_string_lossy().into_owned();
+        let bytes = Arc::new(AtomicU64::new(0));
+        let (records, error) = match open_csv(&path, Arc::clone(&bytes)) {
+            Ok(file) => {
+                let reader = ReaderBuilder::new().has_headers(false).flexible(true).from_reader(file);
+                (Some(reader.into_records()), None)
+            }
+            Err(error) => (None, Some(error)),
+        };
+        GameReader {
+            path,
+            records,
+            error,
+            bytes,
+            game: GameData::new("ai".to_string(), "ai_2".to_string()),
+            run_id: None,
+            line: 0,
+            game_line: 0,
+            meta: None,
+            strict: false,
+            repaired: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl Index<usize> for GamesData {
+    type Output = GameData;
+
+    fn index(&self, index: usize) -> &GameData {
+        &self.game_data[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a GamesData {
+    type Item = &'a GameData;
+    type IntoIter = std::slice::Iter<'a, GameData>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Streams the games of a CSV file, see `GamesData::iter_from_csv`.
+pub struct GameReader {
+    path: String,
+    records: Option<StringRecordsIntoIter<Box<dyn Read + Send>>>,
+    /// Why the file couldn't be opened, returned by the first `next`.
+    error: Option<DataError>,
+    /// Bytes taken from the file so far.
+    bytes: Arc<AtomicU64>,
+    /// The game whose rows are being read.
+    game: GameData,
+    /// The run the rows being read belong to.
+    run_id: Option<String>,
+    /// Line of the last row read.
+    line: u64,
+    /// Line of the last row of `game`.
+    game_line: u64,
+    /// The file's metadata, once its `META_TAG` line is read.
+    meta: Option<DatasetMeta>,
+    /// Whether a skipped row ends the reading with an error.
+    strict: bool,
+    repaired: Vec<RepairedRow>,
+    skipped: Vec<SkippedRow>,
+}
+
+impl GameReader {
+    /// With `strict`, the first row that would be skipped is returned as a
+    /// `DataError::Corrupt` and ends the reading.
+    pub fn strict(mut self, strict: bool) -> GameReader {
+        self.strict = strict;
+        self
+    }
+    /// The metadata read so far, if the file has any.
+    pub fn meta(&self) -> Option<&DatasetMeta> {
+        self.meta.as_ref()
+    }
+    /// Rows repaired so far.
+    pub fn repaired(&self) -> &[RepairedRow] {
+        &self.repaired
+    }
+    /// Rows left out so far.
+    pub fn skipped(&self) -> &[SkippedRow] {
+        &self.skipped
+    }
+    /// Bytes of the file read so far, compressed ones for a gzip file. The
+    /// CSV reader buffers, so this runs ahead of the rows read.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+    /// Undo what crashes and hand edits commonly do to a row that is
+    /// otherwise fine: whitespace around the fields and, on a legacy row,
+    /// a lost trailing comma. Returns false for a blank row, which holds
+    /// nothing to read.
+    fn repair(&mut self, record: &mut StringRecord) -> bool {
+        let mut repairs = Vec::new();
+        if record.iter().any(|field| field.trim() != field) {
+            record.trim();
+            repairs.push("whitespace around fields");
+        }
+        let blank = record.iter().all(str::is_empty);
+        if blank {
+            repairs.push("blank row");
+        } else if record.len() == 9 {
+            repairs.push("no trailing comma");
+        }
+        if !repairs.is_empty() {
+            self.repaired.push(RepairedRow {
+                line: self.line,
+                repair: repairs.join(", "),
+            });
+        }
+        !blank
+    }
+    /// One row into the current game; the game once its last row is in.
+    fn read_row(&mut self, record: &StringRecord) -> Result<Option<GameData>, DataError> {
+        let read = match record.len() {
+            _ if record.get(0) == Some(SCHEMA_TAG) => {
+                let version = record.get(1).unwrap_or("");
+                if version != SCHEMA_VERSION.to_string() {
+                    return Err(DataError::UnsupportedVersion {
+                        path: self.path.clone(),
+                        version: version.to_string(),
+                    });
+                }
+                return Ok(None);
+            }
+            _ if record.get(0) == Some("game_id") => return Ok(None),
+            _ if record.get(0).is_some_and(|field| field.starts_with('#')) => {
+                // The reader split the comment at its commas.
+                let line = record.iter().collect::<Vec<&str>>().join(",");
+                if line == META_TAG {
+                    self.meta = Some(DatasetMeta::default());
+                    return Ok(None);
+                }
+                match self.meta.as_mut() {
+                    Some(meta) => meta.read_line(&line).map(|()| None),
+                    None => return Ok(None),
+                }
+            }
+            _ if record.get(0) == Some(RUN_TAG) => {
+                // An unfinished game before the row isn't part of the run.
+                self.drop_unfinished("ai", "ai_2");
+                self.run_id = record.get(1).filter(|id| !id.is_empty()).map(str::to_string);
+                return Ok(None);
+            }
+            SCHEMA_FIELDS | NAMED_FIELDS | UNNAMED_FIELDS => self.read_extended_row(record),
+            _ => self.read_legacy_row(record),
+        };
+        read.or_else(|reason| {
+            self.skipped.push(SkippedRow { line: self.line, reason });
+            Ok(None)
+        })
+    }
+    /// A row of nine cells and a last field that is empty or, on the
+    /// last move, the winner; without the trailing comma the row is a
+    /// move that doesn't end the game. The players are "ai" and "ai_2" but
+    /// the winner may be any name.
+    fn read_legacy_row(&mut self, record: &StringRecord) -> Result<Option<GameData>, String> {
+        if !matches!(record.len(), 9 | 10) {
+            return Err(format!("expected 10 fields, found {}", record.len()));
+        }
+        let state = parse_cells(record.iter().take(9))?;
+        let winner = record.get(9).unwrap_or("");
+        // A board that takes back a mark starts another game: the one read
+        // so far lost its last rows.
+        if let Some(last) = self.game.state_of_cells_list.last() {
+            if (0..9).any(|cell| last[cell] != 0 && last[cell] != state[cell]) {
+                self.drop_unfinished("ai", "ai_2");
+            }
+        }
+        self.game_line = self.line;
+        let game = &mut self.game;
+        game.periodic_state_of_cells = state;
+        game.state_of_cells_list.push(state);
+        Ok(self.finish(winner))
+    }
+    /// One schema or older recorder row into the current game. Games are
+    /// told apart by id and move number: a first move or a row from
+    /// another game skips the unfinished one.
+    fn read_extended_row(&mut self, record: &StringRecord) -> Result<Option<GameData>, String> {
+        let (player1, player2, cells) = match record.len() {
+            UNNAMED_FIELDS => ("ai", "ai_2", 3),
+            _ => (&record[3], &record[4], 5),
+        };
+        if player1.is_empty() || player2.is_empty() || player1 == player2 {
+            return Err(format!("players {:?} and {:?}", player1, player2));
+        }
+        let state = parse_cells(record.iter().skip(cells).take(9))?;
+        let winner = &record[cells + 9];
+        if !matches!(winner, "" | "draw") && winner != player1 && winner != player2 {
+            return Err(format!("{:?} isn't playing", winner));
+        }
+        let to_move = match record.get(SCHEMA_FIELDS - 1) {
+            None | Some("") => None,
+            Some(name) if name == player1 => Some(PlayerId::Player1),
+            Some(name) if name == player2 => Some(PlayerId::Player2),
+            Some(name) => return Err(format!("{:?} isn't playing", name)),
+        };
+        let move_number = record[2]
+            .parse::<usize>()
+            .map_err(|_| format!("move number {:?}", &record[2]))?;
+        let marks = state.iter().filter(|&&value| value != 0).count();
+        if marks != move_number {
+            return Err(format!("move {} shows {} marks", move_number, marks));
+        }
+        if move_number == 1
+            || self.game.id.as_deref().is_some_and(|id| id != &record[0])
+            || self.game.player1 != player1
+            || self.game.player2 != player2
+        {
+            self.drop_unfinished(player1, player2);
+        }
+        self.game_line = self.line;
+        let game = &mut self.game;
+        if move_number != game.state_of_cells_list.len() + 1 {
+            return Err(format!("move {} follows move {}", move_number, game.state_of_cells_list.len()));
+        }
+        // The player to move can't be the one who just did.
+        let before = game.state_of_cells_list.last().copied().unwrap_or([0; 9]);
+        if let Some(cell) = (0..9).find(|&i| before[i] == 0 && state[i] != 0) {
+            if to_move == Some(PlayerId::of_mark(state[cell])) {
+                return Err(format!("{:?} moves twice in a row", &record[SCHEMA_FIELDS - 1]));
+            }
+        }
+        game.id = Some(record[0].to_string());
+        game.timestamp = record[1].parse().ok();
+        game.periodic_state_of_cells = state;
+        game.state_of_cells_list.push(state);
+        Ok(self.finish(winner))
+    }
+    /// The current game, if `winner` ends it. A game whose first
+    /// snapshot holds several marks lost its first rows, or took them from
+    /// another game, so it is skipped instead.
+    fn finish(&mut self, winner: &str) -> Option<GameData> {
+        if winner.is_empty() {
+            return None;
+        }
+        let mut game = self.reset_game("ai", "ai_2");
+        let marks = game.state_of_cells_list[0].iter().filter(|&&value| value != 0).count();
+        if marks > 1 {
+            self.skipped.push(SkippedRow {
+                line: self.line,
+                reason: format!("the game ending here starts with {} marks", marks),
+            });
+            return None;
+        }
+        game.winner = winner.to_string();
+        game.run_id = self.run_id.clone();
+        Some(game)
+    }
+    /// `reset_game`, skipping the game read so far if it has moves: it
+    /// can't have a winner, or `finish` would have taken it.
+    fn drop_unfinished(&mut self, player1: &str, player2: &str) {
+        let moves = self.game.state_of_cells_list.len();
+        if moves > 0 {
+            let reason = match &self.game.id {
+                Some(id) => format!("game {} ends after {} moves without a winner", id, moves),
+                None => format!("the game of {} moves ending here has no winner", moves),
+            };
+            self.skipped.push(SkippedRow { line: self.game_line, reason });
+        }
+        self.reset_game(player1, player2);
+    }
+    /// Start a new game between `player1` and `player2` from the empty
+    /// board, and hand back the one read so far.
+    fn reset_game(&mut self, player1: &str, player2: &str) -> GameData {
+        std::mem::replace(&mut self.game, GameData::new(player1.to_string(), player2.to_string()))
+    }
+    /// In strict mode, the first row skipped after the first `before` as
+    /// an error.
+    fn check_strict(&self, before: usize) -> Result<(), DataError> {
+        match self.skipped.get(before) {
+            Some(row) if self.strict => Err(DataError::Corrupt {
+                path: self.path.clone(),
+                line: row.line,
+                reason: row.reason.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Iterator for GameReader {
+    type Item = Result<GameData, DataError>;
+
+    fn next(&mut self) -> Option<Result<GameData, DataError>> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        while let Some(result) = self.records.as_mut()?.next() {
+            let skipped = self.skipped.len();
+            let read = result
+                .map_err(|error| DataError::from_csv(&self.path, error))
+                .and_then(|mut record| {
+                    self.line = record.position().map_or(self.line, |position| position.line());
+                    if self.repair(&mut record) {
+                        self.read_row(&record)
+                    } else {
+                        Ok(None)
                     }
+                })
+                .and_then(|game| self.check_strict(skipped).map(|()| game));
+            match read {
+                Ok(Some(game)) => return Some(Ok(game)),
+                Ok(None) => {}
+                Err(error) => {
+                    self.records = None;
+                    return Some(Err(error));
                 }
             }
-            Err(error) => {
-                println!("Error reading file: {}", error);
+        }
+        // A file cut off in the middle of a game, e.g. by a crash.
+        let moves = self.game.state_of_cells_list.len();
+        if moves > 0 {
+            self.skipped.push(SkippedRow {
+                line: self.line,
+                reason: format!("the file ends in a game of {} moves without a winner", moves),
+            });
+            self.reset_game("ai", "ai_2");
+            if let Err(error) = self.check_strict(self.skipped.len() - 1) {
+                self.records = None;
+                return Some(Err(error));
+            }
+        }
+        self.records = None;
+        None
+    }
+}
+
+/// The file at `path`, decompressed on the fly when it is gzip: named
+/// `.gz` or starting with the gzip magic bytes. Gzip files may hold several
+/// members one after the other, as `CsvRecorder` appends them. `bytes`
+/// counts what is read from the file.
+fn open_csv(path: &str, bytes: Arc<AtomicU64>) -> Result<Box<dyn Read + Send>, DataError> {
+    let io_error = |e: std::io::Error| match e.kind() {
+        std::io::ErrorKind::NotFound => DataError::NotFound(path.to_string()),
+        _ => DataError::Io(path.to_string(), e),
+    };
+    let mut file = File::open(path).map_err(io_error)?;
+    let mut start = [0; 2];
+    let read = file.read(&mut start).map_err(io_error)?;
+    file.rewind().map_err(io_error)?;
+    let file = CountingReader { inner: file, bytes };
+    if path.ends_with(".gz") || start[..read] == GZIP_MAGIC {
+        Ok(Box::new(MultiGzDecoder::new(BufReader::new(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Adds the bytes read through it to `bytes`.
+struct CountingReader<R> {
+    inner: R,
+    bytes: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// The examples of `games`, see `GamesData::training_set_capped`.
+fn capped_set<'a>(
+    games: impl IntoIterator<Item = &'a GameData>,
+    max_copies: usize,
+    weights: Option<&OutcomeWeights>,
+) -> TrainingSet {
+    let mut set = TrainingSet::default();
+    let mut copies: HashMap<[i8; 9], usize> = HashMap::new();
+    set.skipped = for_each_example(games, |game, record, board| {
+        let cell = record.cell_index as usize;
+        let seen = copies.entry(board).or_insert(0);
+        if *seen < max_copies {
+            *seen += 1;
+            set.x.push(board.iter().map(|&value| value as f32).collect());
+            set.y.push(cell);
+            if let Some(weights) = weights {
+                set.weights.push(weights.of(game, &record.player_name));
+            }
+        } else {
+            set.capped += 1;
+        }
+    });
+    set
+}
+
+/// Calls `f` with the game, the move and the board before it, from the
+/// mover's side, for every move of `games`. Unlike `GameData::moves` a
+/// transition that isn't a single new mark only loses its own move; the
+/// mover is the owner of the new mark. Returns the number of those
+/// transitions.
+fn for_each_example<'a>(
+    games: impl IntoIterator<Item = &'a GameData>,
+    mut f: impl FnMut(&GameData, &MoveRecord, [i8; 9]),
+) -> usize {
+    let mut skipped = 0;
+    for game in games {
+        let mut before = [0i8; 9];
+        for (snapshot, after) in game.state_of_cells_list.iter().enumerate() {
+            let mut changed = (0..9).filter(|&i| before[i] != after[i]);
+            match (changed.next(), changed.next()) {
+                (Some(cell), None) if before[cell] == 0 => {
+                    let player = if after[cell] == 1 { &game.player1 } else { &game.player2 };
+                    let record = MoveRecord {
+                        move_number: snapshot as u32 + 1,
+                        player_name: player.clone(),
+                        cell_index: cell as i32,
+                    };
+                    f(game, &record, before.map(|value| value * after[cell]));
+                }
+                _ => skipped += 1,
             }
+            before = *after;
         }
     }
-}
\ No newline at end of file
+    skipped
+}
+
+/// A file `load_dataset` read.
+struct LoadedDataset {
+    games: Vec<GameData>,
+    /// Rows skipped if it is CSV.
+    skipped: Vec<SkippedRow>,
+    meta: Option<DatasetMeta>,
+}
+
+/// The games of the dataset at `path` in whichever format it is.
+fn load_dataset(path: &Path) -> Result<LoadedDataset, DataError> {
+    let name = path.to_string_lossy().into_owned();
+    let mut start = [0; 4];
+    let read = File::open(path).and_then(|mut file| file.read(&mut start));
+    let start = match read {
+        Ok(n) => &start[..n],
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(DataError::NotFound(name)),
+        Err(e) => return Err(DataError::Io(name, e)),
+    };
+    let invalid = |message: String| DataError::Dataset {
+        path: name.clone(),
+        message,
+    };
+    if start == games_binary::MAGIC {
+        let games = games_binary::read_games(path).map_err(|e| invalid(e.to_string()))?;
+        Ok(LoadedDataset {
+            games,
+            skipped: Vec::new(),
+            meta: None,
+        })
+    } else if start.trim_ascii_start().starts_with(b"{") {
+        let games = games_json::read_games(path).map_err(|e| invalid(e.to_string()))?;
+        Ok(LoadedDataset {
+            games,
+            skipped: Vec::new(),
+            meta: None,
+        })
+    } else {
+        let mut reader = GamesData::iter_from_csv(path);
+        let games = reader.by_ref().collect::<Result<Vec<GameData>, DataError>>()?;
+        Ok(LoadedDataset {
+            games,
+            skipped: reader.skipped,
+            meta: reader.meta,
+        })
+    }
+}
+
+/// Positions and outcomes built by `GamesData::outcome_labels`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OutcomeSet {
+    /// Snapshots from the side of the player to move.
+    pub states: Vec<[i8; 9]>,
+    /// 1, -1 or 0 for a win, loss or draw of that player.
+    pub outcomes: Vec<i8>,
+    /// Games without a usable result.
+    pub skipped_games: usize,
+}
+
+impl OutcomeSet {
+    /// The states as network inputs.
+    pub fn inputs(&self) -> Vec<Vec<f32>> {
+        self.states.iter().map(|state| state.iter().map(|&value| value as f32).collect()).collect()
+    }
+    /// The outcomes as value targets in [-1, 1].
+    pub fn targets(&self) -> Vec<f32> {
+        self.outcomes.iter().map(|&outcome| outcome as f32).collect()
+    }
+}
+
+/// Header of `GamesData::export_flat` files.
+pub const FLAT_HEADER: &str = "c0,c1,c2,c3,c4,c5,c6,c7,c8,label,winner_code,game_id,move_no";
+
+/// What `GamesData::export_flat` writes.
+pub struct FlatExportOptions {
+    /// Also write every distinct symmetric copy of each game, see
+    /// `GameData::symmetric_copies`.
+    pub augment: bool,
+    /// Keep the games ending in a draw.
+    pub keep_draws: bool,
+}
+
+impl Default for FlatExportOptions {
+    fn default() -> Self {
+        FlatExportOptions {
+            augment: false,
+            keep_draws: true,
+        }
+    }
+}
+
+/// Examples built by `GamesData::annotate_optimal`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OptimalSet {
+    pub examples: Vec<OptimalExample>,
+    /// Transitions left out because they weren't one new mark.
+    pub skipped: usize,
+    /// Moves recorded after the game was over.
+    pub terminal: usize,
+}
+
+impl OptimalSet {
+    /// The examples labelled with the solver's move, as `training_set`
+    /// would label them with the recorded one.
+    pub fn training_set(&self) -> TrainingSet {
+        TrainingSet {
+            x: self.examples.iter().map(|example| example.board.iter().map(|&value| value as f32).collect()).collect(),
+            y: self.examples.iter().map(|example| example.best).collect(),
+            skipped: self.skipped,
+            ..TrainingSet::default()
+        }
+    }
+}
+
+/// A recorded move and the solver's verdict on the board before it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OptimalExample {
+    /// Index of the game in the dataset.
+    pub game: usize,
+    pub move_no: u8,
+    /// From the mover's side, as in `TrainingSet::x`.
+    pub board: [i8; 9],
+    /// The cell the mover took.
+    pub played: usize,
+    /// `Solution::best_move` for `board`.
+    pub best: usize,
+    /// `board`'s value for the mover.
+    pub value: Value,
+}
+
+/// Examples built by `GamesData::training_set`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrainingSet {
+    /// Boards before a move from the mover's side: 1 for its marks, -1 for
+    /// the opponent's, 0 for empty cells.
+    pub x: Vec<Vec<f32>>,
+    /// The cell taken by that move, 0 to 8.
+    pub y: Vec<usize>,
+    /// Transitions left out because they weren't one new mark.
+    pub skipped: usize,
+    /// Examples left out by `training_set_capped`.
+    pub capped: usize,
+    /// Games left out by `training_set_valid`.
+    pub invalid_games: usize,
+    /// One per example from `training_set_weighted`, for
+    /// `HimNetwork::fit_weighted`; empty otherwise.
+    pub weights: Vec<f32>,
+}
+
+/// How much a move counts in `GamesData::training_set_weighted`, by how
+/// the game ended for the player who made it. Moves of games without a
+/// result count as draws.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutcomeWeights {
+    pub win: f32,
+    pub loss: f32,
+    pub draw: f32,
+}
+
+impl Default for OutcomeWeights {
+    fn default() -> OutcomeWeights {
+        OutcomeWeights {
+            win: 1.0,
+            loss: 0.3,
+            draw: 0.5,
+        }
+    }
+}
+
+impl OutcomeWeights {
+    /// The weight of a move by the player named `mover` in `game`.
+    pub fn of(&self, game: &GameData, mover: &str) -> f32 {
+        match game.winner_id() {
+            Some(_) if game.winner == mover => self.win,
+            Some(_) => self.loss,
+            None => self.draw,
+        }
+    }
+}
+
+/// Why `GameData::moves` can't explain a snapshot. `transition` is the
+/// index of the snapshot in `state_of_cells_list`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateDiffError {
+    /// The snapshot is the same as the one before.
+    NoChange { transition: usize },
+    /// More than one empty cell was filled.
+    SeveralChanged { transition: usize, cells: Vec<usize> },
+    /// A mark was replaced by the other player's.
+    OwnerChanged { transition: usize, cell: usize },
+    /// A mark disappeared.
+    Cleared { transition: usize, cell: usize },
+    /// The player who made the previous move moved again.
+    OutOfTurn { transition: usize },
+}
+
+impl fmt::Display for StateDiffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateDiffError::NoChange { transition } => write!(f, "Snapshot {} changes nothing", transition),
+            StateDiffError::SeveralChanged { transition, cells } => {
+                write!(f, "Snapshot {} fills cells {:?} at once", transition, cells)
+            }
+            StateDiffError::OwnerChanged { transition, cell } => {
+                write!(f, "Snapshot {} gives cell {} to the other player", transition, cell)
+            }
+            StateDiffError::Cleared { transition, cell } => write!(f, "Snapshot {} empties cell {}", transition, cell),
+            StateDiffError::OutOfTurn { transition } => {
+                write!(f, "Snapshot {} is a move by the player who just moved", transition)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StateDiffError {}
+
+/// An impossible snapshot found by `GameData::validate`. `snapshot` is its
+/// index in `state_of_cells_list`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateViolation {
+    /// One player has two marks or more than the other.
+    ExtraPiece { snapshot: usize, player1: usize, player2: usize },
+    /// `player` has fewer marks than in the snapshot before.
+    VanishedPiece { snapshot: usize, player: String },
+    /// Both players have a full line.
+    BothWon { snapshot: usize },
+}
+
+impl fmt::Display for StateViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateViolation::ExtraPiece { snapshot, player1, player2 } => {
+                write!(f, "Snapshot {} has {} marks against {}", snapshot, player1, player2)
+            }
+            StateViolation::VanishedPiece { snapshot, player } => {
+                write!(f, "Snapshot {} loses a mark of {}", snapshot, player)
+            }
+            StateViolation::BothWon { snapshot } => write!(f, "Snapshot {} has a line for both players", snapshot),
+        }
+    }
+}
+
+impl std::error::Error for StateViolation {}
+
+/// What `GamesData::validate_all` found.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Games checked.
+    pub games: usize,
+    /// Index and violations of every invalid game, in order.
+    pub invalid: Vec<(usize, Vec<StateViolation>)>,
+}
+
+impl ValidationReport {
+    pub fn valid(&self) -> usize {
+        self.games - self.invalid.len()
+    }
+}
+
+/// How `balance_labels` evens out the labels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BalanceStrategy {
+    /// Keep as many examples of every label as the rarest label has.
+    Undersample,
+    /// Repeat examples of every label until it has as many as the most
+    /// common one.
+    Oversample,
+    /// Keep at most this many examples of any label.
+    CapPerClass(usize),
+}
+
+/// Examples returned by `balance_labels`, with the number of examples of
+/// each label before and after, indexed by label.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BalancedSet {
+    pub x: Vec<Vec<f32>>,
+    pub y: Vec<usize>,
+    pub before: Vec<usize>,
+    pub after: Vec<usize>,
+}
+
+/// Number of examples of each label, indexed by label.
+fn label_counts(y: &[usize]) -> Vec<usize> {
+    let mut counts = vec![0; y.iter().max().map_or(0, |&max| max + 1)];
+    for &label in y {
+        counts[label] += 1;
+    }
+    counts
+}
+
+/// Resample `x`/`y`, e.g. from `GamesData::to_training_set`, so the labels
+/// are about equally common. Which examples are kept or repeated depends
+/// only on `seed`; the result keeps the order of `x`, repeats next to
+/// their original. Labels with no examples are left alone.
+pub fn balance_labels(x: &[Vec<f32>], y: &[usize], strategy: BalanceStrategy, seed: u64) -> BalancedSet {
+    assert_eq!(x.len(), y.len(), "balance_labels needs one label per example");
+    let before = label_counts(y);
+    let present = before.iter().copied().filter(|&count| count > 0);
+    let target = match strategy {
+        BalanceStrategy::Undersample => present.min().unwrap_or(0),
+        BalanceStrategy::Oversample => present.max().unwrap_or(0),
+        BalanceStrategy::CapPerClass(n) => n,
+    };
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut chosen = Vec::with_capacity(y.len());
+    for label in 0..before.len() {
+        let mut examples: Vec<usize> = (0..y.len()).filter(|&i| y[i] == label).collect();
+        if examples.len() > target {
+            examples.shuffle(&mut rng);
+            examples.truncate(target);
+        } else if !examples.is_empty() && strategy == BalanceStrategy::Oversample {
+            let extra: Vec<usize> = (examples.len()..target)
+                .map(|_| *examples.choose(&mut rng).expect("not empty"))
+                .collect();
+            examples.extend(extra);
+        }
+        chosen.extend(examples);
+    }
+    chosen.sort();
+    let y: Vec<usize> = chosen.iter().map(|&i| y[i]).collect();
+    BalancedSet {
+        x: chosen.iter().map(|&i| x[i].clone()).collect(),
+        after: label_counts(&y),
+        y,
+        before,
+    }
+}
+
+/// A test fraction `GamesData::split` can't use; it must be strictly
+/// between 0 and 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FractionError(pub f32);
+
+impl fmt::Display for FractionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The test fraction must be between 0 and 1, not {}", self.0)
+    }
+}
+
+impl std::error::Error for FractionError {}
+
+/// Nine cells of -1, 0 or 1.
+fn parse_cells<'a>(items: impl Iterator<Item = &'a str>) -> Result<[i8; 9], String> {
+    let mut state = [0; 9];
+    let mut count = 0;
+    for (value, item) in state.iter_mut().zip(items) {
+        match item.parse::<i8>() {
+            Ok(cell) if (-1..=1).contains(&cell) => *value = cell,
+            _ => return Err(format!("cell {} is {:?}", count, item)),
+        }
+        count += 1;
+    }
+    if count < 9 {
+        return Err(format!("only {} cells", count));
+    }
+    Ok(state)
+}
+
+/// How far `GamesData::read_in_chunks` is, after a chunk.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChunkSummary {
+    /// Number of the chunk, from 0.
+    pub chunk: usize,
+    /// Games read so far.
+    pub games: usize,
+    /// Games of them kept in memory.
+    pub kept: usize,
+    /// See `GameReader::bytes_read`.
+    pub bytes_read: u64,
+    /// Size of the file, if it can be known.
+    pub total_bytes: Option<u64>,
+    /// Whether the file has been read to its end; the last chunk may hold
+    /// no games.
+    pub done: bool,
+}
+
+/// What `GamesData::read_data` loaded and what it had to repair or leave
+/// out.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReadReport {
+    /// Complete games added by this call.
+    pub games: usize,
+    pub repaired: Vec<RepairedRow>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+impl ReadReport {
+    pub fn rows_skipped(&self) -> usize {
+        self.skipped.len()
+    }
+    /// How the row on `line` was read. A row that was repaired and then
+    /// skipped anyway is corrupt.
+    pub fn row_class(&self, line: u64) -> RowClass {
+        if self.skipped.iter().any(|row| row.line == line) {
+            RowClass::Corrupt
+        } else if self.repaired.iter().any(|row| row.line == line) {
+            RowClass::Recoverable
+        } else {
+            RowClass::Valid
+        }
+    }
+}
+
+/// How `GamesData::read_data` took a row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowClass {
+    Valid,
+    /// Read after a repair, see `RepairedRow`.
+    Recoverable,
+    /// Skipped, see `SkippedRow`.
+    Corrupt,
+}
+
+/// What `GamesData::append_from` added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppendReport {
+    pub games: usize,
+    /// Games left out because their run was already loaded.
+    pub duplicates: usize,
+    pub skipped: Vec<SkippedRow>,
+}
+
+/// What `GamesData::merge` took from each file, in the order given.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MergeReport {
+    pub sources: Vec<SourceCount>,
+}
+
+impl MergeReport {
+    /// Games in the merged data.
+    pub fn games(&self) -> usize {
+        self.sources.iter().map(|source| source.games).sum()
+    }
+    pub fn duplicates(&self) -> usize {
+        self.sources.iter().map(|source| source.duplicates).sum()
+    }
+}
+
+/// One file of a `GamesData::merge`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceCount {
+    pub path: String,
+    /// Games kept.
+    pub games: usize,
+    /// Games left out as copies of earlier ones.
+    pub duplicates: usize,
+    /// Rows of a CSV file that weren't a valid board.
+    pub skipped: Vec<SkippedRow>,
+}
+
+/// A row `read_data` read after repairing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepairedRow {
+    /// 1-based line in the file.
+    pub line: u64,
+    /// What was wrong with it.
+    pub repair: String,
+}
+
+/// A row `read_data` could not use.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedRow {
+    /// 1-based line in the file.
+    pub line: u64,
+    pub reason: String,
+}
+
+/// Why `GamesData::read_data` could not read a file at all.
+#[derive(Debug)]
+pub enum DataError {
+    NotFound(String),
+    Io(String, std::io::Error),
+    /// A record the CSV reader rejected, e.g. invalid UTF-8.
+    Record { path: String, line: Option<u64>, message: String },
+    /// A schema line with a version other than `SCHEMA_VERSION`.
+    UnsupportedVersion { path: String, version: String },
+    /// A JSON archive or binary dataset `GamesData::merge` couldn't read.
+    Dataset { path: String, message: String },
+    /// A file `GamesData::merge` was given whose metadata gives another
+    /// board size than the files before.
+    IncompatibleBoard { path: String, board_size: usize, expected: usize },
+    /// A row `GamesData::read_data_with` in strict mode would have skipped.
+    Corrupt { path: String, line: u64, reason: String },
+}
+
+impl DataError {
+    fn from_csv(path: &str, error: csv::Error) -> DataError {
+        let line = error.position().map(|position| position.line());
+        match error.into_kind() {
+            csv::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::NotFound => DataError::NotFound(path.to_string()),
+            csv::ErrorKind::Io(e) => DataError::Io(path.to_string(), e),
+            kind => DataError::Record {
+                path: path.to_string(),
+                line,
+                message: format!("{:?}", kind),
+            },
+        }
+    }
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataError::NotFound(path) => write!(f, "{} does not exist", path),
+            DataError::Io(path, e) => write!(f, "could not read {}: {}", path, e),
+            DataError::Record { path, line: Some(line), message } => {
+                write!(f, "{} line {}: {}", path, line, message)
+            }
+            DataError::Record { path, line: None, message } => write!(f, "{}: {}", path, message),
+            DataError::UnsupportedVersion { path, version } => {
+                write!(f, "{} uses schema version {:?}, this build reads {}", path, version, SCHEMA_VERSION)
+            }
+            DataError::Dataset { path, message } => write!(f, "{}: {}", path, message),
+            DataError::IncompatibleBoard { path, board_size, expected } => write!(
+                f,
+                "{} holds games on a {}x{} board, the files before on a {}x{} board",
+                path, board_size, board_size, expected, expected
+            ),
+            DataError::Corrupt { path, line, reason } => write!(f, "{} line {}: {}", path, line, reason),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dataset under tests/fixtures, which nothing writes to.
+    fn fixture_path(name: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_read_data_takes_legacy_and_extended_rows() {
+        let path = std::env::temp_dir().join(format!("tictac_mixed_{}.csv", std::process::id()));
+        // An old game, a new one, a new one cut short (skipped), and another new one.
+        let fixture = "\n1,0,0,0,0,0,0,0,0,\
+                       \n1,-1,0,0,0,0,0,0,0,\
+                       \n1,-1,1,0,-1,0,0,0,1,draw\
+                       \n00000000000000a1,1700000000,1,0,0,0,0,1,0,0,0,0,\
+                       \n00000000000000a1,1700000000,2,-1,0,0,0,1,0,0,0,0,ai_2\
+                       \n00000000000000b2,1700000000,1,1,0,0,0,0,0,0,0,0,\
+                       \n00000000000000c3,1700000500,1,0,0,0,0,0,0,0,0,-1,\
+                       \n00000000000000c3,1700000500,2,0,0,0,0,1,0,0,0,-1,ai";
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        let report = data.read_data().unwrap();
+        let strict = GamesData::new(path.to_string_lossy().into_owned()).read_data_with(true);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(strict, Err(DataError::Corrupt { line: 7, .. })), "{:?}", strict);
+        let reason = "game 00000000000000b2 ends after 1 moves without a winner".to_string();
+        let cut_short = SkippedRow { line: 7, reason };
+        assert_eq!(report, ReadReport { games: 3, skipped: vec![cut_short], ..ReadReport::default() });
+
+        let games = &data.game_data;
+        assert_eq!(games.len(), 3);
+        assert_eq!((games[0].id.as_deref(), games[0].timestamp), (None, None));
+        assert_eq!(games[0].winner, "draw");
+        assert_eq!(games[0].state_of_cells_list.len(), 3);
+        assert_eq!(games[1].id.as_deref(), Some("00000000000000a1"));
+        assert_eq!(games[1].timestamp, Some(1_700_000_000));
+        assert_eq!(games[1].winner, "ai_2");
+        assert_eq!(games[1].state_of_cells_list, vec![[0, 0, 0, 0, 1, 0, 0, 0, 0], [-1, 0, 0, 0, 1, 0, 0, 0, 0]]);
+        assert_eq!(games[2].id.as_deref(), Some("00000000000000c3"));
+        assert_eq!(games[2].state_of_cells_list.len(), 2);
+        assert_eq!(games[2].first_player(), "ai_2");
+    }
+
+    #[test]
+    fn test_moves_from_legacy_and_schema_rows() {
+        let read = |name: &str, fixture: String| {
+            let path = std::env::temp_dir().join(format!("tictac_moves_{}_{}.csv", name, std::process::id()));
+            std::fs::write(&path, fixture).unwrap();
+            let mut data = GamesData::new(path.to_string_lossy().into_owned());
+            let report = data.read_data().unwrap();
+            std::fs::remove_file(&path).unwrap();
+            (data, report)
+        };
+        // ai_2 opens in the center, then a game whose second snapshot
+        // adds two marks.
+        let (legacy, _) = read(
+            "legacy",
+            "\n0,0,0,0,-1,0,0,0,0,\
+             \n1,0,0,0,-1,0,0,0,0,\
+             \n1,0,0,0,-1,0,0,0,-1,draw\
+             \n1,0,0,0,0,0,0,0,0,\
+             \n1,-1,1,0,0,0,0,0,0,\
+             \n1,-1,1,0,-1,0,0,0,0,ai"
+                .to_string(),
+        );
+        // The same first game, then one naming the player who just moved.
+        let (schema, report) = read(
+            "schema",
+            format!(
+                "{},{}\n{}\n\
+                 00000000000000a1,1700000000,1,ai,ai_2,0,0,0,0,-1,0,0,0,0,,ai\n\
+                 00000000000000a1,1700000000,2,ai,ai_2,1,0,0,0,-1,0,0,0,0,,ai_2\n\
+                 00000000000000a1,1700000000,3,ai,ai_2,1,0,0,0,-1,0,0,0,-1,draw,\n\
+                 00000000000000b2,1700000000,1,ai,ai_2,1,0,0,0,0,0,0,0,0,ai,ai\n",
+                SCHEMA_TAG, SCHEMA_VERSION, CSV_HEADER
+            ),
+        );
+        assert_eq!(schema.game_data.len(), 1);
+        assert_eq!(report.skipped[0].line, 6);
+        assert_eq!(report.skipped[0].reason, "\"ai\" moves twice in a row");
+        let moves = schema.game_data[0].moves().unwrap();
+        assert_eq!(legacy.game_data[0].moves().unwrap(), moves);
+        let cells: Vec<i32> = moves.iter().map(|record| record.cell_index).collect();
+        assert_eq!(cells, vec![4, 0, 8]);
+        assert_eq!(schema.game_data[0].first_player(), "ai_2");
+        assert_eq!(schema.game_data[0].mover(1), "ai");
+        let cut = &legacy.game_data[1];
+        assert!(matches!(cut.moves(), Err(StateDiffError::SeveralChanged { transition: 1, .. })));
+        assert_eq!(cut.first_player(), "ai");
+
+        let turned = schema.game_data[0].transformed(1).moves().unwrap();
+        let cells: Vec<i32> = turned.iter().map(|record| record.cell_index).collect();
+        assert_eq!(cells, vec![4, 2, 6]);
+
+        // The same examples either way; the cut game still gives its
+        // first move and its last, whose mover comes from the mark.
+        let (schema_set, legacy_set) = (schema.training_set(), legacy.training_set());
+        assert_eq!(schema_set.y, vec![4, 0, 8]);
+        assert_eq!(legacy_set.x[..3], schema_set.x[..]);
+        assert_eq!((legacy_set.y, legacy_set.skipped), (vec![4, 0, 8, 0, 4], 1));
+    }
+
+    #[test]
+    fn test_read_data_row_boundaries() {
+        use crate::recorder::{CsvRecorder, GameRecorder};
+
+        let path = std::env::temp_dir().join(format!("tictac_boundaries_{}.csv", std::process::id()));
+        // A leading blank line, a 9-move draw with two rows missing their
+        // trailing comma and the winner on the full board, then a game cut
+        // off by the end of the file.
+        let fixture = "\n0,0,0,0,1,0,0,0,0,\
+                       \n-1,0,0,0,1,0,0,0,0,\
+                       \n-1,0,1,0,1,0,0,0,0\
+                       \n-1,0,1,0,1,0,-1,0,0,\
+                       \n-1,0,1,1,1,0,-1,0,0\
+                       \n-1,0,1,1,1,-1,-1,0,0,\
+                       \n-1,1,1,1,1,-1,-1,0,0,\
+                       \n-1,1,1,1,1,-1,-1,-1,0,\
+                       \n-1,1,1,1,1,-1,-1,-1,1,draw\
+                       \n0,0,0,0,0,0,0,0,1,\
+                       \n0,0,0,0,-1,0,0,0,1,";
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        let report = data.read_data().unwrap();
+        assert_eq!(report.games, 1);
+        assert_eq!(
+            report.skipped,
+            vec![SkippedRow {
+                line: 12,
+                reason: "the file ends in a game of 2 moves without a winner".to_string()
+            }]
+        );
+        let draw = &data[0];
+        assert_eq!((draw.move_count(), draw.winner.as_str()), (9, "draw"));
+        assert_eq!(draw.state_of_cells_list[0], [0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(draw.periodic_state_of_cells, [-1, 1, 1, 1, 1, -1, -1, -1, 1]);
+        assert_eq!(draw.validate(), Ok(()));
+        assert_eq!(draw.moves().map(|moves| moves.len()), Ok(9));
+
+        // Exactly the games a recorder wrote, whatever their length.
+        std::fs::remove_file(&path).unwrap();
+        let mut recorder = CsvRecorder::new(&path).unwrap();
+        let lengths = [5, 9, 1, 2];
+        for (n, &length) in lengths.iter().enumerate() {
+            for state in &draw.state_of_cells_list[..length] {
+                recorder.record_state(state);
+            }
+            recorder.record_result(if n % 2 == 0 { "ai" } else { "draw" });
+        }
+        drop(recorder);
+        let mut written = GamesData::new(path.to_string_lossy().into_owned());
+        let report = written.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((report.games, report.rows_skipped()), (4, 0));
+        for (game, &length) in written.iter().zip(&lengths) {
+            assert_eq!(game.state_of_cells_list, draw.state_of_cells_list[..length]);
+        }
+    }
+
+    #[test]
+    fn test_games_do_not_take_boards_from_the_one_before() {
+        let path = std::env::temp_dir().join(format!("tictac_bleed_{}.csv", std::process::id()));
+        // ai wins; the next game loses its short first row, so it would
+        // start from two marks; then a clean game. The schema game's first
+        // row repeats the board before it.
+        let fixture = format!(
+            "\n1,0,0,0,0,0,0,0,0,\
+             \n1,-1,0,0,0,0,0,0,0,\
+             \n1,-1,0,0,1,0,0,0,0,\
+             \n1,-1,0,0,1,-1,0,0,0,\
+             \n1,-1,0,0,1,-1,0,0,1,ai\
+             \n0,0,0\
+             \n0,0,1,-1,0,0,0,0,0,\
+             \n0,0,1,-1,0,0,1,0,0,ai\
+             \n0,0,0,0,0,0,0,0,-1,\
+             \n1,0,0,0,0,0,0,0,-1,draw\n{},{}\n{}\n\
+             00000000000000a1,0,1,ai,ai_2,1,0,0,0,0,0,0,0,-1,,ai_2\n\
+             00000000000000a1,0,2,ai,ai_2,1,0,0,0,0,0,0,0,-1,,ai\n\
+             00000000000000b2,0,1,ai,ai_2,0,0,0,0,1,0,0,0,0,,ai_2\n\
+             00000000000000b2,0,2,ai,ai_2,0,0,0,0,1,-1,0,0,0,draw,\n",
+            SCHEMA_TAG, SCHEMA_VERSION, CSV_HEADER
+        );
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        let report = data.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let skipped: Vec<(u64, &str)> = report.skipped.iter().map(|row| (row.line, row.reason.as_str())).collect();
+        assert_eq!(
+            skipped,
+            vec![
+                (7, "expected 10 fields, found 3"),
+                (9, "the game ending here starts with 2 marks"),
+                (14, "move 1 shows 2 marks"),
+                (15, "move 2 follows move 0"),
+            ]
+        );
+        let firsts: Vec<[i8; 9]> = data.iter().map(|game| game.state_of_cells_list[0]).collect();
+        assert_eq!(
+            firsts,
+            vec![[1, 0, 0, 0, 0, 0, 0, 0, 0], [0, 0, 0, 0, 0, 0, 0, 0, -1], [0, 0, 0, 0, 1, 0, 0, 0, 0]]
+        );
+        assert!(data.iter().all(|game| game.validate().is_ok() && game.moves().is_ok()));
+
+        let mut games = GamesData::new(fixture_path("games.csv"));
+        let report = games.read_data().unwrap();
+        assert_eq!((report.games, report.rows_skipped()), (12, 0));
+    }
+
+    #[test]
+    fn test_read_data_reports_malformed_rows() {
+        let path = std::env::temp_dir().join(format!("tictac_malformed_{}.csv", std::process::id()));
+        let fixture = "\n1,0,0,0,0,0,0,0,0,\
+                       \n1,-1,0,0,0,0,0,0,0,draw\
+                       \n1,0,x,0,0,0,0,0,0,\
+                       \n0,0,0,0,1,0,0,0,0,\
+                       \n0,0,0,0,1,-1,0,0\
+                       \n0,0,0,0,1,-1,0,0,1,ai";
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        let report = data.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.games, 2);
+        assert_eq!(report.rows_skipped(), 2);
+        assert_eq!(report.skipped[0].line, 4);
+        assert_eq!(report.skipped[0].reason, "cell 2 is \"x\"");
+        assert_eq!(report.skipped[1].line, 6);
+        assert_eq!(data.game_data[1].state_of_cells_list.len(), 2);
+        assert_eq!(data.game_data[1].winner, "ai");
+    }
+
+    #[test]
+    fn test_read_data_repairs_or_skips_damaged_rows() {
+        let path = std::env::temp_dir().join(format!("tictac_damaged_{}.csv", std::process::id()));
+        // A game with stray whitespace, a lost trailing comma and a blank
+        // row; one cut short by a crash, whose next row has 8 cells; then a
+        // clean game.
+        let fixture = "1,0,0,0,0,0,0,0,0, \
+                       \n1,-1,0,0,0,0,0,0,0\
+                       \n   \
+                       \n1,-1,0,0,1, 0,0,0,0,\
+                       \n1,-1,0,0,1,-1,0,0,0,\
+                       \n1,-1,0,0,1,-1,0,0,1,ai\
+                       \n0,0,0,0,1,0,0,0,0,\
+                       \n0,0,0,0,1,-1,0,\
+                       \n0,0,0,0,1,-1,0,0,0,0,0,0,0,draw\
+                       \n0,0,0,0,0,0,0,0,-1,\
+                       \n1,0,0,0,0,0,0,0,-1,draw\n";
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        let report = data.read_data().unwrap();
+        let repaired: Vec<(u64, &str)> = report.repaired.iter().map(|row| (row.line, row.repair.as_str())).collect();
+        assert_eq!(
+            repaired,
+            vec![
+                (1, "whitespace around fields"),
+                (2, "no trailing comma"),
+                (3, "whitespace around fields, blank row"),
+                (4, "whitespace around fields"),
+            ]
+        );
+        let skipped: Vec<(u64, &str)> = report.skipped.iter().map(|row| (row.line, row.reason.as_str())).collect();
+        assert_eq!(
+            skipped,
+            vec![
+                (8, "expected 10 fields, found 8"),
+                (9, "expected 10 fields, found 14"),
+                (7, "the game of 1 moves ending here has no winner"),
+            ]
+        );
+        let classes: Vec<RowClass> = (1..=11).map(|line| report.row_class(line)).collect();
+        use RowClass::*;
+        assert_eq!(
+            classes,
+            [Recoverable, Recoverable, Recoverable, Recoverable, Valid, Valid, Corrupt, Corrupt, Corrupt, Valid, Valid]
+        );
+        // The games around the damaged one come through whole.
+        assert_eq!(report.games, 2);
+        assert_eq!(data[0].state_of_cells_list.len(), 5);
+        assert_eq!(data[0].state_of_cells_list[2], [1, -1, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!((data[0].winner.as_str(), data[0].validate()), ("ai", Ok(())));
+        assert_eq!(data[1].state_of_cells_list, vec![[0, 0, 0, 0, 0, 0, 0, 0, -1], [1, 0, 0, 0, 0, 0, 0, 0, -1]]);
+
+        let mut strict = GamesData::new(path.to_string_lossy().into_owned());
+        let result = strict.read_data_with(true);
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(error @ DataError::Corrupt { line: 8, .. }) => {
+                assert!(error.to_string().ends_with("line 8: expected 10 fields, found 8"), "{}", error)
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(strict.len(), 1);
+        assert_eq!(GamesData::iter_from_csv(fixture_path("games.csv")).strict(true).count(), 12);
+    }
+
+    #[test]
+    fn test_read_data_checks_the_schema_version() {
+        let path = std::env::temp_dir().join(format!("tictac_schema_{}.csv", std::process::id()));
+        let game = "a1,1,1,al,bo,1,0,0,0,0,0,0,0,0,,bo\na1,1,2,al,bo,1,-1,0,0,0,0,0,0,0,draw,\n";
+        std::fs::write(&path, format!("{},{}\n{}\n{}", SCHEMA_TAG, SCHEMA_VERSION, CSV_HEADER, game)).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        assert_eq!(data.read_data().unwrap(), ReadReport { games: 1, ..ReadReport::default() });
+        assert_eq!((data.game_data[0].player2.as_str(), data.game_data[0].winner.as_str()), ("bo", "draw"));
+
+        std::fs::write(&path, format!("{},3\n{}\n{}", SCHEMA_TAG, CSV_HEADER, game)).unwrap();
+        let result = data.read_data();
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(DataError::UnsupportedVersion { version, .. }) => assert_eq!(version, "3"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_symmetries() {
+        assert_eq!(SYMMETRIES[1][0], 2);
+        assert_eq!(transform(&[1, 0, 0, 0, 0, 0, 0, 0, -1], 1), [0, 0, 1, 0, 0, 0, -1, 0, 0]);
+        let lines = [[0, 1, 2], [3, 4, 5], [6, 7, 8], [0, 3, 6], [1, 4, 7], [2, 5, 8], [0, 4, 8], [2, 4, 6]];
+        for (k, permutation) in SYMMETRIES.iter().enumerate() {
+            assert_eq!(permutation[4], 4, "symmetry {} moves the center", k);
+            let mut sorted = *permutation;
+            sorted.sort();
+            assert_eq!(sorted, SYMMETRIES[0], "symmetry {} is not a permutation", k);
+            // Lines stay lines, so a win stays a win.
+            for line in lines {
+                let mut image = line.map(|i| permutation[i]);
+                image.sort();
+                assert!(lines.contains(&image), "symmetry {} breaks {:?}", k, line);
+            }
+        }
+        let state = [1, -1, 0, 0, 1, 0, 0, 0, -1];
+        let quarter_turns = (0..4).fold(state, |board, _| transform(&board, 1));
+        assert_eq!(quarter_turns, state);
+        for k in 4..8 {
+            assert_eq!(transform(&transform(&state, k), k), state, "mirror {} twice", k);
+        }
+        let images: Vec<[i8; 9]> = (0..8).map(|k| transform(&state, k)).collect();
+        assert!(images.iter().enumerate().all(|(k, image)| !images[..k].contains(image)));
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        // Every board with cells in -1..=1, legal or not.
+        let boards = (0..3usize.pow(9)).map(|code| {
+            let mut board = [0; 9];
+            let mut rest = code;
+            for cell in board.iter_mut() {
+                *cell = (rest % 3) as i8 - 1;
+                rest /= 3;
+            }
+            board
+        });
+        let mut classes = HashSet::new();
+        for board in boards {
+            let (form, op) = canonical_form(&board);
+            assert_eq!(op.apply(&board), form, "{:?}", board);
+            assert_eq!(canonical_form(&form), (form, SymOp::IDENTITY), "{:?}", board);
+            assert!(SymOp::all().all(|other| other.apply(&board) >= form), "{:?}", board);
+            assert_eq!(op.inverse().apply(&form), board, "{:?}", board);
+            for cell in 0..9 {
+                assert_eq!(form[op.map_cell(cell)], board[cell]);
+                assert_eq!(op.unmap_cell(op.map_cell(cell)), cell);
+            }
+            classes.insert(form);
+        }
+        // Burnside: (3^9 + 2*3^3 + 3^5 + 4*3^6) / 8.
+        assert_eq!(classes.len(), 2862);
+
+        assert_eq!(SymOp::new(1).unwrap().inverse(), SymOp::new(3).unwrap());
+        assert!((4..8).all(|k| SymOp::new(k).unwrap().inverse().index() == k));
+        assert_eq!(SymOp::new(8), None);
+        // A move picked on the canonical form, played on the original.
+        let board = [0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let (form, op) = canonical_form(&board);
+        assert_eq!(form, [0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(op, SymOp::IDENTITY);
+        let (form, op) = canonical_form(&[1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(form, [0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(op.unmap_cell(7), 1);
+    }
+
+    #[test]
+    fn test_augment_drops_identical_copies() {
+        let game = |states: Vec<[i8; 9]>| {
+            let mut game = GameData::new("ai".to_string(), "ai_2".to_string());
+            game.winner = "draw".to_string();
+            game.periodic_state_of_cells = *states.last().unwrap();
+            game.state_of_cells_list = states;
+            game
+        };
+        let mut data = GamesData::new(String::new());
+        // Center only: every symmetry gives the same game.
+        data.add_game(game(vec![[0, 0, 0, 0, 1, 0, 0, 0, 0]]));
+        // A corner: four corners.
+        data.add_game(game(vec![[1, 0, 0, 0, 0, 0, 0, 0, 0]]));
+        // No symmetry of its own: eight games.
+        data.add_game(game(vec![[1, 0, 0, 0, 0, 0, 0, 0, 0], [1, -1, 0, 0, 0, 0, 0, 0, 0]]));
+        let augmented = data.augment();
+        assert_eq!(augmented.game_data.len(), 1 + 4 + 8);
+        let corners: Vec<[i8; 9]> = augmented.game_data[1..5].iter().map(|game| game.periodic_state_of_cells).collect();
+        assert!(corners.contains(&[0, 0, 0, 0, 0, 0, 0, 0, 1]));
+        let rotated = &augmented.game_data[6];
+        assert_eq!(rotated.state_of_cells_list, vec![[0, 0, 1, 0, 0, 0, 0, 0, 0], [0, 0, 1, 0, 0, -1, 0, 0, 0]]);
+        assert_eq!(rotated.winner, "draw");
+    }
+
+    #[test]
+    fn test_training_set_trains_a_network() {
+        use crate::him_network::HimNetwork;
+        use crate::training::FitOptions;
+
+        let path = std::env::temp_dir().join(format!("tictac_training_{}.csv", std::process::id()));
+        // ai opens in the corner and wins on the top row; then ai_2 opens
+        // a game, followed by a corrupt snapshot with two new marks.
+        let fixture = "\n1,0,0,0,0,0,0,0,0,\
+                       \n1,0,0,0,-1,0,0,0,0,\
+                       \n1,1,0,0,-1,0,0,0,0,\
+                       \n1,1,0,0,-1,-1,0,0,0,\
+                       \n1,1,1,0,-1,-1,0,0,0,ai\
+                       \n0,0,0,0,-1,0,0,0,0,\
+                       \n1,0,0,0,-1,0,0,-1,0,\
+                       \n1,0,1,0,-1,0,0,-1,0,draw";
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        data.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let set = data.training_set();
+        assert_eq!(set.skipped, 1);
+        assert_eq!(set.y, vec![0, 4, 1, 5, 2, 4, 2]);
+        assert_eq!(set.x[0], vec![0.0; 9]);
+        // Before ai_2's reply the corner is the opponent's.
+        assert_eq!(set.x[1], vec![-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(set.x[2], vec![1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0]);
+        // In the second game ai_2 opens, so it is the one seeing 0.
+        assert_eq!(set.x[5], vec![0.0; 9]);
+        assert_eq!(set.x[6][4], -1.0);
+
+        let (x, y) = data.to_training_set();
+        assert_eq!((x.len(), y.len()), (7, 7));
+        let mut network = HimNetwork::new();
+        network.init_params();
+        let options = FitOptions {
+            epochs: 3,
+            batch_size: 4,
+            ..FitOptions::default()
+        };
+        let history = network.fit(&x, &y, &options).unwrap();
+        assert_eq!(history.epochs.len(), 3);
+        assert!(history.loss().iter().all(|loss| loss.is_finite()));
+        assert!(set.weights.is_empty());
+
+        // ai's moves won, ai_2's lost, and the second game is a draw.
+        let weighted = data.training_set_weighted(usize::MAX, &OutcomeWeights::default());
+        assert_eq!((weighted.x, weighted.y), (set.x, set.y));
+        assert_eq!(weighted.weights, vec![1.0, 0.3, 1.0, 0.3, 1.0, 0.5, 0.5]);
+        let even = OutcomeWeights { win: 1.0, loss: 1.0, draw: 1.0 };
+        assert_eq!(data.training_set_weighted(2, &even).weights, vec![1.0; 7]);
+        let history = network.fit_weighted(&x, &y, &weighted.weights, &options).unwrap();
+        assert!(history.loss().iter().all(|loss| loss.is_finite()));
+    }
+
+    #[test]
+    fn test_get_round_state() {
+        let mut game = GameData::new("ai".to_string(), "ai_2".to_string());
+        assert_eq!(game.get_round_state(0, false), None);
+        assert_eq!(game.get_round_state(0, true), None);
+        game.state_of_cells_list = vec![[1, 0, 0, 0, 0, 0, 0, 0, 0], [1, -1, 0, 0, 0, 0, 0, 0, 0]];
+        assert_eq!(game.get_round_state(0, false), Some(game.state_of_cells_list[0]));
+        assert_eq!(game.get_round_state(1, true), Some(game.state_of_cells_list[1]));
+        assert_eq!(game.get_round_state(2, false), None);
+        assert_eq!(game.get_round_state(usize::MAX, true), Some(game.state_of_cells_list[1]));
+    }
+
+    #[test]
+    fn test_moves_from_snapshots() {
+        let game = |states: &[[i8; 9]]| {
+            let mut game = GameData::new("alice".to_string(), "bob".to_string());
+            game.state_of_cells_list = states.to_vec();
+            game
+        };
+        let clean = [
+            [0, 0, 0, 0, -1, 0, 0, 0, 0],
+            [1, 0, 0, 0, -1, 0, 0, 0, 0],
+            [1, 0, 0, 0, -1, 0, 0, 0, -1],
+        ];
+        let moves = game(&clean).moves().unwrap();
+        let summary: Vec<(u32, &str, i32)> = moves
+            .iter()
+            .map(|record| (record.move_number, record.player_name.as_str(), record.cell_index))
+            .collect();
+        assert_eq!(summary, vec![(1, "bob", 4), (2, "alice", 0), (3, "bob", 8)]);
+        assert_eq!(game(&[]).moves(), Ok(vec![]));
+
+        let corrupt = |transition: usize, state: [i8; 9]| {
+            let mut states = clean.to_vec();
+            states[transition] = state;
+            game(&states).moves().unwrap_err()
+        };
+        assert_eq!(
+            corrupt(1, [1, 1, 0, 0, -1, 0, 0, 0, 0]),
+            StateDiffError::SeveralChanged {
+                transition: 1,
+                cells: vec![0, 1]
+            }
+        );
+        assert_eq!(
+            corrupt(2, [1, 0, 0, 0, 1, 0, 0, 0, 0]),
+            StateDiffError::OwnerChanged { transition: 2, cell: 4 }
+        );
+        assert_eq!(
+            corrupt(2, [0, 0, 0, 0, -1, 0, 0, 0, -1]),
+            StateDiffError::Cleared { transition: 2, cell: 0 }
+        );
+        assert_eq!(corrupt(1, clean[0]), StateDiffError::NoChange { transition: 1 });
+        assert_eq!(corrupt(1, [0, 0, 0, 0, -1, 0, 0, 0, -1]), StateDiffError::OutOfTurn { transition: 1 });
+        assert_eq!(
+            corrupt(0, [1, 0, 0, 0, -1, 0, 0, 0, 0]).to_string(),
+            "Snapshot 0 fills cells [0, 4] at once"
+        );
+    }
+
+    #[test]
+    fn test_validate_finds_impossible_boards() {
+        let game = |states: &[[i8; 9]]| {
+            let mut game = GameData::new("alice".to_string(), "bob".to_string());
+            game.state_of_cells_list = states.to_vec();
+            game
+        };
+        let clean = [
+            [1, 0, 0, 0, 0, 0, 0, 0, 0],
+            [1, 0, 0, 0, -1, 0, 0, 0, 0],
+            [1, 1, 0, 0, -1, 0, 0, 0, 0],
+        ];
+        assert_eq!(game(&clean).validate(), Ok(()));
+        // Two marks in one snapshot pass, as long as the counts stay even.
+        assert_eq!(game(&clean[1..]).validate(), Ok(()));
+
+        let extra = game(&[clean[0], clean[1], [1, 1, 1, 0, -1, 0, 0, 0, 0]]);
+        assert_eq!(
+            extra.validate(),
+            Err(vec![StateViolation::ExtraPiece {
+                snapshot: 2,
+                player1: 3,
+                player2: 1
+            }])
+        );
+        let vanished = game(&[clean[0], clean[1], clean[0]]);
+        assert_eq!(
+            vanished.validate(),
+            Err(vec![StateViolation::VanishedPiece {
+                snapshot: 2,
+                player: "bob".to_string()
+            }])
+        );
+        let both = game(&[[1, 1, 1, -1, -1, -1, 0, 0, 0]]);
+        assert_eq!(both.validate(), Err(vec![StateViolation::BothWon { snapshot: 0 }]));
+        assert_eq!(
+            StateViolation::VanishedPiece {
+                snapshot: 2,
+                player: "bob".to_string()
+            }
+            .to_string(),
+            "Snapshot 2 loses a mark of bob"
+        );
+
+        let mut data = GamesData::new(String::new());
+        for corrupt in [extra, game(&clean), vanished, both] {
+            data.add_game(corrupt);
+        }
+        let report = data.validate_all();
+        assert_eq!((report.games, report.valid()), (4, 1));
+        let invalid: Vec<(usize, usize)> = report.invalid.iter().map(|(index, found)| (*index, found.len())).collect();
+        assert_eq!(invalid, vec![(0, 1), (2, 1), (3, 1)]);
+        let set = data.training_set_valid(usize::MAX);
+        assert_eq!((set.x.len(), set.skipped, set.invalid_games), (3, 0, 3));
+        assert_eq!(set.y, vec![0, 4, 1]);
+        assert_eq!(data.training_set().invalid_games, 0);
+
+        let mut games = GamesData::new(fixture_path("games.csv"));
+        games.read_data().unwrap();
+        assert_eq!(games.validate_all().invalid, vec![]);
+        // A good game, then one whose first player moves twice in a row.
+        let mut games = GamesData::new(fixture_path("invalid_game.csv"));
+        games.read_data().unwrap();
+        let report = games.validate_all();
+        assert_eq!((report.games, report.valid()), (2, 1));
+        let extra = StateViolation::ExtraPiece { snapshot: 1, player1: 2, player2: 0 };
+        assert_eq!(report.invalid, vec![(1, vec![extra])]);
+    }
+
+    #[test]
+    fn test_games_data_borrows() {
+        let mut data = GamesData::new(String::new());
+        assert!(data.is_empty());
+        assert_eq!(data.get(0), None);
+        for (player1, player2) in [("alice", "bob"), ("carol", "dave"), ("erin", "frank")] {
+            data.add_game(GameData::new(player1.to_string(), player2.to_string()));
+        }
+        assert_eq!((data.len(), data.is_empty()), (3, false));
+        assert_eq!(data.get(1).map(|game| game.player1.as_str()), Some("carol"));
+        assert_eq!(data.get(3), None);
+        assert_eq!(data[2].player2, "frank");
+        assert_eq!(data.get_game(0), data[0]);
+        let mut order = Vec::new();
+        for game in &data {
+            order.push(game.player1.as_str());
+        }
+        assert_eq!(order, ["alice", "carol", "erin"]);
+        assert!(data.iter().map(|game| &game.player2).eq(["bob", "dave", "frank"].iter()));
+    }
+
+    #[test]
+    fn test_outcome_labels() {
+        let path = std::env::temp_dir().join(format!("tictac_outcomes_{}.csv", std::process::id()));
+        // ai opens and wins in 5, ai_2 opens and wins in 5, then a draw
+        // cut short.
+        let fixture = "\n1,0,0,0,0,0,0,0,0,\
+                       \n1,-1,0,0,0,0,0,0,0,\
+                       \n1,-1,0,0,1,0,0,0,0,\
+                       \n1,-1,-1,0,1,0,0,0,0,\
+                       \n1,-1,-1,0,1,0,0,0,1,ai\
+                       \n0,0,0,0,-1,0,0,0,0,\
+                       \n1,0,0,0,-1,0,0,0,0,\
+                       \n1,0,-1,0,-1,0,0,0,0,\
+                       \n1,0,-1,0,-1,0,0,0,1,\
+                       \n1,0,-1,0,-1,0,-1,0,1,ai_2\
+                       \n0,0,0,0,1,0,0,0,0,\
+                       \n-1,0,0,0,1,0,0,0,0,draw";
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        data.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut unknown = data[2].clone();
+        unknown.winner = "zed".to_string();
+        data.add_game(unknown);
+        let mut blank = data[2].clone();
+        blank.winner = String::new();
+        data.add_game(blank);
+
+        let set = data.outcome_labels();
+        assert_eq!(set.skipped_games, 2);
+        assert_eq!(set.outcomes, vec![-1, 1, -1, 1, -1, -1, 1, -1, 1, -1, 0, 0]);
+        // After ai's first move ai_2 is to move and sees ai's mark as -1.
+        assert_eq!(set.states[0], [-1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(set.states[1], [1, -1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(set.states[5], [0, 0, 0, 0, -1, 0, 0, 0, 0]);
+        assert_eq!(set.targets()[..3], [-1.0, 1.0, -1.0]);
+        assert_eq!(set.inputs()[1], vec![1.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(data[1].mover(1), "ai");
+        assert_eq!(data[1].winner_id(), Some(PlayerId::Player2));
+    }
+
+    #[test]
+    fn test_annotate_optimal() {
+        let mut data = GamesData::new(String::new());
+        // ai opens in the corner, ai_2 answers on the edge and loses.
+        let mut lost = GameData::new("ai".to_string(), "ai_2".to_string());
+        lost.state_of_cells_list = vec![
+            [1, 0, 0, 0, 0, 0, 0, 0, 0],
+            [1, -1, 0, 0, 0, 0, 0, 0, 0],
+            [1, -1, 0, 0, 1, 0, 0, 0, 0],
+            [1, -1, -1, 0, 1, 0, 0, 0, 0],
+            [1, -1, -1, 0, 1, 0, 0, 0, 1],
+        ];
+        lost.winner = "ai".to_string();
+        data.add_game(lost);
+        // A move recorded after ai completed the top row.
+        let mut late = GameData::new("ai".to_string(), "ai_2".to_string());
+        late.state_of_cells_list = vec![
+            [1, 0, 0, 0, 0, 0, 0, 0, 0],
+            [1, 0, 0, -1, 0, 0, 0, 0, 0],
+            [1, 1, 0, -1, 0, 0, 0, 0, 0],
+            [1, 1, 0, -1, -1, 0, 0, 0, 0],
+            [1, 1, 1, -1, -1, 0, 0, 0, 0],
+            [1, 1, 1, -1, -1, -1, 0, 0, 0],
+        ];
+        late.winner = "ai".to_string();
+        data.add_game(late);
+
+        let set = data.annotate_optimal();
+        assert_eq!((set.examples.len(), set.terminal, set.skipped), (10, 1, 0));
+        let values: Vec<Value> = set.examples[..5].iter().map(|example| example.value).collect();
+        assert_eq!(values, [Value::Draw, Value::Draw, Value::Win, Value::Loss, Value::Win]);
+        assert_eq!((set.examples[1].played, set.examples[1].best), (1, 4));
+        assert_eq!(set.examples[1].board, [-1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        // ai_2 has to block the diagonal, and ai then wins on it.
+        assert_eq!((set.examples[3].played, set.examples[3].best), (2, 8));
+        assert_eq!((set.examples[4].played, set.examples[4].best), (8, 8));
+        assert_eq!((set.examples[9].game, set.examples[9].move_no), (1, 5));
+        assert_eq!(set.examples[9].best, 2);
+
+        let training = set.training_set();
+        assert_eq!(training.y[..5], [0, 4, set.examples[2].best, 8, 8]);
+        assert_eq!(training.x[1], vec![-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let path = std::env::temp_dir().join(format!("tictac_filters_{}.csv", std::process::id()));
+        // ai wins in 5 opening, ai_2 wins in 6 replying, ai_2 wins in 5
+        // opening, then a 9-move draw.
+        let fixture = "\n1,0,0,0,0,0,0,0,0,\
+                       \n1,0,0,-1,0,0,0,0,0,\
+                       \n1,1,0,-1,0,0,0,0,0,\
+                       \n1,1,0,-1,-1,0,0,0,0,\
+                       \n1,1,1,-1,-1,0,0,0,0,ai\
+                       \n1,0,0,0,0,0,0,0,0,\
+                       \n1,0,0,0,-1,0,0,0,0,\
+                       \n1,1,0,0,-1,0,0,0,0,\
+                       \n1,1,-1,0,-1,0,0,0,0,\
+                       \n1,1,-1,0,-1,0,0,0,1,\
+                       \n1,1,-1,0,-1,0,-1,0,1,ai_2\
+                       \n0,0,0,0,-1,0,0,0,0,\
+                       \n1,0,0,0,-1,0,0,0,0,\
+                       \n1,0,-1,0,-1,0,0,0,0,\
+                       \n1,0,-1,0,-1,0,0,0,1,\
+                       \n1,0,-1,0,-1,0,-1,0,1,ai_2\
+                       \n1,0,0,0,0,0,0,0,0,\
+                       \n1,0,0,0,-1,0,0,0,0,\
+                       \n1,1,0,0,-1,0,0,0,0,\
+                       \n1,1,-1,0,-1,0,0,0,0,\
+                       \n1,1,-1,0,-1,0,1,0,0,\
+                       \n1,1,-1,-1,-1,0,1,0,0,\
+                       \n1,1,-1,-1,-1,1,1,0,0,\
+                       \n1,1,-1,-1,-1,1,1,-1,0,\
+                       \n1,1,-1,-1,-1,1,1,-1,1,draw";
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        data.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data.game_data.len(), 4);
+
+        let lengths: Vec<usize> = data.game_data.iter().map(GameData::move_count).collect();
+        assert_eq!(lengths, vec![5, 6, 5, 9]);
+        assert_eq!(data.by_winner("ai").game_data.len(), 1);
+        assert_eq!(data.by_winner("ai_2").game_data.len(), 2);
+        assert_eq!(data.by_winner("draw").game_data.len(), 1);
+        assert_eq!(data.by_winner("nobody").game_data.len(), 0);
+        assert_eq!(data.min_length(6).game_data.len(), 2);
+        assert_eq!(data.min_length(8).by_winner("draw").game_data.len(), 1);
+        let decided = data.decided_only();
+        assert_eq!(decided.game_data.len(), 3);
+        assert_eq!(decided.csv_file, data.csv_file);
+        let opener_won = data.filter(|game| game.winner == game.first_player());
+        let winners: Vec<&str> = opener_won.game_data.iter().map(|game| game.winner.as_str()).collect();
+        assert_eq!(winners, vec!["ai", "ai_2"]);
+        assert_eq!(data.game_data.len(), 4);
+    }
+
+    #[test]
+    fn test_dedup_states() {
+        let game = |winner: &str, states: &[[i8; 9]]| {
+            let mut game = GameData::new("ai".to_string(), "ai_2".to_string());
+            game.winner = winner.to_string();
+            game.state_of_cells_list = states.to_vec();
+            game
+        };
+        let corner = [1, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut data = GamesData::new(String::new());
+        // The same opening three times, answered in the center twice and
+        // on the edge once, and a game opening in another corner.
+        for _ in 0..2 {
+            data.add_game(game("", &[corner, [1, 0, 0, 0, -1, 0, 0, 0, 0]]));
+        }
+        data.add_game(game("", &[corner, [1, -1, 0, 0, 0, 0, 0, 0, 0]]));
+        data.add_game(game("", &[[0, 0, 0, 0, 0, 0, 0, 0, 1], [0, 0, 0, 0, 0, 0, 0, -1, 1]]));
+
+        let unique = data.dedup_states();
+        assert_eq!(unique.iter().map(|&(_, _, count)| count).sum::<u32>(), 8);
+        assert_eq!(unique.len(), 3);
+        // Empty board: three corner 0 openings against one corner 8.
+        assert_eq!(unique[0], ([0; 9], 0, 4));
+        assert_eq!(unique[1], ([-1, 0, 0, 0, 0, 0, 0, 0, 0], 4, 3));
+        assert_eq!(unique[2], ([0, 0, 0, 0, 0, 0, 0, 0, -1], 7, 1));
+
+        // Up to symmetry both corners are one board and the edge replies
+        // 1 and 7 are the same move, tying with the center 2-2.
+        let canonical = data.dedup_states_with(true);
+        assert_eq!(canonical.len(), 2);
+        assert_eq!(canonical[0].2, 4);
+        assert_eq!(canonical[1], ([-1, 0, 0, 0, 0, 0, 0, 0, 0], 1, 4));
+        assert_eq!(super::canonical(&[0, 0, 0, 0, 0, 0, 0, 0, -1], 7), ([-1, 0, 0, 0, 0, 0, 0, 0, 0], 1));
+
+        let capped = data.training_set_capped(2);
+        assert_eq!((capped.y.len(), capped.capped), (5, 3));
+        assert_eq!(data.training_set().capped, 0);
+    }
+
+    #[test]
+    fn test_split_keeps_games_whole() {
+        let mut data = GamesData::new(String::new());
+        for n in 0..50 {
+            let mut game = GameData::new("ai".to_string(), "ai_2".to_string());
+            game.id = Some(format!("{:016x}", n));
+            game.winner = "draw".to_string();
+            game.state_of_cells_list = vec![[1, 0, 0, 0, 0, 0, 0, 0, 0], [1, -1, 0, 0, 0, 0, 0, 0, 0]];
+            data.add_game(game);
+        }
+        let (train, test) = data.split(0.2, 5).unwrap();
+        assert_eq!((train.game_data.len(), test.game_data.len()), (40, 10));
+        let ids = |half: &GamesData| -> Vec<String> { half.game_data.iter().filter_map(|game| game.id.clone()).collect() };
+        let (train_ids, test_ids) = (ids(&train), ids(&test));
+        assert!(test_ids.iter().all(|id| !train_ids.contains(id)));
+        let mut sorted = test_ids.clone();
+        sorted.sort();
+        assert_eq!(sorted, test_ids, "each half keeps file order");
+        // Every example of a test game stays in the test set.
+        assert_eq!(train.to_training_set().1.len() + test.to_training_set().1.len(), 100);
+
+        assert_eq!(ids(&data.split(0.2, 5).unwrap().1), test_ids);
+        assert_ne!(ids(&data.split(0.2, 6).unwrap().1), test_ids);
+        for bad in [0.0, 1.0, -0.5, 1.5, f32::NAN] {
+            assert!(data.split(bad, 5).is_err(), "{}", bad);
+        }
+        assert_eq!(data.split(1.0, 5).err(), Some(FractionError(1.0)));
+        assert_eq!(FractionError(1.0).to_string(), "The test fraction must be between 0 and 1, not 1");
+    }
+
+    #[test]
+    fn test_balance_labels() {
+        // Label 4 a hundred times, 0 ten times, 8 twice; x[i] holds i.
+        let y: Vec<usize> = (0..112)
+            .map(|i| match i {
+                0..=99 => 4,
+                100..=109 => 0,
+                _ => 8,
+            })
+            .collect();
+        let x: Vec<Vec<f32>> = (0..y.len()).map(|i| vec![i as f32; 9]).collect();
+        let aligned = |set: &BalancedSet| set.x.iter().zip(&set.y).all(|(row, &label)| y[row[0] as usize] == label);
+
+        let under = balance_labels(&x, &y, BalanceStrategy::Undersample, 1);
+        assert_eq!(under.before, vec![10, 0, 0, 0, 100, 0, 0, 0, 2]);
+        assert_eq!(under.after, vec![2, 0, 0, 0, 2, 0, 0, 0, 2]);
+        assert!(aligned(&under));
+
+        let over = balance_labels(&x, &y, BalanceStrategy::Oversample, 1);
+        assert_eq!(over.after, vec![100, 0, 0, 0, 100, 0, 0, 0, 100]);
+        assert!(aligned(&over));
+        // Every original example is kept.
+        assert!((0..112).all(|i| over.x.contains(&x[i])));
+
+        let capped = balance_labels(&x, &y, BalanceStrategy::CapPerClass(5), 1);
+        assert_eq!(capped.after, vec![5, 0, 0, 0, 5, 0, 0, 0, 2]);
+        assert!(aligned(&capped));
+
+        assert_eq!(balance_labels(&x, &y, BalanceStrategy::CapPerClass(5), 1), capped);
+        assert_ne!(balance_labels(&x, &y, BalanceStrategy::CapPerClass(5), 2).x, capped.x);
+        assert_eq!(balance_labels(&[], &[], BalanceStrategy::Oversample, 1), BalancedSet::default());
+    }
+
+    #[test]
+    fn test_iter_from_csv_streams_the_same_games() {
+        let path = std::env::temp_dir().join(format!("tictac_stream_{}.csv", std::process::id()));
+        let fixture = "\n1,0,0,0,0,0,0,0,0,\
+                       \n1,-1,0,0,0,0,0,0,0,draw\
+                       \n1,0,x,0,0,0,0,0,0,\
+                       \n00000000000000a1,1700000000,1,0,0,0,0,1,0,0,0,0,\
+                       \n00000000000000a1,1700000000,2,-1,0,0,0,1,0,0,0,0,ai_2\
+                       \n0,0,0,0,1,0,0,0,0,\
+                       \n0,0,0,0,1,-1,0,0,1,ai";
+        std::fs::write(&path, fixture).unwrap();
+        let mut eager = GamesData::new(path.to_string_lossy().into_owned());
+        let report = eager.read_data().unwrap();
+
+        let mut reader = GamesData::iter_from_csv(&path);
+        let first = reader.next().unwrap().unwrap();
+        assert_eq!(first, eager.game_data[0]);
+        // Nothing past the first game has been read yet.
+        assert!(reader.skipped().is_empty());
+        let rest: Vec<GameData> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(rest, eager.game_data[1..]);
+        assert_eq!(reader.skipped(), report.skipped.as_slice());
+        assert!(reader.next().is_none());
+
+        let ai_wins = GamesData::iter_from_csv(&path)
+            .filter_map(Result::ok)
+            .filter(|game| game.winner == "ai")
+            .count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ai_wins, 1);
+
+        let mut missing = GamesData::iter_from_csv("no/such/table.csv");
+        assert!(matches!(missing.next(), Some(Err(DataError::NotFound(_)))));
+        assert!(missing.next().is_none());
+    }
+
+    #[test]
+    fn test_read_in_chunks() {
+        let path = std::env::temp_dir().join(format!("tictac_chunks_{}.csv", std::process::id()));
+        // Five games of two moves, ai_2 winning the odd ones, and a row
+        // that isn't a board.
+        let mut fixture = String::from("1,0,0,0,2,0,0,0,0,\n");
+        for game in 0..5 {
+            let winner = if game % 2 == 1 { "ai_2" } else { "draw" };
+            fixture += &format!("1,0,0,0,0,0,0,0,0,\n1,-1,0,0,0,0,0,0,0,{}\n", winner);
+        }
+        std::fs::write(&path, &fixture).unwrap();
+
+        let mut summaries = Vec::new();
+        let (data, report) = GamesData::read_in_chunks(&path, 2, |summary| {
+            summaries.push(summary);
+            true
+        })
+        .unwrap();
+        assert_eq!((data.len(), report.games, report.rows_skipped()), (5, 5, 1));
+        assert_eq!(data.csv_file, path.to_string_lossy());
+        let counts: Vec<(usize, usize, bool)> = summaries.iter().map(|s| (s.chunk, s.games, s.done)).collect();
+        assert_eq!(counts, vec![(0, 2, false), (1, 4, false), (2, 5, true)]);
+        assert!(summaries.iter().all(|s| s.kept == s.games && s.total_bytes == Some(fixture.len() as u64)));
+        assert_eq!(summaries[2].bytes_read, fixture.len() as u64);
+
+        // Only ai_2's wins are kept, and reading stops after the second
+        // chunk.
+        let mut kept = Vec::new();
+        let (wins, _) = GamesData::read_in_chunks_filtered(&path, 2, |game| game.winner == "ai_2", |summary| {
+            kept.push((summary.games, summary.kept));
+            summary.chunk < 1
+        })
+        .unwrap();
+        assert_eq!(kept, vec![(2, 1), (4, 2)]);
+        assert_eq!(wins.len(), 2);
+        assert!(wins.iter().all(|game| game.winner == "ai_2"));
+
+        // A chunk size of 0 is taken as 1.
+        let mut calls = 0;
+        GamesData::read_in_chunks(&path, 0, |_| {
+            calls += 1;
+            true
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(calls, 6);
+        assert!(matches!(
+            GamesData::read_in_chunks("no/such/table.csv", 2, |_| true),
+            Err(DataError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_merge_drops_duplicates() {
+        let dir = std::env::temp_dir();
+        let legacy = dir.join(format!("tictac_merge_legacy_{}.csv", std::process::id()));
+        let schema = dir.join(format!("tictac_merge_schema_{}.csv", std::process::id()));
+        let binary = dir.join(format!("tictac_merge_{}.bin", std::process::id()));
+        // A draw and an ai_2 win, with a row that isn't a board.
+        std::fs::write(
+            &legacy,
+            "\n1,0,0,0,0,0,0,0,0,\
+             \n1,-1,0,0,0,0,0,0,0,draw\
+             \n1,2,0,0,0,0,0,0,0,\
+             \n0,0,0,0,1,0,0,0,0,\
+             \n-1,0,0,0,1,0,0,0,0,ai_2",
+        )
+        .unwrap();
+        // The same ai_2 win recorded by another machine, and an ai win.
+        std::fs::write(
+            &schema,
+            format!(
+                "{},{}\n{}\n\
+                 00000000000000a1,1700000000,1,ai,ai_2,0,0,0,0,1,0,0,0,0,,ai_2\n\
+                 00000000000000a1,1700000000,2,ai,ai_2,-1,0,0,0,1,0,0,0,0,ai_2,ai\n\
+                 00000000000000b2,1700000000,1,ai,ai_2,0,0,1,0,0,0,0,0,0,ai,ai_2\n",
+                SCHEMA_TAG, SCHEMA_VERSION, CSV_HEADER
+            ),
+        )
+        .unwrap();
+        let (merged, report) = GamesData::merge(&[legacy.clone(), schema.clone()], true).unwrap();
+        assert_eq!(merged.game_data.len(), 3);
+        assert_eq!(merged.csv_file, "");
+        assert_eq!((report.games(), report.duplicates()), (3, 1));
+        let counts: Vec<(usize, usize, usize)> = report
+            .sources
+            .iter()
+            .map(|source| (source.games, source.duplicates, source.skipped.len()))
+            .collect();
+        assert_eq!(counts, vec![(2, 0, 1), (1, 1, 0)]);
+        assert_eq!(report.sources[1].path, schema.to_string_lossy());
+        assert_eq!(merged.game_data[2].id.as_deref(), Some("00000000000000b2"));
+
+        // Without dedup every game stays; a binary dataset merges too.
+        let (all, report) = GamesData::merge(&[legacy.clone(), schema.clone()], false).unwrap();
+        assert_eq!((all.game_data.len(), report.duplicates()), (4, 0));
+        all.save_binary(&binary).unwrap();
+        let (again, report) = GamesData::merge(&[binary.clone(), legacy.clone()], true).unwrap();
+        assert_eq!(again.game_data, merged.game_data);
+        assert_eq!(report.sources[1].duplicates, 2);
+
+        std::fs::write(&binary, b"TTGD\x09").unwrap();
+        let broken = GamesData::merge(&[legacy.clone(), binary.clone()], true);
+        assert!(matches!(broken, Err(DataError::Dataset { .. })));
+        let missing = GamesData::merge(&[legacy.clone(), dir.join("tictac_merge_missing.csv")], true);
+        assert!(matches!(missing, Err(DataError::NotFound(_))));
+        for path in [legacy, schema, binary] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dataset_meta() {
+        use crate::recorder::{CsvRecorder, GameRecorder};
+
+        let dir = std::env::temp_dir();
+        let recorded = dir.join(format!("tictac_meta_{}.csv", std::process::id()));
+        let wider = dir.join(format!("tictac_meta_wide_{}.csv", std::process::id()));
+        let legacy = dir.join(format!("tictac_meta_legacy_{}.csv", std::process::id()));
+        let meta = DatasetMeta::new(&["random", "heuristic"], Some(7));
+        for path in [&recorded, &recorded] {
+            // Appending again doesn't repeat the metadata.
+            let mut recorder = CsvRecorder::with_meta(path, &meta).unwrap();
+            recorder.record_state(&[0, 0, 0, 0, 1, 0, 0, 0, 0]);
+            recorder.record_result("ai");
+            recorder.finish().unwrap();
+        }
+        let text = std::fs::read_to_string(&recorded).unwrap();
+        assert!(text.starts_with(META_TAG));
+        assert_eq!(text.matches(META_TAG).count(), 1);
+        let mut data = GamesData::new(recorded.to_string_lossy().into_owned());
+        let report = data.read_data().unwrap();
+        assert_eq!((report.games, report.rows_skipped()), (2, 0));
+        assert_eq!(data.meta.as_ref(), Some(&meta));
+        assert_eq!(data.filter(|_| true).meta, data.meta);
+
+        std::fs::write(&legacy, "\n1,0,0,0,0,0,0,0,0,\n1,-1,0,0,0,0,0,0,0,draw").unwrap();
+        let mut plain = GamesData::new(legacy.to_string_lossy().into_owned());
+        plain.read_data().unwrap();
+        assert_eq!((plain.len(), plain.meta.as_ref()), (1, None));
+
+        let wide = DatasetMeta { board_size: 4, ..meta.clone() };
+        std::fs::write(&wider, format!("{}{},{}\n{}\n", wide, SCHEMA_TAG, SCHEMA_VERSION, CSV_HEADER)).unwrap();
+        let (merged, _) = GamesData::merge(&[recorded.clone(), legacy.clone()], false).unwrap();
+        assert_eq!((merged.len(), merged.meta), (3, None));
+        let refused = GamesData::merge(&[recorded.clone(), legacy.clone(), wider.clone()], false);
+        match refused {
+            Err(error @ DataError::IncompatibleBoard { board_size: 4, expected: 3, .. }) => {
+                assert!(error.to_string().ends_with("holds games on a 4x4 board, the files before on a 3x3 board"))
+            }
+            other => panic!("{:?}", other.map(|(_, report)| report)),
+        }
+        assert!(GamesData::merge(&[legacy.clone(), wider.clone()], false).is_ok());
+
+        // Saving keeps the metadata.
+        data.save_csv(&wider).unwrap();
+        let mut saved = GamesData::new(wider.to_string_lossy().into_owned());
+        saved.read_data().unwrap();
+        assert_eq!(saved.meta, data.meta);
+        for path in [recorded, wider, legacy] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_gzip_compressed_data() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let dir = std::env::temp_dir();
+        let plain = dir.join(format!("tictac_gzip_{}.csv", std::process::id()));
+        let packed = dir.join(format!("tictac_gzip_{}.csv.gz", std::process::id()));
+        let unnamed = dir.join(format!("tictac_gzip_{}.data", std::process::id()));
+        let fixture = format!(
+            "\n1,0,0,0,0,0,0,0,0,\
+             \n1,-1,0,0,0,0,0,0,0,draw\
+             \n1,2,0,0,0,0,0,0,0,\n{},{}\n{}\n\
+             00000000000000a1,1700000000,1,ai,ai_2,0,0,0,0,1,0,0,0,0,,ai_2\n\
+             00000000000000a1,1700000000,2,ai,ai_2,-1,0,0,0,1,0,0,0,0,ai_2,ai\n",
+            SCHEMA_TAG, SCHEMA_VERSION, CSV_HEADER
+        );
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(fixture.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        std::fs::write(&plain, &fixture).unwrap();
+        std::fs::write(&packed, &compressed).unwrap();
+        // Without the extension the magic bytes tell.
+        std::fs::write(&unnamed, &compressed).unwrap();
+
+        let read = |path: &PathBuf| {
+            let mut data = GamesData::new(path.to_string_lossy().into_owned());
+            data.read_data().map(|report| (report, data.game_data))
+        };
+        let expected = read(&plain).unwrap();
+        assert_eq!((expected.0.games, expected.0.rows_skipped()), (2, 1));
+        assert_eq!(read(&packed).unwrap(), expected);
+        assert_eq!(read(&unnamed).unwrap(), expected);
+        let streamed: Vec<GameData> = GamesData::iter_from_csv(&packed).map(Result::unwrap).collect();
+        assert_eq!(streamed, expected.1);
+
+        std::fs::write(&packed, &compressed[..compressed.len() / 2]).unwrap();
+        match read(&packed) {
+            Err(error @ DataError::Io(..)) => {
+                assert!(error.to_string().starts_with(&format!("could not read {}:", packed.display())), "{}", error)
+            }
+            other => panic!("{:?}", other),
+        }
+        for path in [plain, packed, unnamed] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_export_flat() {
+        let mut data = GamesData::new(fixture_path("games.csv"));
+        data.read_data().unwrap();
+        let path = std::env::temp_dir().join(format!("tictac_flat_{}.csv", std::process::id()));
+        let read_back = |opts: &FlatExportOptions| {
+            let rows = data.export_flat(&path, opts).unwrap();
+            let mut reader = ReaderBuilder::new().from_path(&path).unwrap();
+            assert_eq!(reader.headers().unwrap().iter().collect::<Vec<&str>>().join(","), FLAT_HEADER);
+            let parsed: Vec<Vec<i64>> = reader
+                .records()
+                .map(|record| record.unwrap().iter().map(|field| field.parse().unwrap()).collect())
+                .collect();
+            assert_eq!(parsed.len(), rows);
+            parsed
+        };
+
+        let rows = read_back(&FlatExportOptions::default());
+        let set = data.training_set();
+        assert_eq!(rows.len(), set.y.len());
+        for (row, (x, &y)) in rows.iter().zip(set.x.iter().zip(&set.y)) {
+            assert_eq!(row.len(), 13);
+            assert!(row[..9].iter().zip(x).all(|(&cell, &value)| cell as f32 == value));
+            assert_eq!(row[9] as usize, y);
+            assert!((-1..=1).contains(&row[10]));
+        }
+        // The first game's moves, numbered from 1, and its outcome for
+        // each mover.
+        let first = &data[0];
+        let moves: Vec<&Vec<i64>> = rows.iter().filter(|row| row[11] == 0).collect();
+        assert_eq!(moves.len(), first.move_count());
+        assert!(moves.iter().enumerate().all(|(i, row)| row[12] == i as i64 + 1));
+        let expected_code = |i: usize| match first.winner.as_str() {
+            "draw" => 0,
+            winner if winner == first.mover(i) => 1,
+            _ => -1,
+        };
+        assert!(moves.iter().enumerate().all(|(i, row)| row[10] == expected_code(i)));
+
+        let decided = read_back(&FlatExportOptions {
+            augment: false,
+            keep_draws: false,
+        });
+        assert_eq!(decided.len(), data.filter(|game| game.winner != "draw").training_set().y.len());
+        assert!(decided.iter().all(|row| row[10] != 0));
+        let augmented = read_back(&FlatExportOptions {
+            augment: true,
+            keep_draws: true,
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(augmented.len(), data.augment().training_set().y.len());
+        assert_eq!(augmented.last().unwrap()[11] as usize, data.len() - 1);
+    }
+
+    #[test]
+    fn test_read_data_missing_file() {
+        let mut data = GamesData::new("no/such/table.csv".to_string());
+        match data.read_data() {
+            Err(DataError::NotFound(path)) => assert_eq!(path, "no/such/table.csv"),
+            other => panic!("{:?}", other),
+        }
+        assert!(data.game_data.is_empty());
+    }
+}
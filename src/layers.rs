@@ -0,0 +1,374 @@
+// Building blocks for HimNetwork. Every layer works on matrices stored one
+// row per example and caches in forward whatever its backward needs.
+//
+// Gradients passed to backward are those of the batch-averaged loss, so the
+// parameter gradients a layer stores are already averaged too.
+
+pub trait Layer {
+    /// Output for a batch; remembers its input for backward.
+    fn forward(&mut self, input: &[Vec<f32>]) -> Vec<Vec<f32>>;
+
+    /// Given the gradient w.r.t. the last forward output, store the
+    /// parameter gradients and return the gradient w.r.t. the input.
+    fn backward(&mut self, grad_output: &[Vec<f32>]) -> Vec<Vec<f32>>;
+
+    /// Forward pass for inference: leaves the caches alone and always uses
+    /// running statistics.
+    fn infer(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>>;
+
+    /// W := W - alpha * dW for whatever parameters the layer has.
+    fn update(&mut self, _alpha: f32) {}
+
+    /// Switch between training and inference behavior (batch norm).
+    fn set_training(&mut self, _training: bool) {}
+}
+
+impl<T: Layer + ?Sized> Layer for Box<T> {
+    fn forward(&mut self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        (**self).forward(input)
+    }
+
+    fn backward(&mut self, grad_output: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        (**self).backward(grad_output)
+    }
+
+    fn infer(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        (**self).infer(input)
+    }
+
+    fn update(&mut self, alpha: f32) {
+        (**self).update(alpha)
+    }
+
+    fn set_training(&mut self, training: bool) {
+        (**self).set_training(training)
+    }
+}
+
+/// Activation used by a hidden layer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Activation {
+    ReLU,
+    /// x for x > 0, alpha * x otherwise.
+    LeakyReLU(f32),
+    /// x for x > 0, alpha * (e^x - 1) otherwise.
+    ELU(f32),
+}
+
+impl Activation {
+    pub fn apply(&self, x: f32) -> f32 {
+        match *self {
+            Activation::ReLU => x.max(0.0),
+            Activation::LeakyReLU(alpha) => if x > 0.0 { x } else { alpha * x },
+            Activation::ELU(alpha) => if x > 0.0 { x } else { alpha * (x.exp() - 1.0) },
+        }
+    }
+
+    /// Derivative with respect to z. At exactly zero we take the left-hand
+    /// side (0 for ReLU, alpha for the others), matching the old relu_deriv.
+    pub fn derivative(&self, x: f32) -> f32 {
+        match *self {
+            Activation::ReLU => if x > 0.0 { 1.0 } else { 0.0 },
+            Activation::LeakyReLU(alpha) => if x > 0.0 { 1.0 } else { alpha },
+            Activation::ELU(alpha) => if x > 0.0 { 1.0 } else { alpha * x.exp() },
+        }
+    }
+}
+
+/// Fully connected layer: Z = X * W^T + B.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dense {
+    pub w: Vec<Vec<f32>>, // [node][connection]
+    pub b: Vec<f32>,      // [node]
+    pub d_w: Vec<Vec<f32>>,
+    pub db: Vec<f32>,
+    input: Vec<Vec<f32>>,
+}
+
+impl Dense {
+    /// Zero-initialized layer mapping `inputs` values to `outputs` nodes.
+    pub fn new(inputs: usize, outputs: usize) -> Dense {
+        Dense {
+            w: vec![vec![0.0; inputs]; outputs],
+            b: vec![0.0; outputs],
+            d_w: vec![vec![0.0; inputs]; outputs],
+            db: vec![0.0; outputs],
+            input: Vec::new(),
+        }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.w.first().map_or(0, |row| row.len())
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.w.len()
+    }
+}
+
+impl Layer for Dense {
+    fn forward(&mut self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        self.input = input.to_vec();
+        self.infer(input)
+    }
+
+    /// dW = dZ^T * X, db = column sums of dZ, dX = dZ * W.
+    fn backward(&mut self, grad_output: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let inputs = self.inputs();
+        self.d_w = vec![vec![0.0; inputs]; self.outputs()];
+        self.db = vec![0.0; self.outputs()];
+        for (g_row, x_row) in grad_output.iter().zip(self.input.iter()) {
+            for (j, &g) in g_row.iter().enumerate() {
+                self.db[j] += g;
+                for (d, x) in self.d_w[j].iter_mut().zip(x_row.iter()) {
+                    *d += g * x;
+                }
+            }
+        }
+        grad_output
+            .iter()
+            .map(|g_row| {
+                let mut row = vec![0.0; inputs];
+                for (g, node) in g_row.iter().zip(self.w.iter()) {
+                    for (val, w) in row.iter_mut().zip(node.iter()) {
+                        *val += g * w;
+                    }
+                }
+                row
+            })
+            .collect()
+    }
+
+    fn infer(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let mut out = multiply_matrix(&self.w, input);
+        for row in out.iter_mut() {
+            for (val, b) in row.iter_mut().zip(self.b.iter()) {
+                *val += b;
+            }
+        }
+        out
+    }
+
+    fn update(&mut self, alpha: f32) {
+        for (node, grads) in self.w.iter_mut().zip(self.d_w.iter()) {
+            for (weight, grad) in node.iter_mut().zip(grads.iter()) {
+                *weight -= alpha * grad;
+            }
+        }
+        for (bias, grad) in self.b.iter_mut().zip(self.db.iter()) {
+            *bias -= alpha * grad;
+        }
+    }
+}
+
+/// Elementwise activation: ReLU or one of its variants.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActivationLayer {
+    pub activation: Activation,
+    input: Vec<Vec<f32>>,
+}
+
+impl ActivationLayer {
+    pub fn new(activation: Activation) -> ActivationLayer {
+        ActivationLayer { activation, input: Vec::new() }
+    }
+}
+
+impl Layer for ActivationLayer {
+    fn forward(&mut self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        self.input = input.to_vec();
+        self.infer(input)
+    }
+
+    fn backward(&mut self, grad_output: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        grad_output
+            .iter()
+            .zip(self.input.iter())
+            .map(|(g_row, z_row)| {
+                g_row
+                    .iter()
+                    .zip(z_row.iter())
+                    .map(|(g, &z)| g * self.activation.derivative(z))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn infer(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        input
+            .iter()
+            .map(|row| row.iter().map(|&v| self.activation.apply(v)).collect())
+            .collect()
+    }
+}
+
+/// Row-wise softmax. Its output is kept so the network can read the
+/// probabilities of the last forward pass.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Softmax {
+    output: Vec<Vec<f32>>,
+}
+
+impl Softmax {
+    pub fn new() -> Softmax {
+        Softmax::default()
+    }
+
+    /// Probabilities from the last forward pass (empty before the first).
+    pub fn output(&self) -> &[Vec<f32>] {
+        &self.output
+    }
+}
+
+impl Layer for Softmax {
+    fn forward(&mut self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        self.output = self.infer(input);
+        self.output.clone()
+    }
+
+    /// dZ_j = s_j * (g_j - sum_k g_k s_k). Paired with cross-entropy this
+    /// reduces to A - Y, which HimNetwork uses directly.
+    fn backward(&mut self, grad_output: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        grad_output
+            .iter()
+            .zip(self.output.iter())
+            .map(|(g_row, s_row)| {
+                let dot: f32 = g_row.iter().zip(s_row.iter()).map(|(g, s)| g * s).sum();
+                g_row.iter().zip(s_row.iter()).map(|(g, s)| s * (g - dot)).collect()
+            })
+            .collect()
+    }
+
+    fn infer(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        softmax_rows(input)
+    }
+}
+
+/// Layers applied one after another.
+pub struct Sequential<L = Box<dyn Layer>> {
+    pub layers: Vec<L>,
+}
+
+impl<L: Layer> Layer for Sequential<L> {
+    fn forward(&mut self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let mut out = input.to_vec();
+        for layer in self.layers.iter_mut() {
+            out = layer.forward(&out);
+        }
+        out
+    }
+
+    fn backward(&mut self, grad_output: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let mut grad = grad_output.to_vec();
+        for layer in self.layers.iter_mut().rev() {
+            grad = layer.backward(&grad);
+        }
+        grad
+    }
+
+    fn infer(&self, input: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let mut out = input.to_vec();
+        for layer in self.layers.iter() {
+            out = layer.infer(&out);
+        }
+        out
+    }
+
+    fn update(&mut self, alpha: f32) {
+        for layer in self.layers.iter_mut() {
+            layer.update(alpha);
+        }
+    }
+
+    fn set_training(&mut self, training: bool) {
+        for layer in self.layers.iter_mut() {
+            layer.set_training(training);
+        }
+    }
+}
+
+/// result[i][j] = sum_k w[j][k] * x[i][k], i.e. X * W^T.
+pub fn multiply_matrix(w: &[Vec<f32>], x: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let mut result = vec![vec![0.0; w.len()]; x.len()];
+    for (i, row) in x.iter().enumerate() {
+        for (j, node) in w.iter().enumerate() {
+            let mut sum = 0.0;
+            for (wk, xk) in node.iter().zip(row.iter()) {
+                sum += wk * xk;
+            }
+            result[i][j] = sum;
+        }
+    }
+    result
+}
+
+pub fn softmax_rows(z: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    z.iter()
+        .map(|row| {
+            let max_val = row.iter().cloned().fold(f32::MIN, f32::max);
+            let exps: Vec<f32> = row.iter().map(|&v| (v - max_val).exp()).collect();
+            let sum_exps: f32 = exps.iter().sum();
+            exps.iter().map(|e| e / sum_exps).collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mean_squared(layer: &mut dyn Layer, input: &[Vec<f32>]) -> f32 {
+        let out = layer.forward(input);
+        let n = (out.len() * out[0].len()) as f32;
+        out.iter().flatten().map(|v| v * v).sum::<f32>() / n
+    }
+
+    #[test]
+    fn test_sequential_input_gradient_matches_finite_differences() {
+        let mut dense = Dense::new(3, 4);
+        for (j, node) in dense.w.iter_mut().enumerate() {
+            for (k, w) in node.iter_mut().enumerate() {
+                *w = 0.3 * ((j * 3 + k) as f32 * 0.7).sin();
+            }
+        }
+        dense.b = vec![0.1, -0.2, 0.05, 0.3];
+        let mut model: Sequential = Sequential {
+            layers: vec![
+                Box::new(dense),
+                Box::new(ActivationLayer::new(Activation::ELU(0.5))),
+                Box::new(Softmax::new()),
+            ],
+        };
+        let input = vec![vec![0.5, -1.0, 0.25], vec![-0.3, 0.8, 1.0]];
+        let out = model.forward(&input);
+        let n = (out.len() * out[0].len()) as f32;
+        let grad: Vec<Vec<f32>> = out
+            .iter()
+            .map(|row| row.iter().map(|v| 2.0 * v / n).collect())
+            .collect();
+        let d_input = model.backward(&grad);
+
+        let eps = 1e-2;
+        for i in 0..2 {
+            for k in 0..3 {
+                let mut plus = input.clone();
+                plus[i][k] += eps;
+                let mut minus = input.clone();
+                minus[i][k] -= eps;
+                let numeric =
+                    (mean_squared(&mut model, &plus) - mean_squared(&mut model, &minus)) / (2.0 * eps);
+                assert!((numeric - d_input[i][k]).abs() < 1e-3, "{} vs {}", numeric, d_input[i][k]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_infer_matches_forward_without_caching() {
+        let mut dense = Dense::new(2, 2);
+        dense.w = vec![vec![1.0, -1.0], vec![0.5, 2.0]];
+        let input = vec![vec![1.0, 2.0]];
+        assert_eq!(dense.infer(&input), vec![vec![-1.0, 4.5]]);
+        assert!(dense.input.is_empty());
+        assert_eq!(dense.forward(&input), dense.infer(&input));
+    }
+}
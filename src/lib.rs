@@ -0,0 +1,25 @@
+pub mod ai;
+pub mod games_binary;
+pub mod games_json;
+pub mod input;
+pub mod menu;
+pub mod meta;
+pub mod model;
+pub mod notation;
+pub mod output;
+pub mod recorder;
+pub mod selfplay;
+pub mod series;
+pub mod solver;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod stats;
+pub mod tournament;
+pub mod g_class;
+pub mod g_ai;
+pub mod batch_norm;
+pub mod him_network;
+pub mod layers;
+pub mod numpy;
+pub mod onnx;
+pub mod training;
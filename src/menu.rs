@@ -0,0 +1,195 @@
+// The start menu: pick a game mode on the terminal, answer its questions
+// and play.
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ai::{HeuristicAi, NetworkAi, RandomAi};
+use crate::output::{get_int, get_key_layout, get_string, Game, HumanConsole, MovePolicy, Player, Seat};
+use crate::selfplay;
+use crate::stats::SessionStats;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    HumanVsHuman,
+    HumanVsRandom,
+    HumanVsHeuristic,
+    HumanVsNetwork,
+    AiVsAi,
+}
+
+impl Mode {
+    /// In menu order.
+    pub const ALL: [Mode; 5] = [
+        Mode::HumanVsHuman,
+        Mode::HumanVsRandom,
+        Mode::HumanVsHeuristic,
+        Mode::HumanVsNetwork,
+        Mode::AiVsAi,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Mode::HumanVsHuman => "human vs human",
+            Mode::HumanVsRandom => "human vs random AI",
+            Mode::HumanVsHeuristic => "human vs heuristic AI",
+            Mode::HumanVsNetwork => "human vs network (needs a saved model)",
+            Mode::AiVsAi => "ai vs ai, generating training data",
+        }
+    }
+
+    /// The mode numbered `input` in the menu, counting from 1.
+    pub fn parse(input: &str) -> Option<Mode> {
+        let number = input.trim().parse::<usize>().ok()?;
+        Mode::ALL.get(number.checked_sub(1)?).copied()
+    }
+}
+
+/// `input` as a player's symbol: one visible character, not `taken`. Digits
+/// are refused since they'd read as cell numbers on the board.
+pub fn check_symbol(input: &str, taken: Option<char>) -> Result<char, String> {
+    let mut chars = input.trim().chars();
+    match (chars.next(), chars.next()) {
+        (Some(symbol), None) if taken.is_some_and(|taken| taken.eq_ignore_ascii_case(&symbol)) => {
+            Err(format!("{} is already taken by the other player", symbol))
+        }
+        (Some(symbol), None) if symbol.is_ascii_digit() => Err(format!("{} would look like a cell number", symbol)),
+        (Some(symbol), None) if symbol.is_alphanumeric() || symbol.is_ascii_punctuation() => Ok(symbol),
+        _ => Err("Enter a single letter or sign".to_string()),
+    }
+}
+
+/// Show the menu until a valid mode is picked, then play it.
+pub fn run() {
+    println!("Tic-tac-toe");
+    for (number, mode) in Mode::ALL.iter().enumerate() {
+        println!("  {}. {}", number + 1, mode.label());
+    }
+    let mode = loop {
+        match Mode::parse(&get_string("Choose a mode")) {
+            Some(mode) => break mode,
+            None => println!("Enter a number between 1 and {}", Mode::ALL.len()),
+        }
+    };
+    if mode == Mode::AiVsAi {
+        generate();
+        return;
+    }
+    let (name, symbol) = ask_player("Player 1", None, None);
+    let opponent: Box<dyn MovePolicy> = match mode {
+        Mode::HumanVsHuman => {
+            let (name2, symbol2) = ask_player("Player 2", Some(&name), Some(symbol));
+            play(
+                (Player::new(name, symbol), Box::new(HumanConsole)),
+                (Player::new(name2, symbol2), Box::new(HumanConsole)),
+            );
+            return;
+        }
+        Mode::HumanVsRandom => Box::new(RandomAi::new()),
+        Mode::HumanVsHeuristic => Box::new(HeuristicAi::new(other_symbol(symbol))),
+        Mode::HumanVsNetwork => {
+            let path = get_string("Enter the path of a saved model");
+            Box::new(NetworkAi::load(other_symbol(symbol), Path::new(&path)))
+        }
+        Mode::AiVsAi => unreachable!(),
+    };
+    let ai_name = if name == "computer" { "computer_2" } else { "computer" };
+    play(
+        (Player::new(name, symbol), Box::new(HumanConsole)),
+        (Player::ai(ai_name.to_string(), other_symbol(symbol)), opponent),
+    );
+}
+
+/// The symbol for the side playing against `symbol`.
+pub(crate) fn other_symbol(symbol: char) -> char {
+    if symbol.eq_ignore_ascii_case(&'O') {
+        'X'
+    } else {
+        'O'
+    }
+}
+
+/// Name and symbol of a human player; both must differ from the other
+/// player's.
+fn ask_player(who: &str, taken_name: Option<&str>, taken_symbol: Option<char>) -> (String, char) {
+    let name = loop {
+        let name = get_string(&format!("{} name", who));
+        if Some(name.as_str()) == taken_name {
+            println!("{} is already playing", name);
+        } else {
+            break name;
+        }
+    };
+    let symbol = loop {
+        match check_symbol(&get_string(&format!("{} symbol", who)), taken_symbol) {
+            Ok(symbol) => break symbol,
+            Err(e) => println!("{}", e),
+        }
+    };
+    (name, symbol)
+}
+
+fn play(seat1: Seat, seat2: Seat) {
+    let mut game = Game::with_policies(seat1, seat2);
+    game.set_key_layout(get_key_layout(
+        "Number the cells like a keypad (7 8 9 on top) or in reading order (1 2 3 on top)? keypad/reading",
+    ));
+    let result = game.play();
+    match result.winner {
+        Some(name) => println!("{} won in {} moves", name, result.turns),
+        None => println!("Draw"),
+    }
+}
+
+/// Random-vs-random games appended to table.csv, then a summary.
+fn generate() {
+    let cycles = loop {
+        match get_int("How many games?") {
+            n if n > 0 => break n as usize,
+            _ => println!("Enter a positive number"),
+        }
+    };
+    let random = |_symbol: char, seed: u64| -> Box<dyn MovePolicy> { Box::new(RandomAi::with_seed(seed)) };
+    let seed = rand::random();
+    let results = selfplay::generate_games(cycles, random, random, seed);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    match selfplay::write_run(&results, "table.csv", ["random", "random"], seed, timestamp) {
+        Ok(id) => println!("recorded run {}", id),
+        Err(e) => eprintln!("could not record games: {}", e),
+    }
+    let mut stats = SessionStats::new();
+    for result in &results {
+        stats.add(result);
+    }
+    stats.print_report();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_parse() {
+        assert_eq!(Mode::parse("1"), Some(Mode::HumanVsHuman));
+        assert_eq!(Mode::parse(" 5\n"), Some(Mode::AiVsAi));
+        for bad in ["0", "6", "-1", "", "one", "1.0"] {
+            assert_eq!(Mode::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_check_symbol() {
+        assert_eq!(check_symbol("X", None), Ok('X'));
+        assert_eq!(check_symbol(" O \n", Some('X')), Ok('O'));
+        assert!(check_symbol("X", Some('X')).is_err());
+        assert!(check_symbol("x", Some('X')).is_err());
+        assert!(check_symbol("", None).is_err());
+        assert!(check_symbol("XO", None).is_err());
+        assert!(check_symbol("\u{7}", None).is_err());
+        assert!(check_symbol("7", None).is_err());
+        assert_eq!(other_symbol('o'), 'X');
+        assert_eq!(other_symbol('X'), 'O');
+        assert_eq!(other_symbol('#'), 'O');
+    }
+}
@@ -0,0 +1,118 @@
+// What a CSV dataset was generated with, as comment lines before its
+// schema line:
+//
+//     # tictac-meta
+//     # format_version: 2
+//     # agents: random, heuristic
+//     # seed: 42
+//     # board_size: 3
+//     # crate_version: 0.1.0
+//
+// Only `CsvRecorder::with_meta` writes them, into a new file. Every key is
+// optional and unknown keys are ignored; other comment lines aren't
+// metadata.
+use std::fmt;
+
+use crate::input::SCHEMA_VERSION;
+
+/// First line of the metadata.
+pub const META_TAG: &str = "# tictac-meta";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatasetMeta {
+    /// `input::SCHEMA_VERSION` of the writer, 0 when not given.
+    pub format_version: u32,
+    /// The policies of player 1 and player 2, e.g. "random".
+    pub agents: Vec<String>,
+    /// Seed of the generated games, when they can be generated again.
+    pub seed: Option<u64>,
+    /// Cells per side, 3 when not given.
+    pub board_size: usize,
+    /// Version of the crate that wrote the file, empty when not given.
+    pub crate_version: String,
+}
+
+impl Default for DatasetMeta {
+    fn default() -> DatasetMeta {
+        DatasetMeta {
+            format_version: 0,
+            agents: Vec::new(),
+            seed: None,
+            board_size: 3,
+            crate_version: String::new(),
+        }
+    }
+}
+
+impl DatasetMeta {
+    /// Metadata of games this build generates with `agents` from `seed`.
+    pub fn new(agents: &[&str], seed: Option<u64>) -> DatasetMeta {
+        DatasetMeta {
+            format_version: SCHEMA_VERSION,
+            agents: agents.iter().map(|agent| agent.to_string()).collect(),
+            seed,
+            board_size: 3,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Take the value of a `# key: value` line. Lines that aren't one, or
+    /// whose key is unknown, change nothing; a value that doesn't parse is
+    /// an error.
+    pub fn read_line(&mut self, line: &str) -> Result<(), String> {
+        let Some((key, value)) = line.strip_prefix('#').and_then(|rest| rest.split_once(':')) else {
+            return Ok(());
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let number = |value: &str| format!("metadata {} {:?}", key, value);
+        match key {
+            "format_version" => self.format_version = value.parse().map_err(|_| number(value))?,
+            "agents" => self.agents = value.split(',').map(|agent| agent.trim().to_string()).collect(),
+            "seed" => self.seed = Some(value.parse().map_err(|_| number(value))?),
+            "board_size" => self.board_size = value.parse().map_err(|_| number(value))?,
+            "crate_version" => self.crate_version = value.to_string(),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The comment lines, `META_TAG` first, each ending in a newline.
+impl fmt::Display for DatasetMeta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", META_TAG)?;
+        writeln!(f, "# format_version: {}", self.format_version)?;
+        if !self.agents.is_empty() {
+            writeln!(f, "# agents: {}", self.agents.join(", "))?;
+        }
+        if let Some(seed) = self.seed {
+            writeln!(f, "# seed: {}", seed)?;
+        }
+        writeln!(f, "# board_size: {}", self.board_size)?;
+        writeln!(f, "# crate_version: {}", self.crate_version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meta_lines_round_trip() {
+        let meta = DatasetMeta::new(&["random", "heuristic"], Some(42));
+        let text = meta.to_string();
+        assert!(text.starts_with("# tictac-meta\n# format_version: 2\n# agents: random, heuristic\n# seed: 42\n"));
+        let mut read = DatasetMeta::default();
+        for line in text.lines() {
+            read.read_line(line).unwrap();
+        }
+        assert_eq!(read, meta);
+
+        let mut partial = DatasetMeta::default();
+        partial.read_line("#  board_size : 4").unwrap();
+        partial.read_line("# written by hand").unwrap();
+        partial.read_line("# generator: v2").unwrap();
+        assert_eq!(partial, DatasetMeta { board_size: 4, ..DatasetMeta::default() });
+        assert_eq!(partial.read_line("# seed: soon"), Err("metadata seed \"soon\"".to_string()));
+    }
+}
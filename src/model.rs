@@ -0,0 +1,99 @@
+// What a move-predicting network offers players and training code, so
+// they don't depend on one implementation. Boards are from the side of
+// the player to move, as in `ai`: 1.0 for its marks, -1.0 for the
+// opponent's and 0.0 for empty cells.
+use crate::g_class::NnError;
+use crate::training::FitOptions;
+
+/// Returned by `PolicyModel::train_on`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrainReport {
+    /// Mean loss over the training examples after each epoch.
+    pub loss: Vec<f32>,
+}
+
+pub trait PolicyModel {
+    /// How likely each cell is to be the move played, summing to 1.
+    fn predict_probs(&self, board: &[f32; 9]) -> [f32; 9];
+
+    /// The most likely cell among those set in `legal_mask`. Panics when
+    /// none is.
+    fn best_move(&self, board: &[f32; 9], legal_mask: &[bool; 9]) -> usize {
+        let probs = self.predict_probs(board);
+        (0..9)
+            .filter(|&cell| legal_mask[cell])
+            .max_by(|&a, &b| probs[a].total_cmp(&probs[b]))
+            .expect("no legal move")
+    }
+
+    /// Train on boards `x` labelled with the cell played from them, `y`.
+    /// Fails on examples that don't fit the network, leaving it untrained.
+    fn train_on(&mut self, x: &[Vec<f32>], y: &[usize], config: &FitOptions) -> Result<TrainReport, NnError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::ai::{NetworkAi, RandomAi};
+    use crate::g_class::{NeuralNetwork, OutputKind};
+    use crate::him_network::HimNetwork;
+    use crate::output::{Game, Player, Verbosity};
+
+    #[test]
+    fn test_both_networks_play_through_the_trait() {
+        let mut him = HimNetwork::new();
+        him.init_params_with_rng(&mut StdRng::seed_from_u64(1));
+        let g_class = NeuralNetwork::new_seeded(&[9, 16, 9], 0.1, 1).with_output(OutputKind::Softmax);
+        let models: Vec<Box<dyn PolicyModel>> = vec![Box::new(him), Box::new(g_class)];
+
+        // Take the free cell of the top row.
+        let x = vec![
+            vec![1.0, 1.0, 0.0, -1.0, -1.0, 0.0, 0.0, 0.0, 0.0],
+            vec![0.0, 1.0, 1.0, -1.0, 0.0, -1.0, 0.0, 0.0, 0.0],
+        ];
+        let y = vec![2, 0];
+        let options = FitOptions { epochs: 3, batch_size: 2, ..FitOptions::default() };
+        for (seed, mut model) in models.into_iter().enumerate() {
+            let report = model.train_on(&x, &y, &options).unwrap();
+            assert_eq!(report.loss.len(), 3);
+            assert!(report.loss.iter().all(|loss| loss.is_finite()), "{:?}", report.loss);
+
+            let probs = model.predict_probs(&[0.0; 9]);
+            assert!((probs.iter().sum::<f32>() - 1.0).abs() < 1e-4, "{:?}", probs);
+            let mut legal = [false; 9];
+            legal[5] = true;
+            legal[7] = true;
+            assert!([5, 7].contains(&model.best_move(&x[0].clone().try_into().unwrap(), &legal)));
+
+            let mut game = Game::with_policies(
+                (Player::ai("network".to_string(), 'X'), Box::new(NetworkAi::with_model('X', model))),
+                (Player::ai("random".to_string(), 'O'), Box::new(RandomAi::with_seed(seed as u64))),
+            );
+            game.set_verbosity(Verbosity::Silent);
+            let result = game.play();
+            assert!(game.game_over);
+            assert!((5..=9).contains(&result.turns));
+        }
+    }
+
+    #[test]
+    fn test_train_on_reports_bad_examples() {
+        let mut him = HimNetwork::new();
+        him.init_params_with_rng(&mut StdRng::seed_from_u64(1));
+        let mut g_class = NeuralNetwork::new_seeded(&[9, 16, 9], 0.1, 1).with_output(OutputKind::Softmax);
+        let options = FitOptions { epochs: 1, learning_rate: 0.5, ..FitOptions::default() };
+        let short = vec![vec![0.0; 8]];
+        let mismatch = NnError::InputSizeMismatch { expected: 9, found: 8 };
+        assert_eq!(him.train_on(&short, &[4], &options), Err(mismatch));
+        assert_eq!(g_class.train_on(&short, &[4], &options), Err(mismatch));
+        let negative = FitOptions { learning_rate: -1.0, ..options };
+        assert_eq!(g_class.train_on(&[vec![0.0; 9]], &[4], &negative), Err(NnError::InvalidLearningRate));
+
+        // The rate of `options` is only for that call.
+        g_class.train_on(&[vec![0.0; 9]], &[4], &options).unwrap();
+        assert_eq!(g_class.learning_rate(), 0.1);
+    }
+}
@@ -0,0 +1,267 @@
+// Plain-text game notation for sharing games:
+//
+//     3x3
+//     1. alice b2, 2. bob a1, 3. alice a3, ...
+//     alice wins
+//
+// The first line is the board size. Cells are named by row letter and
+// column number from the top-left, the way `parse_move` reads them. The
+// last line is "<name> wins", "draw" or "unfinished".
+use std::fmt;
+
+use crate::output::{parse_move, GameResult, MoveError, MoveRecord, Player, Table};
+
+/// Why `GameResult::from_notation` rejected a notation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NotationError {
+    /// Text that isn't a move list followed by a result line.
+    Malformed(String),
+    /// Move `move_number` was made by the player who just moved.
+    WrongPlayer { move_number: u32 },
+    /// Move `move_number` is not allowed on the board at that point.
+    IllegalMove { move_number: u32, error: MoveError },
+    /// The result line disagrees with the replayed game.
+    ResultMismatch { claimed: String, actual: String },
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::Malformed(text) => write!(f, "Can't read \"{}\"", text),
+            NotationError::WrongPlayer { move_number } => {
+                write!(f, "Move {} is played out of turn", move_number)
+            }
+            NotationError::IllegalMove { move_number, error } => {
+                write!(f, "Move {} is illegal: {}", move_number, error)
+            }
+            NotationError::ResultMismatch { claimed, actual } => {
+                write!(f, "The notation says \"{}\" but the game ends \"{}\"", claimed, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+/// "b2" for index 4 of a 3x3 board.
+fn cell_name(size: usize, index: usize) -> String {
+    format!("{}{}", (b'a' + (index / size) as u8) as char, index % size + 1)
+}
+
+/// Rows are named by a single letter, so no board is wider than this.
+const MAX_SIZE: usize = 26;
+
+/// Side of the smallest board at least 3x3 that has the cell `name`, None
+/// past `MAX_SIZE`.
+fn size_needed(name: &str) -> Option<usize> {
+    let mut chars = name.chars();
+    let letter = chars.next().filter(char::is_ascii_lowercase)?;
+    let col = chars.as_str().parse::<usize>().ok()?;
+    Some((letter as usize - 'a' as usize + 1).max(col).max(3)).filter(|&size| size <= MAX_SIZE)
+}
+
+/// The side of a "4x4" header, None unless square and 3 to `MAX_SIZE`.
+fn header_size(rows: &str, cols: &str) -> Option<usize> {
+    let size = rows.parse::<usize>().ok()?;
+    (cols.parse::<usize>() == Ok(size) && (3..=MAX_SIZE).contains(&size)).then_some(size)
+}
+
+impl GameResult {
+    /// The game as move notation, see the top of this module.
+    pub fn to_notation(&self) -> String {
+        let mut size = 1;
+        while size * size < self.final_board.len() {
+            size += 1;
+        }
+        let moves: Vec<String> = self
+            .history
+            .iter()
+            .map(|record| {
+                format!(
+                    "{}. {} {}",
+                    record.move_number,
+                    record.player_name,
+                    cell_name(size, record.cell_index as usize)
+                )
+            })
+            .collect();
+        format!("{}x{}\n{}\n{}", size, size, moves.join(", "), self.result_line())
+    }
+
+    fn result_line(&self) -> String {
+        match &self.winner {
+            Some(name) => format!("{} wins", name),
+            None if self.turns as usize == self.final_board.len() => "draw".to_string(),
+            None => "unfinished".to_string(),
+        }
+    }
+
+    /// Replay a notation onto a fresh table, rejecting illegal moves and a
+    /// result line that doesn't match. Without a size line the board is
+    /// the smallest one holding every named cell, 3x3 at least and 26x26
+    /// at most. The first mover becomes player 1 of `final_board`.
+    pub fn from_notation(text: &str) -> Result<GameResult, NotationError> {
+        let text = text.trim();
+        let (header, rest) = text.split_once('\n').unwrap_or((text, ""));
+        let (declared, text) = match header.trim().split_once('x') {
+            Some((rows, cols)) if rows.parse::<usize>().is_ok() => {
+                let size = header_size(rows, cols).ok_or_else(|| NotationError::Malformed(header.to_string()))?;
+                (Some(size), rest.trim())
+            }
+            _ => (None, text),
+        };
+        let (moves_line, claimed) = text.rsplit_once('\n').unwrap_or(("", text));
+        let mut moves = Vec::new();
+        for (i, entry) in moves_line.split(", ").filter(|entry| !entry.trim().is_empty()).enumerate() {
+            let malformed = || NotationError::Malformed(entry.to_string());
+            let (number, rest) = entry.trim().split_once(". ").ok_or_else(malformed)?;
+            if number.parse::<usize>() != Ok(i + 1) {
+                return Err(malformed());
+            }
+            let (name, cell) = rest.rsplit_once(' ').ok_or_else(malformed)?;
+            let size = size_needed(cell).ok_or_else(malformed)?;
+            moves.push((name.to_string(), cell, size));
+        }
+        let size = declared.unwrap_or_else(|| moves.iter().map(|&(_, _, size)| size).max().unwrap_or(3));
+
+        let mut table = Table::with_size(size);
+        table.init();
+        table.set_silent(true);
+        let mut players: Vec<Player> = Vec::new();
+        let mut history = Vec::new();
+        for (i, (name, cell, _)) in moves.into_iter().enumerate() {
+            let move_number = i as u32 + 1;
+            if i < 2 && players.iter().all(|player| player.name != name) {
+                players.push(Player::new(name.clone(), if i == 0 { 'X' } else { 'O' }));
+            }
+            let player = match players.get_mut(i % 2) {
+                Some(player) if player.name == name => player,
+                _ => return Err(NotationError::WrongPlayer { move_number }),
+            };
+            let index = parse_move(cell, size).map_err(|_| NotationError::Malformed(cell.to_string()))?;
+            table
+                .play(player, index as i32)
+                .map_err(|error| NotationError::IllegalMove { move_number, error })?;
+            history.push(MoveRecord {
+                move_number,
+                player_name: name,
+                cell_index: index as i32,
+            });
+        }
+
+        let first_player = players.first().map(|player| player.name.clone()).unwrap_or_default();
+        let final_board = (0..(size * size) as i32)
+            .map(|index| match &table.get_cell(index).owner {
+                owner if owner.is_empty() => 0,
+                owner if *owner == first_player => 1,
+                _ => -1,
+            })
+            .collect();
+        let winner = match table.winner() {
+            "" | "draw" => None,
+            name => Some(name.to_string()),
+        };
+        let result = GameResult {
+            winner,
+            history,
+            final_board,
+            turns: table.play_count() as u32,
+            first_player,
+        };
+        let actual = result.result_line();
+        if claimed != actual {
+            return Err(NotationError::ResultMismatch {
+                claimed: claimed.to_string(),
+                actual,
+            });
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Game;
+
+    fn played(size: usize, positions: &[i32]) -> GameResult {
+        let mut game = Game::with_players(Player::new("alice".to_string(), 'X'), Player::new("bob".to_string(), 'O'));
+        let mut table = Table::with_size(size);
+        table.init();
+        table.set_silent(true);
+        game.tictac_board = table;
+        for &position in positions {
+            game.play_move(position).unwrap();
+        }
+        game.result()
+    }
+
+    #[test]
+    fn test_notation_round_trips() {
+        let win = played(3, &[5, 7, 9, 3, 1]);
+        assert_eq!(
+            win.to_notation(),
+            "3x3\n1. alice b2, 2. bob a1, 3. alice a3, 4. bob c3, 5. alice c1\nalice wins"
+        );
+        let draw = played(3, &[5, 7, 9, 1, 4, 6, 8, 2, 3]);
+        let unfinished = played(3, &[5, 7]);
+        let large = played(4, &[1, 16, 2, 15, 3, 14, 4]);
+        // Three in a row would win on 3x3, so the size has to come along.
+        let positions: Vec<i32> = [0, 4, 1, 5, 2].iter().map(|&i| Table::with_size(4).index_to_position(i)).collect();
+        let corner = played(4, &positions);
+        assert_eq!(
+            corner.to_notation(),
+            "4x4\n1. alice a1, 2. bob b1, 3. alice a2, 4. bob b2, 5. alice a3\nunfinished"
+        );
+        assert_eq!(unfinished.to_notation(), "3x3\n1. alice b2, 2. bob a1\nunfinished");
+        for game in [win, draw, unfinished, large, corner] {
+            assert_eq!(GameResult::from_notation(&game.to_notation()), Ok(game));
+        }
+        // Without moves there is no first player to name.
+        let empty = GameResult::from_notation(&played(3, &[]).to_notation()).unwrap();
+        assert_eq!((empty.turns, empty.first_player.as_str()), (0, ""));
+    }
+
+    #[test]
+    fn test_notation_rejects_illegal_games() {
+        let parse = GameResult::from_notation;
+        assert_eq!(
+            parse("1. alice b2, 2. bob b2\nunfinished"),
+            Err(NotationError::IllegalMove {
+                move_number: 2,
+                error: MoveError::OccupiedCell
+            })
+        );
+        let after_win = "1. alice b2, 2. bob a1, 3. alice a3, 4. bob c3, 5. alice c1, 6. bob a2\nalice wins";
+        assert_eq!(
+            parse(after_win),
+            Err(NotationError::IllegalMove {
+                move_number: 6,
+                error: MoveError::GameOver
+            })
+        );
+        assert_eq!(
+            parse("1. alice b2, 2. alice a1\nunfinished"),
+            Err(NotationError::WrongPlayer { move_number: 2 })
+        );
+        assert_eq!(
+            parse("1. alice b2\nalice wins"),
+            Err(NotationError::ResultMismatch {
+                claimed: "alice wins".to_string(),
+                actual: "unfinished".to_string()
+            })
+        );
+        assert_eq!(
+            parse("1. alice b2, 3. bob a1\nunfinished"),
+            Err(NotationError::Malformed("3. bob a1".to_string()))
+        );
+        assert_eq!(parse("1. alice 5\nunfinished"), Err(NotationError::Malformed("1. alice 5".to_string())));
+        assert_eq!(
+            parse("1. alice a99999999999\nunfinished"),
+            Err(NotationError::Malformed("1. alice a99999999999".to_string()))
+        );
+        assert!(parse("1. alice a26\nunfinished").is_ok());
+        assert_eq!(parse("2x2\n1. alice a1\nunfinished"), Err(NotationError::Malformed("2x2".to_string())));
+        assert_eq!(parse("3x3\n1. alice d1\nunfinished"), Err(NotationError::Malformed("d1".to_string())));
+    }
+}
@@ -0,0 +1,162 @@
+// Readers for weights exported from NumPy: single .npy arrays, .npz
+// archives of them, and a raw little-endian f32 dump.
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+#[derive(Debug)]
+pub enum NumpyError {
+    Io(std::io::Error),
+    /// The file is not a valid npy/npz/raw weight file.
+    Format(String),
+    /// An expected array (e.g. "w2") is not in the archive.
+    MissingArray(String),
+    /// The array exists but its shape does not match the network.
+    ShapeMismatch {
+        name: String,
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+    /// The array is the transpose of what the network expects; pass
+    /// `auto_transpose` to accept it.
+    Transposed {
+        name: String,
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+}
+
+impl fmt::Display for NumpyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NumpyError::Io(e) => write!(f, "io error: {}", e),
+            NumpyError::Format(msg) => write!(f, "invalid weight file: {}", msg),
+            NumpyError::MissingArray(name) => write!(f, "array '{}' not found", name),
+            NumpyError::ShapeMismatch { name, expected, found } => write!(
+                f,
+                "array '{}' has shape {:?}, expected {:?}",
+                name, found, expected
+            ),
+            NumpyError::Transposed { name, expected, found } => write!(
+                f,
+                "array '{}' has shape {:?}, which is the transpose of {:?}",
+                name, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NumpyError {}
+
+impl From<std::io::Error> for NumpyError {
+    fn from(e: std::io::Error) -> Self {
+        NumpyError::Io(e)
+    }
+}
+
+/// A decoded array, always stored row-major (C order) as f32.
+pub struct NpyArray {
+    pub shape: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, NumpyError> {
+    let pattern = format!("'{}':", key);
+    let start = header
+        .find(&pattern)
+        .ok_or_else(|| NumpyError::Format(format!("npy header has no {}", key)))?;
+    Ok(header[start + pattern.len()..].trim_start())
+}
+
+/// Parse a single .npy file. Supports little-endian f4/f8 in C or
+/// Fortran order.
+pub fn parse_npy(bytes: &[u8]) -> Result<NpyArray, NumpyError> {
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err(NumpyError::Format("missing npy magic".to_string()));
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (
+            u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+            12,
+        ),
+        v => return Err(NumpyError::Format(format!("unsupported npy version {}", v))),
+    };
+    let data_start = header_start + header_len;
+    if bytes.len() < data_start {
+        return Err(NumpyError::Format("truncated npy header".to_string()));
+    }
+    let header = String::from_utf8_lossy(&bytes[header_start..data_start]);
+
+    let descr = header_value(&header, "descr")?;
+    let item_size = if descr.starts_with("'<f4'") {
+        4
+    } else if descr.starts_with("'<f8'") {
+        8
+    } else {
+        return Err(NumpyError::Format(format!(
+            "unsupported dtype {}",
+            descr.split(',').next().unwrap_or("")
+        )));
+    };
+    let fortran_order = header_value(&header, "fortran_order")?.starts_with("True");
+    let shape_text = header_value(&header, "shape")?;
+    let shape_end = shape_text
+        .find(')')
+        .ok_or_else(|| NumpyError::Format("malformed shape".to_string()))?;
+    let shape = shape_text[1..shape_end]
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| NumpyError::Format("malformed shape".to_string()))?;
+
+    let count: usize = shape.iter().product();
+    let payload = &bytes[data_start..];
+    if payload.len() < count * item_size {
+        return Err(NumpyError::Format("truncated npy data".to_string()));
+    }
+    let mut data: Vec<f32> = payload[..count * item_size]
+        .chunks(item_size)
+        .map(|c| {
+            if item_size == 4 {
+                f32::from_le_bytes([c[0], c[1], c[2], c[3]])
+            } else {
+                f64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]) as f32
+            }
+        })
+        .collect();
+    if fortran_order && shape.len() == 2 {
+        let (rows, cols) = (shape[0], shape[1]);
+        let mut c_order = vec![0.0; data.len()];
+        for r in 0..rows {
+            for c in 0..cols {
+                c_order[r * cols + c] = data[c * rows + r];
+            }
+        }
+        data = c_order;
+    }
+    Ok(NpyArray { shape, data })
+}
+
+/// Read every array of an .npz archive, keyed by name without ".npy".
+pub fn read_npz(bytes: &[u8]) -> Result<HashMap<String, NpyArray>, NumpyError> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .map_err(|e| NumpyError::Format(format!("bad npz archive: {}", e)))?;
+    let mut arrays = HashMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| NumpyError::Format(format!("bad npz entry: {}", e)))?;
+        let name = entry.name().trim_end_matches(".npy").to_string();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        arrays.insert(name, parse_npy(&contents)?);
+    }
+    Ok(arrays)
+}
+
+pub fn is_npz(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04")
+}
@@ -0,0 +1,199 @@
+// Minimal ONNX writer: hand-rolled protobuf encoding of just the messages
+// needed to describe a dense MLP (ModelProto, GraphProto, NodeProto,
+// AttributeProto, TensorProto and ValueInfoProto).
+use std::io::Write;
+use std::path::Path;
+
+use crate::batch_norm::BatchNorm;
+use crate::layers::Activation;
+
+const IR_VERSION: u64 = 7;
+const OPSET_VERSION: u64 = 13;
+const TENSOR_FLOAT: u64 = 1;
+const ATTRIBUTE_FLOAT: u64 = 1;
+
+/// One dense layer as it should appear in the graph: weights in
+/// [node][connection] order (as HimNetwork stores them) plus biases, then
+/// for hidden layers the batch norm (running statistics), activation and
+/// skip connection that follow.
+pub struct DenseLayer<'a> {
+    pub weights: &'a [Vec<f32>],
+    pub biases: &'a [f32],
+    pub batch_norm: Option<&'a BatchNorm>,
+    pub activation: Activation,
+    pub residual: bool,
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_key(buf: &mut Vec<u8>, field: u32, wire_type: u8) {
+    put_varint(buf, ((field as u64) << 3) | wire_type as u64);
+}
+
+fn put_int(buf: &mut Vec<u8>, field: u32, value: u64) {
+    put_key(buf, field, 0);
+    put_varint(buf, value);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    put_key(buf, field, 2);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn put_str(buf: &mut Vec<u8>, field: u32, value: &str) {
+    put_bytes(buf, field, value.as_bytes());
+}
+
+fn put_float(buf: &mut Vec<u8>, field: u32, value: f32) {
+    put_key(buf, field, 5);
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn node(op_type: &str, inputs: &[&str], output: &str) -> Vec<u8> {
+    node_with(op_type, inputs, output, &[])
+}
+
+/// A node with float attributes, e.g. ("alpha", 0.01) for LeakyRelu.
+fn node_with(op_type: &str, inputs: &[&str], output: &str, attributes: &[(&str, f32)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for input in inputs {
+        put_str(&mut buf, 1, input);
+    }
+    put_str(&mut buf, 2, output);
+    put_str(&mut buf, 3, &format!("{}_{}", op_type, output));
+    put_str(&mut buf, 4, op_type);
+    for &(name, value) in attributes {
+        let mut attribute = Vec::new();
+        put_str(&mut attribute, 1, name);
+        put_float(&mut attribute, 2, value);
+        put_int(&mut attribute, 20, ATTRIBUTE_FLOAT);
+        put_bytes(&mut buf, 5, &attribute);
+    }
+    buf
+}
+
+fn tensor(name: &str, dims: &[usize], data: &[f32]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for &dim in dims {
+        put_int(&mut buf, 1, dim as u64);
+    }
+    put_int(&mut buf, 2, TENSOR_FLOAT);
+    put_str(&mut buf, 8, name);
+    let raw: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+    put_bytes(&mut buf, 9, &raw);
+    buf
+}
+
+/// ValueInfoProto for a float tensor of shape [batch, width].
+fn value_info(name: &str, width: usize) -> Vec<u8> {
+    let mut batch_dim = Vec::new();
+    put_str(&mut batch_dim, 2, "batch");
+    let mut width_dim = Vec::new();
+    put_int(&mut width_dim, 1, width as u64);
+    let mut shape = Vec::new();
+    put_bytes(&mut shape, 1, &batch_dim);
+    put_bytes(&mut shape, 1, &width_dim);
+    let mut tensor_type = Vec::new();
+    put_int(&mut tensor_type, 1, TENSOR_FLOAT);
+    put_bytes(&mut tensor_type, 2, &shape);
+    let mut type_proto = Vec::new();
+    put_bytes(&mut type_proto, 1, &tensor_type);
+    let mut buf = Vec::new();
+    put_str(&mut buf, 1, name);
+    put_bytes(&mut buf, 2, &type_proto);
+    buf
+}
+
+/// Encode the MLP as an ONNX model: MatMul + Add, BatchNormalization when
+/// the layer has one, Relu/LeakyRelu/Elu and the residual Add for every
+/// hidden layer, and MatMul + Add + Softmax for the last one. MatMul
+/// expects [in, out] weights, so each layer is written transposed as `w{l}`.
+pub fn encode_mlp(layers: &[DenseLayer], input_name: &str, output_name: &str) -> Vec<u8> {
+    let mut graph = Vec::new();
+    let mut current = input_name.to_string();
+    for (i, layer) in layers.iter().enumerate() {
+        let l = i + 1;
+        let outputs = layer.weights.len();
+        let inputs = layer.weights.first().map_or(0, |row| row.len());
+        let mut transposed = vec![0.0; inputs * outputs];
+        for (node_idx, row) in layer.weights.iter().enumerate() {
+            for (conn, &value) in row.iter().enumerate() {
+                transposed[conn * outputs + node_idx] = value;
+            }
+        }
+        let w_name = format!("w{}", l);
+        let b_name = format!("b{}", l);
+        put_bytes(&mut graph, 5, &tensor(&w_name, &[inputs, outputs], &transposed));
+        put_bytes(&mut graph, 5, &tensor(&b_name, &[outputs], layer.biases));
+
+        let matmul = format!("matmul{}", l);
+        let z = format!("z{}", l);
+        put_bytes(&mut graph, 1, &node("MatMul", &[&current, &w_name], &matmul));
+        put_bytes(&mut graph, 1, &node("Add", &[&matmul, &b_name], &z));
+        if l == layers.len() {
+            put_bytes(&mut graph, 1, &node("Softmax", &[&z], output_name));
+            continue;
+        }
+        let mut z = z;
+        if let Some(bn) = layer.batch_norm {
+            let names = ["gamma", "beta", "mean", "var"].map(|name| format!("{}{}", name, l));
+            let params = [&bn.gamma, &bn.beta, &bn.running_mean, &bn.running_var];
+            for (name, values) in names.iter().zip(params) {
+                put_bytes(&mut graph, 5, &tensor(name, &[outputs], values));
+            }
+            let normalized = format!("bn{}", l);
+            let inputs = [z.as_str(), &names[0], &names[1], &names[2], &names[3]];
+            put_bytes(&mut graph, 1, &node_with("BatchNormalization", &inputs, &normalized, &[("epsilon", bn.eps)]));
+            z = normalized;
+        }
+        let a = format!("a{}", l);
+        // With a skip connection g(Z) is added to the layer input to give a.
+        let g = if layer.residual { format!("g{}", l) } else { a.clone() };
+        let activation = match layer.activation {
+            Activation::ReLU => node("Relu", &[&z], &g),
+            Activation::LeakyReLU(alpha) => node_with("LeakyRelu", &[&z], &g, &[("alpha", alpha)]),
+            Activation::ELU(alpha) => node_with("Elu", &[&z], &g, &[("alpha", alpha)]),
+        };
+        put_bytes(&mut graph, 1, &activation);
+        if layer.residual {
+            put_bytes(&mut graph, 1, &node("Add", &[&g, &current], &a));
+        }
+        current = a;
+    }
+    let input_width = layers
+        .first()
+        .and_then(|layer| layer.weights.first())
+        .map_or(0, |row| row.len());
+    let output_width = layers.last().map_or(0, |layer| layer.weights.len());
+    put_str(&mut graph, 2, "him_network");
+    put_bytes(&mut graph, 11, &value_info(input_name, input_width));
+    put_bytes(&mut graph, 12, &value_info(output_name, output_width));
+
+    let mut opset = Vec::new();
+    put_str(&mut opset, 1, "");
+    put_int(&mut opset, 2, OPSET_VERSION);
+
+    let mut model = Vec::new();
+    put_int(&mut model, 1, IR_VERSION);
+    put_str(&mut model, 2, "tictac");
+    put_bytes(&mut model, 7, &graph);
+    put_bytes(&mut model, 8, &opset);
+    model
+}
+
+pub fn write_mlp(
+    path: &Path,
+    layers: &[DenseLayer],
+    input_name: &str,
+    output_name: &str,
+) -> std::io::Result<()> {
+    let bytes = encode_mlp(layers, input_name, output_name);
+    std::fs::File::create(path)?.write_all(&bytes)
+}
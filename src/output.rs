@@ -1,6 +1,15 @@
-use rand::Rng;
-use std::io::Write;
+use std::fmt;
+use std::io::IsTerminal;
+use std::time::Duration;
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::ai::{Difficulty, HeuristicAi, MinimaxAi, NetworkAi, RandomAi};
+use crate::menu::{check_symbol, other_symbol};
+use crate::recorder::{GameRecorder, NullRecorder};
+
+#[derive(Clone)]
 pub struct Cell {
     pub owner: String,
     pub symbol: char,
@@ -34,68 +43,281 @@ impl Cell {
 }
 pub fn position_to_index(position: i32) -> i32 {
     if position > 6 {
-        return position - 7;
+        position - 7
     } else if position > 3 {
-        return position - 1;
+        position - 1
     } else {
-        return position + 5;
+        position + 5
     }
 }
-pub struct Table {
-    cells: Vec<Cell>,
-    full: bool,
-    //winning_combo: Vec<Cell>,
-    play_count: i32,
-    winning_combo: [[usize; 3]; 8],
-    winner: String,
+/// What a successful move did to the game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoveOutcome {
+    Placed,
+    Won,
+    Draw,
+}
+
+/// Why a move was rejected. The board and turn are left unchanged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoveError {
+    OccupiedCell,
+    OutOfRange,
+    GameOver,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::OccupiedCell => write!(f, "Cell is already occupied"),
+            MoveError::OutOfRange => write!(f, "Move is not on the board"),
+            MoveError::GameOver => write!(f, "The game is already over"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// Why `Table::from_state` rejected a board.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateError {
+    /// A cell holding something other than -1, 0 or 1.
+    InvalidValue { index: usize, value: i8 },
+    /// Players alternate, so their mark counts differ by at most one.
+    PieceCounts { player1: usize, player2: usize },
+    /// Both players have a complete line.
+    TwoWinners,
+    /// The winner has fewer marks than the loser, so didn't move last.
+    WinnerMovedFirst,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::InvalidValue { index, value } => write!(f, "Cell {} holds {}", index, value),
+            StateError::PieceCounts { player1, player2 } => {
+                write!(f, "Player 1 has {} marks and player 2 has {}", player1, player2)
+            }
+            StateError::TwoWinners => write!(f, "Both players have a line"),
+            StateError::WinnerMovedFirst => write!(f, "The winner has fewer marks than the loser"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Why `parse_move` could not read a move.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseMoveError {
+    Empty,
+    /// None of the accepted formats.
+    InvalidFormat(String),
+    /// Well formed, but not a cell of the board.
+    OffBoard(String),
+}
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseMoveError::Empty => write!(f, "No move entered"),
+            ParseMoveError::InvalidFormat(input) => write!(f, "Can't read \"{}\" as a move", input),
+            ParseMoveError::OffBoard(input) => write!(f, "\"{}\" is not on the board", input),
+        }
+    }
+}
+
+impl std::error::Error for ParseMoveError {}
+
+/// Cell index of a move typed by a player on a `board_size` x `board_size`
+/// board. Accepts a keypad position ("7" is the top-left cell on 3x3),
+/// 1-based "row,col" counted from the top-left ("2,3"), or a row letter and
+/// a column number ("a1" top-left, "c3" bottom-right on 3x3).
+pub fn parse_move(input: &str, board_size: usize) -> Result<usize, ParseMoveError> {
+    parse_move_with(input, board_size, KeyLayout::NumpadStyle)
+}
+
+/// `parse_move` with single numbers read in `layout`.
+pub fn parse_move_with(input: &str, board_size: usize, layout: KeyLayout) -> Result<usize, ParseMoveError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(ParseMoveError::Empty);
+    }
+    let invalid = || ParseMoveError::InvalidFormat(input.to_string());
+    let off_board = || ParseMoveError::OffBoard(input.to_string());
+    let on_board = |n: usize| (1..=board_size).contains(&n);
+    let from_row_col = |row: usize, col: usize| {
+        if on_board(row) && on_board(col) {
+            Ok((row - 1) * board_size + col - 1)
+        } else {
+            Err(off_board())
+        }
+    };
+    if let Ok(position) = input.parse::<usize>() {
+        if !(1..=board_size * board_size).contains(&position) {
+            return Err(off_board());
+        }
+        return Ok(layout.position_to_index(board_size, position as i32) as usize);
+    }
+    if let Some((row, col)) = input.split_once(',') {
+        let row = row.trim().parse::<usize>().map_err(|_| invalid())?;
+        let col = col.trim().parse::<usize>().map_err(|_| invalid())?;
+        return from_row_col(row, col);
+    }
+    let mut chars = input.chars();
+    match chars.next().map(|c| c.to_ascii_lowercase()) {
+        Some(letter @ 'a'..='z') => {
+            let col = chars.as_str().parse::<usize>().map_err(|_| invalid())?;
+            from_row_col(letter as usize - 'a' as usize + 1, col)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// The three move formats `parse_move` accepts, for prompts.
+pub fn move_input_hint(board_size: usize) -> String {
+    let cells = board_size * board_size;
+    let last_row = (b'a' + board_size as u8 - 1) as char;
+    format!(
+        "Enter a keypad number 1-{}, a row and column like 1,{} or a cell like a1-{}{}",
+        cells, board_size, last_row, board_size
+    )
+}
+
+/// Inverse of `position_to_index`: keypad position (1-9) of a cell index.
+pub fn index_to_position(index: i32) -> i32 {
+    if index < 3 {
+        index + 7
+    } else if index < 6 {
+        index + 1
+    } else {
+        index - 5
+    }
 }
 
-/// Creates a new `Table` instance with default values.
+/// Keypad numbering on a `size` x `size` board: 1 is the bottom-left cell,
+/// counting left to right and then upwards, like `position_to_index`.
+fn position_to_index_on(size: usize, position: i32) -> i32 {
+    let size = size as i32;
+    let (row_from_bottom, col) = ((position - 1) / size, (position - 1) % size);
+    (size - 1 - row_from_bottom) * size + col
+}
 
-/// Returns the list of winning combinations that include the given cell index.
+fn index_to_position_on(size: usize, index: i32) -> i32 {
+    let size = size as i32;
+    let (row, col) = (index / size, index % size);
+    (size - 1 - row) * size + col + 1
+}
 
-/// Checks if the given player has won after making a move at the specified index.
+/// How the numbers players type map onto cells.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyLayout {
+    /// Like a numeric keypad: 1 is the bottom-left cell, so 7 8 9 is the
+    /// top row of a 3x3 board.
+    #[default]
+    NumpadStyle,
+    /// Like reading a page: 1 is the top-left cell, so 1 2 3 is the top row.
+    ReadingOrder,
+}
 
-/// Initializes the `Table` for a new game.
+impl KeyLayout {
+    /// Cell index of `position` on a `size` x `size` board.
+    pub fn position_to_index(self, size: usize, position: i32) -> i32 {
+        match self {
+            KeyLayout::NumpadStyle => position_to_index_on(size, position),
+            KeyLayout::ReadingOrder => position - 1,
+        }
+    }
 
-/// Retrieves a reference to the `Cell` at the specified index.
+    /// Position a player types for cell `index`; inverse of `position_to_index`.
+    pub fn index_to_position(self, size: usize, index: i32) -> i32 {
+        match self {
+            KeyLayout::NumpadStyle => index_to_position_on(size, index),
+            KeyLayout::ReadingOrder => index + 1,
+        }
+    }
+}
 
-/// Clears the console and prints the current state of the table.
+/// Every row, every column and both diagonals of a `size` x `size` board.
+fn winning_combos(size: usize) -> Vec<Vec<usize>> {
+    let mut combos = Vec::new();
+    for row in 0..size {
+        combos.push((0..size).map(|col| row * size + col).collect());
+    }
+    for col in 0..size {
+        combos.push((0..size).map(|row| row * size + col).collect());
+    }
+    combos.push((0..size).map(|i| i * size + i).collect());
+    combos.push((0..size).map(|i| i * size + size - 1 - i).collect());
+    combos
+}
+
+/// How `Table::render_to_string` draws the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderStyle {
+    /// ASCII only, for terminals that can't draw boxes.
+    #[default]
+    Plain,
+    /// Box drawing characters with row and column labels.
+    Unicode,
+}
+
+#[derive(Clone)]
+pub struct Table {
+    size: usize,
+    cells: Vec<Cell>,
+    full: bool,
+    //winning_combo: Vec<Cell>,
+    play_count: i32,
+    winning_combo: Vec<Vec<usize>>,
+    winner: String,
+    last_played: Option<usize>,
+    no_color: bool,
+    silent: bool,
+    layout: KeyLayout,
+    style: RenderStyle,
+}
 
-/// Processes a player's move at the specified index.
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-/// Checks if the table is full (i.e., no more moves can be made).
 impl Table {
+    /// Creates a new 3x3 `Table` instance with default values.
     pub fn new() -> Table {
-        let cells_in = (0..9)
+        Table::with_size(3)
+    }
+    /// A `size` x `size` table; a win takes a full row, column or diagonal.
+    pub fn with_size(size: usize) -> Table {
+        let cells_in = (0..(size * size) as i32)
             .map(|i| Cell::new(String::new(), ' ', false, i, i, false, 0))
             .collect();
         Table {
+            size,
             cells: cells_in,
             full: false,
-            winning_combo: [
-                [0, 1, 2],
-                [3, 4, 5],
-                [6, 7, 8],
-                [0, 3, 6],
-                [1, 4, 7],
-                [2, 5, 8],
-                [0, 4, 8],
-                [2, 4, 6],
-            ],
+            winning_combo: winning_combos(size),
             play_count: 0,
             winner: String::new(),
+            last_played: None,
+            no_color: false,
+            silent: false,
+            layout: KeyLayout::default(),
+            style: RenderStyle::default(),
         }
     }
-    fn get_relevant_list(&self, index: i32) -> Vec<[usize; 3]> {
+    /// Returns the list of winning combinations that include the given cell index.
+    fn get_relevant_list(&self, index: i32) -> Vec<Vec<usize>> {
         let mut relevant_list = Vec::new();
         for combo in self.winning_combo.iter() {
             if combo.contains(&(index as usize)) {
-                relevant_list.push(*combo);
+                relevant_list.push(combo.clone());
             }
         }
         relevant_list
     }
+    /// Checks if the given player has won after making a move at the specified index.
     fn check_winner(&mut self, player: &Player, index: i32) -> bool {
         for combo in self.get_relevant_list(index) {
             let mut count = 0;
@@ -104,7 +326,7 @@ impl Table {
                     count += 1;
                 }
             }
-            if count == 3 {
+            if count == self.size {
                 for cell in combo.iter() {
                     self.cells[*cell].winning_cell = true;
                 }
@@ -113,119 +335,396 @@ impl Table {
         }
         false
     }
+    /// Initializes the `Table` for a new game.
     pub fn init(&mut self) {
-        let mut count = 0;
-        let mut position = 7;
-        let mut row_count = 0;
-        for cell in self.cells.iter_mut() {
+        let (size, layout) = (self.size, self.layout);
+        for (count, cell) in self.cells.iter_mut().enumerate() {
+            let count = count as i32;
             cell.owner = String::new();
             cell.symbol = count.to_string().chars().next().unwrap();
             cell.is_occupied = false;
             cell.winning_cell = false;
-            cell.position = position;
+            cell.position = layout.index_to_position(size, count);
             cell.index = count;
-            position += 1;
-            count += 1;
-            row_count += 1;
-            if row_count == 3 {
-                row_count = 0;
-                position -= 6;
-            }
         }
+        self.last_played = None;
+    }
+    /// Cells per row and column.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    /// Cell index of keypad `position` in this table's `KeyLayout`.
+    pub fn position_to_index(&self, position: i32) -> i32 {
+        self.layout.position_to_index(self.size, position)
+    }
+    /// Keypad position of cell `index` in this table's `KeyLayout`.
+    pub fn index_to_position(&self, index: i32) -> i32 {
+        self.layout.index_to_position(self.size, index)
+    }
+    /// Number the cells in `layout`; empty cells show the new numbers.
+    pub fn set_key_layout(&mut self, layout: KeyLayout) {
+        self.layout = layout;
+        for cell in self.cells.iter_mut() {
+            cell.position = layout.index_to_position(self.size, cell.index);
+        }
+    }
+    pub fn key_layout(&self) -> KeyLayout {
+        self.layout
     }
+    /// Name of the winner, "draw", or empty while the game is running.
+    pub fn winner(&self) -> &str {
+        &self.winner
+    }
+    /// Number of moves placed so far.
+    pub fn play_count(&self) -> i32 {
+        self.play_count
+    }
+    /// Keypad positions (1-9 on a 3x3 table) of the empty cells.
+    pub fn legal_positions(&self) -> Vec<i32> {
+        self.legal_moves()
+            .into_iter()
+            .map(|index| self.index_to_position(index as i32))
+            .collect()
+    }
+    /// Whether cell `index` is free. Indices off the board are never
+    /// free, so this is false for them rather than a panic.
+    pub fn is_empty(&self, index: usize) -> bool {
+        self.cells.get(index).is_some_and(|cell| !cell.is_occupied)
+    }
+    /// Indices of the free cells, in order. Empty once the board is full,
+    /// but not after a win; see `is_over`.
+    pub fn legal_moves(&self) -> Vec<usize> {
+        (0..self.cells.len()).filter(|&index| self.is_empty(index)).collect()
+    }
+    /// Whether someone has won or every cell is taken.
+    pub fn is_over(&self) -> bool {
+        !self.winner.is_empty() || self.play_count >= self.cells.len() as i32
+    }
+    /// Retrieves a reference to the `Cell` at the specified index.
     pub fn get_cell(&self, index: i32) -> &Cell {
         &self.cells[index as usize]
     }
 
+    /// Prints the current state of the table, clearing the screen first
+    /// when stdout is a terminal. Does nothing in silent mode.
     pub fn print(&self) {
-        if cfg!(target_os = "windows") {
-            std::process::Command::new("cmd")
-                .args(&["/C", "cls"])
-                .status()
-                .unwrap();
+        if self.silent {
+            return;
+        }
+        let terminal = std::io::stdout().is_terminal();
+        if terminal {
+            print!("\x1b[2J\x1b[H");
+        }
+        println!("{}", self.render_to_string(terminal && !self.no_color));
+    }
+    /// Silent tables (and games on them) print nothing at all; used when
+    /// generating games in bulk.
+    pub fn set_silent(&mut self, silent: bool) {
+        self.silent = silent;
+    }
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+    /// Never use color in `print`, even on a terminal.
+    pub fn set_no_color(&mut self, no_color: bool) {
+        self.no_color = no_color;
+    }
+    pub fn set_render_style(&mut self, style: RenderStyle) {
+        self.style = style;
+    }
+    pub fn render_style(&self) -> RenderStyle {
+        self.style
+    }
+    /// The board as text in the table's `RenderStyle`. With `color`, X and
+    /// O get their own colors, winning cells are bold green and the last
+    /// move is underlined.
+    pub fn render_to_string(&self, color: bool) -> String {
+        let rows: Vec<Vec<String>> = (0..self.size)
+            .map(|row| (0..self.size).map(|col| self.cell_text(row * self.size + col, color)).collect())
+            .collect();
+        match self.style {
+            RenderStyle::Plain => {
+                let separator = "-".repeat(self.cell_width() * self.size + 3 * (self.size - 1));
+                let rows: Vec<String> = rows.iter().map(|cells| cells.join(" | ")).collect();
+                rows.join(&format!("\n{}\n", separator))
+            }
+            RenderStyle::Unicode => self.render_unicode(&rows),
+        }
+    }
+    /// Positions go up to size², so every cell is padded to that width.
+    fn cell_width(&self) -> usize {
+        (self.size * self.size).to_string().len()
+    }
+    fn cell_text(&self, index: usize, color: bool) -> String {
+        let text = format!("{:<width$}", self.symbol_or_position(index as i32), width = self.cell_width());
+        if color {
+            self.colorize(index, text)
         } else {
-            std::process::Command::new("clear").status().unwrap();
+            text
         }
-        println!(
-            "{} | {} | {}",
-            self.symbol_or_position(0),
-            self.symbol_or_position(1),
-            self.symbol_or_position(2)
-        );
-        println!("---------");
-        println!(
-            "{} | {} | {}",
-            self.symbol_or_position(3),
-            self.symbol_or_position(4),
-            self.symbol_or_position(5)
-        );
-        println!("---------");
-        println!(
-            "{} | {} | {}",
-            self.symbol_or_position(6),
-            self.symbol_or_position(7),
-            self.symbol_or_position(8)
-        );
+    }
+    /// Box drawing with column numbers on top and row letters down the
+    /// side, the names `parse_move` reads ("b2" is the center of 3x3).
+    fn render_unicode(&self, rows: &[Vec<String>]) -> String {
+        let width = self.cell_width();
+        let rule = |left: &str, middle: &str, right: &str| {
+            let segments = vec!["─".repeat(width + 2); self.size];
+            format!("  {}{}{}", left, segments.join(middle), right)
+        };
+        let mut lines = Vec::new();
+        let labels: String = (1..=self.size).map(|col| format!("  {:<width$} ", col)).collect();
+        lines.push(format!("  {}", labels).trim_end().to_string());
+        lines.push(rule("┌", "┬", "┐"));
+        for (row, cells) in rows.iter().enumerate() {
+            if row > 0 {
+                lines.push(rule("├", "┼", "┤"));
+            }
+            let letter = (b'a' + row as u8) as char;
+            let cells: String = cells.iter().map(|cell| format!(" {} │", cell)).collect();
+            lines.push(format!("{} │{}", letter, cells));
+        }
+        lines.push(rule("└", "┴", "┘"));
+        lines.join("\n")
+    }
+    fn colorize(&self, index: usize, text: String) -> String {
+        let cell = &self.cells[index];
+        if !cell.is_occupied {
+            return text;
+        }
+        let mut codes = vec![match cell.symbol {
+            'X' | 'x' => "31",
+            'O' | 'o' => "34",
+            _ => "35",
+        }];
+        if cell.winning_cell {
+            codes = vec!["1", "32"];
+        }
+        if self.last_played == Some(index) {
+            codes.push("4");
+        }
+        format!("\x1b[{}m{}\x1b[0m", codes.join(";"), text)
+    }
+    /// Index of the cell taken by the latest move, which the colored
+    /// rendering underlines. None before the first move.
+    pub fn last_played(&self) -> Option<usize> {
+        self.last_played
+    }
+    /// Indices of the cells on the winning line, empty without a winner.
+    pub fn winning_line(&self) -> Vec<i32> {
+        self.cells
+            .iter()
+            .filter(|cell| cell.winning_cell)
+            .map(|cell| cell.index)
+            .collect()
     }
     fn symbol_or_position(&self, index: i32) -> String {
         if self.cells[index as usize].is_occupied {
             return self.cells[index as usize].symbol.to_string();
         }
-        return self.cells[index as usize].position.to_string();
+        self.cells[index as usize].position.to_string()
     }
-    pub fn play(&mut self, player: &mut Player, index: i32) {
-        if self.cells[index as usize].is_occupied {
-            println!("Cell is already occupied");
-            return;
+    /// Cell index of keypad `position` if a move there is legal now. Every
+    /// move is checked here before it touches the cells.
+    pub fn validate_move(&self, position: i32) -> Result<i32, MoveError> {
+        if !(1..=self.cells.len() as i32).contains(&position) {
+            return Err(MoveError::OutOfRange);
         }
-        if self.check_full() {
-            return;
-        };
-
-        self.place_cell(player, index.clone());//place the cell
+        let index = self.position_to_index(position);
+        self.check_cell(index)?;
+        Ok(index)
+    }
+    fn check_cell(&self, index: i32) -> Result<(), MoveError> {
+        if !(0..self.cells.len() as i32).contains(&index) {
+            return Err(MoveError::OutOfRange);
+        }
+        if self.is_over() {
+            return Err(MoveError::GameOver);
+        }
+        if !self.is_empty(index as usize) {
+            return Err(MoveError::OccupiedCell);
+        }
+        Ok(())
+    }
+    /// Processes a player's move at the specified index.
+    pub fn play(&mut self, player: &mut Player, index: i32) -> Result<MoveOutcome, MoveError> {
+        self.check_cell(index)?;
+        self.place_cell(player, index);//place the cell
         self.check_full();//check if the table is fullfor the update of winner incase its a draw
-        self.save_table_csv();// save the table state to a csv file
+        if self.winner == player.name {
+            Ok(MoveOutcome::Won)
+        } else if self.full {
+            Ok(MoveOutcome::Draw)
+        } else {
+            Ok(MoveOutcome::Placed)
+        }
     }
     fn place_cell(&mut self, player: &mut Player, index: i32) {
-        self.cells[index as usize].owner = player.name.clone();
-        self.cells[index as usize].symbol = player.symbol.clone();
-        self.cells[index as usize].is_occupied = true;
-        self.cells[index as usize].owner_id = if player.name == "ai" { 1 } else { -1 };
+        self.set_cell(index, &player.name, player.symbol);
+        // Check first so the winning line is highlighted in this print.
+        let won = self.check_winner(player, index);
         self.print();
-        self.play_count += 1;
-        if self.check_winner(player, index) {
-            println!("{} wins!", player.name.clone());
+        if won {
+            if !self.silent {
+                println!("{} wins with cells {:?}!", player.name.clone(), self.winning_line());
+            }
             self.winner = player.name.clone();
         };
 
     }
+    /// Mark a cell as taken without checking for a winner or printing.
+    fn set_cell(&mut self, index: i32, owner: &str, symbol: char) {
+        let cell = &mut self.cells[index as usize];
+        cell.owner = owner.to_string();
+        cell.symbol = symbol;
+        cell.is_occupied = true;
+        cell.owner_id = if owner == "ai" { 1 } else { -1 };
+        self.play_count += 1;
+        self.last_played = Some(index as usize);
+    }
+    /// Checks if the table is full (i.e., no more moves can be made).
     pub fn check_full(&mut self) -> bool {
-        if self.play_count > 8 {
+        if self.play_count >= self.cells.len() as i32 {
             self.full = true;
-            self.winner = "draw".to_string();
+            // A win on the last cell is still a win.
+            if self.winner.is_empty() {
+                self.winner = "draw".to_string();
+            }
         }
         self.full
     }
-    pub fn save_table_csv(&self) {
-        let mut csv = String::new();
-        csv.push_str("\n");
-        for cell in self.cells.iter() {
-            csv.push_str(&cell.owner_id.to_string());
-            csv.push_str(",");
-        }
-        csv.push_str(&self.winner);
-
-        std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("table.csv")
-            .unwrap()
-            .write_all(csv.as_bytes())
-            .unwrap();
+}
 
+impl Table {
+    /// The board in the table.csv encoding: 1 for cells owned by "ai", -1
+    /// for the other player, 0 for empty cells. Only for 3x3 tables.
+    pub fn to_state(&self) -> [i8; 9] {
+        assert_eq!(self.size, 3, "states are 3x3 boards");
+        let mut state = [0; 9];
+        for (value, cell) in state.iter_mut().zip(&self.cells) {
+            *value = cell.owner_id as i8;
+        }
+        state
+    }
+    /// The board from the side playing `symbol`: 1 for its marks, -1 for
+    /// the opponent's, 0 for empty cells. Only for 3x3 tables.
+    pub fn to_state_for(&self, symbol: char) -> [i8; 9] {
+        assert_eq!(self.size, 3, "states are 3x3 boards");
+        let mut state = [0; 9];
+        for (value, cell) in state.iter_mut().zip(&self.cells) {
+            if cell.is_occupied {
+                *value = if cell.symbol == symbol { 1 } else { -1 };
+            }
+        }
+        state
+    }
+    /// A silent 3x3 table with `player1` on the 1 cells and `player2` on
+    /// the -1 cells, its play count, winner and winning line set. Boards no
+    /// game could reach are rejected.
+    pub fn from_state(state: &[i8; 9], player1: &Player, player2: &Player) -> Result<Table, StateError> {
+        if let Some((index, &value)) = state.iter().enumerate().find(|(_, value)| !(-1..=1).contains(*value)) {
+            return Err(StateError::InvalidValue { index, value });
+        }
+        let count = |side: i8| state.iter().filter(|&&value| value == side).count();
+        let (marks1, marks2) = (count(1), count(-1));
+        if marks1.abs_diff(marks2) > 1 {
+            return Err(StateError::PieceCounts {
+                player1: marks1,
+                player2: marks2,
+            });
+        }
+        let mut table = Table::new();
+        table.init();
+        table.set_silent(true);
+        for (index, &value) in state.iter().enumerate() {
+            match value {
+                1 => table.set_cell(index as i32, &player1.name, player1.symbol),
+                -1 => table.set_cell(index as i32, &player2.name, player2.symbol),
+                _ => {}
+            }
+        }
+        let lines: Vec<Vec<usize>> = table
+            .winning_combo
+            .iter()
+            .filter(|combo| combo.iter().all(|&i| state[i] != 0 && state[i] == state[combo[0]]))
+            .cloned()
+            .collect();
+        let winners: Vec<i8> = lines.iter().map(|combo| state[combo[0]]).collect();
+        if winners.contains(&1) && winners.contains(&-1) {
+            return Err(StateError::TwoWinners);
+        }
+        if let Some(&side) = winners.first() {
+            let (winner, own, other) = if side == 1 {
+                (player1, marks1, marks2)
+            } else {
+                (player2, marks2, marks1)
+            };
+            if own < other {
+                return Err(StateError::WinnerMovedFirst);
+            }
+            for &index in lines.iter().flatten() {
+                table.cells[index].winning_cell = true;
+            }
+            table.winner = winner.name.clone();
+        }
+        table.check_full();
+        Ok(table)
     }
 }
 
+#[cfg(test)]
+impl Table {
+    /// A table from nine symbols by cell index, '.' for an empty cell.
+    /// Nothing is printed or saved.
+    pub(crate) fn from_symbols(symbols: &str) -> Table {
+        let mut table = Table::new();
+        table.init();
+        for (index, symbol) in symbols.chars().enumerate() {
+            if symbol != '.' {
+                table.set_cell(index as i32, &symbol.to_string(), symbol);
+            }
+        }
+        table
+    }
+}
+
+/// Picks moves for one side of a `Game`. Moves are keypad positions
+/// (1-9) in the table's `KeyLayout`; `legal` lists the free ones and is
+/// never empty.
+pub trait MovePolicy {
+    fn choose_move(&mut self, table: &Table, legal: &[i32]) -> i32;
+    /// A person is asked again after an illegal move however often it
+    /// takes; anything else gets `MAX_REJECTED_MOVES` tries.
+    fn is_human(&self) -> bool {
+        false
+    }
+}
+
+/// Asks on stdin in any format `parse_move` reads, until one parses.
+/// Illegal moves are rejected by `Game::play`, which asks again.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HumanConsole;
+
+impl MovePolicy for HumanConsole {
+    fn choose_move(&mut self, table: &Table, _legal: &[i32]) -> i32 {
+        let hint = move_input_hint(table.size());
+        loop {
+            println!("{}", hint);
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input).unwrap();
+            match parse_move_with(&input, table.size(), table.key_layout()) {
+                Ok(index) => return table.index_to_position(index as i32),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+    fn is_human(&self) -> bool {
+        true
+    }
+}
+
+/// A player and the policy choosing its moves.
+pub type Seat = (Player, Box<dyn MovePolicy>);
+
 pub struct Player {
     pub name: String,
     pub symbol: char,
@@ -234,8 +733,9 @@ pub struct Player {
 }
 
 impl Player {
+    /// "ai" and "ai_2" count as AI players, everyone else as human.
     pub fn new(name: String, symbol: char) -> Player {
-        let is_ai = if name == "ai" || name == "ai_2" { true } else { false };
+        let is_ai = name == "ai" || name == "ai_2";
         Player {
             name,
             symbol,
@@ -243,9 +743,19 @@ impl Player {
             previous_moves: Vec::new(),
         }
     }
-    pub fn play(&mut self, table: &mut Table, index: i32) {
-        table.play(self, position_to_index(index));
+    /// A computer player with any name.
+    pub fn ai(name: String, symbol: char) -> Player {
+        let mut player = Player::new(name, symbol);
+        player.is_ai = true;
+        player
+    }
+    /// Play at keypad position `index` (1-9 on a 3x3 table). The move is
+    /// only recorded if the table accepts it.
+    pub fn play(&mut self, table: &mut Table, index: i32) -> Result<MoveOutcome, MoveError> {
+        let cell = table.validate_move(index)?;
+        let outcome = table.play(self, cell)?;
         self.previous_moves.push(index);
+        Ok(outcome)
     }
 }
 
@@ -260,7 +770,7 @@ pub fn get_int(message: &str) -> i32 {
         }
     }
 }
-fn get_string(message: &str) -> String {
+pub(crate) fn get_string(message: &str) -> String {
     loop {
         println!("{}", message);
         let mut input = String::new();
@@ -273,110 +783,1000 @@ fn get_string(message: &str) -> String {
         }
     }
 }
-fn get_char(message: &str) -> char {
+/// A symbol `menu::check_symbol` accepts, asking again until one is given.
+fn get_symbol(message: &str, taken: Option<char>) -> char {
     loop {
-        println!("{}", message);
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        let input = input.trim().to_string();
-        if input.is_empty() || input.chars().count() > 1 {
-            println!("Invalid input");
-        } else {
-            return input.chars().next().unwrap();
+        match check_symbol(&get_string(message), taken) {
+            Ok(symbol) => return symbol,
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+fn get_difficulty(message: &str) -> Difficulty {
+    loop {
+        match get_string(message).to_lowercase().as_str() {
+            "easy" => return Difficulty::Easy,
+            "medium" => return Difficulty::Medium,
+            "hard" => return Difficulty::Hard,
+            _ => println!("Invalid input"),
+        }
+    }
+}
+pub(crate) fn get_key_layout(message: &str) -> KeyLayout {
+    loop {
+        match get_string(message).to_lowercase().as_str() {
+            "keypad" | "numpad" => return KeyLayout::NumpadStyle,
+            "reading" => return KeyLayout::ReadingOrder,
+            _ => println!("Invalid input"),
         }
     }
 }
+/// One accepted move of a game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveRecord {
+    /// 1 for the first move of the game.
+    pub move_number: u32,
+    pub player_name: String,
+    pub cell_index: i32,
+}
+
+/// How long `Game::replay` shows each position.
+const REPLAY_PAUSE: Duration = Duration::from_millis(700);
+/// Illegal moves in a row before a non-human policy's turn is played on
+/// the first free cell instead.
+const MAX_REJECTED_MOVES: usize = 10;
+
+/// Summary of a finished (or abandoned) game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameResult {
+    /// None for a draw.
+    pub winner: Option<String>,
+    /// Every accepted move, in order.
+    pub history: Vec<MoveRecord>,
+    /// 1 for player 1, -1 for player 2, 0 for empty, by cell index.
+    pub final_board: Vec<i8>,
+    pub turns: u32,
+    /// Name of the player who made the first move.
+    pub first_player: String,
+}
+
+/// How much a `Game` prints while it is played.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Nothing at all; the outcome is only in the returned `GameResult`.
+    Silent,
+    /// The board after every move and the winner.
+    #[default]
+    Normal,
+    /// Also a line per move and the final result.
+    Verbose,
+}
+
 pub struct Game {
     pub tictac_board: Table,
     pub player1: Player,
     pub player2: Player,
-    pub player1_moves: Vec<i32>,
-    pub player2_moves: Vec<i32>,
+    pub policy1: Box<dyn MovePolicy>,
+    pub policy2: Box<dyn MovePolicy>,
+    /// Gets every position of the game; records nothing by default.
+    pub recorder: Box<dyn GameRecorder>,
     pub game_over: bool,
+    turn: usize, // 0 for player1, 1 for player2
+    first_turn: usize,
+    history: Vec<MoveRecord>,
+    verbosity: Verbosity,
 }
 
 impl Game {
     pub fn new(player_type:String) -> Game {
+        Game::setup(player_type, None)
+    }
+    /// Like `new`, but every random decision (the random players' moves,
+    /// the network player's fallback) is drawn from `seed`, so the same
+    /// seed and player type replay the same game.
+    pub fn with_seed(player_type: String, seed: u64) -> Game {
+        Game::setup(player_type, Some(seed))
+    }
+    fn setup(player_type: String, seed: Option<u64>) -> Game {
+        let (seat1, seat2) = Game::init_player_seeded(player_type, seed);
+        let humans = !seat1.0.is_ai || !seat2.0.is_ai;
+        let mut game = Game::with_policies(seat1, seat2);
+        if humans {
+            game.set_key_layout(get_key_layout("Number the cells like a keypad (7 8 9 on top) or in reading order (1 2 3 on top)? keypad/reading"));
+        }
+        game
+    }
+    /// AI players play random moves, the others are asked on the console.
+    pub fn with_players(player1: Player, player2: Player) -> Game {
+        let policy = |player: &Player| -> Box<dyn MovePolicy> {
+            if player.is_ai {
+                Box::new(RandomAi::new())
+            } else {
+                Box::new(HumanConsole)
+            }
+        };
+        let (policy1, policy2) = (policy(&player1), policy(&player2));
+        Game::with_policies((player1, policy1), (player2, policy2))
+    }
+    pub fn with_policies((player1, policy1): Seat, (player2, policy2): Seat) -> Game {
         let mut tictac_board = Table::new();
         tictac_board.init();
-        let (player1, player2) = Game::init_player(player_type);
         Game {
             tictac_board,
             player1,
             player2,
-            player1_moves: Vec::new(),
-            player2_moves: Vec::new(),
+            policy1,
+            policy2,
+            recorder: Box::new(NullRecorder),
             game_over: false,
+            turn: 0,
+            first_turn: 0,
+            history: Vec::new(),
+            verbosity: Verbosity::default(),
+        }
+    }
+    /// The player whose move it is.
+    pub fn current_player(&self) -> &Player {
+        if self.turn == 0 {
+            &self.player1
+        } else {
+            &self.player2
         }
     }
     //initialize the players based oin the game type the user insrtucts
-    pub fn init_player(player_type:String)->(Player,Player){
+    pub fn init_player(player_type:String)->(Seat,Seat){
+        Game::init_player_seeded(player_type, None)
+    }
+    /// `init_player` with the seat 1 and seat 2 policies seeded from `seed`.
+    fn init_player_seeded(player_type: String, seed: Option<u64>) -> (Seat, Seat) {
+        let seeds = seed.map(|seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            [rng.gen::<u64>(), rng.gen::<u64>()]
+        });
+        let human = |name_prompt: &str, symbol_prompt: &str, taken: Option<char>| -> Seat {
+            (Player::new(get_string(name_prompt), get_symbol(symbol_prompt, taken)), Box::new(HumanConsole))
+        };
+        let random = |name: &str, symbol: char, seat: usize| -> Seat {
+            let policy = match seeds {
+                Some(seeds) => RandomAi::with_seed(seeds[seat - 1]),
+                None => RandomAi::new(),
+            };
+            (Player::new(name.to_string(), symbol), Box::new(policy))
+        };
         if player_type == "ai_Vs_ai" {
-            let player1 = Player::new("ai".to_string(), 'X');
-            let player2 = Player::new("ai_2".to_string(), 'O');
-            (player1, player2)
+            (random("ai", 'X', 1), random("ai_2", 'O', 2))
         } else if player_type == "human_Vs_human" {
-            let player1 = Player::new(
-                get_string("Enter player 1 name"),
-                get_char("Choose symbol for player 1"),
-            );
-            let player2 = Player::new(
-                get_string("Enter Player two name"),
-                get_char("Choose symbol for player 2"),
-            );
-            (player1, player2)
+            let seat1 = human("Enter player 1 name", "Choose symbol for player 1", None);
+            let seat2 = human("Enter Player two name", "Choose symbol for player 2", Some(seat1.0.symbol));
+            (seat1, seat2)
+        } else if player_type == "minimax_Vs_random" {
+            let player1 = Player::ai("minimax".to_string(), 'X');
+            let policy1 = Box::new(MinimaxAi::with_difficulty('X', Difficulty::Hard));
+            ((player1, policy1), random("ai_2", 'O', 2))
+        } else if player_type == "human_Vs_minimax" {
+            let seat1 = human("Enter player 1 name", "Choose symbol for player 1", None);
+            let symbol = other_symbol(seat1.0.symbol);
+            let difficulty = get_difficulty("Choose difficulty: easy, medium or hard");
+            let player2 = Player::ai("minimax".to_string(), symbol);
+            (seat1, (player2, Box::new(MinimaxAi::with_difficulty(symbol, difficulty))))
+        } else if player_type == "heuristic_Vs_random" {
+            let player1 = Player::ai("heuristic".to_string(), 'X');
+            ((player1, Box::new(HeuristicAi::new('X'))), random("ai_2", 'O', 2))
+        } else if player_type == "human_Vs_heuristic" {
+            let seat1 = human("Enter player 1 name", "Choose symbol for player 1", None);
+            let symbol = other_symbol(seat1.0.symbol);
+            let player2 = Player::ai("heuristic".to_string(), symbol);
+            (seat1, (player2, Box::new(HeuristicAi::new(symbol))))
+        } else if player_type == "network_Vs_random" {
+            let path = get_string("Enter the path of a saved model");
+            let player1 = Player::ai("network".to_string(), 'X');
+            let mut policy1 = NetworkAi::load('X', std::path::Path::new(&path));
+            if let Some(seeds) = seeds {
+                policy1.set_seed(seeds[0]);
+            }
+            ((player1, Box::new(policy1)), random("ai_2", 'O', 2))
         }
         else {
-            let player1 = Player::new("ai".to_string(), get_char("Choose symbol for 'ai' :"));
-            let player2 = Player::new(
-                get_string("Enter player 2 name"),
-                get_char("Choose symbol for player 2"),
-            );
-            (player1, player2)
+            let symbol = get_symbol("Choose symbol for 'ai' :", None);
+            (random("ai", symbol, 1), human("Enter player 2 name", "Choose symbol for player 2", Some(symbol)))
         }
     }
-    pub fn ai_play_move(&mut self) -> i32 {
-        let mut rng = rand::thread_rng();
-        let mut ai_move = rng.gen_range(1..10);
-        while self.player1_moves.contains(&ai_move) || self.player2_moves.contains(&ai_move) {
-            ai_move = rng.gen_range(1..10);
+    /// Silent also silences the table, so no board is drawn either.
+    pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
+        self.tictac_board.set_silent(verbosity == Verbosity::Silent);
+    }
+    /// Silent whenever the table is, however that was set.
+    pub fn verbosity(&self) -> Verbosity {
+        if self.tictac_board.is_silent() {
+            Verbosity::Silent
+        } else {
+            self.verbosity
         }
-        ai_move
     }
-    pub fn play(&mut self) {
-        let mut iterator = 0;
+    pub fn play(&mut self) -> GameResult {
         self.tictac_board.print();
+        let mut rejected = 0;
         loop {
-            let input = self.get_input();
-            if iterator == 0 {
-                self.player1.play(&mut self.tictac_board, input);
-                self.player1_moves.push(input);
-            } else {
-                self.player2.play(&mut self.tictac_board, input);
-                self.player2_moves.push(input);
+            let input = self.get_input(rejected);
+            // A rejected move is retried by the same player.
+            if let Err(e) = self.play_move(input) {
+                rejected += 1;
+                if self.verbosity() != Verbosity::Silent {
+                    println!(
+                        "{} ({}), try again. Free cells: {:?}",
+                        e,
+                        input,
+                        self.tictac_board.legal_positions()
+                    );
+                }
+                continue;
             }
-
-            if self.check_game_over() {
+            rejected = 0;
+            if self.verbosity() == Verbosity::Verbose {
+                let record = &self.history[self.history.len() - 1];
+                println!("Move {}: {} at {}", record.move_number, record.player_name, input);
+            }
+            if self.game_over {
                 break;
             }
-
-            iterator = if iterator == 0 { 1 } else { 0 };
         }
+        let result = self.result();
+        if self.verbosity() == Verbosity::Verbose {
+            match &result.winner {
+                Some(name) => println!("{} won in {} moves", name, result.turns),
+                None => println!("Draw after {} moves", result.turns),
+            }
+        }
+        result
+    }
+    /// The game so far as a `GameResult`.
+    pub fn result(&self) -> GameResult {
+        let mut final_board = vec![0; self.tictac_board.cells.len()];
+        for (index, value) in final_board.iter_mut().enumerate() {
+            let owner = &self.tictac_board.get_cell(index as i32).owner;
+            if owner.is_empty() {
+                continue;
+            }
+            *value = if *owner == self.player1.name { 1 } else { -1 };
+        }
+        let winner = match self.tictac_board.winner() {
+            "" | "draw" => None,
+            name => Some(name.to_string()),
+        };
+        GameResult {
+            winner,
+            history: self.history.clone(),
+            final_board,
+            turns: self.tictac_board.play_count() as u32,
+            first_player: self.first_player().name.clone(),
+        }
+    }
+    /// Let player 1 or 2 make the first move. Only before any move.
+    pub fn set_first_player(&mut self, player: usize) {
+        assert!(player == 1 || player == 2, "player must be 1 or 2, got {}", player);
+        assert!(self.history.is_empty(), "the game has already started");
+        self.first_turn = player - 1;
+        self.turn = self.first_turn;
+    }
+    /// Number the cells in `layout`, for moves typed and shown. Only
+    /// before any move.
+    pub fn set_key_layout(&mut self, layout: KeyLayout) {
+        assert!(self.history.is_empty(), "the game has already started");
+        self.tictac_board.set_key_layout(layout);
+    }
+    /// The player who moves (or moved) first.
+    pub fn first_player(&self) -> &Player {
+        if self.first_turn == 0 {
+            &self.player1
+        } else {
+            &self.player2
+        }
+    }
+    /// Play `position` (1-9) for the current player. Only an accepted move
+    /// is recorded and passes the turn.
+    pub fn play_move(&mut self, position: i32) -> Result<MoveOutcome, MoveError> {
+        if self.game_over {
+            return Err(MoveError::GameOver);
+        }
+        let outcome = if self.turn == 0 {
+            self.player1.play(&mut self.tictac_board, position)?
+        } else {
+            self.player2.play(&mut self.tictac_board, position)?
+        };
+        self.history.push(MoveRecord {
+            move_number: self.history.len() as u32 + 1,
+            player_name: self.current_player().name.clone(),
+            cell_index: self.tictac_board.position_to_index(position),
+        });
+        if !self.check_game_over() {
+            self.turn = 1 - self.turn;
+        }
+        self.record();
+        Ok(outcome)
+    }
+    /// Hand the current position, and the winner once the game is over, to
+    /// the recorder. Recorders take 3x3 boards, so larger games are skipped.
+    fn record(&mut self) {
+        if self.tictac_board.size() != 3 {
+            return;
+        }
+        if self.history.len() == 1 {
+            self.recorder.record_players(&self.player1.name, &self.player2.name);
+        }
+        self.recorder.record_state(&self.tictac_board.to_state_for(self.player1.symbol));
+        if self.game_over {
+            self.recorder.record_result(self.tictac_board.winner());
+        }
+    }
+    /// The players and policies back, ready for another game; the
+    /// players forget their moves.
+    pub fn into_seats(self) -> (Seat, Seat) {
+        let (mut player1, mut player2) = (self.player1, self.player2);
+        player1.previous_moves.clear();
+        player2.previous_moves.clear();
+        ((player1, self.policy1), (player2, self.policy2))
+    }
+    /// Take the recorder out, leaving one that records nothing; lets a
+    /// single recorder be passed from game to game.
+    pub fn take_recorder(&mut self) -> Box<dyn GameRecorder> {
+        std::mem::replace(&mut self.recorder, Box::new(NullRecorder))
+    }
+    /// Every accepted move so far, in order.
+    pub fn history(&self) -> &[MoveRecord] {
+        &self.history
+    }
+    /// Show the board after each move of the history, pausing in between.
+    pub fn replay(&self) {
+        for (record, table) in self.history.iter().zip(self.replay_tables()) {
+            table.print();
+            println!(
+                "Move {}: {} at {}",
+                record.move_number,
+                record.player_name,
+                self.tictac_board.index_to_position(record.cell_index)
+            );
+            std::thread::sleep(REPLAY_PAUSE);
+        }
+    }
+    /// The table after each move, rebuilt from the history alone.
+    fn replay_tables(&self) -> Vec<Table> {
+        let mut table = Table::with_size(self.tictac_board.size());
+        table.init();
+        self.history
+            .iter()
+            .map(|record| {
+                let symbol = if record.player_name == self.player1.name {
+                    self.player1.symbol
+                } else {
+                    self.player2.symbol
+                };
+                table.set_cell(record.cell_index, &record.player_name, symbol);
+                table.clone()
+            })
+            .collect()
     }
     fn check_game_over(&mut self)-> bool {
-        if self.tictac_board.check_full() || self.tictac_board.winner != "" {
+        // check_full also settles the winner to "draw" on a full board.
+        self.tictac_board.check_full();
+        if self.tictac_board.is_over() {
             self.game_over = true;
         }
         self.game_over
     }
-    fn get_input (&mut self)-> i32 {
-        let mut  input = 0;
-        if (self.player1.is_ai) || (self.player2.is_ai) {
-            let ai_move = self.ai_play_move();
-            input = ai_move;
-        } else {
-            input = get_int("Enter a number between 1 and 9")
+    fn get_input (&mut self, rejected: usize)-> i32 {
+        let legal = self.tictac_board.legal_positions();
+        let policy = if self.turn == 0 { &mut self.policy1 } else { &mut self.policy2 };
+        if rejected >= MAX_REJECTED_MOVES && !policy.is_human() {
+            return legal[0];
+        }
+        policy.choose_move(&self.tictac_board, &legal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::{CsvRecorder, MemoryRecorder};
+
+    /// Plays a fixed list of positions in order.
+    struct Scripted(Vec<i32>);
+
+    impl Scripted {
+        fn new(moves: &[i32]) -> Box<Scripted> {
+            Box::new(Scripted(moves.to_vec()))
+        }
+    }
+
+    impl MovePolicy for Scripted {
+        fn choose_move(&mut self, _table: &Table, _legal: &[i32]) -> i32 {
+            assert!(!self.0.is_empty(), "script ran out of moves");
+            self.0.remove(0)
+        }
+    }
+
+    fn test_game() -> Game {
+        let mut game = Game::with_players(Player::new("alice".to_string(), 'X'), Player::new("bob".to_string(), 'O'));
+        game.tictac_board.set_silent(true);
+        game
+    }
+
+    fn sized_test_game(size: usize) -> Game {
+        let mut game = test_game();
+        let mut table = Table::with_size(size);
+        table.init();
+        table.set_silent(true);
+        game.tictac_board = table;
+        game
+    }
+
+    #[test]
+    fn test_occupied_cell_keeps_turn() {
+        let mut game = test_game();
+        assert_eq!(game.play_move(5), Ok(MoveOutcome::Placed));
+        assert_eq!(game.current_player().name, "bob");
+        assert_eq!(game.play_move(5), Err(MoveError::OccupiedCell));
+        assert_eq!(game.play_move(5), Err(MoveError::OccupiedCell));
+        assert_eq!(game.current_player().name, "bob");
+        assert_eq!(game.play_move(0), Err(MoveError::OutOfRange));
+        assert_eq!(game.play_move(1), Ok(MoveOutcome::Placed));
+        assert_eq!(game.current_player().name, "alice");
+        assert_eq!(game.player2.previous_moves, vec![1]);
+    }
+
+    #[test]
+    fn test_out_of_range_moves_change_nothing() {
+        let mut game = test_game();
+        let recorder = MemoryRecorder::new();
+        game.recorder = Box::new(recorder.clone());
+        for position in [0, 10, 12, -3, i32::MIN, i32::MAX] {
+            assert_eq!(game.play_move(position), Err(MoveError::OutOfRange), "{}", position);
+        }
+        assert_eq!(game.current_player().name, "alice");
+        assert!(game.history().is_empty());
+        assert!(game.player1.previous_moves.is_empty());
+        assert!(recorder.current_states().is_empty());
+        assert_eq!(game.tictac_board.play_count(), 0);
+
+        let table = Table::with_size(4);
+        assert_eq!(table.validate_move(16), Ok(3));
+        assert_eq!(table.validate_move(17), Err(MoveError::OutOfRange));
+        let mut player = Player::new("alice".to_string(), 'X');
+        let mut table = Table::new();
+        assert_eq!(table.play(&mut player, 9), Err(MoveError::OutOfRange));
+        assert_eq!(table.play(&mut player, -1), Err(MoveError::OutOfRange));
+    }
+
+    #[test]
+    fn test_outcomes_win_and_game_over() {
+        let mut game = test_game();
+        // alice takes the top row (keypad 7, 8, 9).
+        for &position in &[7, 1, 8, 2] {
+            assert_eq!(game.play_move(position), Ok(MoveOutcome::Placed));
+        }
+        assert_eq!(game.play_move(9), Ok(MoveOutcome::Won));
+        assert!(game.game_over);
+        assert_eq!(game.play_move(3), Err(MoveError::GameOver));
+        let mut table = Table::new();
+        table.winner = "alice".to_string();
+        assert_eq!(table.play(&mut game.player2, 0), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn test_last_cell_win_is_not_a_draw() {
+        let mut game = test_game();
+        // X: 7 8 4 3 9 ; O: 5 1 6 2 -> X's last move completes the top row.
+        for &position in &[7, 5, 8, 1, 4, 6, 3, 2] {
+            assert_eq!(game.play_move(position), Ok(MoveOutcome::Placed));
+        }
+        assert_eq!(game.play_move(9), Ok(MoveOutcome::Won));
+        let result = game.result();
+        assert_eq!(result.winner, Some("alice".to_string()));
+        assert_eq!(result.turns, 9);
+        assert_eq!(result.final_board, [1, 1, 1, 1, -1, -1, -1, -1, 1]);
+        assert_eq!(result.history.len(), 9);
+        assert_eq!(
+            result.history[1],
+            MoveRecord {
+                move_number: 2,
+                player_name: "bob".to_string(),
+                cell_index: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_result_of_draw() {
+        let mut game = test_game();
+        // X O X / X O O / O X X
+        for &position in &[7, 8, 9, 5, 4, 6, 2, 1, 3] {
+            game.play_move(position).unwrap();
+        }
+        let result = game.result();
+        assert_eq!(game.tictac_board.winner(), "draw");
+        assert_eq!(result.winner, None);
+        assert_eq!(result.turns, 9);
+    }
+
+    #[test]
+    fn test_index_to_position_inverts_position_to_index() {
+        for position in 1..=9 {
+            assert_eq!(index_to_position(position_to_index(position)), position);
+        }
+    }
+
+    #[test]
+    fn test_minimax_player_finishes_the_row() {
+        let mut game = test_game();
+        game.policy2 = Box::new(MinimaxAi::with_difficulty('O', Difficulty::Hard));
+        // O holds keypad 4 and 5; the middle row closes at 6.
+        for &position in &[7, 4, 8, 5, 1] {
+            game.play_move(position).unwrap();
+        }
+        let position = game.get_input(0);
+        assert_eq!(position, 6);
+        assert_eq!(game.play_move(position), Ok(MoveOutcome::Won));
+    }
+
+    #[test]
+    fn test_network_player_plays_a_full_game() {
+        let mut game = test_game();
+        let mut network = crate::him_network::HimNetwork::new();
+        network.init_params();
+        game.policy1 = Box::new(NetworkAi::new('X', network));
+        game.policy2 = Box::new(RandomAi::new());
+        let result = game.play();
+        assert!(game.game_over);
+        assert!((5..=9).contains(&result.turns));
+        assert_eq!(result.history.len(), result.turns as usize);
+    }
+
+    #[test]
+    fn test_scripted_policies_play_a_deterministic_game() {
+        let mut game = test_game();
+        game.policy1 = Scripted::new(&[7, 8, 9]);
+        // bob's 7 is taken and gets retried with the next scripted move.
+        game.policy2 = Scripted::new(&[5, 7, 1]);
+        let result = game.play();
+        assert_eq!(result.winner, Some("alice".to_string()));
+        assert_eq!(result.turns, 5);
+        let order: Vec<i32> = result.history.iter().map(|record| record.cell_index).collect();
+        assert_eq!(order, vec![0, 4, 1, 6, 2]);
+    }
+
+    /// Always asks for the centre.
+    struct Centre;
+
+    impl MovePolicy for Centre {
+        fn choose_move(&mut self, _table: &Table, _legal: &[i32]) -> i32 {
+            5
+        }
+    }
+
+    #[test]
+    fn test_policy_stuck_on_an_occupied_cell_falls_back_to_a_free_one() {
+        let mut game = test_game();
+        game.policy1 = Box::new(Centre);
+        game.policy2 = Box::new(Centre);
+        let result = game.play();
+        // bob never gets the centre, so each of his turns takes the first free cell.
+        assert_eq!(result.turns, 9);
+        let order: Vec<i32> = result.history.iter().map(|record| record.cell_index).collect();
+        assert_eq!(order[..2], [4, 0]);
+    }
+
+    #[test]
+    fn test_rejected_move_leaves_no_trace() {
+        let mut game = test_game();
+        game.policy1 = Scripted::new(&[5, 4, 6]);
+        game.policy2 = Scripted::new(&[5, 3, 1]);
+        let result = game.play();
+        assert_eq!(game.tictac_board.get_cell(position_to_index(5)).owner, "alice");
+        assert_eq!(game.player1.previous_moves, vec![5, 4, 6]);
+        assert_eq!(game.player2.previous_moves, vec![3, 1]);
+        let mut cells: Vec<i32> = result.history.iter().map(|record| record.cell_index).collect();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(cells.len(), result.history.len());
+        let movers: Vec<&str> = result.history.iter().map(|record| record.player_name.as_str()).collect();
+        assert_eq!(movers, vec!["alice", "bob", "alice", "bob", "alice"]);
+        assert_eq!(result.winner, Some("alice".to_string()));
+    }
+
+    #[test]
+    fn test_same_seed_replays_the_same_game() {
+        let path = |run: u32| std::env::temp_dir().join(format!("tictac_seeded_{}_{}.csv", std::process::id(), run));
+        let run = |seed: u64, run: u32| {
+            let mut histories = Vec::new();
+            let mut csv = CsvRecorder::new(path(run)).unwrap();
+            csv.start_run(seed, 1_700_000_000).unwrap();
+            let mut recorder: Box<dyn GameRecorder> = Box::new(csv);
+            for game_number in 0..5 {
+                let mut game = Game::with_seed("ai_Vs_ai".to_string(), seed + game_number);
+                game.tictac_board.set_silent(true);
+                game.recorder = recorder;
+                histories.push(game.play().history);
+                recorder = game.take_recorder();
+            }
+            drop(recorder);
+            let csv = std::fs::read_to_string(path(run)).unwrap();
+            std::fs::remove_file(path(run)).unwrap();
+            (histories, csv)
         };
-        input
+        let (first, second) = (run(42, 1), run(42, 2));
+        assert_eq!(first, second);
+        assert!(first.1.lines().count() > 5);
+        let other = run(1000, 3);
+        assert_ne!(first.0, other.0);
+    }
+
+    #[test]
+    fn test_cell_queries() {
+        let table = Table::from_symbols("X...O...X");
+        assert!(!table.is_empty(0));
+        assert!(table.is_empty(1));
+        assert!(!table.is_empty(9));
+        assert!(!table.is_empty(usize::MAX));
+        assert_eq!(table.legal_moves(), vec![1, 2, 3, 5, 6, 7]);
+        assert!(!table.is_over());
+        assert!(Table::from_symbols("XOXOXOOXO").is_over());
+        assert!(Table::from_symbols("XOXOXOOXO").legal_moves().is_empty());
+
+        let mut game = test_game();
+        for position in [7, 1, 8, 2, 9] {
+            game.play_move(position).unwrap();
+        }
+        assert!(game.tictac_board.is_over());
+        assert_eq!(game.tictac_board.legal_moves().len(), 4);
+    }
+
+    #[test]
+    fn test_state_round_trips() {
+        let (ai, other) = (Player::new("ai".to_string(), 'X'), Player::new("bob".to_string(), 'O'));
+        let states: [[i8; 9]; 5] = [
+            [0; 9],
+            [0, 0, 0, 0, 1, 0, 0, 0, 0],
+            [1, -1, 0, 0, 1, 0, 0, 0, -1],
+            [-1, 1, 0, 0, -1, 1, 0, 0, 0],
+            [1, -1, 1, 1, -1, -1, -1, 1, 1],
+        ];
+        for state in &states {
+            let table = Table::from_state(state, &ai, &other).unwrap();
+            assert_eq!(table.to_state(), *state);
+            assert_eq!(table.to_state_for('X'), *state);
+            assert_eq!(table.to_state_for('O'), state.map(|value| -value));
+            assert_eq!(table.play_count() as usize, state.iter().filter(|&&value| value != 0).count());
+        }
+        assert_eq!(Table::from_state(&states[4], &ai, &other).unwrap().winner(), "draw");
+        assert_eq!(Table::from_state(&states[2], &ai, &other).unwrap().winner(), "");
+
+        // A live game encodes to the same state it rebuilds from.
+        let mut game = Game::with_players(ai, other);
+        game.tictac_board.set_silent(true);
+        for position in [7, 5, 8, 1, 9] {
+            game.play_move(position).unwrap();
+        }
+        let state = game.tictac_board.to_state();
+        let rebuilt = Table::from_state(&state, &game.player1, &game.player2).unwrap();
+        assert_eq!(rebuilt.winner(), "ai");
+        assert_eq!(rebuilt.winning_line(), vec![0, 1, 2]);
+        assert_eq!(rebuilt.legal_positions(), game.tictac_board.legal_positions());
+    }
+
+    #[test]
+    fn test_from_state_rejects_unreachable_boards() {
+        let (a, b) = (Player::new("ai".to_string(), 'X'), Player::new("bob".to_string(), 'O'));
+        let from = |state: [i8; 9]| Table::from_state(&state, &a, &b).err();
+        assert_eq!(from([1, 2, 0, 0, 0, 0, 0, 0, 0]), Some(StateError::InvalidValue { index: 1, value: 2 }));
+        assert_eq!(
+            from([1, 1, 0, 0, 0, 0, 0, 0, 0]),
+            Some(StateError::PieceCounts { player1: 2, player2: 0 })
+        );
+        assert_eq!(from([1, 1, 1, -1, -1, -1, 0, 0, 0]), Some(StateError::TwoWinners));
+        assert_eq!(from([1, 1, 1, -1, -1, 0, -1, -1, 0]), Some(StateError::WinnerMovedFirst));
+        assert_eq!(from([1, 1, 1, -1, -1, 0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_legal_positions_are_the_free_cells() {
+        let table = Table::from_symbols("X...O...X");
+        assert_eq!(table.legal_positions(), vec![8, 9, 4, 6, 1, 2]);
+    }
+
+    #[test]
+    fn test_history_replays_to_the_final_board() {
+        let mut game = test_game();
+        game.policy1 = Box::new(RandomAi::new());
+        game.policy2 = Box::new(RandomAi::new());
+        game.play();
+        assert_eq!(game.history().len() as i32, game.tictac_board.play_count());
+        let numbers: Vec<u32> = game.history().iter().map(|record| record.move_number).collect();
+        assert_eq!(numbers, (1..=game.history().len() as u32).collect::<Vec<u32>>());
+        let replayed = game.replay_tables().pop().unwrap();
+        for index in 0..9 {
+            let (got, want) = (replayed.get_cell(index), game.tictac_board.get_cell(index));
+            assert_eq!(got.is_occupied, want.is_occupied);
+            assert_eq!(got.owner, want.owner);
+            assert_eq!(got.symbol, want.symbol);
+        }
+    }
+
+    #[test]
+    fn test_winning_combos_per_size() {
+        assert_eq!(Table::new().winning_combo.len(), 8);
+        assert_eq!(Table::with_size(4).winning_combo.len(), 10);
+        assert_eq!(Table::with_size(5).winning_combo.len(), 12);
+        let combos = winning_combos(4);
+        assert!(combos.contains(&vec![0, 5, 10, 15]));
+        assert!(combos.contains(&vec![3, 6, 9, 12]));
+        assert!(combos.iter().all(|combo| combo.len() == 4));
+    }
+
+    #[test]
+    fn test_keypad_numbering_matches_on_3x3() {
+        let table = Table::new();
+        for position in 1..=9 {
+            assert_eq!(table.position_to_index(position), position_to_index(position));
+        }
+        let table = Table::with_size(4);
+        assert_eq!(table.position_to_index(1), 12);
+        assert_eq!(table.position_to_index(16), 3);
+        assert_eq!(table.index_to_position(table.position_to_index(7)), 7);
+    }
+
+    #[test]
+    fn test_shown_number_claims_that_cell_in_both_layouts() {
+        for layout in [KeyLayout::NumpadStyle, KeyLayout::ReadingOrder] {
+            for index in 0..9 {
+                let mut game = test_game();
+                game.set_key_layout(layout);
+                let rendered = game.tictac_board.render_to_string(false);
+                let shown: Vec<&str> = rendered.lines().step_by(2).flat_map(|row| row.split(" | ")).collect();
+                let typed: i32 = shown[index].trim().parse().unwrap();
+                assert_eq!(parse_move_with(&typed.to_string(), 3, layout), Ok(index));
+                game.play_move(typed).unwrap();
+                assert_eq!(game.history()[0].cell_index, index as i32);
+                assert_eq!(game.tictac_board.get_cell(index as i32).owner, "alice");
+            }
+        }
+        let mut table = Table::new();
+        table.init();
+        table.set_key_layout(KeyLayout::ReadingOrder);
+        assert!(table.render_to_string(false).starts_with("1 | 2 | 3\n"));
+    }
+
+    #[test]
+    fn test_parse_move_formats() {
+        for index in 0..9 {
+            let (row, col) = (index / 3 + 1, index % 3 + 1);
+            let letter = (b'a' + row as u8 - 1) as char;
+            assert_eq!(parse_move(&index_to_position(index as i32).to_string(), 3), Ok(index));
+            assert_eq!(parse_move(&format!("{},{}", row, col), 3), Ok(index));
+            assert_eq!(parse_move(&format!(" {} , {} ", row, col), 3), Ok(index));
+            assert_eq!(parse_move(&format!("{}{}", letter, col), 3), Ok(index));
+            assert_eq!(parse_move(&format!("{}{}", letter.to_ascii_uppercase(), col), 3), Ok(index));
+        }
+        assert_eq!(parse_move("16", 4), Ok(3));
+        assert_eq!(parse_move("4,4", 4), Ok(15));
+        assert_eq!(parse_move("d1", 4), Ok(12));
+    }
+
+    #[test]
+    fn test_parse_move_rejects_bad_input() {
+        let invalid = |s: &str| Err(ParseMoveError::InvalidFormat(s.to_string()));
+        let off_board = |s: &str| Err(ParseMoveError::OffBoard(s.to_string()));
+        assert_eq!(parse_move("", 3), Err(ParseMoveError::Empty));
+        assert_eq!(parse_move("  \n", 3), Err(ParseMoveError::Empty));
+        for bad in ["x", "1,", ",2", "1,2,3", "a", "a-1", "1a", "-1", "two", "1.5"] {
+            assert_eq!(parse_move(bad, 3), invalid(bad), "{}", bad);
+        }
+        for off in ["0", "10", "0,1", "4,1", "1,4", "d1", "a0", "a4", "z9"] {
+            assert_eq!(parse_move(off, 3), off_board(off), "{}", off);
+        }
+        assert_eq!(move_input_hint(3), "Enter a keypad number 1-9, a row and column like 1,3 or a cell like a1-c3");
+    }
+
+    #[test]
+    fn test_4x4_game_plays_to_a_draw() {
+        let mut game = sized_test_game(4);
+        // X X O O / O O X X / X X O O / O O X X: three in a row never wins.
+        let moves = [13, 15, 14, 16, 11, 9, 12, 10, 5, 7, 6, 8, 3, 1, 4];
+        for &position in &moves {
+            assert_eq!(game.play_move(position), Ok(MoveOutcome::Placed));
+        }
+        assert_eq!(game.play_move(2), Ok(MoveOutcome::Draw));
+        let result = game.result();
+        assert_eq!(result.winner, None);
+        assert_eq!(result.turns, 16);
+        assert_eq!(result.final_board, vec![1, 1, -1, -1, -1, -1, 1, 1, 1, 1, -1, -1, -1, -1, 1, 1]);
+        assert_eq!(game.play_move(2), Err(MoveError::GameOver));
+        assert_eq!(game.play_move(17), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn test_4x4_needs_a_full_row() {
+        let mut game = sized_test_game(4);
+        // alice fills the bottom row 1-4, bob plays above her.
+        for &position in &[1, 5, 2, 6, 3, 7] {
+            assert_eq!(game.play_move(position), Ok(MoveOutcome::Placed));
+        }
+        assert_eq!(game.play_move(17), Err(MoveError::OutOfRange));
+        assert_eq!(game.play_move(4), Ok(MoveOutcome::Won));
+        assert_eq!(game.result().winner, Some("alice".to_string()));
+    }
+
+    #[test]
+    fn test_render_uses_escapes_only_with_color() {
+        let table = Table::from_symbols("XO..X...O");
+        let plain = table.render_to_string(false);
+        assert!(!plain.contains('\x1b'));
+        assert_eq!(plain, "X | O | 9\n---------\n4 | X | 6\n---------\n1 | 2 | O");
+        let colored = table.render_to_string(true);
+        assert!(colored.contains("\x1b[31mX\x1b[0m"));
+        // O on cell 8 was placed last.
+        assert!(colored.contains("\x1b[34;4mO\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_marks_only_the_last_move() {
+        let mut game = test_game();
+        let underlined = |table: &Table| table.render_to_string(true).matches(";4m").count();
+        assert_eq!(game.tictac_board.last_played(), None);
+        assert_eq!(underlined(&game.tictac_board), 0);
+        game.play_move(5).unwrap();
+        assert_eq!(game.tictac_board.last_played(), Some(4));
+        assert!(game.tictac_board.render_to_string(true).contains("\x1b[31;4mX\x1b[0m"));
+        game.play_move(7).unwrap();
+        assert_eq!(game.tictac_board.last_played(), Some(0));
+        let colored = game.tictac_board.render_to_string(true);
+        assert!(colored.starts_with("\x1b[34;4mO\x1b[0m"), "{:?}", colored);
+        assert!(colored.contains("\x1b[31mX\x1b[0m"));
+        assert_eq!(underlined(&game.tictac_board), 1);
+        // The replay tables carry the marker too.
+        let replayed: Vec<Option<usize>> = game.replay_tables().iter().map(Table::last_played).collect();
+        assert_eq!(replayed, vec![Some(4), Some(0)]);
+        game.tictac_board.init();
+        assert_eq!(underlined(&game.tictac_board), 0);
+    }
+
+    #[test]
+    fn test_unicode_render_snapshot() {
+        let mut table = Table::from_symbols("XO..X...O");
+        table.set_render_style(RenderStyle::Unicode);
+        let expected = [
+            "    1   2   3",
+            "  ┌───┬───┬───┐",
+            "a │ X │ O │ 9 │",
+            "  ├───┼───┼───┤",
+            "b │ 4 │ X │ 6 │",
+            "  ├───┼───┼───┤",
+            "c │ 1 │ 2 │ O │",
+            "  └───┴───┴───┘",
+        ];
+        assert_eq!(table.render_to_string(false), expected.join("\n"));
+        assert!(table.render_to_string(true).contains("│ \x1b[31mX\x1b[0m │"));
+
+        let mut table = Table::with_size(4);
+        table.init();
+        table.set_render_style(RenderStyle::Unicode);
+        let rendered = table.render_to_string(false);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], "    1    2    3    4");
+        assert_eq!(lines[1], "  ┌────┬────┬────┬────┐");
+        assert_eq!(lines[2], "a │ 13 │ 14 │ 15 │ 16 │");
+        assert_eq!(lines[8], "d │ 1  │ 2  │ 3  │ 4  │");
+    }
+
+    #[test]
+    fn test_render_highlights_the_winning_line() {
+        let mut game = test_game();
+        for &position in &[7, 1, 8, 2, 9] {
+            game.play_move(position).unwrap();
+        }
+        let table = &game.tictac_board;
+        assert_eq!(table.winning_line(), vec![0, 1, 2]);
+        let colored = table.render_to_string(true);
+        assert_eq!(colored.matches("\x1b[1;32").count(), 3);
+        assert!(!table.render_to_string(false).contains('\x1b'));
+    }
+
+    #[test]
+    fn test_print_works_without_a_terminal() {
+        // Used to spawn `clear` and panic when it was missing.
+        let mut table = Table::from_symbols("X...O....");
+        table.print();
+        table.set_silent(true);
+        table.print();
+    }
+
+    #[test]
+    fn test_silent_game_prints_nothing() {
+        // Rerun just this test in a child process and look at its stdout.
+        if std::env::var_os("TICTAC_SILENT_CHILD").is_some() {
+            println!("<<begin>>");
+            let mut game = Game::with_players(Player::new("ai".to_string(), 'X'), Player::new("ai_2".to_string(), 'O'));
+            game.set_verbosity(Verbosity::Silent);
+            game.play();
+            println!("<<end>>");
+            return;
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "output::tests::test_silent_game_prints_nothing", "--nocapture", "--test-threads=1"])
+            .env("TICTAC_SILENT_CHILD", "1")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let between = stdout.split("<<begin>>").nth(1).and_then(|rest| rest.split("<<end>>").next());
+        assert_eq!(between, Some("\n"), "{}", stdout);
+        assert!(output.status.success());
+
+        let mut game = test_game();
+        game.set_verbosity(Verbosity::Verbose);
+        game.tictac_board.set_silent(true);
+        assert_eq!(game.verbosity(), Verbosity::Silent);
+    }
+
+    #[test]
+    fn test_memory_recorder_gets_every_snapshot() {
+        let mut game = Game::with_players(Player::new("ai".to_string(), 'X'), Player::new("ai_2".to_string(), 'O'));
+        game.tictac_board.set_silent(true);
+        game.policy1 = Scripted::new(&[7, 8, 9]);
+        game.policy2 = Scripted::new(&[5, 1]);
+        let recorder = MemoryRecorder::new();
+        game.recorder = Box::new(recorder.clone());
+        game.play();
+        let games = recorder.games();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].winner, "ai");
+        assert_eq!(
+            games[0].state_of_cells_list,
+            vec![
+                [1, 0, 0, 0, 0, 0, 0, 0, 0],
+                [1, 0, 0, 0, -1, 0, 0, 0, 0],
+                [1, 1, 0, 0, -1, 0, 0, 0, 0],
+                [1, 1, 0, 0, -1, 0, -1, 0, 0],
+                [1, 1, 1, 0, -1, 0, -1, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_random_games_only_play_free_cells() {
+        for _ in 0..10_000 {
+            let mut game = Game::with_players(Player::new("ai".to_string(), 'X'), Player::new("ai_2".to_string(), 'O'));
+            game.tictac_board.set_silent(true);
+            let mut moves = 0;
+            while !game.game_over {
+                let position = game.get_input(0);
+                assert!(game.play_move(position).is_ok(), "rejected {}", position);
+                moves += 1;
+                assert!(moves <= 9);
+            }
+            assert!(game.tictac_board.legal_positions().is_empty() || game.result().winner.is_some());
+        }
+    }
+
+    #[test]
+    fn test_alternating_first_player() {
+        let recorder = MemoryRecorder::new();
+        let mut first = Vec::new();
+        for game_number in 0..10 {
+            let mut game = Game::with_players(Player::new("ai".to_string(), 'X'), Player::new("ai_2".to_string(), 'O'));
+            game.tictac_board.set_silent(true);
+            game.recorder = Box::new(recorder.clone());
+            game.set_first_player(game_number % 2 + 1);
+            let result = game.play();
+            assert_eq!(result.history[0].player_name, result.first_player);
+            first.push(result.first_player);
+        }
+        assert_eq!(first.iter().filter(|name| *name == "ai").count(), 5);
+        assert_eq!(first.iter().filter(|name| *name == "ai_2").count(), 5);
+        // The recorded games know their first mover and who made each move.
+        for (game, name) in recorder.games().iter().zip(first.iter()) {
+            assert_eq!(game.first_player(), name);
+            assert_eq!(game.mover(0), name);
+            assert_ne!(game.mover(1), name);
+            assert_eq!(game.mover(2), name);
+        }
     }
 }
@@ -0,0 +1,518 @@
+// Where finished and running games get recorded. Boards use the table.csv
+// encoding: 1 for cells owned by player 1 ("ai" in self-play), -1 for
+// player 2, 0 empty.
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::input::{DataError, GameData, GamesData, ReadReport, CSV_HEADER, RUN_TAG, SCHEMA_TAG, SCHEMA_VERSION};
+use crate::meta::DatasetMeta;
+use crate::selfplay::game_seed;
+
+/// Receives the player names before the first move, a snapshot after every
+/// accepted move and the winner ("draw" for a draw) once the game is over.
+pub trait GameRecorder {
+    /// Names of the players owning the 1 and the -1 cells. Without this
+    /// call they are taken to be "ai" and "ai_2".
+    fn record_players(&mut self, _player1: &str, _player2: &str) {}
+    fn record_state(&mut self, board: &[i8; 9]);
+    fn record_result(&mut self, winner: &str);
+}
+
+/// Appends games to a CSV file in the schema `GamesData::read_data` reads
+/// (see `input::CSV_HEADER`), writing the schema line and the header first
+/// when the file is new. Moves are kept until the game's result arrives and
+/// written together; a game that never ends isn't written. Every game gets
+/// the time the recorder was created and a random id, or in a run one
+/// derived from the run's seed and the game's place in it. A new file made
+/// `with_meta` starts with the metadata.
+///
+/// A path ending in `.gz` gets gzip-compressed rows: every recorder appends
+/// a gzip member, complete once the recorder is dropped.
+pub struct CsvRecorder {
+    writer: CsvOutput,
+    run_timestamp: u64,
+    players: [String; 2],
+    states: Vec<[i8; 9]>,
+    /// Seed of the current run and the games recorded in it so far.
+    run: Option<(u64, usize)>,
+}
+
+impl CsvRecorder {
+    pub fn new(path: impl AsRef<Path>) -> io::Result<CsvRecorder> {
+        CsvRecorder::open(path, None)
+    }
+
+    /// `new`, writing `meta` first if the file is new; an existing file
+    /// keeps the metadata it has, if any.
+    pub fn with_meta(path: impl AsRef<Path>, meta: &DatasetMeta) -> io::Result<CsvRecorder> {
+        CsvRecorder::open(path, Some(meta))
+    }
+
+    fn open(path: impl AsRef<Path>, meta: Option<&DatasetMeta>) -> io::Result<CsvRecorder> {
+        let compressed = path.as_ref().to_string_lossy().ends_with(".gz");
+        let mut file = std::fs::OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        // Rows end with a newline, legacy files don't. The last byte of a
+        // compressed file isn't at hand, so a new member starts with an
+        // empty line, which the reader skips.
+        let mut last = [b'\n'];
+        if len > 0 && compressed {
+            last[0] = 0;
+        } else if len > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+        }
+        let run_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let writer = if compressed {
+            CsvOutput::Gzip(GzEncoder::new(BufWriter::new(file), Compression::default()))
+        } else {
+            CsvOutput::Plain(BufWriter::new(file))
+        };
+        let mut recorder = CsvRecorder {
+            writer,
+            run_timestamp,
+            players: ["ai".to_string(), "ai_2".to_string()],
+            states: Vec::new(),
+            run: None,
+        };
+        if len == 0 {
+            if let Some(meta) = meta {
+                write!(recorder.writer, "{}", meta)?;
+            }
+            writeln!(recorder.writer, "{},{}\n{}", SCHEMA_TAG, SCHEMA_VERSION, CSV_HEADER)?;
+        } else if last[0] != b'\n' {
+            writeln!(recorder.writer)?;
+        }
+        recorder.writer.flush()?;
+        Ok(recorder)
+    }
+
+    /// Unix time in seconds, written on every game of this run.
+    pub fn run_timestamp(&self) -> u64 {
+        self.run_timestamp
+    }
+
+    /// Start a run of games generated from `seed`: write a run row (see
+    /// `input::RUN_TAG`) and record the games from now on with
+    /// `timestamp` and ids from `seed`, until `end_run`. Returns the run's
+    /// id, `run_id(seed, timestamp)`.
+    pub fn start_run(&mut self, seed: u64, timestamp: u64) -> io::Result<String> {
+        let id = run_id(seed, timestamp);
+        self.write_run_row(&id)?;
+        self.run_timestamp = timestamp;
+        self.run = Some((seed, 0));
+        Ok(id)
+    }
+
+    /// End the run: the games recorded after this belong to none.
+    pub fn end_run(&mut self) -> io::Result<()> {
+        self.run = None;
+        self.write_run_row("")
+    }
+
+    /// The run row of `id`, with the seed and timestamp it was made from
+    /// when it is a `run_id`; an empty `id` ends the run before.
+    fn write_run_row(&mut self, id: &str) -> io::Result<()> {
+        let parts = id
+            .split_once('-')
+            .and_then(|(seed, timestamp)| Some((u64::from_str_radix(seed, 16).ok()?, timestamp.parse::<u64>().ok()?)));
+        match parts {
+            Some((seed, timestamp)) => writeln!(self.writer, "{},{},{},{}", RUN_TAG, id, seed, timestamp)?,
+            None => writeln!(self.writer, "{},{},,", RUN_TAG, id)?,
+        }
+        self.writer.flush()
+    }
+
+    /// Write out what is left and report any error, which dropping the
+    /// recorder would ignore. Only compressed files need it.
+    pub fn finish(self) -> io::Result<()> {
+        match self.writer {
+            CsvOutput::Plain(mut writer) => writer.flush(),
+            CsvOutput::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+
+    /// One row per snapshot. The first snapshot shows who opened; the
+    /// player to move alternates from the other one.
+    fn write_game(&mut self, id: &str, timestamp: u64, states: &[[i8; 9]], winner: &str) -> io::Result<()> {
+        let opener = match states.first() {
+            Some(state) if !state.contains(&1) => 1,
+            _ => 0,
+        };
+        for (index, state) in states.iter().enumerate() {
+            let last = index + 1 == states.len();
+            let cells: Vec<String> = state.iter().map(i8::to_string).collect();
+            writeln!(
+                self.writer,
+                "{},{},{},{},{},{},{},{}",
+                id,
+                timestamp,
+                index + 1,
+                self.players[0],
+                self.players[1],
+                cells.join(","),
+                if last { winner } else { "" },
+                if last { "" } else { &self.players[(opener + index + 1) % 2] }
+            )?;
+        }
+        self.writer.flush()
+    }
+}
+
+/// Id of the run generated from `seed` whose games are stamped
+/// `timestamp`; running again with both gives the same id.
+pub fn run_id(seed: u64, timestamp: u64) -> String {
+    format!("{:016x}-{}", seed, timestamp)
+}
+
+/// The file behind a `CsvRecorder`.
+enum CsvOutput {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Write for CsvOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CsvOutput::Plain(writer) => writer.write(buf),
+            CsvOutput::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CsvOutput::Plain(writer) => writer.flush(),
+            CsvOutput::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// `name` made safe for an unquoted CSV field.
+fn csv_field(name: &str) -> String {
+    name.replace([',', '"', '\n', '\r'], "_")
+}
+
+impl GameRecorder for CsvRecorder {
+    fn record_players(&mut self, player1: &str, player2: &str) {
+        self.players = [csv_field(player1), csv_field(player2)];
+    }
+
+    fn record_state(&mut self, board: &[i8; 9]) {
+        self.states.push(*board);
+    }
+
+    fn record_result(&mut self, winner: &str) {
+        let id = match self.run.as_mut() {
+            Some((seed, games)) => {
+                *games += 1;
+                format!("{:016x}", game_seed(*seed, *games - 1))
+            }
+            None => format!("{:016x}", rand::random::<u64>()),
+        };
+        let states = std::mem::take(&mut self.states);
+        if let Err(e) = self.write_game(&id, self.run_timestamp, &states, &csv_field(winner)) {
+            eprintln!("could not record game: {}", e);
+        }
+        self.players = ["ai".to_string(), "ai_2".to_string()];
+    }
+}
+
+/// Rewrite the games of `old_path`, in any format `read_data` takes, to a
+/// new file at `new_path` in the current schema. Ids and timestamps are
+/// kept where the old rows had them; legacy games get new ids and the
+/// time of the migration. Rows `read_data` skipped are in the report and
+/// not migrated.
+pub fn migrate_csv(old_path: impl AsRef<Path>, new_path: impl AsRef<Path>) -> Result<ReadReport, DataError> {
+    let mut data = GamesData::new(old_path.as_ref().to_string_lossy().into_owned());
+    let report = data.read_data()?;
+    save_csv(&new_path, &data).map_err(|e| DataError::Io(new_path.as_ref().to_string_lossy().into_owned(), e))?;
+    Ok(report)
+}
+
+/// Write `games` to a new file at `path` in the current schema, e.g. the
+/// result of `GamesData::merge`; gzip-compressed when `path` ends in
+/// `.gz`. Ids, timestamps and runs are kept where the games have them;
+/// the others get new ids and the current time. The games' `meta`, if
+/// any, heads the file.
+pub fn save_csv(path: impl AsRef<Path>, games: &GamesData) -> io::Result<()> {
+    File::create(&path)?;
+    let mut recorder = CsvRecorder::open(&path, games.meta.as_ref())?;
+    let mut run = None;
+    for game in games {
+        if game.run_id != run {
+            recorder.write_run_row(game.run_id.as_deref().unwrap_or(""))?;
+            run = game.run_id.clone();
+        }
+        recorder.record_players(&game.player1, &game.player2);
+        let id = game
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+        let timestamp = game.timestamp.unwrap_or(recorder.run_timestamp);
+        recorder.write_game(&csv_field(&id), timestamp, &game.state_of_cells_list, &csv_field(&game.winner))?;
+    }
+    recorder.finish()
+}
+
+/// Keeps games in memory as `GameData`, for training straight from
+/// generated games. Clones share the same storage, so keep one to read the
+/// games back after handing another to a `Game`.
+#[derive(Clone, Default)]
+pub struct MemoryRecorder {
+    log: Rc<RefCell<MemoryLog>>,
+}
+
+#[derive(Default)]
+struct MemoryLog {
+    games: Vec<GameData>,
+    current: Vec<[i8; 9]>,
+    players: Option<(String, String)>,
+}
+
+impl MemoryRecorder {
+    pub fn new() -> MemoryRecorder {
+        MemoryRecorder::default()
+    }
+
+    /// Finished games, oldest first.
+    pub fn games(&self) -> Vec<GameData> {
+        self.log.borrow().games.clone()
+    }
+
+    /// Snapshots of the game in progress.
+    pub fn current_states(&self) -> Vec<[i8; 9]> {
+        self.log.borrow().current.clone()
+    }
+}
+
+impl GameRecorder for MemoryRecorder {
+    fn record_players(&mut self, player1: &str, player2: &str) {
+        self.log.borrow_mut().players = Some((player1.to_string(), player2.to_string()));
+    }
+
+    fn record_state(&mut self, board: &[i8; 9]) {
+        self.log.borrow_mut().current.push(*board);
+    }
+
+    fn record_result(&mut self, winner: &str) {
+        let mut log = self.log.borrow_mut();
+        // Same default names GamesData::read_data assumes.
+        let (player1, player2) = log
+            .players
+            .take()
+            .unwrap_or_else(|| ("ai".to_string(), "ai_2".to_string()));
+        let mut game = GameData::new(player1, player2);
+        game.winner = winner.to_string();
+        game.state_of_cells_list = std::mem::take(&mut log.current);
+        if let Some(last) = game.state_of_cells_list.last() {
+            game.periodic_state_of_cells = *last;
+        }
+        log.games.push(game);
+    }
+}
+
+/// Records nothing; for games that shouldn't end up in the training data.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullRecorder;
+
+impl GameRecorder for NullRecorder {
+    fn record_state(&mut self, _board: &[i8; 9]) {}
+
+    fn record_result(&mut self, _winner: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::GamesData;
+
+    #[test]
+    fn test_csv_recorder_writes_the_table_csv_format() {
+        let path = std::env::temp_dir().join(format!("tictac_recorder_{}.csv", std::process::id()));
+        let boards = [
+            [1, 0, 0, 0, 0, 0, 0, 0, 0],
+            [1, 0, 0, 0, -1, 0, 0, 0, 0],
+            [1, 1, 0, 0, -1, 0, 0, 0, 0],
+            [1, 1, 0, 0, -1, 0, -1, 0, 0],
+            [1, 1, 1, 0, -1, 0, -1, 0, 0],
+        ];
+        let timestamp = {
+            let mut recorder = CsvRecorder::new(&path).unwrap();
+            for _ in 0..2 {
+                for board in &boards {
+                    recorder.record_state(board);
+                }
+                recorder.record_result("ai");
+            }
+            recorder.run_timestamp()
+        };
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("tictac-games,2"));
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), 10);
+        for (number, row) in rows.iter().enumerate() {
+            assert_eq!(row.len(), 16);
+            assert_eq!(row[0], rows[number / 5 * 5][0]);
+            assert_eq!(row[1], timestamp.to_string());
+            assert_eq!(row[2], (number % 5 + 1).to_string());
+            assert_eq!(row[3..5], ["ai", "ai_2"]);
+            let cells: Vec<String> = boards[number % 5].iter().map(i8::to_string).collect();
+            assert_eq!(row[5..14], cells[..]);
+            assert_eq!(row[14], if number % 5 == 4 { "ai" } else { "" });
+            let to_move = ["ai_2", "ai", "ai_2", "ai", ""][number % 5];
+            assert_eq!(row[15], to_move);
+        }
+        assert_ne!(rows[0][0], rows[5][0]);
+
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        data.read_data().unwrap();
+        assert_eq!(data.game_data.len(), 2);
+        let game = data.get_game(0);
+        assert_eq!(game.winner, "ai");
+        assert_eq!(game.state_of_cells_list, boards.to_vec());
+        assert_eq!(game.id.as_deref(), Some(rows[0][0]));
+        assert_eq!(game.timestamp, Some(timestamp));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_named_players_round_trip() {
+        use crate::output::{Game, Player};
+
+        let path = std::env::temp_dir().join(format!("tictac_named_{}.csv", std::process::id()));
+        let mut game = Game::with_players(Player::new("alice".to_string(), 'X'), Player::new("bob".to_string(), 'O'));
+        game.tictac_board.set_silent(true);
+        game.recorder = Box::new(CsvRecorder::new(&path).unwrap());
+        for position in [5, 7, 9, 3, 1] {
+            game.play_move(position).unwrap();
+        }
+        drop(game);
+        let mut recorder = CsvRecorder::new(&path).unwrap();
+        recorder.record_players("carol, jr", "dave");
+        recorder.record_state(&[0, 0, 0, 0, -1, 0, 0, 0, 0]);
+        recorder.record_result("dave");
+        drop(recorder);
+        // A row naming a winner who isn't playing is left out.
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, b"\n00000000000000e5,0,1,erin,frank,1,0,0,0,0,0,0,0,0,gina").unwrap();
+
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        let report = data.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((report.games, report.rows_skipped()), (2, 1));
+        let alice_bob = data.get_game(0);
+        assert_eq!((alice_bob.player1.as_str(), alice_bob.player2.as_str()), ("alice", "bob"));
+        assert_eq!(alice_bob.winner, "alice");
+        assert_eq!(alice_bob.first_player(), "alice");
+        assert_eq!(alice_bob.state_of_cells_list[1], [-1, 0, 0, 0, 1, 0, 0, 0, 0]);
+        let carol_dave = data.get_game(1);
+        assert_eq!((carol_dave.player1.as_str(), carol_dave.player2.as_str()), ("carol_ jr", "dave"));
+        assert_eq!((carol_dave.winner.as_str(), carol_dave.first_player()), ("dave", "dave"));
+    }
+
+    #[test]
+    fn test_migrated_legacy_file_reads_the_same() {
+        let old = std::env::temp_dir().join(format!("tictac_legacy_{}.csv", std::process::id()));
+        let new = std::env::temp_dir().join(format!("tictac_migrated_{}.csv", std::process::id()));
+        let legacy = "\n0,0,0,0,-1,0,0,0,0,\
+                      \n1,0,0,0,-1,0,0,0,0,\
+                      \n1,0,0,0,-1,-1,0,0,0,\
+                      \n1,0,0,1,-1,-1,0,0,0,\
+                      \n1,0,0,1,-1,-1,-1,0,0,\
+                      \n1,0,-1,1,-1,-1,-1,0,0,ai_2\
+                      \n1,0,0,0,0,0,0,0,0,\
+                      \n1,-1,0,0,0,0,0,0,0,draw";
+        std::fs::write(&old, legacy).unwrap();
+        let mut before = GamesData::new(old.to_string_lossy().into_owned());
+        before.read_data().unwrap();
+        let report = migrate_csv(&old, &new).unwrap();
+        assert_eq!((report.games, report.rows_skipped()), (2, 0));
+
+        let mut after = GamesData::new(new.to_string_lossy().into_owned());
+        assert_eq!(after.read_data().unwrap().games, 2);
+        let text = std::fs::read_to_string(&new).unwrap();
+        assert!(text.starts_with("tictac-games,2\n"));
+        // ai_2 opened, so ai moves second.
+        assert_eq!(text.lines().nth(2).unwrap().split(',').nth(15), Some("ai"));
+        for (old_game, new_game) in before.game_data.iter().zip(&after.game_data) {
+            assert_eq!(new_game.winner, old_game.winner);
+            assert_eq!((&new_game.player1, &new_game.player2), (&old_game.player1, &old_game.player2));
+            assert_eq!(new_game.state_of_cells_list, old_game.state_of_cells_list);
+            assert_eq!(new_game.periodic_state_of_cells, old_game.periodic_state_of_cells);
+            assert!(new_game.id.is_some());
+        }
+        // Migrating again keeps the ids, and appending to the legacy file
+        // starts the new rows on a line of their own.
+        let mut again = GamesData::new(new.to_string_lossy().into_owned());
+        migrate_csv(&new, &old).unwrap();
+        again.csv_file = old.to_string_lossy().into_owned();
+        again.read_data().unwrap();
+        assert_eq!(again.game_data[1].id, after.game_data[1].id);
+        std::fs::write(&old, legacy).unwrap();
+        let mut recorder = CsvRecorder::new(&old).unwrap();
+        recorder.record_state(&[0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        recorder.record_result("draw");
+        drop(recorder);
+        let mut appended = GamesData::new(old.to_string_lossy().into_owned());
+        let report = appended.read_data().unwrap();
+        std::fs::remove_file(&old).unwrap();
+        std::fs::remove_file(&new).unwrap();
+        assert_eq!((report.games, report.rows_skipped()), (3, 0));
+    }
+
+    #[test]
+    fn test_compressed_recording_appends() {
+        let path = std::env::temp_dir().join(format!("tictac_recorded_{}.csv.gz", std::process::id()));
+        let copy = std::env::temp_dir().join(format!("tictac_saved_{}.csv.gz", std::process::id()));
+        let mut recorder = CsvRecorder::new(&path).unwrap();
+        recorder.record_players("alice", "bob");
+        recorder.record_state(&[0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        recorder.record_state(&[-1, 0, 0, 0, 1, 0, 0, 0, 0]);
+        recorder.record_result("draw");
+        recorder.finish().unwrap();
+        // A second run appends a gzip member of its own, dropped unfinished.
+        let mut recorder = CsvRecorder::new(&path).unwrap();
+        recorder.record_state(&[0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        recorder.record_result("ai");
+        drop(recorder);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes[..2], [0x1f, 0x8b]);
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        let report = data.read_data().unwrap();
+        assert_eq!((report.games, report.rows_skipped()), (2, 0));
+        assert_eq!((data[0].player1.as_str(), data[0].move_count()), ("alice", 2));
+        assert_eq!(data[1].winner, "ai");
+
+        data.save_csv(&copy).unwrap();
+        let mut saved = GamesData::new(copy.to_string_lossy().into_owned());
+        saved.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&copy).unwrap();
+        assert_eq!(saved.game_data, data.game_data);
+    }
+
+    #[test]
+    fn test_memory_recorder_clones_share_games() {
+        let reader = MemoryRecorder::new();
+        let mut writer = reader.clone();
+        writer.record_state(&[1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(reader.current_states().len(), 1);
+        writer.record_result("draw");
+        assert!(reader.current_states().is_empty());
+        let games = reader.games();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].winner, "draw");
+    }
+}
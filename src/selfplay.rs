@@ -0,0 +1,178 @@
+// Bulk ai-vs-ai games for training data, played in parallel.
+use std::io;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::meta::DatasetMeta;
+use crate::output::{Game, GameResult, MovePolicy, Player, Verbosity};
+use crate::recorder::{CsvRecorder, GameRecorder};
+
+/// Play `n` silent games between "ai" (X, built by `policy_a`) and "ai_2"
+/// (O, built by `policy_b`) on the rayon thread pool. The opener
+/// alternates, "ai" first in even games. Each game's policies get seeds
+/// derived from `seed` and the game index only, so the results are the
+/// same whatever the number of threads.
+pub fn generate_games<A, B>(n: usize, policy_a: A, policy_b: B, seed: u64) -> Vec<GameResult>
+where
+    A: Fn(char, u64) -> Box<dyn MovePolicy> + Sync,
+    B: Fn(char, u64) -> Box<dyn MovePolicy> + Sync,
+{
+    (0..n)
+        .into_par_iter()
+        .map(|index| {
+            let mut rng = StdRng::seed_from_u64(game_seed(seed, index));
+            let mut game = Game::with_policies(
+                (Player::ai("ai".to_string(), 'X'), policy_a('X', rng.gen())),
+                (Player::ai("ai_2".to_string(), 'O'), policy_b('O', rng.gen())),
+            );
+            game.set_verbosity(Verbosity::Silent);
+            game.set_first_player(index % 2 + 1);
+            game.play()
+        })
+        .collect()
+}
+
+/// SplitMix64 of `seed` and `index` together, so that the runs of nearby
+/// seeds don't share games the way `seed + index` would.
+pub(crate) fn game_seed(seed: u64, index: usize) -> u64 {
+    let mut z = (seed ^ (index as u64).rotate_left(32)).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Append `results` to `path` in the table.csv format, in one write;
+/// compressed when `path` ends in `.gz`.
+pub fn write_csv(results: &[GameResult], path: impl AsRef<Path>) -> io::Result<()> {
+    let mut recorder = CsvRecorder::new(path)?;
+    record_results(&mut recorder, results);
+    recorder.finish()
+}
+
+/// `write_csv` for the games `generate_games` made from `seed` with the
+/// policies named `agents`, marked as one run stamped `timestamp` (see
+/// `CsvRecorder::start_run`) so that `GamesData::append_from` takes them
+/// once. A new file gets the metadata of the run. Returns the run id.
+pub fn write_run(
+    results: &[GameResult],
+    path: impl AsRef<Path>,
+    agents: [&str; 2],
+    seed: u64,
+    timestamp: u64,
+) -> io::Result<String> {
+    let mut recorder = CsvRecorder::with_meta(path, &DatasetMeta::new(&agents, Some(seed)))?;
+    let id = recorder.start_run(seed, timestamp)?;
+    record_results(&mut recorder, results);
+    recorder.end_run()?;
+    recorder.finish()?;
+    Ok(id)
+}
+
+fn record_results(recorder: &mut CsvRecorder, results: &[GameResult]) {
+    for result in results {
+        recorder.record_players("ai", "ai_2");
+        let mut board = [0; 9];
+        for record in &result.history {
+            board[record.cell_index as usize] = if record.player_name == "ai" { 1 } else { -1 };
+            recorder.record_state(&board);
+        }
+        recorder.record_result(result.winner.as_deref().unwrap_or("draw"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::RandomAi;
+    use crate::input::GamesData;
+
+    const RANDOM: [&str; 2] = ["random", "random"];
+
+    fn random(_symbol: char, seed: u64) -> Box<dyn MovePolicy> {
+        Box::new(RandomAi::with_seed(seed))
+    }
+
+    fn generate_on(threads: usize, n: usize, seed: u64) -> Vec<GameResult> {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| generate_games(n, random, random, seed))
+    }
+
+    #[test]
+    fn test_generated_games_do_not_depend_on_thread_count() {
+        let serial = generate_on(1, 200, 9);
+        assert_eq!(serial.len(), 200);
+        assert_eq!(serial, generate_on(4, 200, 9));
+        assert_ne!(serial, generate_on(4, 200, 10));
+        assert_eq!(serial[0].first_player, "ai");
+        assert_eq!(serial[1].first_player, "ai_2");
+    }
+
+    #[test]
+    fn test_runs_of_neighbouring_seeds_do_not_overlap() {
+        let seeds: std::collections::HashSet<u64> =
+            (9..12).flat_map(|seed| (0..100).map(move |index| game_seed(seed, index))).collect();
+        assert_eq!(seeds.len(), 300);
+    }
+
+    #[test]
+    fn test_write_csv_reads_back() {
+        let path = std::env::temp_dir().join(format!("tictac_selfplay_{}.csv", std::process::id()));
+        let results = generate_on(2, 20, 3);
+        write_csv(&results, &path).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        data.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data.game_data.len(), 20);
+        for (index, result) in results.iter().enumerate() {
+            let game = data.get_game(index);
+            assert_eq!(game.winner, result.winner.clone().unwrap_or_else(|| "draw".to_string()));
+            assert_eq!(game.state_of_cells_list.len(), result.history.len());
+            assert_eq!(game.first_player(), result.first_player);
+        }
+    }
+
+    #[test]
+    fn test_appending_a_run_twice_adds_it_once() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("tictac_run_{}.csv", std::process::id()));
+        let rerun = dir.join(format!("tictac_rerun_{}.csv", std::process::id()));
+        let table = dir.join(format!("tictac_runs_{}.csv", std::process::id()));
+        let id = write_run(&generate_on(2, 10, 5), &first, RANDOM, 5, 1_700_000_000).unwrap();
+        assert_eq!(id, "0000000000000005-1700000000");
+        // Rerunning after a crash repeats the run.
+        let again = write_run(&generate_on(2, 10, 5), &rerun, RANDOM, 5, 1_700_000_000).unwrap();
+        assert_eq!(again, id);
+
+        let mut data = GamesData::new(String::new());
+        let report = data.append_from(&first).unwrap();
+        assert_eq!((report.games, report.duplicates, report.skipped.len()), (10, 0, 0));
+        let meta = data.meta.clone().unwrap();
+        assert_eq!((meta.agents, meta.seed, meta.board_size), (vec!["random".to_string(), "random".to_string()], Some(5), 3));
+        assert!(data.iter().all(|game| game.run_id.as_deref() == Some(id.as_str())));
+        assert!(data.iter().all(|game| game.timestamp == Some(1_700_000_000)));
+        let report = data.append_from(&rerun).unwrap();
+        assert_eq!((report.games, report.duplicates), (0, 10));
+        assert_eq!(data.len(), 10);
+
+        // Another run in the same file still comes in, and saving keeps
+        // the runs apart.
+        write_run(&generate_on(2, 4, 6), &first, RANDOM, 6, 1_700_000_000).unwrap();
+        write_csv(&generate_on(2, 3, 7), &first).unwrap();
+        let report = data.append_from(&first).unwrap();
+        assert_eq!((report.games, report.duplicates), (7, 10));
+        assert_eq!(data.len(), 17);
+        assert_eq!(data[16].run_id, None);
+        data.save_csv(&table).unwrap();
+        let mut saved = GamesData::new(String::new());
+        saved.append_from(&table).unwrap();
+        assert_eq!(saved.game_data, data.game_data);
+        let report = saved.append_from(&first).unwrap();
+        for path in [first, rerun, table] {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert_eq!((report.games, report.duplicates), (3, 14));
+    }
+}
@@ -0,0 +1,219 @@
+// Matches: the same two players play game after game until one of them
+// reaches a target score, e.g. first to 3 wins.
+use std::fmt;
+
+use crate::output::{Game, GameResult, Seat, Verbosity};
+
+/// Running score of a match; index 0 is the first seat.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scoreboard {
+    pub names: [String; 2],
+    pub points: [f32; 2],
+    pub wins: [u32; 2],
+    pub draws: u32,
+}
+
+impl Scoreboard {
+    pub fn games(&self) -> u32 {
+        self.wins[0] + self.wins[1] + self.draws
+    }
+}
+
+impl fmt::Display for Scoreboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} - {} {} after {} games ({} drawn)",
+            self.names[0],
+            self.points[0],
+            self.points[1],
+            self.names[1],
+            self.games(),
+            self.draws
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchResult {
+    /// None when the match was quit or ran out of games first.
+    pub winner: Option<String>,
+    pub score: Scoreboard,
+    /// Every game of the match, in order.
+    pub games: Vec<GameResult>,
+    /// Whether it ended because someone chose to stop.
+    pub quit: bool,
+}
+
+pub struct Match {
+    seats: Option<(Seat, Seat)>,
+    /// Points needed to win the match.
+    pub target: f32,
+    /// Points each player gets for a draw; 0.5 by default.
+    pub draw_points: f32,
+    /// The match stops without a winner after this many games, so two
+    /// players who always draw can't go on forever.
+    pub max_games: u32,
+    pub verbosity: Verbosity,
+}
+
+impl Match {
+    pub fn new(seat1: Seat, seat2: Seat, target: f32) -> Match {
+        Match {
+            seats: Some((seat1, seat2)),
+            target,
+            draw_points: 0.5,
+            max_games: 100,
+            verbosity: Verbosity::Normal,
+        }
+    }
+
+    /// Play the match. When a human is playing they are asked after every
+    /// game whether to go on.
+    pub fn play(&mut self) -> MatchResult {
+        let (seat1, seat2) = self.seats.as_ref().expect("the seats are in use");
+        let human = !seat1.0.is_ai || !seat2.0.is_ai;
+        self.play_with(|_| !human || ask_to_continue())
+    }
+
+    /// Play the match, calling `keep_going` with the score after every game
+    /// that doesn't end it; the match is quit when it returns false. The
+    /// first seat opens the first game and the opener alternates.
+    pub fn play_with(&mut self, mut keep_going: impl FnMut(&Scoreboard) -> bool) -> MatchResult {
+        let mut seats = self.seats.take().expect("the seats are in use");
+        let mut score = Scoreboard {
+            names: [seats.0 .0.name.clone(), seats.1 .0.name.clone()],
+            ..Scoreboard::default()
+        };
+        let mut games = Vec::new();
+        let mut winner = None;
+        let mut quit = false;
+        loop {
+            let mut game = Game::with_policies(seats.0, seats.1);
+            game.set_verbosity(self.verbosity);
+            game.set_first_player(games.len() % 2 + 1);
+            let result = game.play();
+            seats = game.into_seats();
+
+            match result.winner.as_ref().and_then(|name| score.names.iter().position(|n| n == name)) {
+                Some(side) => {
+                    score.wins[side] += 1;
+                    score.points[side] += 1.0;
+                }
+                None => {
+                    score.draws += 1;
+                    score.points[0] += self.draw_points;
+                    score.points[1] += self.draw_points;
+                }
+            }
+            games.push(result);
+            if self.verbosity != Verbosity::Silent {
+                println!("{}", score);
+            }
+
+            let [a, b] = score.points;
+            if a >= self.target && a > b {
+                winner = Some(score.names[0].clone());
+            } else if b >= self.target && b > a {
+                winner = Some(score.names[1].clone());
+            }
+            if winner.is_some() || games.len() as u32 >= self.max_games {
+                break;
+            }
+            if !keep_going(&score) {
+                quit = true;
+                break;
+            }
+        }
+        self.seats = Some(seats);
+        MatchResult {
+            winner,
+            score,
+            games,
+            quit,
+        }
+    }
+}
+
+fn ask_to_continue() -> bool {
+    println!("Press enter for the next game or q to quit the match");
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).unwrap();
+    !input.trim().eq_ignore_ascii_case("q")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Difficulty, MinimaxAi};
+    use crate::output::{MovePolicy, Player, Table};
+
+    /// Always takes the free cell with the lowest index; whoever opens
+    /// wins on the diagonal 2-4-6.
+    struct FirstFree;
+
+    impl MovePolicy for FirstFree {
+        fn choose_move(&mut self, _table: &Table, legal: &[i32]) -> i32 {
+            legal[0]
+        }
+    }
+
+    fn first_free_match(target: f32) -> Match {
+        let mut series = Match::new(
+            (Player::ai("a".to_string(), 'X'), Box::new(FirstFree)),
+            (Player::ai("b".to_string(), 'O'), Box::new(FirstFree)),
+            target,
+        );
+        series.verbosity = Verbosity::Silent;
+        series
+    }
+
+    #[test]
+    fn test_match_ends_when_the_target_is_reached() {
+        let result = first_free_match(3.0).play_with(|_| true);
+        // The opener wins every game and a opens the odd ones: 1-0 .. 3-2.
+        assert_eq!(result.games.len(), 5);
+        assert_eq!(result.winner, Some("a".to_string()));
+        assert_eq!(result.score.points, [3.0, 2.0]);
+        assert_eq!(result.score.wins, [3, 2]);
+        let openers: Vec<&str> = result.games.iter().map(|game| game.first_player.as_str()).collect();
+        assert_eq!(openers, vec!["a", "b", "a", "b", "a"]);
+        assert!(!result.quit);
+        assert_eq!(result.score.to_string(), "a 3 - 2 b after 5 games (0 drawn)");
+    }
+
+    #[test]
+    fn test_match_can_be_quit() {
+        let mut calls = 0;
+        let result = first_free_match(3.0).play_with(|score| {
+            calls += 1;
+            score.games() < 2
+        });
+        assert_eq!((calls, result.games.len()), (2, 2));
+        assert!(result.quit);
+        assert_eq!(result.winner, None);
+    }
+
+    #[test]
+    fn test_drawn_match_stops_at_max_games() {
+        let minimax = |symbol| -> Box<dyn MovePolicy> { Box::new(MinimaxAi::with_difficulty(symbol, Difficulty::Hard)) };
+        let mut series = Match::new(
+            (Player::ai("a".to_string(), 'X'), minimax('X')),
+            (Player::ai("b".to_string(), 'O'), minimax('O')),
+            1.0,
+        );
+        series.verbosity = Verbosity::Silent;
+        series.draw_points = 0.0;
+        series.max_games = 4;
+        let result = series.play_with(|_| true);
+        assert_eq!(result.games.len(), 4);
+        assert_eq!(result.score.draws, 4);
+        assert_eq!(result.score.points, [0.0, 0.0]);
+        assert_eq!(result.winner, None);
+
+        series.draw_points = 0.5;
+        series.max_games = 100;
+        // Both on 1.0 after two draws: a tie at the target plays on.
+        assert_eq!(series.play_with(|score| score.games() < 3).games.len(), 3);
+    }
+}
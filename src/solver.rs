@@ -0,0 +1,154 @@
+// Perfect play by exhaustive search. Boards are from the side of the
+// player to move, as in `ai`: 1 for its marks, -1 for the opponent's.
+use std::collections::HashMap;
+
+use crate::ai::winner;
+
+/// Game-theoretic value of a position for the player to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Value {
+    Loss,
+    Draw,
+    Win,
+}
+
+impl Value {
+    /// 1, 0 or -1, as in `OutcomeSet::outcomes`.
+    pub fn sign(self) -> i8 {
+        match self {
+            Value::Loss => -1,
+            Value::Draw => 0,
+            Value::Win => 1,
+        }
+    }
+}
+
+/// What `Solver::solve` found for an unfinished position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    pub value: Value,
+    /// Every move keeping `value`, lowest cell first.
+    pub optimal_moves: Vec<usize>,
+    /// The optimal move winning soonest or losing latest, the lowest cell
+    /// on a tie.
+    pub best_move: usize,
+}
+
+/// Solves positions, remembering every position it has scored; there are
+/// only a few thousand reachable ones.
+#[derive(Clone, Debug, Default)]
+pub struct Solver {
+    scores: HashMap<[i8; 9], i8>,
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver::default()
+    }
+
+    /// The value and optimal moves of `board`, None once the game is over
+    /// (a line is complete or the board is full).
+    pub fn solve(&mut self, board: &[i8; 9]) -> Option<Solution> {
+        if winner(board) != 0 || !board.contains(&0) {
+            return None;
+        }
+        let scores: Vec<(usize, i8)> = (0..9)
+            .filter(|&cell| board[cell] == 0)
+            .map(|cell| (cell, self.move_score(board, cell)))
+            .collect();
+        let best_score = scores.iter().map(|&(_, score)| score).max().expect("an empty cell");
+        let value = value_of(best_score);
+        Some(Solution {
+            value,
+            optimal_moves: scores.iter().filter(|&&(_, score)| value_of(score) == value).map(|&(cell, _)| cell).collect(),
+            best_move: scores.iter().find(|&&(_, score)| score == best_score).expect("a best move").0,
+        })
+    }
+
+    /// `solve`'s value alone.
+    pub fn value(&mut self, board: &[i8; 9]) -> Option<Value> {
+        if winner(board) != 0 || !board.contains(&0) {
+            return None;
+        }
+        Some(value_of(self.score(board)))
+    }
+
+    /// Score of playing `cell` for the player to move.
+    fn move_score(&mut self, board: &[i8; 9], cell: usize) -> i8 {
+        let mut after = *board;
+        after[cell] = 1;
+        // The opponent moves next, so the board flips to their side.
+        -self.score(&after.map(|value| -value))
+    }
+
+    /// Score of `board` for the player to move: 0 for a draw, otherwise
+    /// positive for a win and negative for a loss, by one more than the
+    /// number of empty cells left when the game ends, so sooner wins and
+    /// later losses score higher.
+    fn score(&mut self, board: &[i8; 9]) -> i8 {
+        if let Some(&score) = self.scores.get(board) {
+            return score;
+        }
+        let empty = board.iter().filter(|&&value| value == 0).count() as i8;
+        let score = if winner(board) != 0 {
+            // Only the opponent's last move can have completed a line.
+            -(empty + 1)
+        } else if empty == 0 {
+            0
+        } else {
+            (0..9)
+                .filter(|&cell| board[cell] == 0)
+                .map(|cell| self.move_score(board, cell))
+                .max()
+                .expect("an empty cell")
+        };
+        self.scores.insert(*board, score);
+        score
+    }
+}
+
+fn value_of(score: i8) -> Value {
+    match score {
+        0 => Value::Draw,
+        s if s > 0 => Value::Win,
+        _ => Value::Loss,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solver_known_positions() {
+        let mut solver = Solver::new();
+        let empty = solver.solve(&[0; 9]).unwrap();
+        assert_eq!(empty.value, Value::Draw);
+        assert_eq!(empty.optimal_moves, (0..9).collect::<Vec<usize>>());
+        assert_eq!(solver.value(&[0; 9]), Some(Value::Draw));
+
+        // Two in a row on top, the opponent threatening the middle row:
+        // 2 wins at once.
+        let win = solver.solve(&[1, 1, 0, -1, -1, 0, 0, 0, 0]).unwrap();
+        assert_eq!(win.value, Value::Win);
+        assert_eq!(win.best_move, 2);
+        assert!(win.optimal_moves.contains(&2));
+        assert!(!win.optimal_moves.contains(&5));
+
+        // The opponent threatens both 2 and 6: blocking one loses to the
+        // other, so every move is as bad.
+        let fork = solver.solve(&[-1, -1, 0, -1, 1, 0, 0, 1, 0]).unwrap();
+        assert_eq!(fork.value, Value::Loss);
+        assert_eq!(fork.optimal_moves, vec![2, 5, 6, 8]);
+        assert_eq!(fork.best_move, 2);
+
+        // Answering a corner opening on an edge loses; the center holds.
+        let corner = solver.solve(&[-1, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!((corner.value, corner.optimal_moves), (Value::Draw, vec![4]));
+        assert_eq!(solver.value(&[-1, 1, 0, 0, 0, 0, 0, 0, 0].map(|value: i8| -value)), Some(Value::Win));
+
+        assert_eq!(solver.solve(&[1, 1, 1, -1, -1, 0, 0, 0, 0]), None);
+        assert_eq!(solver.solve(&[1, -1, 1, 1, -1, -1, -1, 1, 1]), None);
+        assert!(solver.scores.len() < 6000, "{} positions", solver.scores.len());
+    }
+}
@@ -0,0 +1,371 @@
+// Recorded games in SQLite (the `sqlite` feature), for queries like "every
+// game alice won in six moves or fewer" without loading a whole CSV. The
+// database is in WAL mode, so several stores, e.g. one per generating
+// thread, can write to the same file.
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use rusqlite::{params, Connection, ToSql};
+
+use crate::input::{GameData, GamesData, StateDiffError};
+use crate::output::GameResult;
+use crate::recorder::GameRecorder;
+
+/// Stored in `PRAGMA user_version`.
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS games (
+        id TEXT PRIMARY KEY,
+        timestamp INTEGER,
+        player1 TEXT NOT NULL,
+        player2 TEXT NOT NULL,
+        -- a player's name, 'draw', or '' for an unfinished game
+        winner TEXT NOT NULL,
+        move_count INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS moves (
+        game_id TEXT NOT NULL REFERENCES games(id),
+        move_no INTEGER NOT NULL,
+        player TEXT NOT NULL,
+        cell INTEGER NOT NULL,
+        PRIMARY KEY (game_id, move_no)
+    );";
+
+/// Why the store couldn't save or load games.
+#[derive(Debug)]
+pub enum StoreError {
+    Sqlite(rusqlite::Error),
+    /// The snapshots of a game don't add up to moves.
+    InvalidGame(StateDiffError),
+    /// Only 3x3 games are stored.
+    BoardSize(usize),
+    /// The file was written by a newer schema.
+    UnsupportedVersion(i64),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(e) => write!(f, "SQLite error: {}", e),
+            StoreError::InvalidGame(e) => write!(f, "Can't store the game: {}", e),
+            StoreError::BoardSize(cells) => write!(f, "Only 3x3 games are stored, not {} cells", cells),
+            StoreError::UnsupportedVersion(version) => {
+                write!(f, "Schema version {} is not supported, this build uses {}", version, SCHEMA_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> StoreError {
+        StoreError::Sqlite(e)
+    }
+}
+
+/// Which games `SqliteGameStore::load_games` returns; every field that is
+/// set must match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GameFilter {
+    /// A player's name or "draw".
+    pub winner: Option<String>,
+    /// Games with this player on either side.
+    pub player: Option<String>,
+    pub min_moves: Option<u32>,
+    pub max_moves: Option<u32>,
+}
+
+pub struct SqliteGameStore {
+    connection: Connection,
+    /// The game being recorded through `GameRecorder`.
+    players: (String, String),
+    states: Vec<[i8; 9]>,
+}
+
+impl SqliteGameStore {
+    /// Open the database at `path`, creating the tables when they are
+    /// missing.
+    pub fn open(path: impl AsRef<Path>) -> Result<SqliteGameStore, StoreError> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.busy_timeout(Duration::from_secs(10))?;
+        let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(StoreError::UnsupportedVersion(version));
+        }
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(SqliteGameStore {
+            connection,
+            players: ("ai".to_string(), "ai_2".to_string()),
+            states: Vec::new(),
+        })
+    }
+
+    /// Store a played 3x3 game and return its new id. A game with moves
+    /// by one player only keeps an empty name for the other.
+    pub fn insert_game(&mut self, result: &GameResult) -> Result<String, StoreError> {
+        if result.final_board.len() != 9 {
+            return Err(StoreError::BoardSize(result.final_board.len()));
+        }
+        let side = |value: i8| {
+            result
+                .history
+                .iter()
+                .find(|record| result.final_board[record.cell_index as usize] == value)
+                .map_or(String::new(), |record| record.player_name.clone())
+        };
+        let mut game = GameData::new(side(1), side(-1));
+        let mut board = [0; 9];
+        for record in &result.history {
+            let cell = record.cell_index as usize;
+            board[cell] = result.final_board[cell];
+            game.state_of_cells_list.push(board);
+        }
+        game.periodic_state_of_cells = board;
+        game.winner = match &result.winner {
+            Some(name) => name.clone(),
+            None if result.turns == 9 => "draw".to_string(),
+            None => String::new(),
+        };
+        self.insert_game_data(&game)
+    }
+
+    /// Store a game read from a file or a recorder and return its id, the
+    /// one it has or a new one.
+    pub fn insert_game_data(&mut self, game: &GameData) -> Result<String, StoreError> {
+        let moves = game.moves().map_err(StoreError::InvalidGame)?;
+        let id = game
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO games (id, timestamp, player1, player2, winner, move_count) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                game.timestamp.map(|timestamp| timestamp as i64),
+                game.player1,
+                game.player2,
+                game.winner,
+                moves.len() as i64
+            ],
+        )?;
+        {
+            let mut insert =
+                transaction.prepare("INSERT INTO moves (game_id, move_no, player, cell) VALUES (?1, ?2, ?3, ?4)")?;
+            for record in &moves {
+                insert.execute(params![id, record.move_number, record.player_name, record.cell_index])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(id)
+    }
+
+    /// The games matching `filter`, in the order they were stored.
+    pub fn load_games(&self, filter: &GameFilter) -> Result<GamesData, StoreError> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<&dyn ToSql> = Vec::new();
+        if let Some(winner) = &filter.winner {
+            conditions.push("winner = ?");
+            values.push(winner);
+        }
+        if let Some(player) = &filter.player {
+            conditions.push("(player1 = ? OR player2 = ?)");
+            values.push(player);
+            values.push(player);
+        }
+        if let Some(min) = &filter.min_moves {
+            conditions.push("move_count >= ?");
+            values.push(min);
+        }
+        if let Some(max) = &filter.max_moves {
+            conditions.push("move_count <= ?");
+            values.push(max);
+        }
+        let mut sql = "SELECT rowid, id, timestamp, player1, player2, winner FROM games".to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY rowid");
+
+        let mut games = GamesData::new(self.connection.path().unwrap_or_default().to_string());
+        let mut select = self.connection.prepare(&sql)?;
+        let mut moves = self
+            .connection
+            .prepare("SELECT player, cell FROM moves WHERE game_id = ?1 ORDER BY move_no")?;
+        let mut rows = select.query(values.as_slice())?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(1)?;
+            let mut game = GameData::new(row.get(3)?, row.get(4)?);
+            game.id = Some(id.clone());
+            game.timestamp = row.get::<_, Option<i64>>(2)?.map(|timestamp| timestamp as u64);
+            game.winner = row.get(5)?;
+            let mut board = [0; 9];
+            let mut played = moves.query(params![id])?;
+            while let Some(record) = played.next()? {
+                let player: String = record.get(0)?;
+                let cell: usize = record.get(1)?;
+                board[cell] = if player == game.player1 { 1 } else { -1 };
+                game.state_of_cells_list.push(board);
+            }
+            game.periodic_state_of_cells = board;
+            games.add_game(game);
+        }
+        Ok(games)
+    }
+
+    /// Number of stored games.
+    pub fn game_count(&self) -> Result<usize, StoreError> {
+        let count: i64 = self.connection.query_row("SELECT COUNT(*) FROM games", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+}
+
+/// Games played with the store as their recorder are inserted when they
+/// end.
+impl GameRecorder for SqliteGameStore {
+    fn record_players(&mut self, player1: &str, player2: &str) {
+        self.players = (player1.to_string(), player2.to_string());
+    }
+
+    fn record_state(&mut self, board: &[i8; 9]) {
+        self.states.push(*board);
+    }
+
+    fn record_result(&mut self, winner: &str) {
+        let (player1, player2) = std::mem::replace(&mut self.players, ("ai".to_string(), "ai_2".to_string()));
+        let mut game = GameData::new(player1, player2);
+        game.state_of_cells_list = std::mem::take(&mut self.states);
+        game.periodic_state_of_cells = game.state_of_cells_list.last().copied().unwrap_or([0; 9]);
+        game.winner = winner.to_string();
+        if let Err(e) = self.insert_game_data(&game) {
+            eprintln!("could not record game: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::RandomAi;
+    use crate::output::{Game, MovePolicy, Player};
+    use crate::selfplay;
+
+    fn temp_db(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("tictac_{}_{}.db", name, std::process::id()));
+        remove_db(&path);
+        path
+    }
+
+    fn remove_db(path: &Path) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    fn random(_symbol: char, seed: u64) -> Box<dyn MovePolicy> {
+        Box::new(RandomAi::with_seed(seed))
+    }
+
+    #[test]
+    fn test_insert_and_query_round_trip() {
+        let path = temp_db("store");
+        let results = selfplay::generate_games(40, random, random, 11);
+        {
+            let mut store = SqliteGameStore::open(&path).unwrap();
+            for result in &results {
+                store.insert_game(result).unwrap();
+            }
+        }
+        // Reopening keeps the games and the schema.
+        let store = SqliteGameStore::open(&path).unwrap();
+        assert_eq!(store.game_count().unwrap(), 40);
+        let all = store.load_games(&GameFilter::default()).unwrap();
+        for (result, game) in results.iter().zip(&all.game_data) {
+            let moves: Vec<(String, i32)> = game
+                .moves()
+                .unwrap()
+                .into_iter()
+                .map(|record| (record.player_name, record.cell_index))
+                .collect();
+            let played: Vec<(String, i32)> = result
+                .history
+                .iter()
+                .map(|record| (record.player_name.clone(), record.cell_index))
+                .collect();
+            assert_eq!(moves, played);
+            assert_eq!(game.winner, result.winner.clone().unwrap_or_else(|| "draw".to_string()));
+            assert_eq!(game.periodic_state_of_cells.to_vec(), result.final_board);
+        }
+
+        let quick_ai_wins = GameFilter {
+            winner: Some("ai".to_string()),
+            max_moves: Some(6),
+            ..GameFilter::default()
+        };
+        let expected = results
+            .iter()
+            .filter(|result| result.winner.as_deref() == Some("ai") && result.turns <= 6)
+            .count();
+        assert!(expected > 0);
+        assert_eq!(store.load_games(&quick_ai_wins).unwrap().game_data.len(), expected);
+        let draws = GameFilter {
+            winner: Some("draw".to_string()),
+            ..GameFilter::default()
+        };
+        let long = GameFilter {
+            min_moves: Some(9),
+            player: Some("ai_2".to_string()),
+            ..GameFilter::default()
+        };
+        let full = results.iter().filter(|result| result.turns == 9).count();
+        assert!(store.load_games(&draws).unwrap().game_data.len() <= full);
+        assert_eq!(store.load_games(&long).unwrap().game_data.len(), full);
+
+        // As a recorder, with the names of a human game.
+        let mut game = Game::with_players(Player::new("alice".to_string(), 'X'), Player::new("bob".to_string(), 'O'));
+        game.tictac_board.set_silent(true);
+        game.recorder = Box::new(store);
+        for position in [5, 7, 9, 3, 1] {
+            game.play_move(position).unwrap();
+        }
+        drop(game);
+        let store = SqliteGameStore::open(&path).unwrap();
+        let alice = GameFilter {
+            player: Some("alice".to_string()),
+            ..GameFilter::default()
+        };
+        let games = store.load_games(&alice).unwrap();
+        assert_eq!(games.game_data.len(), 1);
+        assert_eq!(games.game_data[0].winner, "alice");
+        assert_eq!(games.game_data[0].first_player(), "alice");
+        drop(store);
+        remove_db(&path);
+    }
+
+    #[test]
+    fn test_parallel_writers() {
+        let path = temp_db("parallel");
+        SqliteGameStore::open(&path).unwrap();
+        std::thread::scope(|scope| {
+            for seed in 0..4 {
+                let path = &path;
+                scope.spawn(move || {
+                    let mut store = SqliteGameStore::open(path).unwrap();
+                    for result in selfplay::generate_games(25, random, random, seed * 100) {
+                        store.insert_game(&result).unwrap();
+                    }
+                });
+            }
+        });
+        let store = SqliteGameStore::open(&path).unwrap();
+        assert_eq!(store.game_count().unwrap(), 100);
+        drop(store);
+        remove_db(&path);
+    }
+}
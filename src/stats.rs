@@ -0,0 +1,304 @@
+// Outcomes accumulated over many games, e.g. an ai-vs-ai session, and a
+// sanity report on a recorded dataset.
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Write};
+
+use serde::Serialize;
+
+use crate::input::{canonical_form, GameData};
+use crate::output::GameResult;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SessionStats {
+    pub games: u32,
+    /// Wins by player name.
+    pub wins: BTreeMap<String, u32>,
+    pub draws: u32,
+    /// Sum of the game lengths in moves.
+    pub total_turns: u64,
+    /// How often each cell index was the opening move.
+    pub first_moves: BTreeMap<i32, u32>,
+}
+
+impl SessionStats {
+    pub fn new() -> SessionStats {
+        SessionStats::default()
+    }
+
+    pub fn add(&mut self, result: &GameResult) {
+        self.games += 1;
+        match &result.winner {
+            Some(name) => *self.wins.entry(name.clone()).or_insert(0) += 1,
+            None => self.draws += 1,
+        }
+        self.total_turns += u64::from(result.turns);
+        if let Some(first) = result.history.first() {
+            *self.first_moves.entry(first.cell_index).or_insert(0) += 1;
+        }
+    }
+
+    /// Mean number of moves per game, 0 before any game.
+    pub fn average_length(&self) -> f32 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.total_turns as f32 / self.games as f32
+        }
+    }
+
+    /// The summary `print_report` prints.
+    pub fn report(&self) -> String {
+        let percent = |n: u32| if self.games > 0 { 100.0 * n as f32 / self.games as f32 } else { 0.0 };
+        let mut out = String::new();
+        writeln!(out, "games        {:>6}", self.games).unwrap();
+        for (name, wins) in &self.wins {
+            writeln!(out, "{:<12} {:>6} {:>5.1}%", name, wins, percent(*wins)).unwrap();
+        }
+        writeln!(out, "{:<12} {:>6} {:>5.1}%", "draws", self.draws, percent(self.draws)).unwrap();
+        writeln!(out, "avg length   {:>6.2}", self.average_length()).unwrap();
+        writeln!(out, "first moves (cell: games)").unwrap();
+        for (cell, count) in &self.first_moves {
+            writeln!(out, "  {}: {}", cell, count).unwrap();
+        }
+        out
+    }
+
+    pub fn print_report(&self) {
+        print!("{}", self.report());
+    }
+
+    /// The same numbers as a JSON object.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("plain data")
+    }
+}
+
+/// One player's games in a dataset.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PlayerRecord {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+/// What a dataset holds, from `GamesData::stats`. Cells are indexed 0 to 8
+/// from the top-left.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DatasetStats {
+    pub games: u32,
+    pub draws: u32,
+    /// Games without a result.
+    pub unfinished: u32,
+    /// Games by player name.
+    pub players: BTreeMap<String, PlayerRecord>,
+    /// Number of games of each length in moves.
+    pub lengths: BTreeMap<usize, u32>,
+    /// How often each cell was the opening move.
+    pub first_moves: [u32; 9],
+    /// In how many snapshots each cell was taken.
+    pub occupancy: [u64; 9],
+    /// Snapshots over all games.
+    pub states: u64,
+    /// Distinct snapshots, counting symmetric boards as one (see
+    /// `canonical_form`).
+    pub positions: u64,
+}
+
+impl DatasetStats {
+    pub fn from_games(games: &[GameData]) -> DatasetStats {
+        let mut stats = DatasetStats::default();
+        let mut positions = HashSet::new();
+        for game in games {
+            stats.games += 1;
+            match game.winner.as_str() {
+                "" => stats.unfinished += 1,
+                "draw" => stats.draws += 1,
+                _ => {}
+            }
+            for (name, opponent) in [(&game.player1, &game.player2), (&game.player2, &game.player1)] {
+                let record = stats.players.entry(name.clone()).or_default();
+                record.games += 1;
+                match game.winner.as_str() {
+                    "draw" => record.draws += 1,
+                    winner if winner == name => record.wins += 1,
+                    winner if winner == opponent => record.losses += 1,
+                    _ => {}
+                }
+            }
+            *stats.lengths.entry(game.move_count()).or_insert(0) += 1;
+            if let Some(first) = game.state_of_cells_list.first() {
+                if let [cell] = (0..9).filter(|&i| first[i] != 0).collect::<Vec<usize>>()[..] {
+                    stats.first_moves[cell] += 1;
+                }
+            }
+            for state in &game.state_of_cells_list {
+                stats.states += 1;
+                positions.insert(canonical_form(state).0);
+                for (count, &value) in stats.occupancy.iter_mut().zip(state) {
+                    *count += u64::from(value != 0);
+                }
+            }
+        }
+        stats.positions = positions.len() as u64;
+        stats
+    }
+
+    /// Mean number of moves per game, 0 without games.
+    pub fn average_length(&self) -> f32 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.lengths.iter().map(|(&length, &count)| length as f32 * count as f32).sum::<f32>() / self.games as f32
+        }
+    }
+
+    /// The same numbers as a JSON object.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("plain data")
+    }
+}
+
+impl fmt::Display for DatasetStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |n: u64, of: u64| if of > 0 { 100.0 * n as f32 / of as f32 } else { 0.0 };
+        let games = u64::from(self.games);
+        writeln!(f, "games        {:>6}", self.games)?;
+        writeln!(f, "draws        {:>6} {:>5.1}%", self.draws, percent(self.draws.into(), games))?;
+        if self.unfinished > 0 {
+            writeln!(f, "unfinished   {:>6}", self.unfinished)?;
+        }
+        writeln!(f, "player          games   wins  draws losses")?;
+        for (name, record) in &self.players {
+            writeln!(
+                f,
+                "{:<14} {:>6} {:>6} {:>6} {:>6}",
+                name, record.games, record.wins, record.draws, record.losses
+            )?;
+        }
+        writeln!(f, "avg length   {:>6.2}", self.average_length())?;
+        writeln!(f, "lengths (moves: games)")?;
+        for (length, count) in &self.lengths {
+            writeln!(f, "  {}: {}", length, count)?;
+        }
+        writeln!(f, "first moves, % of games")?;
+        for row in self.first_moves.chunks(3) {
+            let cells: Vec<String> = row.iter().map(|&n| format!("{:>5.1}", percent(n.into(), games))).collect();
+            writeln!(f, "  {}", cells.join(" "))?;
+        }
+        writeln!(f, "positions    {:>6} up to symmetry", self.positions)?;
+        writeln!(f, "occupancy, % of {} states", self.states)?;
+        for row in self.occupancy.chunks(3) {
+            let cells: Vec<String> = row.iter().map(|&n| format!("{:>5.1}", percent(n, self.states))).collect();
+            writeln!(f, "  {}", cells.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::GamesData;
+    use crate::output::MoveRecord;
+
+    fn result(winner: Option<&str>, first_cell: i32, turns: u32) -> GameResult {
+        GameResult {
+            winner: winner.map(str::to_string),
+            history: vec![MoveRecord {
+                move_number: 1,
+                player_name: "ai".to_string(),
+                cell_index: first_cell,
+            }],
+            final_board: vec![0; 9],
+            turns,
+            first_player: "ai".to_string(),
+        }
+    }
+
+    fn session() -> SessionStats {
+        let mut stats = SessionStats::new();
+        stats.add(&result(Some("ai"), 4, 5));
+        stats.add(&result(Some("ai_2"), 0, 6));
+        stats.add(&result(None, 4, 9));
+        stats.add(&result(Some("ai"), 4, 7));
+        stats
+    }
+
+    #[test]
+    fn test_session_stats_accumulate() {
+        let stats = session();
+        assert_eq!(stats.games, 4);
+        assert_eq!(stats.wins["ai"], 2);
+        assert_eq!(stats.wins["ai_2"], 1);
+        assert_eq!(stats.draws, 1);
+        assert_eq!(stats.average_length(), 6.75);
+        assert_eq!(stats.first_moves[&4], 3);
+        assert_eq!(stats.first_moves[&0], 1);
+        assert_eq!(SessionStats::new().average_length(), 0.0);
+        let report = stats.report();
+        assert!(report.contains("ai                2  50.0%"), "{}", report);
+        assert!(report.contains("draws             1  25.0%"), "{}", report);
+    }
+
+    #[test]
+    fn test_session_stats_to_json() {
+        assert_eq!(
+            session().to_json(),
+            "{\"games\":4,\"wins\":{\"ai\":2,\"ai_2\":1},\"draws\":1,\"total_turns\":27,\"first_moves\":{\"0\":1,\"4\":3}}"
+        );
+        let mut quoted = SessionStats::new();
+        quoted.wins.insert("a\"b\\c\n".to_string(), 1);
+        assert!(quoted.to_json().contains("\"wins\":{\"a\\\"b\\\\c\\n\":1}"), "{}", quoted.to_json());
+    }
+
+    #[test]
+    fn test_dataset_stats() {
+        let path = std::env::temp_dir().join(format!("tictac_dataset_stats_{}.csv", std::process::id()));
+        // ai wins in 5 opening in the corner, ai_2 wins in 5 opening in the
+        // center, and a 2-move game marked a draw, ai opening in the center.
+        let fixture = "\n1,0,0,0,0,0,0,0,0,\
+                       \n1,0,0,-1,0,0,0,0,0,\
+                       \n1,1,0,-1,0,0,0,0,0,\
+                       \n1,1,0,-1,-1,0,0,0,0,\
+                       \n1,1,1,-1,-1,0,0,0,0,ai\
+                       \n0,0,0,0,-1,0,0,0,0,\
+                       \n1,0,0,0,-1,0,0,0,0,\
+                       \n1,0,-1,0,-1,0,0,0,0,\
+                       \n1,0,-1,0,-1,0,0,0,1,\
+                       \n1,0,-1,0,-1,0,-1,0,1,ai_2\
+                       \n0,0,0,0,1,0,0,0,0,\
+                       \n-1,0,0,0,1,0,0,0,0,draw";
+        std::fs::write(&path, fixture).unwrap();
+        let mut data = GamesData::new(path.to_string_lossy().into_owned());
+        data.read_data().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let stats = data.stats();
+        assert_eq!((stats.games, stats.draws, stats.unfinished), (3, 1, 0));
+        let record = |games, wins, draws, losses| PlayerRecord { games, wins, draws, losses };
+        assert_eq!(stats.players["ai"], record(3, 1, 1, 1));
+        assert_eq!(stats.players["ai_2"], record(3, 1, 1, 1));
+        assert_eq!(stats.lengths, BTreeMap::from([(2, 1), (5, 2)]));
+        assert_eq!(stats.average_length(), 4.0);
+        assert_eq!(stats.first_moves, [1, 0, 0, 0, 2, 0, 0, 0, 0]);
+        assert_eq!(stats.states, 12);
+        assert_eq!(stats.occupancy, [10, 3, 4, 4, 9, 0, 1, 0, 2]);
+        assert_eq!(stats.positions, 12);
+        // Mirrored and rotated games add states but no positions.
+        let augmented = data.augment().stats();
+        assert!(augmented.states > stats.states);
+        assert_eq!(augmented.positions, 12);
+        let report = stats.to_string();
+        assert!(report.contains("ai                  3      1      1      1"), "{}", report);
+        assert!(report.contains("   33.3   0.0   0.0\n    0.0  66.7   0.0"), "{}", report);
+        assert!(stats.to_json().starts_with("{\"games\":3,\"draws\":1,\"unfinished\":0,\"players\":{\"ai\":"));
+        assert!(stats.to_json().contains("\"lengths\":{\"2\":1,\"5\":2},\"first_moves\":[1,0,0,0,2,0,0,0,0]"));
+
+        let empty = GamesData::new(String::new()).stats();
+        assert_eq!(empty, DatasetStats::default());
+        assert_eq!(empty.average_length(), 0.0);
+        assert!(empty.to_string().contains("occupancy, % of 0 states"));
+        assert!(empty.to_json().contains("\"occupancy\":[0,0,0,0,0,0,0,0,0]"));
+    }
+}
@@ -0,0 +1,201 @@
+// Round-robin between computer players: every agent meets every other one
+// both as the opening player and as the second player.
+use std::fmt::Write;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::output::{Game, MovePolicy, Player, Verbosity};
+
+/// Builds a fresh policy for one game, given the symbol it plays and a seed
+/// for any randomness it uses.
+pub type PolicyFactory = Box<dyn Fn(char, u64) -> Box<dyn MovePolicy>>;
+
+/// Games of one ordered pairing, from the opening player's side.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PairingRecord {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl PairingRecord {
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    /// Fraction of the games won, 0 before any game.
+    pub fn win_rate(&self) -> f32 {
+        if self.games() == 0 {
+            0.0
+        } else {
+            self.wins as f32 / self.games() as f32
+        }
+    }
+}
+
+pub struct Tournament {
+    entrants: Vec<(String, PolicyFactory)>,
+    pub games_per_pairing: u32,
+    /// Seeds every policy, so a run with the same entrants is repeatable.
+    pub seed: u64,
+}
+
+impl Tournament {
+    pub fn new(games_per_pairing: u32, seed: u64) -> Tournament {
+        Tournament {
+            entrants: Vec::new(),
+            games_per_pairing,
+            seed,
+        }
+    }
+
+    /// Enter an agent. Names identify the winner, so they must be unique.
+    pub fn add(&mut self, name: &str, factory: impl Fn(char, u64) -> Box<dyn MovePolicy> + 'static) {
+        assert!(
+            self.entrants.iter().all(|(other, _)| other != name),
+            "two entrants named {}",
+            name
+        );
+        self.entrants.push((name.to_string(), Box::new(factory)));
+    }
+
+    /// Play `games_per_pairing` games for every ordered pairing, silently.
+    /// The first agent of a pairing plays X and moves first.
+    pub fn run(&self) -> TournamentResult {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let n = self.entrants.len();
+        let mut records = vec![vec![PairingRecord::default(); n]; n];
+        for (first, (name1, factory1)) in self.entrants.iter().enumerate() {
+            for (second, (name2, factory2)) in self.entrants.iter().enumerate() {
+                if first == second {
+                    continue;
+                }
+                for _ in 0..self.games_per_pairing {
+                    let mut game = Game::with_policies(
+                        (Player::ai(name1.clone(), 'X'), factory1('X', rng.gen())),
+                        (Player::ai(name2.clone(), 'O'), factory2('O', rng.gen())),
+                    );
+                    game.set_verbosity(Verbosity::Silent);
+                    let record = &mut records[first][second];
+                    match game.play().winner {
+                        Some(winner) if winner == *name1 => record.wins += 1,
+                        Some(_) => record.losses += 1,
+                        None => record.draws += 1,
+                    }
+                }
+            }
+        }
+        TournamentResult {
+            names: self.entrants.iter().map(|(name, _)| name.clone()).collect(),
+            records,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TournamentResult {
+    /// Entrants in the order they were added.
+    pub names: Vec<String>,
+    /// `records[i][j]`: entrant i opening against entrant j.
+    pub records: Vec<Vec<PairingRecord>>,
+}
+
+impl TournamentResult {
+    /// Everything entrant `i` played, opening or not, from its side.
+    pub fn total(&self, i: usize) -> PairingRecord {
+        let mut total = PairingRecord::default();
+        for j in 0..self.names.len() {
+            let (opening, replying) = (self.records[i][j], self.records[j][i]);
+            total.wins += opening.wins + replying.losses;
+            total.losses += opening.losses + replying.wins;
+            total.draws += opening.draws + replying.draws;
+        }
+        total
+    }
+
+    /// Points per game (1 a win, half a draw) of entrant `i`.
+    pub fn score(&self, i: usize) -> f32 {
+        let total = self.total(i);
+        if total.games() == 0 {
+            0.0
+        } else {
+            (total.wins as f32 + 0.5 * total.draws as f32) / total.games() as f32
+        }
+    }
+
+    /// Entrant names with their score, best first.
+    pub fn ranking(&self) -> Vec<(String, f32)> {
+        let mut ranking: Vec<(String, f32)> =
+            (0..self.names.len()).map(|i| (self.names[i].clone(), self.score(i))).collect();
+        ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranking
+    }
+
+    /// Win rates with the opening player down the side, then the ranking.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        write!(out, "{:<12}", "first \\ 2nd").unwrap();
+        for name in &self.names {
+            write!(out, " {:>10}", name).unwrap();
+        }
+        writeln!(out).unwrap();
+        for (i, name) in self.names.iter().enumerate() {
+            write!(out, "{:<12}", name).unwrap();
+            for j in 0..self.names.len() {
+                if i == j {
+                    write!(out, " {:>10}", "-").unwrap();
+                } else {
+                    write!(out, " {:>9.1}%", 100.0 * self.records[i][j].win_rate()).unwrap();
+                }
+            }
+            writeln!(out).unwrap();
+        }
+        writeln!(out, "ranking (points per game)").unwrap();
+        for (place, (name, score)) in self.ranking().iter().enumerate() {
+            writeln!(out, "{:>2}. {:<12} {:.3}", place + 1, name, score).unwrap();
+        }
+        out
+    }
+
+    pub fn print_report(&self) {
+        print!("{}", self.report());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Difficulty, MinimaxAi, RandomAi};
+
+    fn minimax_vs_random(seed: u64) -> TournamentResult {
+        let mut tournament = Tournament::new(10, seed);
+        tournament.add("minimax", |symbol, _| Box::new(MinimaxAi::with_difficulty(symbol, Difficulty::Hard)));
+        tournament.add("random", |_, seed| Box::new(RandomAi::with_seed(seed)));
+        tournament.run()
+    }
+
+    #[test]
+    fn test_minimax_never_loses_to_random() {
+        let result = minimax_vs_random(1);
+        let minimax = result.total(0);
+        assert_eq!(minimax.games(), 20);
+        assert_eq!(minimax.losses, 0);
+        assert_eq!(result.total(1).wins, 0);
+        assert_eq!(result.ranking()[0].0, "minimax");
+        let report = result.report();
+        assert!(report.contains(" 1. minimax"), "{}", report);
+    }
+
+    #[test]
+    fn test_same_seed_same_results() {
+        let run = |seed| {
+            let mut tournament = Tournament::new(20, seed);
+            tournament.add("a", |_, seed| Box::new(RandomAi::with_seed(seed)));
+            tournament.add("b", |_, seed| Box::new(RandomAi::with_seed(seed)));
+            tournament.run()
+        };
+        assert_eq!(run(7), run(7));
+        assert_eq!(run(7).total(0).games(), 40);
+    }
+}
@@ -0,0 +1,219 @@
+// Options and results for HimNetwork::fit, and the epoch history both
+// networks' fit drivers return.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Header of `FitHistory::to_csv`.
+pub const HISTORY_HEADER: &str = "epoch,loss,validation_loss,accuracy,learning_rate,seconds";
+
+pub struct FitOptions {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    /// Reshuffle the examples before every epoch.
+    pub shuffle: bool,
+    /// Record per-phase timings; when off only each epoch's duration is
+    /// measured.
+    pub timing: bool,
+}
+
+impl Default for FitOptions {
+    fn default() -> Self {
+        FitOptions {
+            epochs: 10,
+            batch_size: 32,
+            learning_rate: 0.1,
+            shuffle: true,
+            timing: false,
+        }
+    }
+}
+
+/// Where the time of a `fit` call went. Forward passes run while measuring
+/// the epoch loss count as evaluation, not as training forward time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrainingTimings {
+    pub forward: Duration,
+    pub backward: Duration,
+    pub update: Duration,
+    /// Shuffling and gathering the examples of each batch.
+    pub shuffle: Duration,
+    pub evaluation: Duration,
+    /// Wall time of the whole fit call.
+    pub total: Duration,
+}
+
+impl TrainingTimings {
+    /// Sum of the individual phases; a little under `total`.
+    pub fn phases(&self) -> Duration {
+        self.forward + self.backward + self.update + self.shuffle + self.evaluation
+    }
+}
+
+impl fmt::Display for TrainingTimings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total.as_secs_f64();
+        let percent = |d: Duration| if total > 0.0 { 100.0 * d.as_secs_f64() / total } else { 0.0 };
+        writeln!(f, "total      {:>10.3?}", self.total)?;
+        for (name, d) in [
+            ("forward", self.forward),
+            ("backward", self.backward),
+            ("update", self.update),
+            ("shuffle", self.shuffle),
+            ("evaluation", self.evaluation),
+        ] {
+            writeln!(f, "{:<10} {:>10.3?} {:>5.1}%", name, d, percent(d))?;
+        }
+        Ok(())
+    }
+}
+
+/// How one epoch of a `fit` call went.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EpochStats {
+    /// Mean loss over the training examples after the epoch.
+    pub loss: f64,
+    /// Mean loss over the validation examples, if there were any.
+    pub validation_loss: Option<f64>,
+    /// Share of training examples predicted right, when they had labels.
+    pub accuracy: Option<f64>,
+    /// The learning rate the epoch trained with.
+    pub learning_rate: f64,
+    /// Wall time of the epoch, scoring included.
+    pub duration: Duration,
+}
+
+/// Returned by `HimNetwork::fit` and `g_class::NeuralNetwork::fit`: an
+/// entry per epoch run. Epochs are counted from 0.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FitHistory {
+    pub epochs: Vec<EpochStats>,
+    /// The epoch of the lowest validation loss, or of the lowest training
+    /// loss for a g_class fit keeping its best weights without validation
+    /// examples.
+    pub best_epoch: Option<usize>,
+    /// The epoch after which the patience for a better validation loss
+    /// ran out, if it did.
+    pub stopped_epoch: Option<usize>,
+    /// Where the time went, when `HimNetwork::fit` was timing.
+    pub timings: TrainingTimings,
+}
+
+impl FitHistory {
+    /// The training loss of each epoch.
+    pub fn loss(&self) -> Vec<f64> {
+        self.epochs.iter().map(|epoch| epoch.loss).collect()
+    }
+
+    /// Write a `HISTORY_HEADER` line, then a line per epoch; values that
+    /// weren't measured are left empty.
+    pub fn to_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HISTORY_HEADER)?;
+        let optional = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+        for (i, epoch) in self.epochs.iter().enumerate() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                i,
+                epoch.loss,
+                optional(epoch.validation_loss),
+                optional(epoch.accuracy),
+                epoch.learning_rate,
+                epoch.duration.as_secs_f64()
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// One line on how the loss went, e.g. "12 epochs, loss 2.1972 to
+    /// 0.8410, accuracy 0.750".
+    pub fn summary(&self) -> String {
+        let (Some(first), Some(last)) = (self.epochs.first(), self.epochs.last()) else {
+            return "0 epochs".to_string();
+        };
+        let mut line = format!("{} epochs, loss {:.4} to {:.4}", self.epochs.len(), first.loss, last.loss);
+        if let Some(accuracy) = last.accuracy {
+            line += &format!(", accuracy {:.3}", accuracy);
+        }
+        if let Some(best) = self.best_epoch {
+            line += &match self.epochs[best].validation_loss {
+                Some(loss) => format!(", best validation loss {:.4} at epoch {}", loss, best),
+                None => format!(", best loss {:.4} at epoch {}", self.epochs[best].loss, best),
+            };
+        }
+        if let Some(stopped) = self.stopped_epoch {
+            line += &format!(", stopped after epoch {}", stopped);
+        }
+        let seconds: f64 = self.epochs.iter().map(|epoch| epoch.duration.as_secs_f64()).sum();
+        if seconds > 0.0 {
+            line += &format!(", {:.2}s", seconds);
+        }
+        line
+    }
+}
+
+/// Run `f`, adding its duration to `slot` when `enabled`.
+pub(crate) fn timed<T>(enabled: bool, slot: &mut Duration, f: impl FnOnce() -> T) -> T {
+    if !enabled {
+        return f();
+    }
+    let start = Instant::now();
+    let out = f();
+    *slot += start.elapsed();
+    out
+}
+
+/// Result of `HimNetwork::sanity_check`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SanityReport {
+    /// Whether the loss got down to the target.
+    pub reached: bool,
+    /// Training epochs run before stopping.
+    pub epochs: usize,
+    pub final_loss: f32,
+    /// Fraction of the batch classified correctly at the end.
+    pub accuracy: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::g_class::{self, NeuralNetwork};
+    use crate::him_network::HimNetwork;
+
+    #[test]
+    fn test_history_csv_is_the_same_for_both_networks() {
+        let x: Vec<Vec<f32>> = (0..9).map(|cell| (0..9).map(|i| if i == cell { 1.0 } else { 0.0 }).collect()).collect();
+        let y: Vec<usize> = (0..9).map(|cell| (cell + 1) % 9).collect();
+        let mut him = HimNetwork::new();
+        him.init_params();
+        let him_history = him.fit(&x, &y, &FitOptions { epochs: 2, timing: true, ..FitOptions::default() }).unwrap();
+        let inputs: Vec<Vec<f64>> = x.iter().map(|row| row.iter().copied().map(f64::from).collect()).collect();
+        let mut nn = NeuralNetwork::new_seeded(&[9, 8, 9], 0.5, 1);
+        let options = g_class::FitConfig { epochs: 3, ..g_class::FitConfig::default() };
+        let nn_history = nn.fit_validated(&inputs, &inputs, &inputs[..3], &inputs[..3], &options).unwrap();
+
+        for (name, history, epochs) in [("him", him_history, 2), ("g_class", nn_history, 3)] {
+            let path = std::env::temp_dir().join(format!("tictac_history_{}_{}.csv", name, std::process::id()));
+            history.to_csv(&path).unwrap();
+            let text = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines[0], HISTORY_HEADER);
+            assert_eq!(lines.len(), epochs + 1, "{}", text);
+            assert!(lines.iter().all(|line| line.split(',').count() == 6), "{}", text);
+            assert!(lines[1].starts_with("0,"));
+            assert!(history.summary().starts_with(&format!("{} epochs, loss ", epochs)), "{}", history.summary());
+        }
+        assert_eq!(FitHistory::default().summary(), "0 epochs");
+
+        let epoch = |loss| EpochStats { loss, learning_rate: 0.1, ..EpochStats::default() };
+        let epochs = vec![epoch(0.5), epoch(0.25), epoch(0.375)];
+        let history = FitHistory { epochs, best_epoch: Some(1), ..FitHistory::default() };
+        assert_eq!(history.summary(), "3 epochs, loss 0.5000 to 0.3750, best loss 0.2500 at epoch 1");
+    }
+}